@@ -1,8 +1,9 @@
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 
 use criterion::{criterion_group, criterion_main, Criterion};
-use localfiles::indexer::FileIndex;
+use localfiles::indexer::{FileIndex, IndexConfig, SearchFilters};
 use tempfile::TempDir;
 
 const NUM_FILES: usize = 1000;
@@ -48,6 +49,21 @@ fn bench_index_directory(c: &mut Criterion) {
             idx.commit().unwrap();
         });
     });
+
+    c.bench_function("index_directory_1000_files_4_threads", |b| {
+        b.iter(|| {
+            let index_dir = TempDir::new().unwrap();
+            let config = IndexConfig {
+                writer_threads: Some(4),
+                writer_heap_bytes: 200_000_000,
+                ..IndexConfig::default()
+            };
+            let mut idx =
+                FileIndex::with_config(Some(index_dir.path().join("index")), config).unwrap();
+            idx.index_directory(dataset_dir.path()).unwrap();
+            idx.commit().unwrap();
+        });
+    });
 }
 
 fn bench_commit(c: &mut Criterion) {
@@ -128,5 +144,52 @@ fn bench_search(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_index_directory, bench_commit, bench_search);
+fn bench_latency_budget(c: &mut Criterion) {
+    let dataset_dir = TempDir::new().unwrap();
+    generate_dataset(dataset_dir.path());
+
+    // Without the query cache, so every iteration does the search work
+    let index_dir = TempDir::new().unwrap();
+    let config = IndexConfig {
+        query_cache: 0,
+        ..IndexConfig::default()
+    };
+    let mut idx = FileIndex::with_config(Some(index_dir.path().join("index")), config).unwrap();
+    idx.index_directory(dataset_dir.path()).unwrap();
+    idx.commit().unwrap();
+    let handle = idx.search_handle();
+
+    let full = SearchFilters {
+        snippets_per_file: 5,
+        ..SearchFilters::default()
+    };
+    let budgeted = SearchFilters {
+        latency_budget: Some(Duration::from_millis(2)),
+        ..full.clone()
+    };
+
+    let mut group = c.benchmark_group("latency_budget");
+
+    group.bench_function("broad_query_limit_100_snippets_5", |b| {
+        b.iter(|| {
+            handle.search_filtered("common_word", 100, &full).unwrap();
+        });
+    });
+
+    group.bench_function("broad_query_limit_100_snippets_5_budget_2ms", |b| {
+        b.iter(|| {
+            handle.search_filtered("common_word", 100, &budgeted).unwrap();
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_index_directory,
+    bench_commit,
+    bench_search,
+    bench_latency_budget
+);
 criterion_main!(benches);
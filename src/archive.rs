@@ -0,0 +1,252 @@
+//! Text members of `.zip` and `.tar.gz` archives.
+//!
+//! Members are addressed by a virtual path made of the archive path, `!/`, and the
+//! member name, e.g. `/snapshots/proj.zip!/src/main.rs`. Reading archives requires the
+//! `archives` feature; without it no file is treated as an archive.
+
+use std::path::{Path, PathBuf};
+
+use crate::{email, spreadsheet};
+
+/// Separator between the archive path and the member name in a virtual path.
+pub const SEPARATOR: &str = "!/";
+
+/// Most members read out of one archive; the rest are not indexed.
+pub const MAX_MEMBERS: usize = 10_000;
+
+/// A text member read out of an archive.
+pub struct Member {
+    pub name: String,
+    pub content: String,
+}
+
+/// Whether `path` names an archive whose members should be indexed.
+pub fn is_archive(path: &Path) -> bool {
+    if !cfg!(feature = "archives") {
+        return false;
+    }
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    name.ends_with(".zip") || name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+/// Build the virtual path of `member` inside `archive`.
+pub fn member_path(archive: &Path, member: &str) -> PathBuf {
+    PathBuf::from(format!(
+        "{}{}{}",
+        archive.display(),
+        SEPARATOR,
+        member_name(member)
+    ))
+}
+
+/// Name a member is indexed and looked up under: without a leading `/`.
+fn member_name(name: &str) -> &str {
+    name.trim_start_matches('/')
+}
+
+/// Prefix shared by the virtual paths of every member of `archive`.
+pub fn member_prefix(archive: &Path) -> String {
+    format!("{}{}", archive.display(), SEPARATOR)
+}
+
+/// Split a virtual path into the archive path and the member name.
+///
+/// Only splits where the part before `!/` is an archive, mailbox or spreadsheet, so an
+/// ordinary file under a directory whose name ends in `!` keeps its own path.
+pub fn split_virtual(path: &str) -> Option<(PathBuf, String)> {
+    path.match_indices(SEPARATOR).find_map(|(at, _)| {
+        let (archive, member) = (&path[..at], &path[at + SEPARATOR.len()..]);
+        let archive = Path::new(archive);
+        let container =
+            is_archive(archive) || email::is_mbox(archive) || spreadsheet::is_spreadsheet(archive);
+        (container && !member.is_empty()).then(|| (archive.to_path_buf(), member.to_string()))
+    })
+}
+
+/// Read every regular member accepted by `wanted`, skipping members larger than
+/// `max_size` and members that are not valid UTF-8. At most `MAX_MEMBERS` members are
+/// read, and sizes are checked on the decompressed bytes, not the sizes the archive
+/// declares.
+#[cfg(feature = "archives")]
+pub fn read_members(
+    path: &Path,
+    max_size: u64,
+    wanted: impl Fn(&Path) -> bool,
+) -> anyhow::Result<Vec<Member>> {
+    let mut members = Vec::new();
+    let mut seen = 0;
+    let mut keep = |name: &str, reader: &mut dyn std::io::Read| {
+        let name = member_name(name);
+        if !wanted(Path::new(name)) {
+            return;
+        }
+        if let Ok(Some(content)) = read_limited(reader, max_size) {
+            members.push(Member {
+                name: name.to_string(),
+                content,
+            });
+        }
+    };
+
+    let file = std::fs::File::open(path)?;
+    if is_zip(path) {
+        let mut zip = zip::ZipArchive::new(file)?;
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i)?;
+            if entry.is_file() {
+                seen += 1;
+                if seen > MAX_MEMBERS {
+                    break;
+                }
+                let name = entry.name().to_string();
+                keep(&name, &mut entry);
+            }
+        }
+    } else {
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_file() {
+                seen += 1;
+                if seen > MAX_MEMBERS {
+                    break;
+                }
+                let name = entry.path()?.to_string_lossy().to_string();
+                keep(&name, &mut entry);
+            }
+        }
+    }
+    if seen > MAX_MEMBERS {
+        tracing::warn!(
+            "{} has more than {} members; indexed the first {}",
+            path.display(),
+            MAX_MEMBERS,
+            MAX_MEMBERS
+        );
+    }
+    Ok(members)
+}
+
+/// Read `reader` as text, stopping after `max_size` bytes: `None` if there was more.
+#[cfg(feature = "archives")]
+fn read_limited(reader: &mut dyn std::io::Read, max_size: u64) -> anyhow::Result<Option<String>> {
+    use std::io::Read;
+
+    let mut bytes = Vec::new();
+    reader
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > max_size {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8(bytes)?))
+}
+
+#[cfg(not(feature = "archives"))]
+pub fn read_members(
+    path: &Path,
+    _max_size: u64,
+    _wanted: impl Fn(&Path) -> bool,
+) -> anyhow::Result<Vec<Member>> {
+    anyhow::bail!("Archive support is not enabled: {}", path.display())
+}
+
+/// Read a single member of an archive as text, by the name it was indexed under.
+/// Fails if it is larger than `max_size` once decompressed.
+#[cfg(feature = "archives")]
+pub fn read_member(path: &Path, member: &str, max_size: u64) -> anyhow::Result<String> {
+    let member = member_name(member);
+    let too_large = || {
+        anyhow::anyhow!(
+            "Member {} of {} is larger than {} bytes",
+            member,
+            path.display(),
+            max_size
+        )
+    };
+    let file = std::fs::File::open(path)?;
+    if is_zip(path) {
+        let mut zip = zip::ZipArchive::new(file)?;
+        let name = zip
+            .file_names()
+            .find(|name| member_name(name) == member)
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("No member {} in {}", member, path.display()))?;
+        return read_limited(&mut zip.by_name(&name)?, max_size)?.ok_or_else(too_large);
+    }
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if member_name(&entry.path()?.to_string_lossy()) == member {
+            return read_limited(&mut entry, max_size)?.ok_or_else(too_large);
+        }
+    }
+    anyhow::bail!("No member {} in {}", member, path.display())
+}
+
+#[cfg(not(feature = "archives"))]
+pub fn read_member(path: &Path, _member: &str, _max_size: u64) -> anyhow::Result<String> {
+    anyhow::bail!("Archive support is not enabled: {}", path.display())
+}
+
+#[cfg(feature = "archives")]
+fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("zip"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_virtual_path_round_trip() {
+        let p = member_path(Path::new("/snap/proj.zip"), "src/main.rs");
+        assert_eq!(p, PathBuf::from("/snap/proj.zip!/src/main.rs"));
+        let (archive, member) = split_virtual(&p.to_string_lossy()).unwrap();
+        assert_eq!(archive, PathBuf::from("/snap/proj.zip"));
+        assert_eq!(member, "src/main.rs");
+        assert!(split_virtual("/snap/plain.rs").is_none());
+    }
+
+    #[test]
+    fn test_split_virtual_needs_a_container() {
+        assert!(split_virtual("/notes/todo!/list.md").is_none());
+        let (mbox, message) = split_virtual("/mail/to do!/inbox.mbox!/3").unwrap();
+        assert_eq!(mbox, PathBuf::from("/mail/to do!/inbox.mbox"));
+        assert_eq!(message, "3");
+        assert!(split_virtual("/mail/inbox.mbox!/").is_none());
+        assert_eq!(
+            split_virtual("/snap/proj.tar.gz!/src/lib.rs").is_some(),
+            cfg!(feature = "archives")
+        );
+    }
+
+    #[cfg(feature = "archives")]
+    #[test]
+    fn test_members_are_read_by_their_indexed_name_and_size_limited() {
+        use std::io::Write;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("snap.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        let opts = zip::write::SimpleFileOptions::default();
+        zip.start_file("/etc/notes.md", opts).unwrap();
+        zip.write_all(b"rooted").unwrap();
+        zip.start_file("big.md", opts).unwrap();
+        zip.write_all(&[b'x'; 64]).unwrap();
+        zip.finish().unwrap();
+
+        let members = read_members(&path, 16, |_| true).unwrap();
+        let names: Vec<_> = members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["etc/notes.md"]);
+        assert_eq!(read_member(&path, "etc/notes.md", 16).unwrap(), "rooted");
+        assert!(read_member(&path, "big.md", 16).is_err());
+        assert_eq!(read_member(&path, "big.md", 64).unwrap().len(), 64);
+    }
+}
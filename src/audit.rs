@@ -0,0 +1,221 @@
+//! Append-only audit log of the files and queries agents accessed.
+//!
+//! One JSON object per line, written by every tool that returns file paths, contents or
+//! metadata. The log is rotated when it reaches its size limit: `audit.jsonl`
+//! becomes `audit.jsonl.1`, `.1` becomes `.2` and so on, keeping `KEEP_ROTATED` old
+//! files.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::paths;
+use crate::purge;
+
+/// `1`/`true` to log to `audit.jsonl` in the data dir, or a path to log to.
+pub const AUDIT_LOG_ENV: &str = "LOCALFILES_AUDIT_LOG";
+pub const AUDIT_MAX_MB_ENV: &str = "LOCALFILES_AUDIT_MAX_MB";
+
+const DEFAULT_MAX_BYTES: u64 = 10 * 1_000_000;
+/// Rotated files kept besides the active one.
+const KEEP_ROTATED: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEntry {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub tool: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// Files whose contents or snippets were returned.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    /// Client name and version from the MCP handshake.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(tool: &str) -> Self {
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            tool: tool.to_string(),
+            path: None,
+            query: None,
+            files: Vec::new(),
+            client: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Active {
+    file: File,
+    len: u64,
+}
+
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    active: Mutex<Active>,
+}
+
+impl AuditLog {
+    /// Open (or create) the log at `path`, rotating once it exceeds `max_bytes`.
+    pub fn open(path: PathBuf, max_bytes: u64) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let active = Self::open_active(&path)?;
+        Ok(Self {
+            path,
+            max_bytes,
+            active: Mutex::new(active),
+        })
+    }
+
+    /// The log configured by the environment, or `None` when auditing is off.
+    pub fn path_from_env() -> anyhow::Result<Option<PathBuf>> {
+        Ok(match std::env::var(AUDIT_LOG_ENV) {
+            Ok(v) if v == "1" || v.eq_ignore_ascii_case("true") => {
+                Some(paths::data_dir()?.join("audit.jsonl"))
+            }
+            Ok(v) if v.trim().is_empty() || v == "0" || v.eq_ignore_ascii_case("false") => None,
+            Ok(v) => {
+                let base = std::env::current_dir().unwrap_or_default();
+                Some(paths::expand(v.trim(), &base))
+            }
+            Err(_) => None,
+        })
+    }
+
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(path) = Self::path_from_env()? else {
+            return Ok(None);
+        };
+        let max_bytes = match std::env::var(AUDIT_MAX_MB_ENV) {
+            Ok(mb) if !mb.trim().is_empty() => {
+                let mb: u64 = mb
+                    .trim()
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid {}: {}", AUDIT_MAX_MB_ENV, e))?;
+                mb * 1_000_000
+            }
+            _ => DEFAULT_MAX_BYTES,
+        };
+        Self::open(path, max_bytes).map(Some)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `entry` as one line, rotating first if it would not fit.
+    pub fn record(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        if active.len > 0 && active.len + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+            *active = Self::open_active(&self.path)?;
+        }
+        active.file.write_all(line.as_bytes())?;
+        active.len += line.len() as u64;
+        Ok(())
+    }
+
+    /// Scrub the log and its rotated files (see `purge::scrub`) and start an empty log.
+    /// Returns the files scrubbed.
+    pub fn purge(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        let mut purged = Vec::new();
+        for path in log_files(&self.path) {
+            if purge::scrub(&path)? > 0 {
+                purged.push(path);
+            }
+        }
+        *active = Self::open_active(&self.path)?;
+        Ok(purged)
+    }
+
+    fn open_active(path: &Path) -> anyhow::Result<Active> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Active { file, len })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        rotated_path(&self.path, n)
+    }
+
+    fn rotate(&self) -> anyhow::Result<()> {
+        for n in (1..KEEP_ROTATED).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated_path(1))?;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// The log at `path` and the rotated files it may have.
+pub fn log_files(path: &Path) -> Vec<PathBuf> {
+    std::iter::once(path.to_path_buf())
+        .chain((1..=KEEP_ROTATED).map(|n| rotated_path(path, n)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_record_appends_json_lines_and_rotates() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = AuditLog::open(path.clone(), 200).unwrap();
+
+        let mut entry = AuditEntry::new("read_file");
+        entry.path = Some("/notes/plan.md".to_string());
+        entry.client = Some("test-client 1.0".to_string());
+        log.record(&entry).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(content.trim()).unwrap();
+        assert_eq!(parsed["tool"], "read_file");
+        assert_eq!(parsed["path"], "/notes/plan.md");
+        assert!(parsed.get("query").is_none());
+
+        for _ in 0..KEEP_ROTATED + 3 {
+            log.record(&entry).unwrap();
+        }
+        assert!(log.rotated_path(1).exists());
+        assert!(log.rotated_path(KEEP_ROTATED).exists());
+        assert!(!log.rotated_path(KEEP_ROTATED + 1).exists());
+        assert!(std::fs::metadata(&path).unwrap().len() <= 200);
+
+        let purged = log.purge().unwrap();
+        assert_eq!(purged.len(), KEEP_ROTATED + 1);
+        assert!(!log.rotated_path(1).exists());
+        log.record(&entry).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+}
@@ -0,0 +1,206 @@
+//! Response budgets: fitting tool output into a client's `max_response_chars`.
+//!
+//! An agent with a small context window would rather get the top results with short
+//! snippets than a response it has to throw away. Search results are fitted in stages,
+//! each only if the previous one was not enough: extra snippets per file are dropped,
+//! then the remaining snippets are shortened, then the lowest-ranked results are left
+//! out. `Trimmed` records what was omitted so the response can say so.
+
+use std::fmt;
+
+use crate::indexer::SearchResult;
+
+/// Characters a snippet is shortened to, at most.
+pub const SHORT_SNIPPET_CHARS: usize = 120;
+/// Smallest budget accepted; less would not fit one result.
+pub const MIN_RESPONSE_CHARS: usize = 500;
+/// Characters of a budget kept back for the notes after the results or content.
+pub const NOTES_CHARS: usize = 300;
+
+/// What fitting a response into its budget left out.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Trimmed {
+    /// Snippets past the first of their result.
+    pub snippets_dropped: usize,
+    /// Snippets cut to `SHORT_SNIPPET_CHARS`.
+    pub snippets_shortened: usize,
+    /// Results left out from the end.
+    pub results_dropped: usize,
+}
+
+impl Trimmed {
+    pub fn is_empty(&self) -> bool {
+        *self == Trimmed::default()
+    }
+}
+
+impl fmt::Display for Trimmed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.results_dropped > 0 {
+            parts.push(format!("{} lower-ranked results omitted", self.results_dropped));
+        }
+        if self.snippets_dropped > 0 {
+            parts.push(format!("{} extra snippets omitted", self.snippets_dropped));
+        }
+        if self.snippets_shortened > 0 {
+            parts.push(format!("{} snippets shortened", self.snippets_shortened));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Fit `results` into `max_chars` of output as rendered by `render` (its position and
+/// the result), trimming them in place.
+pub fn fit_results(
+    results: &mut Vec<SearchResult>,
+    max_chars: usize,
+    render: impl Fn(usize, &SearchResult) -> String,
+) -> Trimmed {
+    let size = |results: &[SearchResult]| -> usize {
+        results.iter().enumerate().map(|(i, r)| render(i, r).chars().count()).sum()
+    };
+    let mut trimmed = Trimmed::default();
+    if size(results) <= max_chars {
+        return trimmed;
+    }
+
+    for r in results.iter_mut() {
+        if r.snippets.len() > 1 {
+            trimmed.snippets_dropped += r.snippets.len() - 1;
+            r.snippets.truncate(1);
+        }
+    }
+    if size(results) <= max_chars {
+        return trimmed;
+    }
+
+    let shortened: Vec<usize> = results
+        .iter_mut()
+        .map(|r| {
+            let snippets = r.snippets.iter_mut().map(|s| (&mut s.text, &mut s.highlights));
+            std::iter::once((&mut r.snippet, &mut r.highlights))
+                .chain(snippets)
+                .map(|(text, highlights)| shorten(text, highlights))
+                .filter(|shortened| *shortened)
+                .count()
+        })
+        .collect();
+
+    // Keep at least the top result, however long
+    let mut total = 0;
+    let keep = results
+        .iter()
+        .enumerate()
+        .position(|(i, r)| {
+            total += render(i, r).chars().count();
+            total > max_chars
+        })
+        .map_or(results.len(), |over| over.max(1));
+    trimmed.results_dropped = results.len() - keep;
+    trimmed.snippets_shortened = shortened[..keep].iter().sum();
+    results.truncate(keep);
+    trimmed
+}
+
+/// The start of `text` that fits in `max_chars`, cut after its last whole line if it has
+/// one, and the number of characters left out.
+pub fn truncate_text(text: &str, max_chars: usize) -> (&str, usize) {
+    let Some((end, _)) = text.char_indices().nth(max_chars) else {
+        return (text, 0);
+    };
+    let end = match text[..end].rfind('\n') {
+        Some(newline) if newline > 0 => newline + 1,
+        _ => end,
+    };
+    (&text[..end], text[end..].chars().count())
+}
+
+/// Cut `text` to `SHORT_SNIPPET_CHARS`, dropping highlights past the cut. Whether it
+/// was cut.
+fn shorten(text: &mut String, highlights: &mut Vec<std::ops::Range<usize>>) -> bool {
+    let (kept, omitted) = truncate_text(text, SHORT_SNIPPET_CHARS);
+    if omitted == 0 {
+        return false;
+    }
+    let end = kept.trim_end().len();
+    text.truncate(end);
+    text.push_str("...");
+    highlights.retain(|range| range.end <= end);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::Snippet;
+
+    fn result(path: &str, snippets: usize, snippet_chars: usize) -> SearchResult {
+        let snippet = |text: String| Snippet {
+            text,
+            line_number: Some(1),
+            row: None,
+            context_start: None,
+            highlights: Vec::new(),
+        };
+        SearchResult {
+            file_path: path.to_string(),
+            file_name: path.to_string(),
+            snippet: "x".repeat(snippet_chars),
+            score: 1.0,
+            relevance: 1.0,
+            line_number: Some(1),
+            cell: None,
+            section: None,
+            revision: None,
+            commit_time: None,
+            truncated: false,
+            log_time: None,
+            row: None,
+            highlights: vec![0..1, 100..110, 150..160],
+            snippets: (0..snippets).map(|_| snippet("y".repeat(snippet_chars))).collect(),
+            context_start: None,
+            match_count: None,
+            modified: None,
+        }
+    }
+
+    fn render(_: usize, r: &SearchResult) -> String {
+        let snippets: String = r.snippets.iter().map(|s| format!("{}\n", s.text)).collect();
+        format!("{}\n{}\n{}", r.file_path, r.snippet, snippets)
+    }
+
+    #[test]
+    fn test_fit_results_in_stages() {
+        let mut results = vec![result("a", 3, 50), result("b", 3, 50)];
+        assert!(fit_results(&mut results, 10_000, render).is_empty());
+
+        // Dropping extra snippets is enough
+        let trimmed = fit_results(&mut results, 250, render);
+        assert_eq!((trimmed.snippets_dropped, trimmed.results_dropped), (4, 0));
+        assert_eq!(results.iter().map(|r| r.snippets.len()).collect::<Vec<_>>(), [1, 1]);
+
+        let mut results = vec![result("a", 1, 400), result("b", 1, 400), result("c", 1, 400)];
+        let trimmed = fit_results(&mut results, 600, render);
+        assert_eq!(trimmed.snippets_shortened, 4);
+        assert_eq!(trimmed.results_dropped, 1);
+        assert_eq!(results[0].snippet.len(), SHORT_SNIPPET_CHARS + 3);
+        assert_eq!(results[0].highlights, [0..1, 100..110]);
+        assert_eq!(
+            trimmed.to_string(),
+            "1 lower-ranked results omitted, 4 snippets shortened"
+        );
+
+        // The top result is kept whatever its size
+        let mut results = vec![result("a", 0, 400), result("b", 0, 400)];
+        assert_eq!(fit_results(&mut results, 10, render).results_dropped, 1);
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_truncate_text_at_line_end() {
+        assert_eq!(truncate_text("short", 10), ("short", 0));
+        assert_eq!(truncate_text("one\ntwo\nthree\n", 10), ("one\ntwo\n", 6));
+        assert_eq!(truncate_text("éééééé", 4), ("éééé", 2));
+    }
+}
@@ -0,0 +1,87 @@
+//! Checkpoints of directory walks, so an interrupted index of a large tree resumes where
+//! it left off instead of starting over.
+//!
+//! While `index_directory` walks a root, every commit saves the root and the directories
+//! whose files are all indexed to `<index>.crawl.json`, and the commit after the walk
+//! removes it. A checkpoint found when the index is opened means the previous process
+//! died mid-walk: the server indexes that root again in the background. Files of
+//! completed directories are only read again if their modification time no longer
+//! matches the index, and the documents of files deleted from them are dropped.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::{self, RootEntry};
+
+/// Progress of the walk of one root, as saved in `<index>.crawl.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The root being walked, with its watch setting and hidden-file policy.
+    pub root: RootEntry,
+    /// Directories whose files have all been indexed and committed.
+    pub done: BTreeSet<PathBuf>,
+}
+
+/// Path of the checkpoint file of the index at `index_path`.
+pub fn checkpoint_path(index_path: &Path) -> PathBuf {
+    let name = index_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "index".to_string());
+    index_path.with_file_name(format!("{}.crawl.json", name))
+}
+
+/// The walk the index at `index_path` was interrupted in, if any.
+pub fn load(index_path: &Path) -> anyhow::Result<Option<Checkpoint>> {
+    match std::fs::read_to_string(checkpoint_path(index_path)) {
+        Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace the checkpoint of the index at `index_path` with `checkpoint`.
+pub fn save(index_path: &Path, checkpoint: &Checkpoint) -> anyhow::Result<()> {
+    manifest::write_json_atomic(&checkpoint_path(index_path), checkpoint)
+}
+
+/// Remove the checkpoint of the index at `index_path` once its walk finished.
+pub fn remove(index_path: &Path) -> anyhow::Result<()> {
+    match std::fs::remove_file(checkpoint_path(index_path)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_load_and_remove() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        assert_eq!(load(&index_path).unwrap(), None);
+        remove(&index_path).unwrap();
+
+        let checkpoint = Checkpoint {
+            root: RootEntry {
+                path: PathBuf::from("/data"),
+                watched: false,
+                policy: None,
+            },
+            done: BTreeSet::from([PathBuf::from("/data"), PathBuf::from("/data/a")]),
+        };
+        save(&index_path, &checkpoint).unwrap();
+        assert_eq!(
+            checkpoint_path(&index_path),
+            dir.path().join("index.crawl.json")
+        );
+        assert_eq!(load(&index_path).unwrap(), Some(checkpoint));
+        remove(&index_path).unwrap();
+        assert_eq!(load(&index_path).unwrap(), None);
+    }
+}
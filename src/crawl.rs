@@ -0,0 +1,102 @@
+//! Order in which `index_directory` indexes the files of a root.
+//!
+//! With `CrawlOrder::Priority` (the default) the tree is listed first, then indexed
+//! breadth-first: shallow files before deep ones, with files modified in the last day
+//! moved up two levels and in the last week one, and the newest first within a level.
+//! Together with a commit after the first `EARLY_COMMIT_FILES` files, the top-level docs
+//! and the files being worked on become searchable while deep directories are still being
+//! indexed. `CrawlOrder::Walk` indexes files in directory walk order as the walk goes.
+
+use std::cmp::Reverse;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// `priority` or `walk`.
+pub const CRAWL_ORDER_ENV: &str = "LOCALFILES_CRAWL_ORDER";
+
+/// Files indexed in priority order before an early commit makes them searchable.
+pub const EARLY_COMMIT_FILES: usize = 1_000;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+const WEEK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CrawlOrder {
+    /// Shallow and recently modified files first.
+    #[default]
+    Priority,
+    /// Directory walk order, depth-first.
+    Walk,
+}
+
+impl CrawlOrder {
+    /// Parse `priority` or `walk`.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        match spec.trim().to_lowercase().as_str() {
+            "priority" => Ok(Self::Priority),
+            "walk" => Ok(Self::Walk),
+            other => anyhow::bail!("Unknown crawl order '{}' (use priority or walk)", other),
+        }
+    }
+}
+
+/// A file found by walking a root, waiting to be indexed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Queued {
+    pub path: PathBuf,
+    /// Directories between the root and the file; 1 for files directly in the root.
+    pub depth: usize,
+    pub modified: Option<SystemTime>,
+}
+
+/// Sort `files` into priority order, with ages measured at `now`.
+pub fn prioritize(files: &mut [Queued], now: SystemTime) {
+    files.sort_by(|a, b| {
+        let key = |f: &Queued| (level(f, now), Reverse(f.modified));
+        key(a).cmp(&key(b)).then_with(|| a.path.cmp(&b.path))
+    });
+}
+
+/// Depth a file is indexed at: its own, less the levels recent changes lift it by.
+fn level(file: &Queued, now: SystemTime) -> usize {
+    let age = file.modified.and_then(|m| now.duration_since(m).ok());
+    let lift = match age {
+        Some(age) if age <= DAY => 2,
+        Some(age) if age <= WEEK => 1,
+        _ => 0,
+    };
+    file.depth.saturating_sub(lift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prioritize_shallow_and_recent_first() {
+        let now = SystemTime::now();
+        let queued = |path: &str, depth: usize, days: u64| Queued {
+            path: PathBuf::from(path),
+            depth,
+            modified: Some(now - Duration::from_secs(days * 24 * 60 * 60) - DAY / 2),
+        };
+        let mut files = vec![
+            queued("/r/a/b/c/old.md", 4, 400),
+            queued("/r/a/b/deep.md", 3, 30),
+            queued("/r/a/mid.md", 2, 30),
+            queued("/r/a/b/c/today.md", 4, 0),
+            queued("/r/top.md", 1, 90),
+            queued("/r/a/b/week.md", 3, 3),
+            queued("/r/newer.md", 1, 10),
+        ];
+        prioritize(&mut files, now);
+        let order: Vec<_> = files.iter().map(|f| f.path.file_name().unwrap()).collect();
+        assert_eq!(
+            order,
+            ["newer.md", "top.md", "today.md", "week.md", "mid.md", "deep.md", "old.md"]
+        );
+
+        assert_eq!(CrawlOrder::parse(" Walk ").unwrap(), CrawlOrder::Walk);
+        assert!(CrawlOrder::parse("random").is_err());
+    }
+}
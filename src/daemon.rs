@@ -0,0 +1,262 @@
+//! Shared daemon mode.
+//!
+//! `localfiles --daemon` owns one machine-wide index and serves MCP sessions over a
+//! unix socket. `localfiles --proxy` is the thin stdio frontend that editors and agents
+//! spawn: it starts the daemon if needed and relays bytes between stdio and the socket,
+//! so the index is built once rather than per client process.
+//!
+//! Before the MCP stream, the proxy sends one JSON line, `{"base_dir": "..."}`, so
+//! relative path arguments resolve against the client's directory, not the daemon's.
+//!
+//! Sessions can read every indexed file, so only the user running the daemon may
+//! connect: the socket is created with mode 0600 and connections from other uids are
+//! dropped. Handshakes longer than `MAX_HELLO_BYTES` are rejected.
+
+use std::path::{Path, PathBuf};
+
+use localfiles::{owner, paths};
+use serde::{Deserialize, Serialize};
+
+use crate::server::FileSearchServer;
+
+/// Environment variable overriding the daemon socket path.
+pub const SOCKET_ENV: &str = "LOCALFILES_SOCKET";
+
+/// How long the proxy waits for a freshly spawned daemon to accept connections.
+#[cfg(unix)]
+const STARTUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Longest handshake line the daemon reads, so a client cannot make it buffer without
+/// bound.
+const MAX_HELLO_BYTES: u64 = 4096;
+
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    base_dir: PathBuf,
+}
+
+/// Daemon socket: `LOCALFILES_SOCKET`, else `daemon.sock` in the per-user data dir.
+pub fn socket_path() -> anyhow::Result<PathBuf> {
+    Ok(match std::env::var(SOCKET_ENV) {
+        Ok(p) if !p.trim().is_empty() => {
+            paths::expand(p.trim(), &std::env::current_dir().unwrap_or_default())
+        }
+        _ => paths::data_dir()?.join("daemon.sock"),
+    })
+}
+
+/// Accept connections on `socket` and serve each as its own MCP session.
+#[cfg(unix)]
+pub async fn serve(server: FileSearchServer, socket: &Path) -> anyhow::Result<()> {
+    use rmcp::ServiceExt;
+    use tokio::net::UnixStream;
+
+    if socket.exists() {
+        if UnixStream::connect(socket).await.is_ok() {
+            anyhow::bail!("A localfiles daemon is already listening on {}", socket.display());
+        }
+        // Left behind by a daemon that did not shut down cleanly
+        std::fs::remove_file(socket)?;
+    }
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let listener = bind(socket)?;
+    tracing::info!("localfiles daemon listening on {}", socket.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        if !same_user(&stream) {
+            tracing::warn!("Refused a connection from another user");
+            continue;
+        }
+        let server = server.clone();
+        tokio::spawn(async move {
+            let (read, write) = stream.into_split();
+            let mut reader = tokio::io::BufReader::new(read);
+            let server = match read_hello(&mut reader).await {
+                Ok(hello) => server.with_base_dir(hello.base_dir).with_new_session(),
+                Err(e) => {
+                    tracing::warn!("Invalid proxy handshake: {}", e);
+                    return;
+                }
+            };
+            tracing::info!("Client session started");
+            match server.serve((reader, write)).await {
+                Ok(service) => {
+                    if let Err(e) = service.waiting().await {
+                        tracing::warn!("Client session ended with error: {}", e);
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to start client session: {}", e),
+            }
+            tracing::info!("Client session closed");
+        });
+    }
+}
+
+/// Relay stdio to the daemon at `socket`, starting the daemon if it is not running.
+#[cfg(unix)]
+pub async fn proxy(socket: &Path, base_dir: &Path) -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UnixStream;
+
+    let stream = match UnixStream::connect(socket).await {
+        Ok(stream) => stream,
+        Err(_) => {
+            spawn_daemon()?;
+            let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+            loop {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                match UnixStream::connect(socket).await {
+                    Ok(stream) => break stream,
+                    Err(e) if tokio::time::Instant::now() >= deadline => {
+                        anyhow::bail!(
+                            "localfiles daemon did not start listening on {}: {}",
+                            socket.display(),
+                            e
+                        )
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+    };
+
+    let (mut from_daemon, mut to_daemon) = stream.into_split();
+    write_hello(&mut to_daemon, base_dir).await?;
+
+    let upstream = async {
+        tokio::io::copy(&mut tokio::io::stdin(), &mut to_daemon).await?;
+        // Client closed stdin: end the session
+        to_daemon.shutdown().await
+    };
+    let downstream = async {
+        let mut stdout = tokio::io::stdout();
+        tokio::io::copy(&mut from_daemon, &mut stdout).await?;
+        stdout.flush().await
+    };
+    tokio::select! {
+        result = upstream => result?,
+        result = downstream => result?,
+    }
+    Ok(())
+}
+
+/// Listen on `socket`, which only this user may connect to.
+#[cfg(unix)]
+fn bind(socket: &Path) -> anyhow::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let listener = tokio::net::UnixListener::bind(socket)?;
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Whether the process at the other end of `stream` runs as this user.
+#[cfg(unix)]
+fn same_user(stream: &tokio::net::UnixStream) -> bool {
+    match (stream.peer_cred(), owner::current_uid()) {
+        (Ok(peer), Some(uid)) => peer.uid() == uid,
+        _ => false,
+    }
+}
+
+/// Send the handshake line naming `base_dir`.
+#[cfg(unix)]
+async fn write_hello(
+    writer: &mut (impl tokio::io::AsyncWrite + Unpin),
+    base_dir: &Path,
+) -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut hello = serde_json::to_string(&Hello {
+        base_dir: base_dir.to_path_buf(),
+    })?;
+    hello.push('\n');
+    writer.write_all(hello.as_bytes()).await?;
+    Ok(())
+}
+
+/// Read the handshake line, at most `MAX_HELLO_BYTES` of it.
+#[cfg(unix)]
+async fn read_hello(reader: &mut (impl tokio::io::AsyncBufRead + Unpin)) -> anyhow::Result<Hello> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    let mut line = String::new();
+    (&mut *reader).take(MAX_HELLO_BYTES).read_line(&mut line).await?;
+    if !line.ends_with('\n') {
+        anyhow::bail!("Handshake not ended within {} bytes", MAX_HELLO_BYTES);
+    }
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// Start `localfiles --daemon` detached from this process, logging to the data dir.
+#[cfg(unix)]
+fn spawn_daemon() -> anyhow::Result<()> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let data_dir = paths::data_dir()?;
+    let log_path = data_dir.join("daemon.log");
+    std::fs::create_dir_all(&data_dir)?;
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)?;
+    tracing::info!("Starting localfiles daemon (log: {})", log_path.display());
+    Command::new(std::env::current_exe()?)
+        .arg("--daemon")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(log)
+        // Own process group, so the client's Ctrl-C or exit does not stop the daemon
+        .process_group(0)
+        .spawn()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn serve(_server: FileSearchServer, _socket: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("Daemon mode is only supported on unix platforms")
+}
+
+#[cfg(not(unix))]
+pub async fn proxy(_socket: &Path, _base_dir: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("Proxy mode is only supported on unix platforms")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncWriteExt, BufReader};
+    use tokio::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_handshake_round_trip_over_private_socket() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let socket = dir.path().join("daemon.sock");
+        let listener = bind(&socket).unwrap();
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut client = UnixStream::connect(&socket).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        assert!(same_user(&stream));
+        write_hello(&mut client, Path::new("/work/project")).await.unwrap();
+        let hello = read_hello(&mut BufReader::new(stream)).await.unwrap();
+        assert_eq!(hello.base_dir, PathBuf::from("/work/project"));
+
+        // A handshake that never ends is cut off instead of buffered
+        let mut client = UnixStream::connect(&socket).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move {
+            let _ = client.write_all(&[b'x'; 64 * 1024]).await;
+        });
+        let err = read_hello(&mut BufReader::new(stream)).await.err().unwrap();
+        assert!(err.to_string().contains("4096"), "{}", err);
+    }
+}
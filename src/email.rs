@@ -0,0 +1,253 @@
+//! Parsing for `.eml` messages and `.mbox` mailboxes.
+//!
+//! Each message in a mailbox is indexed separately under a virtual path made of the
+//! mailbox path, `!/`, and the 1-based message number, e.g. `/mail/inbox.mbox!/12`.
+//! Only `text/plain` parts are kept from multipart messages; quoted-printable and
+//! base64 bodies are decoded.
+
+use std::path::Path;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Email {
+    pub subject: String,
+    pub from: String,
+    pub to: String,
+    pub date: String,
+    pub body: String,
+}
+
+/// Whether `path` is a mailbox holding several messages.
+pub fn is_mbox(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("mbox"))
+}
+
+/// Whether `path` is a single message file.
+pub fn is_eml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("eml"))
+}
+
+/// Split an mbox file into raw messages, dropping the `From ` separator lines.
+pub fn split_mbox(content: &str) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut current = String::new();
+    let mut previous_blank = true;
+    for line in content.lines() {
+        if previous_blank && line.starts_with("From ") {
+            if !current.trim().is_empty() {
+                messages.push(std::mem::take(&mut current));
+            }
+            current.clear();
+        } else {
+            // mboxrd escapes body lines starting with "From " as ">From "
+            let line = match line.strip_prefix('>') {
+                Some(rest) if rest.trim_start_matches('>').starts_with("From ") => rest,
+                _ => line,
+            };
+            current.push_str(line);
+            current.push('\n');
+        }
+        previous_blank = line.is_empty();
+    }
+    if !current.trim().is_empty() {
+        messages.push(current);
+    }
+    messages
+}
+
+/// Parse a raw RFC 822 message.
+pub fn parse(raw: &str) -> Email {
+    let (headers, body) = split_headers(raw);
+    let header = |name: &str| {
+        headers
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let to = [header("to"), header("cc")]
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Email {
+        subject: header("subject"),
+        from: header("from"),
+        to,
+        date: header("date"),
+        body: text_body(&headers, body),
+    }
+}
+
+/// Unfold the header block and return it with the remaining body.
+fn split_headers(raw: &str) -> (Vec<(String, String)>, &str) {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut offset = 0;
+    for line in raw.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            return (headers, &raw[offset..]);
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    (headers, "")
+}
+
+/// Decode the plain text of a message or MIME part body.
+fn text_body(headers: &[(String, String)], body: &str) -> String {
+    let get = |name: &str| {
+        headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .unwrap_or("")
+    };
+    let content_type = get("content-type").to_lowercase();
+
+    if content_type.starts_with("multipart/") {
+        let Some(boundary) = param(get("content-type"), "boundary") else {
+            return body.to_string();
+        };
+        let delimiter = format!("--{}", boundary);
+        return body
+            .split(&delimiter)
+            .skip(1) // preamble
+            .filter(|part| !part.starts_with("--")) // closing delimiter
+            .map(|part| {
+                let (part_headers, part_body) = split_headers(part.trim_start_matches(['\r', '\n']));
+                text_body(&part_headers, part_body)
+            })
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+    if !content_type.is_empty() && !content_type.starts_with("text/plain") {
+        return String::new();
+    }
+
+    match get("content-transfer-encoding").to_lowercase().as_str() {
+        "quoted-printable" => decode_quoted_printable(body),
+        "base64" => decode_base64(body)
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .unwrap_or_default(),
+        _ => body.to_string(),
+    }
+}
+
+/// Value of a `name=value` parameter in a header such as `Content-Type`.
+fn param(header: &str, name: &str) -> Option<String> {
+    header.split(';').skip(1).find_map(|p| {
+        let (k, v) = p.split_once('=')?;
+        k.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+fn decode_quoted_printable(body: &str) -> String {
+    let mut bytes = Vec::with_capacity(body.len());
+    for line in body.split('\n') {
+        let line = line.trim_end_matches('\r');
+        let (line, soft_break) = match line.strip_suffix('=') {
+            Some(l) => (l, true),
+            None => (line, false),
+        };
+        let raw = line.as_bytes();
+        let mut i = 0;
+        while i < raw.len() {
+            let hex = raw.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+            match (raw[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+                (b'=', Some(b)) => {
+                    bytes.push(b);
+                    i += 3;
+                }
+                (c, _) => {
+                    bytes.push(c);
+                    i += 1;
+                }
+            }
+        }
+        if !soft_break {
+            bytes.push(b'\n');
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn decode_base64(body: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = Vec::new();
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for c in body.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let v = ALPHABET.iter().position(|&a| a == c)? as u32;
+        buf = (buf << 6) | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buf >> bits) as u8);
+            buf &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// Read message `number` (1-based) from a mailbox.
+pub fn read_message(path: &Path, number: usize) -> anyhow::Result<String> {
+    let content = std::fs::read_to_string(path)?;
+    split_mbox(&content)
+        .into_iter()
+        .nth(number.wrapping_sub(1))
+        .ok_or_else(|| anyhow::anyhow!("No message {} in {}", number, path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_headers_and_quoted_printable() {
+        let raw = "From: Alice <alice@example.com>\r\nTo: bob@example.com\r\n\
+                   Subject: Vacation\r\n plans\r\nDate: Mon, 1 Jul 2024 09:00:00 +0000\r\n\
+                   Content-Transfer-Encoding: quoted-printable\r\n\r\n\
+                   Caf=C3=A9 on Fri=\r\nday\r\n";
+        let email = parse(raw);
+        assert_eq!(email.subject, "Vacation plans");
+        assert_eq!(email.from, "Alice <alice@example.com>");
+        assert_eq!(email.to, "bob@example.com");
+        assert_eq!(email.date, "Mon, 1 Jul 2024 09:00:00 +0000");
+        assert!(email.body.contains("Café on Friday"));
+    }
+
+    #[test]
+    fn test_parse_multipart_keeps_text_plain() {
+        let raw = "Subject: Report\nContent-Type: multipart/alternative; boundary=\"XYZ\"\n\n\
+                   preamble\n--XYZ\nContent-Type: text/plain\nContent-Transfer-Encoding: base64\n\n\
+                   cGxhaW4gdGV4dA==\n--XYZ\nContent-Type: text/html\n\n<p>html</p>\n--XYZ--\n";
+        let email = parse(raw);
+        assert_eq!(email.body.trim(), "plain text");
+    }
+
+    #[test]
+    fn test_split_mbox() {
+        let mbox = "From alice@example.com Mon Jul  1 09:00:00 2024\nSubject: One\n\n\
+                    >From the top\n\nFrom bob@example.com Mon Jul  1 10:00:00 2024\n\
+                    Subject: Two\n\nbody\n";
+        let messages = split_mbox(mbox);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("From the top"));
+        assert_eq!(parse(&messages[1]).subject, "Two");
+    }
+}
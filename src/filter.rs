@@ -0,0 +1,402 @@
+//! Filter expressions over file metadata: the `filter` parameter of searches.
+//!
+//! One parameter combines conditions that would otherwise each need their own, e.g.
+//! `ext in [rs, toml] AND path startswith src AND modified > 2024-01-01`. Conditions are
+//! joined with `AND`, `OR` and `NOT` (case-insensitive, `AND` binding tighter than `OR`)
+//! and grouped with parentheses, nested at most 64 levels deep. `SearchHandle` compiles
+//! a parsed `Filter` into a tantivy query, so filtering happens in the index like the
+//! other filters.
+//!
+//! | Field      | Operators                       | Values                                  |
+//! |------------|---------------------------------|-----------------------------------------|
+//! | `ext`      | `=`, `!=`, `in`                 | extensions without the dot              |
+//! | `language` | `=`, `!=`, `in`                 | `python`, `cpp`, `make`, ...            |
+//! | `project`  | `=`, `!=`, `in`                 | project names, as `list_projects` shows |
+//! | `path`     | `=`, `!=`, `startswith`         | an absolute path, or directory names    |
+//! | `mime`     | `=`, `!=`, `in`                 | `text/x-rust`, or `text/*`              |
+//! | `owner`    | `=`, `!=`, `in`                 | `me`, a user name or a uid              |
+//! | `tracked`  | `=`                             | `true` or `false`                       |
+//! | `generated`| `=`, `!=`, `in`                 | `true`, `false`, `lockfile`, `minified` |
+//! | `modified` | `>`, `>=`, `<`, `<=`            | `2024-01-01`, a timestamp, Unix seconds |
+//!
+//! Values containing spaces or brackets are written in double quotes.
+
+use std::ops::Bound;
+
+use crate::logs;
+use crate::owner::Users;
+
+/// A parsed filter expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+    /// Files with any of these extensions (lowercase).
+    Extension(Vec<String>),
+    /// Files in any of these programming languages (lowercase).
+    Language(Vec<String>),
+    /// Files of projects with any of these names (lowercase).
+    Project(Vec<String>),
+    /// The file at this absolute path.
+    Path(String),
+    /// Files below this absolute directory.
+    PathBelow(String),
+    /// Files below directories with these path components (lowercase), like `path_prefix`.
+    Directory(Vec<String>),
+    /// Files of any of these MIME types, or of any `type/*` (lowercase).
+    Mime(Vec<String>),
+    /// Files owned by any of these uids.
+    Owner(Vec<u32>),
+    Tracked(bool),
+    /// Generated files with any of these reasons (lowercase), `true` for all of them.
+    Generated(Vec<String>),
+    /// Files modified in this range of Unix seconds.
+    Modified(Bound<u64>, Bound<u64>),
+}
+
+impl Filter {
+    /// Parse a filter expression.
+    pub fn parse(expression: &str) -> anyhow::Result<Filter> {
+        let tokens = tokenize(expression)?;
+        if tokens.is_empty() {
+            anyhow::bail!("Empty filter expression");
+        }
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let filter = parser.or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(token) => anyhow::bail!("Unexpected '{}' in filter", token.text()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    /// A double-quoted value, never a keyword.
+    Quoted(String),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn text(&self) -> &str {
+        match self {
+            Token::Word(s) | Token::Quoted(s) => s,
+            Token::Symbol(s) => s,
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(w) if w.eq_ignore_ascii_case(keyword))
+    }
+}
+
+/// Field names and their aliases.
+const FIELDS: &[&str] = &[
+    "ext", "extension", "language", "lang", "project", "path", "mime", "owner", "tracked",
+    "generated", "modified",
+];
+
+/// Symbols, longest first so `>=` is not read as `>` then `=`.
+const SYMBOLS: &[&str] = &[">=", "<=", "!=", "(", ")", "[", "]", ",", "=", ">", "<"];
+
+fn tokenize(expression: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while !rest.is_empty() {
+        if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| anyhow::anyhow!("Unterminated quote in filter"))?;
+            tokens.push(Token::Quoted(quoted[..end].to_string()));
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "()[],=!<>\"".contains(c))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                anyhow::bail!("Unexpected '{}' in filter", &rest[..1]);
+            }
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Deepest nesting of parentheses and `NOT`s a filter may have; the parser recurses once
+/// per level, so this bounds its stack use.
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Parentheses and `NOT`s around the current token.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> anyhow::Result<Token> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Filter ends too early"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek().is_some_and(|t| t.is_keyword(keyword));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> anyhow::Result<()> {
+        match self.next()? {
+            Token::Symbol(s) if s == symbol => Ok(()),
+            token => anyhow::bail!("Expected '{}' in filter, found '{}'", symbol, token.text()),
+        }
+    }
+
+    fn or(&mut self) -> anyhow::Result<Filter> {
+        let mut any = vec![self.and()?];
+        while self.eat_keyword("or") {
+            any.push(self.and()?);
+        }
+        Ok(if any.len() == 1 { any.remove(0) } else { Filter::Or(any) })
+    }
+
+    fn and(&mut self) -> anyhow::Result<Filter> {
+        let mut all = vec![self.unary()?];
+        while self.eat_keyword("and") {
+            all.push(self.unary()?);
+        }
+        Ok(if all.len() == 1 { all.remove(0) } else { Filter::And(all) })
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Filter> {
+        if self.eat_keyword("not") {
+            self.nest()?;
+            let filter = Filter::Not(Box::new(self.unary()?));
+            self.depth -= 1;
+            return Ok(filter);
+        }
+        if self.peek() == Some(&Token::Symbol("(")) {
+            self.pos += 1;
+            self.nest()?;
+            let filter = self.or()?;
+            self.expect(")")?;
+            self.depth -= 1;
+            return Ok(filter);
+        }
+        self.condition()
+    }
+
+    /// Enter one more level of nesting.
+    fn nest(&mut self) -> anyhow::Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            anyhow::bail!("Filter nests deeper than {} levels", MAX_DEPTH);
+        }
+        Ok(())
+    }
+
+    /// A single value: a word or a quoted string.
+    fn value(&mut self) -> anyhow::Result<String> {
+        match self.next()? {
+            Token::Word(s) | Token::Quoted(s) => Ok(s),
+            Token::Symbol(s) => anyhow::bail!("Expected a value in filter, found '{}'", s),
+        }
+    }
+
+    /// The values of `= value` or `in [a, b]`.
+    fn values(&mut self, op: &str) -> anyhow::Result<Vec<String>> {
+        if op != "in" {
+            return Ok(vec![self.value()?]);
+        }
+        self.expect("[")?;
+        let mut values = vec![self.value()?];
+        while self.peek() == Some(&Token::Symbol(",")) {
+            self.pos += 1;
+            values.push(self.value()?);
+        }
+        self.expect("]")?;
+        Ok(values)
+    }
+
+    fn condition(&mut self) -> anyhow::Result<Filter> {
+        let field = match self.next()? {
+            Token::Word(field) => field.to_lowercase(),
+            token => anyhow::bail!("Expected a field name in filter, found '{}'", token.text()),
+        };
+        let op = match self.next()? {
+            Token::Word(op) => op.to_lowercase(),
+            Token::Symbol(op) => op.to_string(),
+            Token::Quoted(op) => {
+                anyhow::bail!("Expected an operator after '{}', found '{}'", field, op)
+            }
+        };
+        let unsupported = || {
+            anyhow::anyhow!("Operator '{}' is not supported for '{}' in filter", op, field)
+        };
+        let negate = op == "!=";
+        let filter = match (field.as_str(), op.as_str()) {
+            ("ext" | "extension", "=" | "!=" | "in") => Filter::Extension(
+                self.values(&op)?
+                    .iter()
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .collect(),
+            ),
+            ("language" | "lang", "=" | "!=" | "in") => {
+                Filter::Language(self.values(&op)?.iter().map(|l| l.to_lowercase()).collect())
+            }
+            ("project", "=" | "!=" | "in") => {
+                Filter::Project(self.values(&op)?.iter().map(|p| p.to_lowercase()).collect())
+            }
+            ("path", "=" | "!=") => Filter::Path(self.value()?.trim_end_matches('/').to_string()),
+            ("path", "startswith") => {
+                let prefix = self.value()?;
+                if prefix.starts_with('/') {
+                    Filter::PathBelow(prefix.trim_end_matches('/').to_string())
+                } else {
+                    Filter::Directory(
+                        prefix
+                            .split('/')
+                            .filter(|s| !s.is_empty())
+                            .map(str::to_lowercase)
+                            .collect(),
+                    )
+                }
+            }
+            ("mime", "=" | "!=" | "in") => Filter::Mime(
+                self.values(&op)?.iter().map(|m| m.trim().to_lowercase()).collect(),
+            ),
+            ("owner", "=" | "!=" | "in") => {
+                let users = Users::load();
+                let owners = self.values(&op)?;
+                let uids = owners.iter().map(|o| users.resolve(o));
+                Filter::Owner(uids.collect::<anyhow::Result<_>>()?)
+            }
+            ("tracked", "=") => match self.value()?.to_lowercase().as_str() {
+                "true" => Filter::Tracked(true),
+                "false" => Filter::Tracked(false),
+                other => anyhow::bail!("Expected true or false for 'tracked', found '{}'", other),
+            },
+            ("generated", "=" | "!=" | "in") => {
+                let reasons: Vec<String> =
+                    self.values(&op)?.iter().map(|r| r.to_lowercase()).collect();
+                if reasons == ["false"] {
+                    // `generated = false` is `NOT generated = true`
+                    let generated = Filter::Generated(vec!["true".into()]);
+                    return Ok(if negate { generated } else { Filter::Not(Box::new(generated)) });
+                }
+                Filter::Generated(reasons)
+            }
+            ("modified", ">" | ">=" | "<" | "<=") => {
+                let at = logs::parse_time_bound(&self.value()?)?.max(0) as u64;
+                match op.as_str() {
+                    ">" => Filter::Modified(Bound::Excluded(at), Bound::Unbounded),
+                    ">=" => Filter::Modified(Bound::Included(at), Bound::Unbounded),
+                    "<" => Filter::Modified(Bound::Unbounded, Bound::Excluded(at)),
+                    _ => Filter::Modified(Bound::Unbounded, Bound::Included(at)),
+                }
+            }
+            _ if FIELDS.contains(&field.as_str()) => return Err(unsupported()),
+            _ => anyhow::bail!(
+                "Unknown filter field '{}' (use one of {})",
+                field,
+                FIELDS.join(", ")
+            ),
+        };
+        Ok(if negate { Filter::Not(Box::new(filter)) } else { filter })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_precedence_and_operators() {
+        let filter =
+            Filter::parse("ext in [rs, .TOML] AND path startswith src/ OR NOT modified > 2024-01-01")
+                .unwrap();
+        assert_eq!(
+            filter,
+            Filter::Or(vec![
+                Filter::And(vec![
+                    Filter::Extension(vec!["rs".into(), "toml".into()]),
+                    Filter::Directory(vec!["src".into()]),
+                ]),
+                Filter::Not(Box::new(Filter::Modified(
+                    Bound::Excluded(1_704_067_200),
+                    Bound::Unbounded
+                ))),
+            ])
+        );
+        assert_eq!(
+            Filter::parse("(tracked = true) and path != \"/home/me/my notes/\"").unwrap(),
+            Filter::And(vec![
+                Filter::Tracked(true),
+                Filter::Not(Box::new(Filter::Path("/home/me/my notes".into()))),
+            ])
+        );
+
+        assert_eq!(
+            Filter::parse("LANG in [Python, cpp]").unwrap(),
+            Filter::Language(vec!["python".into(), "cpp".into()])
+        );
+        let generated = Filter::Generated(vec!["true".into()]);
+        assert_eq!(
+            Filter::parse("generated = false").unwrap(),
+            Filter::Not(Box::new(generated.clone()))
+        );
+        assert_eq!(Filter::parse("generated != FALSE").unwrap(), generated);
+
+        let bad = [
+            "",
+            "ext",
+            "ext > rs",
+            "language startswith c",
+            "size > 10",
+            "ext = rs AND",
+            "(ext = rs",
+            "ext = \"rs",
+        ];
+        for bad in bad {
+            assert!(Filter::parse(bad).is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_limits_nesting() {
+        let nested = |depth: usize| {
+            format!("{}ext = rs{}", "(".repeat(depth), ")".repeat(depth))
+        };
+        assert!(Filter::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Filter::parse(&format!("{}ext = rs", "NOT ".repeat(MAX_DEPTH))).is_ok());
+
+        // Deeper expressions are rejected instead of overflowing the stack
+        let err = Filter::parse(&nested(3_000)).unwrap_err().to_string();
+        assert!(err.contains("deeper than 64"), "{}", err);
+        assert!(Filter::parse(&format!("{}ext = rs", "not ".repeat(3_000))).is_err());
+        let mixed = format!("{}ext = rs{}", "NOT (".repeat(40), ")".repeat(40));
+        assert!(Filter::parse(&mixed).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Output formats of the read-oriented tools: plain text, markdown or JSON.
+//!
+//! Plain text suits most LLM clients and stays the default. Chat UIs render `markdown`
+//! (headings, tables, code fences tagged with the file's language) better, and scripts
+//! and agents that post-process results want `json`. The text rendering of each tool
+//! lives with the tool; the pieces shared by the other two formats are here.
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::indexer::SearchResult;
+use crate::language;
+use crate::logs;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Markdown,
+    Json,
+}
+
+impl OutputFormat {
+    /// Parse a `format` parameter: `text`, `markdown` (or `md`) or `json`.
+    pub fn parse(name: &str) -> anyhow::Result<OutputFormat> {
+        match name.trim().to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "json" => Ok(OutputFormat::Json),
+            other => anyhow::bail!("Unknown format '{}' (use text, markdown or json)", other),
+        }
+    }
+}
+
+/// Where in its file a search result matched: `path:line`, or the notebook cell or
+/// table row.
+pub fn location(r: &SearchResult) -> String {
+    match (r.cell, r.row, r.line_number) {
+        (Some(cell), _, _) => format!("{} (cell {})", r.file_path, cell),
+        (None, Some(row), _) => format!("{} (row {})", r.file_path, row),
+        (None, None, Some(ln)) => format!("{}:{}", r.file_path, ln),
+        (None, None, None) => r.file_path.clone(),
+    }
+}
+
+/// `text` in a code fence tagged with the language of the file at `path`, fenced with
+/// more backticks than any run inside it.
+pub fn code_fence(text: &str, path: &str) -> String {
+    let path = Path::new(path);
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    let language = language::detect(path, extension.as_deref().unwrap_or(""), text);
+    let longest_run = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!("{}{}\n{}\n{}\n", fence, language.unwrap_or(""), text.trim_end_matches('\n'), fence)
+}
+
+/// A markdown table, with `|` in cells escaped and line breaks flattened.
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let row = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+    let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+    let mut out = row(headers.iter().map(|h| cell(h)).collect());
+    out.push_str(&row(headers.iter().map(|_| "---".to_string()).collect()));
+    for cells in rows {
+        out.push_str(&row(cells.iter().map(|c| cell(c)).collect()));
+    }
+    out
+}
+
+/// One search result in markdown: a heading with its position (0-based) and name, its
+/// location and relevance, and its snippets in code fences.
+pub fn result_markdown(i: usize, r: &SearchResult) -> String {
+    let mut out = format!(
+        "### {}. {}\n`{}` — relevance {:.2}\n",
+        i + 1,
+        r.file_name,
+        location(r),
+        r.relevance
+    );
+    if let Some(modified) = r.modified {
+        out.push_str(&format!("- Modified: {}\n", logs::format_timestamp(modified)));
+    }
+    if let Some(count) = r.match_count {
+        out.push_str(&format!("- Matches: {}\n", count));
+    }
+    if let Some(section) = &r.section {
+        out.push_str(&format!("- Section: {}\n", section));
+    }
+    if let Some((start, end)) = r.log_time {
+        out.push_str(&format!(
+            "- Log time: {} to {}\n",
+            logs::format_timestamp(start),
+            logs::format_timestamp(end)
+        ));
+    }
+    if r.truncated {
+        out.push_str("- Truncated: only the start and end of the file are indexed\n");
+    }
+    out.push('\n');
+    if r.snippets.is_empty() {
+        if let Some(start) = r.context_start {
+            out.push_str(&format!("From line {}:\n", start));
+        }
+        out.push_str(&code_fence(&r.snippet, &r.file_path));
+    }
+    for snippet in &r.snippets {
+        match (snippet.context_start, snippet.row, snippet.line_number) {
+            (Some(start), _, _) => out.push_str(&format!("From line {}:\n", start)),
+            (None, Some(row), _) => out.push_str(&format!("Row {}:\n", row)),
+            (None, None, Some(ln)) => out.push_str(&format!("Line {}:\n", ln)),
+            (None, None, None) => {}
+        }
+        out.push_str(&code_fence(&snippet.text, &r.file_path));
+    }
+    out.push('\n');
+    out
+}
+
+/// One search result as a JSON object, leaving out fields that do not apply.
+pub fn result_json(r: &SearchResult) -> Value {
+    let snippets: Vec<Value> = r
+        .snippets
+        .iter()
+        .map(|s| {
+            without_nulls(json!({
+                "text": s.text,
+                "line": s.line_number,
+                "row": s.row,
+                "context_start": s.context_start,
+            }))
+        })
+        .collect();
+    without_nulls(json!({
+        "path": r.file_path,
+        "name": r.file_name,
+        "location": location(r),
+        "relevance": r.relevance,
+        "score": r.score,
+        "line": r.line_number,
+        "cell": r.cell,
+        "row": r.row,
+        "section": r.section,
+        "snippet": r.snippet,
+        "context_start": r.context_start,
+        "snippets": if snippets.is_empty() { Value::Null } else { Value::Array(snippets) },
+        "match_count": r.match_count,
+        "modified": r.modified.map(logs::format_timestamp),
+        "log_time": r.log_time.map(|(start, end)| json!({
+            "start": logs::format_timestamp(start),
+            "end": logs::format_timestamp(end),
+        })),
+        "truncated": if r.truncated { Value::Bool(true) } else { Value::Null },
+    }))
+}
+
+/// `value` with the null fields of an object removed.
+pub fn without_nulls(mut value: Value) -> Value {
+    if let Value::Object(map) = &mut value {
+        map.retain(|_, v| !v.is_null());
+    }
+    value
+}
+
+/// Cursor naming `path`, the last path of a page, as a JSON string so paths with quotes
+/// or control characters survive the round trip through text output.
+pub fn encode_cursor(path: &str) -> String {
+    Value::String(path.to_string()).to_string()
+}
+
+/// Path a cursor names: the decoded JSON string, or `cursor` itself when it is not one
+/// (e.g. the raw path from a JSON page).
+pub fn decode_cursor(cursor: &str) -> String {
+    serde_json::from_str(cursor).unwrap_or_else(|_| cursor.to_string())
+}
+
+/// Index in the sorted `paths` where a page starts: after the path `cursor` names, if
+/// any, then `offset` further on.
+pub fn page_start(paths: &[String], cursor: Option<&str>, offset: usize) -> usize {
+    let after = cursor.map_or(0, |cursor| {
+        let cursor = decode_cursor(cursor);
+        paths.partition_point(|p| *p <= cursor)
+    });
+    after.saturating_add(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::Snippet;
+
+    #[test]
+    fn test_page_start_and_cursor() {
+        let paths: Vec<String> = ["/a.rs", "/b \"x\".rs", "/c.rs"].map(String::from).into();
+        assert_eq!(page_start(&paths, None, 0), 0);
+        assert_eq!(page_start(&paths, None, 2), 2);
+        assert_eq!(page_start(&paths, None, usize::MAX), usize::MAX);
+        assert_eq!(page_start(&paths, Some("/a.rs"), 0), 1);
+        assert_eq!(page_start(&paths, Some("/a.rs"), usize::MAX), usize::MAX);
+
+        let cursor = encode_cursor(&paths[1]);
+        assert_eq!(cursor, r#""/b \"x\".rs""#);
+        assert_eq!(decode_cursor(&cursor), paths[1]);
+        assert_eq!(page_start(&paths, Some(&cursor), 0), 2);
+        // The raw path of a JSON page works too, and so does a path removed since
+        assert_eq!(page_start(&paths, Some(&paths[1]), 0), 2);
+        assert_eq!(page_start(&paths, Some("/bb.rs"), 0), 2);
+    }
+
+    #[test]
+    fn test_markdown_and_json() {
+        assert_eq!(OutputFormat::parse(" MD ").unwrap(), OutputFormat::Markdown);
+        assert!(OutputFormat::parse("xml").is_err());
+
+        assert_eq!(code_fence("fn main() {}\n", "/src/main.rs"), "```rust\nfn main() {}\n```\n");
+        let nested = "Use ```sh blocks";
+        assert_eq!(code_fence(nested, "/notes.txt"), "````\nUse ```sh blocks\n````\n");
+        assert_eq!(
+            table(&["Path", "Owner"], &[vec!["/a|b.md".into(), "me".into()]]),
+            "| Path | Owner |\n| --- | --- |\n| /a\\|b.md | me |\n"
+        );
+
+        let result = SearchResult {
+            file_path: "/src/lib.rs".to_string(),
+            file_name: "lib.rs".to_string(),
+            snippet: "pub fn parse()".to_string(),
+            score: 2.5,
+            relevance: 1.0,
+            line_number: Some(12),
+            cell: None,
+            section: None,
+            revision: None,
+            commit_time: None,
+            truncated: false,
+            log_time: None,
+            row: None,
+            highlights: Vec::new(),
+            snippets: vec![Snippet {
+                text: "pub fn parse()".to_string(),
+                line_number: Some(12),
+                row: None,
+                context_start: None,
+                highlights: Vec::new(),
+            }],
+            context_start: None,
+            match_count: None,
+            modified: Some(1_719_826_200),
+        };
+        assert_eq!(
+            result_markdown(0, &result),
+            "### 1. lib.rs\n`/src/lib.rs:12` — relevance 1.00\n\
+             - Modified: 2024-07-01T09:30:00Z\n\n\
+             Line 12:\n```rust\npub fn parse()\n```\n\n"
+        );
+        assert_eq!(
+            result_json(&result),
+            json!({
+                "path": "/src/lib.rs",
+                "name": "lib.rs",
+                "location": "/src/lib.rs:12",
+                "relevance": 1.0,
+                "score": 2.5,
+                "line": 12,
+                "snippet": "pub fn parse()",
+                "snippets": [{"text": "pub fn parse()", "line": 12}],
+                "modified": "2024-07-01T09:30:00Z",
+            })
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Generated files: lockfiles, vendored dependencies, minified bundles and encoded blobs.
+//!
+//! Such files match common tokens everywhere and crowd out the sources a search is
+//! after. They are still indexed, but tagged with the reason they look generated, so the
+//! server's `exclude_generated` default can leave them out and `generated = ...` filters
+//! can find them on purpose.
+
+use std::path::Path;
+
+/// Lockfiles by name (lowercase).
+const LOCKFILES: &[&str] = &[
+    "cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "pipfile.lock",
+    "uv.lock",
+    "composer.lock",
+    "gemfile.lock",
+    "go.sum",
+];
+
+/// Directories whose files are third-party code.
+const VENDORED_DIRS: &[&str] =
+    &["node_modules", "bower_components", "vendor", "third_party", "site-packages"];
+
+/// Comments marking a file as written by a tool, looked for in its first lines.
+const MARKERS: &[&str] = &["@generated", "DO NOT EDIT", "auto-generated", "autogenerated"];
+/// Lines searched for a marker.
+const MARKER_LINES: usize = 5;
+
+/// Average length of non-empty lines above which low-whitespace text is minified.
+const MINIFIED_LINE_LENGTH: usize = 250;
+/// Share of spaces and tabs below which long lines are minified (prose has ~15%).
+const MINIFIED_WHITESPACE: f64 = 0.05;
+/// Bits of entropy per byte above which unbroken text is an encoded blob (base64 is ~6).
+const ENCODED_ENTROPY: f64 = 5.5;
+/// Text shorter than this is never judged minified or encoded.
+const MIN_SAMPLE: usize = 1_024;
+/// Bytes of content the statistics are computed on.
+const SAMPLE: usize = 64 * 1024;
+
+/// Why the file at `path` with text `content` looks generated: `lockfile`, `vendored`,
+/// `generated` (a tool's marker comment), `minified` or `encoded`; `None` for sources.
+pub fn detect(path: &Path, content: &str) -> Option<&'static str> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if LOCKFILES.contains(&name.as_str()) {
+        return Some("lockfile");
+    }
+    let vendored = path.parent().is_some_and(|dir| {
+        dir.components()
+            .any(|c| VENDORED_DIRS.contains(&c.as_os_str().to_string_lossy().as_ref()))
+    });
+    if vendored {
+        return Some("vendored");
+    }
+    let mut head = content.lines().take(MARKER_LINES);
+    if head.any(|line| MARKERS.iter().any(|marker| line.contains(marker))) {
+        return Some("generated");
+    }
+    if name.contains(".min.") {
+        return Some("minified");
+    }
+    let sample = &content.as_bytes()[..content.len().min(SAMPLE)];
+    if sample.len() < MIN_SAMPLE {
+        return None;
+    }
+    let blanks = sample.iter().filter(|b| **b == b' ' || **b == b'\t').count();
+    let whitespace = blanks as f64 / sample.len() as f64;
+    if whitespace < MINIFIED_WHITESPACE && average_line_length(sample) > MINIFIED_LINE_LENGTH {
+        return Some("minified");
+    }
+    if whitespace < MINIFIED_WHITESPACE && entropy(sample) > ENCODED_ENTROPY {
+        return Some("encoded");
+    }
+    None
+}
+
+/// Average length of the non-empty lines of `text`.
+fn average_line_length(text: &[u8]) -> usize {
+    let lines = text.split(|b| *b == b'\n').filter(|line| !line.is_empty());
+    let (count, total) = lines.fold((0, 0), |(count, total), line| (count + 1, total + line.len()));
+    total.checked_div(count).unwrap_or(0)
+}
+
+/// Shannon entropy of `text` in bits per byte, ignoring line breaks.
+fn entropy(text: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for byte in text.iter().filter(|b| **b != b'\n' && **b != b'\r') {
+        counts[*byte as usize] += 1;
+    }
+    let total: usize = counts.iter().sum();
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_reasons() {
+        let source = "fn main() {\n    println!(\"hello\");\n}\n".repeat(50);
+        assert_eq!(detect(Path::new("/src/main.rs"), &source), None);
+        assert_eq!(detect(Path::new("/app/package-lock.json"), "{}"), Some("lockfile"));
+        let vendored = Path::new("/app/node_modules/left-pad/index.js");
+        assert_eq!(detect(vendored, &source), Some("vendored"));
+        let marked = "// Code generated by protoc-gen-go. DO NOT EDIT.\npackage api\n";
+        assert_eq!(detect(Path::new("/api/api.pb.go"), marked), Some("generated"));
+        assert_eq!(detect(Path::new("/web/app.min.css"), "a{}"), Some("minified"));
+
+        let minified = "function(a,b){return a+b};var x=[1,2,3].map(function(c){return c*2});"
+            .repeat(40);
+        assert_eq!(detect(Path::new("/web/bundle.js"), &minified), Some("minified"));
+        // Unwrapped prose has long lines too, but plenty of spaces
+        let prose = format!("{}\n", "The quick brown fox jumps over the lazy dog. ".repeat(30));
+        assert_eq!(detect(Path::new("/notes/essay.md"), &prose.repeat(3)), None);
+
+        let encoded: String = (0..4_000u32)
+            .map(|i| {
+                let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+                let c = alphabet[(i.wrapping_mul(2_654_435_761) >> 7) as usize % 64] as char;
+                if i % 76 == 75 { '\n' } else { c }
+            })
+            .collect();
+        assert_eq!(detect(Path::new("/certs/blob.txt"), &encoded), Some("encoded"));
+    }
+}
@@ -0,0 +1,328 @@
+//! Git metadata for files inside repositories.
+//!
+//! With the `git` feature, files in a git work tree are indexed with the checked-out
+//! branch, whether they are tracked, and the last commit that touched them. History is
+//! walked once per repository when it is first seen (newest first, up to
+//! `MAX_COMMITS`), not per file. Without the feature no repository is ever found.
+//!
+//! Past revisions can also be read whole (`read_revision`) so their file contents can
+//! be indexed for history search.
+
+#[cfg(feature = "git")]
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Commits walked when looking for each tracked file's last change.
+#[cfg(feature = "git")]
+const MAX_COMMITS: usize = 10_000;
+
+/// Revision spec expanding to the most recent tags.
+pub const TAGS_SPEC: &str = "tags";
+
+/// Tags indexed for the `tags` spec, newest first.
+#[cfg(feature = "git")]
+const MAX_TAGS: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitInfo {
+    pub hash: String,
+    /// `Name <email>`
+    pub author: String,
+    /// Commit time in seconds since the Unix epoch.
+    pub time: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileInfo {
+    pub branch: Option<String>,
+    pub tracked: bool,
+    pub last_commit: Option<CommitInfo>,
+}
+
+/// A file's content at a past revision, with its path relative to the work tree.
+#[derive(Debug)]
+pub struct RevisionFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// The files of a repository at one revision.
+#[derive(Debug)]
+pub struct Revision {
+    /// The spec the revision was resolved from, e.g. `v1.2.0` or `HEAD~10`.
+    pub name: String,
+    pub commit: CommitInfo,
+    pub files: Vec<RevisionFile>,
+}
+
+/// Whether `path` is inside a `.git` directory.
+pub fn is_git_internal(path: &Path) -> bool {
+    path.components().any(|c| c.as_os_str() == ".git")
+}
+
+/// Snapshot of a repository's branch, tracked files and last commit per file.
+#[derive(Debug)]
+pub struct Repo {
+    #[cfg(feature = "git")]
+    root: PathBuf,
+    #[cfg(feature = "git")]
+    branch: Option<String>,
+    /// Tracked paths relative to `root`.
+    #[cfg(feature = "git")]
+    tracked: HashSet<PathBuf>,
+    #[cfg(feature = "git")]
+    last_commits: HashMap<PathBuf, CommitInfo>,
+}
+
+#[cfg(feature = "git")]
+impl Repo {
+    /// The repository whose work tree contains `path`, if any.
+    pub fn discover(path: &Path) -> Option<Self> {
+        let repo = git2::Repository::discover(path).ok()?;
+        let root = repo.workdir()?.canonicalize().ok()?;
+        let branch = repo
+            .head()
+            .ok()
+            .and_then(|head| head.shorthand().map(str::to_string));
+        let tracked: HashSet<PathBuf> = repo
+            .index()
+            .ok()?
+            .iter()
+            .map(|entry| PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned()))
+            .collect();
+        let last_commits = match last_commits(&repo, &tracked) {
+            Ok(found) => found,
+            Err(e) => {
+                // An unborn HEAD (no commits yet) lands here too
+                tracing::debug!("No commit history for {}: {}", root.display(), e);
+                HashMap::new()
+            }
+        };
+        Some(Self {
+            root,
+            branch,
+            tracked,
+            last_commits,
+        })
+    }
+
+    /// Root of the work tree.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Metadata for `path`, which must be inside the work tree.
+    pub fn file_info(&self, path: &Path) -> FileInfo {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        FileInfo {
+            branch: self.branch.clone(),
+            tracked: self.tracked.contains(relative),
+            last_commit: self.last_commits.get(relative).cloned(),
+        }
+    }
+}
+
+#[cfg(feature = "git")]
+fn commit_info(commit: &git2::Commit) -> CommitInfo {
+    let author = commit.author();
+    CommitInfo {
+        hash: commit.id().to_string(),
+        author: format!(
+            "{} <{}>",
+            author.name().unwrap_or(""),
+            author.email().unwrap_or("")
+        ),
+        time: commit.time().seconds(),
+    }
+}
+
+/// Expand revision specs for the repository at `root`: `tags` becomes the newest tags,
+/// anything else (`v1.0`, `main~20`, a hash) is kept as written.
+#[cfg(feature = "git")]
+pub fn expand_revisions(root: &Path, specs: &[String]) -> anyhow::Result<Vec<String>> {
+    let repo = git2::Repository::open(root)?;
+    let mut revisions = Vec::new();
+    for spec in specs {
+        if spec != TAGS_SPEC {
+            revisions.push(spec.clone());
+            continue;
+        }
+        let mut tags: Vec<(i64, String)> = repo
+            .tag_names(None)?
+            .iter()
+            .flatten()
+            .filter_map(|name| {
+                let commit = repo.revparse_single(name).ok()?.peel_to_commit().ok()?;
+                Some((commit.time().seconds(), name.to_string()))
+            })
+            .collect();
+        tags.sort_by(|a, b| b.cmp(a));
+        revisions.extend(tags.into_iter().take(MAX_TAGS).map(|(_, name)| name));
+    }
+    revisions.dedup();
+    Ok(revisions)
+}
+
+/// Read the files at revision `spec` that pass `wanted` and are at most `max_size` bytes.
+/// Binary (non-UTF-8) blobs are skipped.
+#[cfg(feature = "git")]
+pub fn read_revision(
+    root: &Path,
+    spec: &str,
+    max_size: u64,
+    wanted: impl Fn(&Path) -> bool,
+) -> anyhow::Result<Revision> {
+    let repo = git2::Repository::open(root)?;
+    let commit = repo.revparse_single(spec)?.peel_to_commit()?;
+    let mut files = Vec::new();
+    commit.tree()?.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() != Some(git2::ObjectType::Blob) {
+            return git2::TreeWalkResult::Ok;
+        }
+        let path = PathBuf::from(format!("{}{}", dir, entry.name().unwrap_or("")));
+        if !wanted(&path) {
+            return git2::TreeWalkResult::Ok;
+        }
+        let Ok(blob) = repo.find_blob(entry.id()) else {
+            return git2::TreeWalkResult::Ok;
+        };
+        if blob.size() as u64 <= max_size {
+            if let Ok(content) = std::str::from_utf8(blob.content()) {
+                files.push(RevisionFile {
+                    path,
+                    content: content.to_string(),
+                });
+            }
+        }
+        git2::TreeWalkResult::Ok
+    })?;
+    Ok(Revision {
+        name: spec.to_string(),
+        commit: commit_info(&commit),
+        files,
+    })
+}
+
+#[cfg(not(feature = "git"))]
+pub fn expand_revisions(_root: &Path, _specs: &[String]) -> anyhow::Result<Vec<String>> {
+    anyhow::bail!("History indexing requires building with --features git")
+}
+
+#[cfg(not(feature = "git"))]
+pub fn read_revision(
+    _root: &Path,
+    _spec: &str,
+    _max_size: u64,
+    _wanted: impl Fn(&Path) -> bool,
+) -> anyhow::Result<Revision> {
+    anyhow::bail!("History indexing requires building with --features git")
+}
+
+#[cfg(not(feature = "git"))]
+impl Repo {
+    pub fn discover(_path: &Path) -> Option<Self> {
+        None
+    }
+
+    pub fn root(&self) -> &Path {
+        Path::new("")
+    }
+
+    pub fn file_info(&self, _path: &Path) -> FileInfo {
+        FileInfo::default()
+    }
+}
+
+/// Walk history from HEAD, recording the newest commit touching each path, until every
+/// tracked file has one or `MAX_COMMITS` have been seen.
+#[cfg(feature = "git")]
+fn last_commits(
+    repo: &git2::Repository,
+    tracked: &HashSet<PathBuf>,
+) -> Result<HashMap<PathBuf, CommitInfo>, git2::Error> {
+    let mut found: HashMap<PathBuf, CommitInfo> = HashMap::new();
+    let mut tracked_found = 0;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(git2::Sort::TIME)?;
+    for oid in walk.take(MAX_COMMITS) {
+        let commit = repo.find_commit(oid?)?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None, // root commit
+        };
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        let info = commit_info(&commit);
+        for delta in diff.deltas() {
+            let Some(path) = delta.new_file().path() else {
+                continue;
+            };
+            if !found.contains_key(path) {
+                if tracked.contains(path) {
+                    tracked_found += 1;
+                }
+                found.insert(path.to_path_buf(), info.clone());
+            }
+        }
+        if tracked_found >= tracked.len() {
+            break;
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_git_internal() {
+        assert!(is_git_internal(Path::new("/repo/.git/HEAD")));
+        assert!(is_git_internal(Path::new("/repo/.git")));
+        assert!(!is_git_internal(Path::new("/repo/.github/workflows/ci.yml")));
+        assert!(!is_git_internal(Path::new("/repo/src/git.rs")));
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn test_repo_file_info() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join("tracked.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.path().join("untracked.rs"), "fn other() {}").unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("tracked.rs")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Alice", "alice@example.com").unwrap();
+        let oid = repo
+            .commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[])
+            .unwrap();
+
+        let found = Repo::discover(&dir.path().join("tracked.rs")).unwrap();
+        assert_eq!(found.root(), dir.path().canonicalize().unwrap());
+        let info = found.file_info(&found.root().join("tracked.rs"));
+        assert!(info.tracked);
+        assert!(info.branch.is_some());
+        let commit = info.last_commit.unwrap();
+        assert_eq!(commit.hash, oid.to_string());
+        assert_eq!(commit.author, "Alice <alice@example.com>");
+
+        let untracked = found.file_info(&found.root().join("untracked.rs"));
+        assert!(!untracked.tracked);
+        assert!(untracked.last_commit.is_none());
+
+        let commit = repo.find_commit(oid).unwrap();
+        repo.tag_lightweight("v1.0", commit.as_object(), false).unwrap();
+        assert_eq!(
+            expand_revisions(dir.path(), &[TAGS_SPEC.to_string(), "HEAD".to_string()]).unwrap(),
+            vec!["v1.0", "HEAD"]
+        );
+        let revision = read_revision(dir.path(), "v1.0", 1024, |_| true).unwrap();
+        assert_eq!(revision.commit.hash, oid.to_string());
+        assert_eq!(revision.files.len(), 1);
+        assert_eq!(revision.files[0].path, PathBuf::from("tracked.rs"));
+        assert_eq!(revision.files[0].content, "fn main() {}");
+    }
+}
@@ -0,0 +1,156 @@
+//! Health checks behind the `health` tool.
+//!
+//! Each check passes or fails with a one-line detail, and the report serializes to JSON
+//! so clients can tell which part of the server is broken (`index_opens`, `query`,
+//! `watcher`, `event_channel`, `disk_space`) without parsing prose.
+
+use std::path::Path;
+
+use serde::Serialize;
+
+/// Free space below which `disk_space` fails: commits and segment merges write new
+/// files before the old ones are deleted.
+pub const MIN_FREE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Fraction of the watcher channel in use at which `event_channel` fails; beyond it
+/// events start to overflow and must be rescanned.
+pub const CHANNEL_SATURATED: f64 = 0.9;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    pub fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: true,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthReport {
+    /// Whether every check passed.
+    pub ok: bool,
+    pub checks: Vec<Check>,
+}
+
+impl HealthReport {
+    pub fn new(checks: Vec<Check>) -> Self {
+        Self {
+            ok: checks.iter().all(|c| c.ok),
+            checks,
+        }
+    }
+
+    /// One line per check, for the text part of the tool result.
+    pub fn summary(&self) -> String {
+        let mut out = format!("Health: {}\n", if self.ok { "ok" } else { "FAILING" });
+        for check in &self.checks {
+            let verdict = if check.ok { "pass" } else { "FAIL" };
+            out.push_str(&format!("  [{}] {}: {}\n", verdict, check.name, check.detail));
+        }
+        out
+    }
+}
+
+/// Open the on-disk index at `dir` read-only and load its segment list, the way a
+/// restart would.
+pub fn index_opens(dir: &Path) -> Check {
+    let opened = tantivy::Index::open_in_dir(dir).and_then(|index| index.searchable_segment_ids());
+    match opened {
+        Ok(segments) => Check::pass("index_opens", format!("{} segments", segments.len())),
+        Err(e) => Check::fail("index_opens", format!("{}: {}", dir.display(), e)),
+    }
+}
+
+/// Whether `used` of the watcher channel's `capacity` slots leaves room for bursts.
+pub fn event_channel(used: usize, capacity: usize) -> Check {
+    let detail = format!("{}/{} slots in use", used, capacity);
+    if capacity > 0 && used as f64 >= capacity as f64 * CHANNEL_SATURATED {
+        Check::fail("event_channel", format!("{}; events will overflow", detail))
+    } else {
+        Check::pass("event_channel", detail)
+    }
+}
+
+/// Whether the filesystem holding `path` has at least `MIN_FREE_BYTES` free.
+pub fn disk_space(path: &Path) -> Check {
+    match free_bytes(path) {
+        Some(free) if free >= MIN_FREE_BYTES => {
+            Check::pass("disk_space", format!("{} MB free", free / 1_000_000))
+        }
+        Some(free) => Check::fail(
+            "disk_space",
+            format!(
+                "{} MB free at {}, need {} MB",
+                free / 1_000_000,
+                path.display(),
+                MIN_FREE_BYTES / 1_000_000
+            ),
+        ),
+        None => Check::pass("disk_space", "free space unknown on this platform"),
+    }
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path` (unix only).
+pub fn free_bytes(path: &Path) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+        // SAFETY: `path` is NUL-terminated and `stat` is a valid out-pointer
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+            return None;
+        }
+        Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_checks_and_report() {
+        let dir = TempDir::new().unwrap();
+        assert!(!index_opens(dir.path()).ok);
+        let index_dir = dir.path().join("index");
+        crate::indexer::FileIndex::new(Some(index_dir.clone())).unwrap();
+        assert!(index_opens(&index_dir).ok);
+
+        assert!(event_channel(10, 256).ok);
+        assert!(!event_channel(240, 256).ok);
+        #[cfg(unix)]
+        assert!(free_bytes(dir.path()).is_some_and(|free| free > 0));
+
+        let report = HealthReport::new(vec![
+            Check::pass("query", "ran in 1ms"),
+            Check::fail("watcher", "stopped"),
+        ]);
+        assert!(!report.ok);
+        assert!(report.summary().contains("[FAIL] watcher: stopped"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["checks"][0]["name"], "query");
+    }
+}
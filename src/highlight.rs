@@ -0,0 +1,234 @@
+//! Match highlighting in search snippets.
+//!
+//! The words and phrases of a query (`query::text_terms`) are located in each result's
+//! snippet and wrapped in markers, `**term**` by default, so it is clear which text
+//! matched. Matching approximates the index's tokenizer: terms match case-insensitively
+//! at word boundaries, and the words of a phrase may be separated by any run of spaces
+//! or punctuation, as they are when tantivy matches the phrase. In wildcard words `*`
+//! matches any run of letters and digits and `?` one. `Matching` adjusts this for
+//! case-sensitive searches and prefix or substring matches.
+
+use std::ops::Range;
+
+use crate::query::MatchMode;
+
+/// Environment variable setting the markers: `off`, one marker for both sides (`**`),
+/// or an opening and a closing marker separated by a space (`<mark> </mark>`).
+pub const HIGHLIGHT_ENV: &str = "LOCALFILES_HIGHLIGHT";
+
+/// Markers placed around matches in snippets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    pub open: String,
+    pub close: String,
+}
+
+impl Default for Highlight {
+    fn default() -> Self {
+        Self {
+            open: "**".to_string(),
+            close: "**".to_string(),
+        }
+    }
+}
+
+impl Highlight {
+    /// No markers; matches are still reported as `SearchResult::highlights`.
+    pub fn none() -> Self {
+        Self {
+            open: String::new(),
+            close: String::new(),
+        }
+    }
+
+    /// Parse a `LOCALFILES_HIGHLIGHT` value.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let words: Vec<&str> = spec.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(Self::none()),
+            [off] if off.eq_ignore_ascii_case("off") || off.eq_ignore_ascii_case("none") => {
+                Ok(Self::none())
+            }
+            [marker] => Ok(Self {
+                open: marker.to_string(),
+                close: marker.to_string(),
+            }),
+            [open, close] => Ok(Self {
+                open: open.to_string(),
+                close: close.to_string(),
+            }),
+            _ => anyhow::bail!("Expected 'off', a marker, or two markers, got '{}'", spec),
+        }
+    }
+
+    /// `text` with the markers around `ranges` (sorted and disjoint), and the ranges of
+    /// the matched text in the result.
+    pub fn apply(&self, text: &str, ranges: &[Range<usize>]) -> (String, Vec<Range<usize>>) {
+        let mut out = String::with_capacity(text.len() + ranges.len() * 4);
+        let mut marked = Vec::with_capacity(ranges.len());
+        let mut last = 0;
+        for range in ranges {
+            out.push_str(&text[last..range.start]);
+            out.push_str(&self.open);
+            let start = out.len();
+            out.push_str(&text[range.clone()]);
+            marked.push(start..out.len());
+            out.push_str(&self.close);
+            last = range.end;
+        }
+        out.push_str(&text[last..]);
+        (out, marked)
+    }
+}
+
+/// How terms are located in text; the default matches whole words in any case.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Matching {
+    /// Compare letters exactly (then terms keep their case).
+    pub case_sensitive: bool,
+    /// Which word boundaries a match needs: both, only the start, or none.
+    pub mode: MatchMode,
+}
+
+/// Byte ranges of `terms` (lowercase words or phrases) in `text`, sorted and disjoint;
+/// where matches overlap the earliest, then longest, wins.
+pub fn find_matches(text: &str, terms: &[String]) -> Vec<Range<usize>> {
+    find_matches_with(text, terms, Matching::default())
+}
+
+/// `find_matches`, locating terms as `matching` says.
+pub fn find_matches_with(text: &str, terms: &[String], matching: Matching) -> Vec<Range<usize>> {
+    let mut terms: Vec<&str> = terms
+        .iter()
+        .map(|t| t.trim())
+        .filter(|t| t.chars().any(char::is_alphanumeric))
+        .collect();
+    terms.sort_by_key(|t| std::cmp::Reverse(t.len()));
+
+    let mut matches = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut skip_to = 0;
+    for (i, c) in text.char_indices() {
+        let at_boundary = !prev.is_some_and(char::is_alphanumeric);
+        prev = Some(c);
+        if i < skip_to {
+            continue;
+        }
+        let found = terms.iter().find_map(|term| {
+            let starts_word = term.starts_with(char::is_alphanumeric);
+            if starts_word && !at_boundary && matching.mode != MatchMode::Substring {
+                return None;
+            }
+            let end = i + match_len(&text[i..], term, matching.case_sensitive)?;
+            let ends_word = term.ends_with(char::is_alphanumeric);
+            let next = text[end..].chars().next();
+            let ends_at_boundary = !next.is_some_and(char::is_alphanumeric);
+            (!ends_word || ends_at_boundary || matching.mode != MatchMode::Word).then_some(end)
+        });
+        if let Some(end) = found {
+            matches.push(i..end);
+            skip_to = end;
+        }
+    }
+    matches
+}
+
+/// Length of the match of `term` at the start of `text`, if it matches there.
+fn match_len(text: &str, term: &str, case_sensitive: bool) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+    let mut term_chars = term.char_indices().peekable();
+    while let Some((ti, t)) = term_chars.next() {
+        if t == '*' {
+            // The longest run after which the rest of the term matches
+            let rest = &term[ti + 1..];
+            let start = chars.peek().map_or(text.len(), |(i, _)| *i);
+            let mut ends = vec![start];
+            while let Some((i, c)) = chars.next_if(|(_, c)| c.is_alphanumeric()) {
+                ends.push(i + c.len_utf8());
+            }
+            return ends.into_iter().rev().find_map(|end| {
+                Some(end + match_len(&text[end..], rest, case_sensitive)?)
+            });
+        } else if t == '?' {
+            chars.next_if(|(_, c)| c.is_alphanumeric())?;
+        } else if t.is_whitespace() {
+            while term_chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+            let mut separators = 0;
+            while chars.next_if(|(_, c)| !c.is_alphanumeric()).is_some() {
+                separators += 1;
+            }
+            if separators == 0 {
+                return None;
+            }
+        } else {
+            let (_, c) = chars.next()?;
+            let same = if case_sensitive {
+                c == t
+            } else {
+                c.to_lowercase().eq(t.to_lowercase())
+            };
+            if !same {
+                return None;
+            }
+        }
+    }
+    Some(chars.peek().map_or(text.len(), |(i, _)| *i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_find_and_mark_matches() {
+        let text = "Error: connection error_code, errors. Connection\n  Refused!";
+        let ranges = find_matches(text, &terms(&["error", "connection refused"]));
+        // `error_code` is the tokens `error` and `code`; `errors` is another word
+        assert_eq!(ranges, vec![0..5, 18..23, 38..58]);
+        let (marked, highlights) = Highlight::default().apply(text, &ranges);
+        assert_eq!(
+            marked,
+            "**Error**: connection **error**_code, errors. **Connection\n  Refused**!"
+        );
+        assert_eq!(&marked[highlights[0].clone()], "Error");
+
+        let ranges = find_matches("status: failed | note: ok", &terms(&["status: failed"]));
+        assert_eq!(ranges, vec![0..14]);
+        let ranges = find_matches("a b", &terms(&["a"]));
+        let (plain, highlights) = Highlight::none().apply("a b", &ranges);
+        assert_eq!((plain.as_str(), highlights), ("a b", ranges));
+
+        let exact = Matching {
+            case_sensitive: true,
+            ..Matching::default()
+        };
+        let ranges = find_matches_with("error Error ERROR", &terms(&["Error"]), exact);
+        assert_eq!(ranges, vec![6..11]);
+        let text = "get_user getuser user";
+        let matching = |mode| Matching {
+            mode,
+            ..Matching::default()
+        };
+        let prefix = find_matches_with(text, &terms(&["get"]), matching(MatchMode::Prefix));
+        assert_eq!(prefix, vec![0..3, 9..12]);
+        let substring = find_matches_with(text, &terms(&["user"]), matching(MatchMode::Substring));
+        assert_eq!(substring, vec![4..8, 12..16, 17..21]);
+
+        let text = "config conf test_1.py test_10.py";
+        let ranges = find_matches(text, &terms(&["conf*", "test_?.py", "c*g"]));
+        assert_eq!(ranges, vec![0..6, 7..11, 12..21]);
+    }
+
+    #[test]
+    fn test_parse_markers() {
+        assert_eq!(Highlight::parse("off").unwrap(), Highlight::none());
+        let mark = Highlight::parse("<mark> </mark>").unwrap();
+        assert_eq!((mark.open.as_str(), mark.close.as_str()), ("<mark>", "</mark>"));
+        assert_eq!(Highlight::parse(">>").unwrap().close, ">>");
+        assert!(Highlight::parse("a b c").is_err());
+    }
+}
@@ -1,602 +1,7392 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use tantivy::collector::TopDocs;
-use tantivy::query::{BooleanQuery, Occur, QueryParser, TermQuery};
-use tantivy::schema::{Field, IndexRecordOption, Schema, STORED, STRING, TEXT};
-use tantivy::schema::Value;
-use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+use arc_swap::ArcSwap;
+use tantivy::collector::{DocSetCollector, FacetCollector, TopDocs};
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, MoreLikeThisQuery, Occur,
+    PhrasePrefixQuery, QueryParser, QueryParserError, RangeQuery, RegexQuery, TermQuery,
+};
+use tantivy::schema::{
+    Facet, FacetOptions, Field, FieldType, IndexRecordOption, Schema, TextFieldIndexing,
+    TextOptions, FAST, INDEXED, STORED, STRING, TEXT,
+};
+use tantivy::tokenizer::{LowerCaser, RawTokenizer, TextAnalyzer, TokenStream};
+use tantivy::query_grammar::{Delimiter, UserInputAst, UserInputLeaf, UserInputLiteral};
+use tantivy::schema::{OwnedValue, Value};
+use tantivy::{
+    doc, DocAddress, DocId, DocSet, Index, IndexReader, IndexWriter, Order, ReloadPolicy, Score,
+    Searcher, SegmentReader, TantivyDocument, Term, TERMINATED,
+};
+use tokio::sync::{mpsc, watch};
 use walkdir::WalkDir;
 
-const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024; // 10MB
-const SCHEMA_VERSION: u32 = 2;
+use crate::archive;
+use crate::checkpoint::{self, Checkpoint};
+use crate::crawl::{self, CrawlOrder};
+use crate::email;
+use crate::filter::Filter;
+use crate::git;
+use crate::highlight::{self, Highlight, Matching};
+use crate::instance::InstanceLock;
+use crate::logs::{self, TimeRange};
+use crate::manifest::{self, RootEntry};
+use crate::pins;
+use crate::purge;
+use crate::ranking::RankingRule;
+use crate::synonyms::Synonyms;
+use crate::usage::Usage;
+use crate::markdown;
+use crate::mime;
+use crate::notebook;
+use crate::keys;
+use crate::generated;
+use crate::language;
+use crate::latency::Pace;
+use crate::ocr::OcrConfig;
+use crate::owner::{self, Owner, Users};
+use crate::paths;
+use crate::project::{self, Project, ProjectSummary};
+use crate::query::{self, MatchMode};
+use crate::query_cache::QueryCache;
+use crate::retry::{FailedFile, RetryQueue};
+use crate::spreadsheet;
+use crate::sqlite;
+use crate::summary;
+use crate::tabular;
+use crate::telemetry;
+use crate::watcher::FileEvent;
+
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024; // 10MB
+/// Documents re-tokenized when analyzing terms of a subset of the index.
+const MAX_ANALYZED_DOCS: usize = 2_000;
+/// Hits fetched per requested result when grouping by file, since chunked files take
+/// several.
+const GROUP_OVERFETCH: usize = 5;
+/// Hits fetched per requested result of a case-sensitive search, since some only match
+/// in another case.
+const CASE_OVERFETCH: usize = 5;
+const SCHEMA_VERSION: u32 = 19;
+
+/// Directories that hold caches, virtualenvs and build state rather than content.
+const DEFAULT_SKIP_DIRS: &[&str] = &[
+    ".cache", ".venv", "venv", ".tox", ".nox", ".mypy_cache", ".pytest_cache", ".ruff_cache",
+    "__pycache__", ".terraform", "node_modules", ".next", ".nuxt", ".gradle", ".idea",
+];
+
+/// Bytes before the indexed end of a followed log that must be unchanged for new bytes
+/// to count as an append.
+const TAIL_FINGERPRINT_BYTES: usize = 4_096;
+
+/// Tags are matched whole and case-insensitively, so `tag:project/alpha` works.
+const TAG_TOKENIZER: &str = "tag";
 
 const SUPPORTED_EXTENSIONS: &[&str] = &[
-    "txt", "md", "rs", "py", "js", "ts", "jsx", "tsx", "json", "toml", "yaml", "yml", "html",
-    "css", "scss", "sh", "bash", "zsh", "c", "cpp", "h", "hpp", "java", "go", "rb", "php",
-    "sql", "xml", "csv", "log", "cfg", "conf", "ini", "env", "makefile", "dockerfile",
+    "txt", "md", "rs", "py", "pyi", "js", "ts", "jsx", "tsx", "json", "toml", "yaml", "yml",
+    "html", "css", "scss", "sh", "bash", "zsh", "c", "cpp", "h", "hpp", "java", "go", "rb",
+    "php", "sql", "xml", "csv", "tsv", "log", "cfg", "conf", "ini", "env", "ipynb", "eml",
+    "mbox", "makefile", "dockerfile",
 ];
 
+#[derive(Clone)]
 pub struct SearchResult {
     pub file_path: String,
     pub file_name: String,
     pub snippet: String,
+    /// Raw BM25 score; only comparable between results of the same query.
     pub score: f32,
+    /// `score` relative to the best result of the query, from 0 to 1.
+    pub relevance: f32,
+    pub line_number: Option<usize>,
+    /// Notebook cell containing the match, for `.ipynb` files.
+    pub cell: Option<usize>,
+    /// Heading of the markdown section containing the match.
+    pub section: Option<String>,
+    /// Revision the match was found at, for history search results.
+    pub revision: Option<String>,
+    /// Commit time of `revision`, in seconds since the Unix epoch.
+    pub commit_time: Option<i64>,
+    /// Only the start and end of the file are indexed because it exceeds the size limit.
+    pub truncated: bool,
+    /// Time range of the matching log chunk, in seconds since the Unix epoch.
+    pub log_time: Option<(i64, i64)>,
+    /// 1-based data row containing the match, for `.csv` and `.tsv` files.
+    pub row: Option<usize>,
+    /// Byte ranges of the matched words and phrases in `snippet`, inside the markers.
+    pub highlights: Vec<std::ops::Range<usize>>,
+    /// One snippet per matching line in file order, when more than one per file was
+    /// requested (`SearchFilters::snippets_per_file`).
+    pub snippets: Vec<Snippet>,
+    /// With `SearchFilters::context_lines`, `snippet` is whole lines around the match and
+    /// this is the line (or row, for tables) number of its first line.
+    pub context_start: Option<usize>,
+    /// With `SearchFilters::group_by_file`, the file's matching lines (or matching
+    /// documents, for queries on fields only) across all its chunks.
+    pub match_count: Option<usize>,
+    /// Modification time of the file when it was indexed, in seconds since the Unix epoch.
+    pub modified: Option<i64>,
+}
+
+/// A matching line of a search result.
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    pub text: String,
     pub line_number: Option<usize>,
+    /// 1-based data row, for table rows.
+    pub row: Option<usize>,
+    /// Line (or row) number of the first line of `text`, with context lines.
+    pub context_start: Option<usize>,
+    /// Byte ranges of the matched words and phrases in `text`.
+    pub highlights: Vec<std::ops::Range<usize>>,
+}
+
+/// An indexed file similar to a given file or text.
+#[derive(Debug, Clone)]
+pub struct SimilarFile {
+    pub file_path: String,
+    pub score: f32,
+}
+
+/// An indexed file, with the modification time it was indexed with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentFile {
+    pub file_path: String,
+    pub modified: SystemTime,
+}
+
+/// Term frequencies across a set of indexed files.
+#[derive(Debug, Default)]
+pub struct TermStats {
+    /// Terms with the number of files containing each, most frequent first.
+    pub terms: Vec<(String, u64)>,
+    /// Files the counts were computed over.
+    pub documents: u64,
+    /// Whether more files matched than were analyzed (`MAX_ANALYZED_DOCS`).
+    pub sampled: bool,
+    /// Whether `terms` and `documents` count index documents rather than files: the
+    /// chunks of split logs and tables one by one, and deleted documents until their
+    /// segments are merged.
+    pub approximate: bool,
 }
 
+#[derive(Clone)]
 pub struct SearchOutput {
     pub results: Vec<SearchResult>,
     pub total_count: usize,
+    /// Commit generation of the index snapshot the search ran against.
+    pub generation: u64,
+    /// Whether `SearchFilters::latency_budget` cut results, snippets or match counts.
+    pub degraded: bool,
 }
 
-pub struct IndexStatus {
-    pub num_files: usize,
-    pub watched_paths: Vec<String>,
-    pub index_path: String,
+/// Writer and commit tuning.
+#[derive(Debug, Clone)]
+pub struct IndexConfig {
+    /// Total memory budget of the tantivy writer, shared by its threads (at least 15MB each).
+    pub writer_heap_bytes: usize,
+    /// Indexing threads; `None` lets tantivy choose from the available cores and heap.
+    pub writer_threads: Option<usize>,
+    /// Commit after this many added documents...
+    pub commit_every_docs: usize,
+    /// ...or once this much time has passed since the last commit, so large walks become
+    /// searchable progressively and bursts of watcher events share a commit.
+    pub commit_interval: Duration,
+    /// Build the index in RAM instead of on disk; nothing is persisted or left behind.
+    pub in_memory: bool,
+    /// Extensions (or extensionless names such as `makefile`) of files to index, in
+    /// lowercase; `None` uses the built-in list of text formats.
+    pub extensions: Option<Vec<String>>,
+    /// Larger files are skipped, or partially indexed with `LargeFiles::Truncate`.
+    pub max_file_size: u64,
+    pub large_files: LargeFiles,
+    /// Which dotfiles and directories walks of watched roots skip, unless a root has its
+    /// own policy (`FileIndex::set_hidden_policy`).
+    pub hidden: HiddenPolicy,
+    /// Index SQLite databases' schema plus up to this many rows of text per table;
+    /// `None` leaves databases out. Requires the `sqlite` feature.
+    pub sqlite_rows: Option<usize>,
+    /// Markers around matches in result snippets.
+    pub highlight: Highlight,
+    /// When the on-disk index cannot be opened or recovered, move it aside to a
+    /// `*.corrupt-<timestamp>` directory and start an empty one. Off by default: opening
+    /// fails and the index is left untouched.
+    pub rebuild_corrupt: bool,
+    /// Outputs of this many recent searches are reused until the next commit; 0 turns
+    /// the cache off.
+    pub query_cache: usize,
+    /// Keep a summary document per directory for `SearchHandle::search_directories`.
+    pub directory_summaries: bool,
+    /// Factors the scores of matches in files selected by globs are multiplied by; the
+    /// first matching rule applies.
+    pub ranking: Vec<RankingRule>,
+    /// Groups of equivalent words and phrases a query's words also match.
+    pub synonyms: Synonyms,
+    /// Count reads of search results (`SearchHandle::record_read`) and rank often-read
+    /// files higher.
+    pub usage_ranking: bool,
+    /// Order `index_directory` indexes the files of a root in.
+    pub crawl_order: CrawlOrder,
+    /// Move an index left at the old temp location (`legacy_index_path`) to the index
+    /// path on open, after locking it, if the index path has none yet.
+    pub migrate_legacy: bool,
 }
 
-pub struct FileIndex {
-    index: Index,
-    writer: IndexWriter,
-    reader: IndexReader,
-    field_path: Field,
-    field_name: Field,
-    field_content: Field,
-    field_modified: Field,
-    field_extension: Field,
-    field_directory: Field,
-    indexed_paths: HashSet<PathBuf>,
-    watched_roots: Vec<PathBuf>,
-    index_path: PathBuf,
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            writer_heap_bytes: 100_000_000,
+            writer_threads: None,
+            commit_every_docs: 10_000,
+            commit_interval: Duration::from_secs(5),
+            in_memory: false,
+            extensions: None,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            large_files: LargeFiles::Skip,
+            hidden: HiddenPolicy::default(),
+            sqlite_rows: None,
+            highlight: Highlight::default(),
+            rebuild_corrupt: false,
+            query_cache: 256,
+            directory_summaries: false,
+            ranking: Vec::new(),
+            synonyms: Synonyms::default(),
+            usage_ranking: false,
+            crawl_order: CrawlOrder::default(),
+            migrate_legacy: false,
+        }
+    }
 }
 
-impl FileIndex {
-    pub fn new(index_path: Option<PathBuf>) -> anyhow::Result<Self> {
-        let index_path = index_path.unwrap_or_else(|| {
-            let mut p = std::env::temp_dir();
-            p.push("localfiles_index");
-            p
-        });
+/// What to do with plain text files larger than `IndexConfig::max_file_size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LargeFiles {
+    /// Leave them out of the index.
+    Skip,
+    /// Index their first and last `max_file_size / 2` bytes, cut at line boundaries, and
+    /// mark their search results as truncated. Suited to large logs.
+    Truncate,
+}
 
-        // Schema version migration: delete stale index if version mismatches
-        let version_file = index_path.join("schema_version");
-        if index_path.exists() {
-            let needs_recreate = match std::fs::read_to_string(&version_file) {
-                Ok(v) => v.trim().parse::<u32>().unwrap_or(0) != SCHEMA_VERSION,
-                Err(_) => true, // missing version file means old schema
-            };
-            if needs_recreate {
-                tracing::info!("Schema version changed, recreating index at {}", index_path.display());
-                std::fs::remove_dir_all(&index_path)?;
-            }
+impl LargeFiles {
+    /// Parse `skip` or `truncate`.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        match spec.trim().to_lowercase().as_str() {
+            "skip" => Ok(Self::Skip),
+            "truncate" => Ok(Self::Truncate),
+            other => anyhow::bail!("Unknown large file mode '{}' (use skip or truncate)", other),
         }
+    }
+}
 
-        let mut schema_builder = Schema::builder();
-        let field_path = schema_builder.add_text_field("file_path", STRING | STORED);
-        let field_name = schema_builder.add_text_field("file_name", TEXT | STORED);
-        let field_content = schema_builder.add_text_field("content", TEXT | STORED);
-        let field_modified = schema_builder.add_text_field("last_modified", STRING | STORED);
-        let field_extension = schema_builder.add_text_field("extension", TEXT | STORED);
-        let field_directory = schema_builder.add_text_field("directory", TEXT | STORED);
-        let schema = schema_builder.build();
-
-        let index = if index_path.exists() {
-            match Index::open_in_dir(&index_path) {
-                Ok(idx) => idx,
-                Err(_) => {
-                    tracing::warn!("Corrupted index, recreating at {}", index_path.display());
-                    std::fs::remove_dir_all(&index_path)?;
-                    std::fs::create_dir_all(&index_path)?;
-                    Index::create_in_dir(&index_path, schema.clone())?
-                }
-            }
-        } else {
-            std::fs::create_dir_all(&index_path)?;
-            Index::create_in_dir(&index_path, schema.clone())?
-        };
+/// Which hidden files and well-known junk directories are indexed below a watched root.
+///
+/// `.git` is always skipped. Files indexed individually with `index_file`, outside any
+/// watched root, are not subject to the policy.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HiddenPolicy {
+    /// Index dotfiles and descend into dot-directories such as `.github`.
+    pub hidden: bool,
+    /// Directory names never descended into, hidden or not.
+    pub skip_dirs: Vec<String>,
+}
 
-        // Write schema version file
-        std::fs::write(&version_file, SCHEMA_VERSION.to_string())?;
+impl Default for HiddenPolicy {
+    fn default() -> Self {
+        Self {
+            hidden: true,
+            skip_dirs: DEFAULT_SKIP_DIRS.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+}
 
-        let writer = index.writer(50_000_000)?; // 50MB heap
-        let reader = index
-            .reader_builder()
-            .reload_policy(ReloadPolicy::OnCommitWithDelay)
-            .try_into()?;
+impl HiddenPolicy {
+    /// Parse a comma-separated list of directory names; `none` skips no directories.
+    pub fn parse_skip_dirs(spec: &str) -> Vec<String> {
+        if spec.trim().eq_ignore_ascii_case("none") {
+            return Vec::new();
+        }
+        spec.split(',')
+            .map(|d| d.trim().trim_end_matches('/').to_string())
+            .filter(|d| !d.is_empty())
+            .collect()
+    }
 
-        Ok(Self {
-            index,
-            writer,
-            reader,
-            field_path,
-            field_name,
-            field_content,
-            field_modified,
-            field_extension,
-            field_directory,
-            indexed_paths: HashSet::new(),
-            watched_roots: Vec::new(),
-            index_path,
-        })
+    /// Whether an entry named `name` below a root is indexed (or, for a directory,
+    /// descended into).
+    fn allows(&self, name: &std::ffi::OsStr, is_dir: bool) -> bool {
+        let name = name.to_string_lossy();
+        if name == ".git" || (is_dir && self.skip_dirs.iter().any(|d| *d == name)) {
+            return false;
+        }
+        self.hidden || !name.starts_with('.')
     }
 
-    pub fn index_file(&mut self, path: &Path) -> anyhow::Result<()> {
-        if !Self::is_supported(path) {
-            return Ok(());
+    /// Whether the file at `relative` (below a root) or one of its directories is skipped.
+    fn excludes(&self, relative: &Path) -> bool {
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            let is_dir = components.peek().is_some();
+            if !self.allows(component.as_os_str(), is_dir) {
+                return true;
+            }
         }
+        false
+    }
+}
 
-        let metadata = std::fs::metadata(path)?;
-        if metadata.len() > MAX_FILE_SIZE {
-            return Ok(());
+/// How much of a log is indexed, so lines appended to it can be indexed on their own.
+#[derive(Debug, Clone)]
+struct LogTail {
+    /// Bytes indexed; always just after a line break.
+    offset: u64,
+    /// Lines indexed.
+    lines: usize,
+    /// Time of the last timestamped line indexed.
+    last_time: Option<i64>,
+    /// The last `TAIL_FINGERPRINT_BYTES` (or fewer) bytes indexed, to tell an append
+    /// from a rewrite.
+    fingerprint: Vec<u8>,
+}
+
+impl LogTail {
+    fn new(indexed: &[u8], lines: usize, last_time: Option<i64>) -> Self {
+        let keep = indexed.len().min(TAIL_FINGERPRINT_BYTES);
+        Self {
+            offset: indexed.len() as u64,
+            lines,
+            last_time,
+            fingerprint: indexed[indexed.len() - keep..].to_vec(),
         }
+    }
+}
 
-        let content = match std::fs::read_to_string(path) {
-            Ok(c) => c,
-            Err(_) => return Ok(()), // skip binary / unreadable files
-        };
+/// Identity of a file on disk, shared by every path linking to it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum FileId {
+    /// Device and inode number.
+    #[cfg(unix)]
+    Inode(u64, u64),
+    /// Fully resolved path, where inode numbers are not available.
+    #[cfg(not(unix))]
+    Canonical(PathBuf),
+}
 
-        let modified = metadata
-            .modified()
-            .unwrap_or(SystemTime::UNIX_EPOCH)
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_default();
-        let file_name = path
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_string();
-        let file_path_str = path.to_string_lossy().to_string();
-        let extension = path
-            .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("")
-            .to_lowercase();
-        let directory = path
-            .parent()
-            .map(|p| p.to_string_lossy().to_string())
-            .unwrap_or_default();
+impl FileId {
+    #[cfg(unix)]
+    fn of(_path: &Path, metadata: &std::fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Some(FileId::Inode(metadata.dev(), metadata.ino()))
+    }
 
-        // Upsert: remove existing then add
-        self.remove_file(path)?;
+    #[cfg(not(unix))]
+    fn of(path: &Path, _metadata: &std::fs::Metadata) -> Option<Self> {
+        path.canonicalize().ok().map(FileId::Canonical)
+    }
+}
 
-        self.writer.add_document(doc!(
-            self.field_path => file_path_str,
-            self.field_name => file_name,
-            self.field_content => content,
-            self.field_modified => format!("{}s", modified.as_secs()),
-            self.field_extension => extension,
-            self.field_directory => directory,
-        ))?;
-        self.indexed_paths.insert(path.to_path_buf());
-        Ok(())
+/// Builds a `FileIndex` for embedding, without the MCP server.
+///
+/// ```no_run
+/// use localfiles::prelude::*;
+///
+/// let mut index = FileIndex::builder()
+///     .path("/var/lib/myapp/index")
+///     .extensions(["md", "txt"])
+///     .max_file_size(1_000_000)
+///     .build()?;
+/// index.index_directory(std::path::Path::new("/home/me/notes"))?;
+/// index.commit()?;
+/// let results = index.search_handle().search("meeting", 10, None, None)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+/// `build` creates the index directory if needed, takes its lock file
+/// (`<path>.lock`) for the lifetime of the index, and recreates the directory if it
+/// holds an index from an older schema or cannot be opened. Nothing is written with
+/// `in_memory`. Without `path`, the index goes to the per-user data directory.
+#[derive(Default)]
+pub struct IndexBuilder {
+    path: Option<PathBuf>,
+    config: IndexConfig,
+    ocr: Option<OcrConfig>,
+    history_revisions: Vec<String>,
+}
+
+impl IndexBuilder {
+    /// Directory to keep the index in.
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
     }
 
-    pub fn remove_file(&mut self, path: &Path) -> anyhow::Result<()> {
-        let path_str = path.to_string_lossy().to_string();
-        self.writer
-            .delete_term(Term::from_field_text(self.field_path, &path_str));
-        self.indexed_paths.remove(path);
-        Ok(())
+    /// Keep the index in RAM; `path` is ignored.
+    pub fn in_memory(mut self) -> Self {
+        self.config.in_memory = true;
+        self
     }
 
-    pub fn index_directory(&mut self, dir: &Path) -> anyhow::Result<u64> {
-        let mut count = 0u64;
-        for entry in WalkDir::new(dir)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            if entry.file_type().is_file() {
-                if self.index_file(entry.path()).is_ok() {
-                    count += 1;
-                }
-            }
-        }
-        if !self.watched_roots.contains(&dir.to_path_buf()) {
-            self.watched_roots.push(dir.to_path_buf());
-        }
-        Ok(count)
+    /// Index only files with these extensions (or extensionless names such as `Makefile`)
+    /// instead of the built-in list, e.g. `["md", "txt"]`.
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.extensions = Some(
+            extensions
+                .into_iter()
+                .map(|e| e.into().trim_start_matches('.').to_lowercase())
+                .collect(),
+        );
+        self
     }
 
-    pub fn commit(&mut self) -> anyhow::Result<()> {
-        self.writer.commit()?;
-        self.reader.reload()?;
-        Ok(())
+    /// Skip files larger than `bytes` (default 10MB).
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.config.max_file_size = bytes;
+        self
     }
 
-    pub fn search(
-        &self,
-        query_str: &str,
-        limit: usize,
-        file_type: Option<&str>,
-        path_prefix: Option<&str>,
-    ) -> anyhow::Result<SearchOutput> {
-        let has_text_query = !query_str.trim().is_empty();
-        let has_filters = file_type.is_some() || path_prefix.is_some();
+    /// Partially index files over `max_file_size` instead of skipping them.
+    pub fn large_files(mut self, mode: LargeFiles) -> Self {
+        self.config.large_files = mode;
+        self
+    }
 
-        if !has_text_query && !has_filters {
-            return Ok(SearchOutput {
-                results: vec![],
-                total_count: 0,
-            });
+    /// Which dotfiles and directories to skip when walking directories.
+    pub fn hidden_policy(mut self, policy: HiddenPolicy) -> Self {
+        self.config.hidden = policy;
+        self
+    }
+
+    /// Index SQLite databases, with up to `rows` rows of text per table (0: schema only).
+    pub fn sqlite_rows(mut self, rows: usize) -> Self {
+        self.config.sqlite_rows = Some(rows);
+        self
+    }
+
+    /// Markers around matches in result snippets (`**` by default).
+    pub fn highlight(mut self, highlight: Highlight) -> Self {
+        self.config.highlight = highlight;
+        self
+    }
+
+    /// Move an index that cannot be opened aside and start over instead of failing.
+    pub fn rebuild_corrupt(mut self) -> Self {
+        self.config.rebuild_corrupt = true;
+        self
+    }
+
+    /// Reuse the outputs of up to `entries` recent searches until the next commit (256 by
+    /// default; 0 turns the cache off).
+    pub fn query_cache(mut self, entries: usize) -> Self {
+        self.config.query_cache = entries;
+        self
+    }
+
+    /// Keep a summary document per directory (file list, dominant language, README head)
+    /// for `SearchHandle::search_directories`. Off by default.
+    pub fn directory_summaries(mut self, enabled: bool) -> Self {
+        self.config.directory_summaries = enabled;
+        self
+    }
+
+    /// Index walked files shallow and recently modified first (the default), or in walk
+    /// order.
+    pub fn crawl_order(mut self, order: CrawlOrder) -> Self {
+        self.config.crawl_order = order;
+        self
+    }
+
+    /// Writer and commit tuning; replaces everything set so far except `path`.
+    pub fn config(mut self, config: IndexConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Run matching files through external OCR commands.
+    pub fn ocr(mut self, ocr: OcrConfig) -> Self {
+        self.ocr = Some(ocr);
+        self
+    }
+
+    /// Also index git repositories at these revisions, for `SearchHandle::search_history`.
+    pub fn history_revisions(mut self, revisions: Vec<String>) -> Self {
+        self.history_revisions = revisions;
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<FileIndex> {
+        let mut index = FileIndex::with_config(self.path, self.config)?;
+        if let Some(ocr) = self.ocr {
+            index.set_ocr(ocr);
         }
+        index.set_history_revisions(self.history_revisions);
+        Ok(index)
+    }
+}
 
-        let searcher = self.reader.searcher();
+/// Why a file was left out of the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The file, or the directory it is in, cannot be read.
+    Permission,
+    /// Larger than `max_file_size`, with `LargeFiles::Skip`.
+    TooLarge,
+    /// Not valid UTF-8 text.
+    Binary,
+    /// Of a type that is not indexed.
+    Unsupported,
+}
 
-        // Build query clauses
-        let mut clauses: Vec<(Occur, Box<dyn tantivy::query::Query>)> = Vec::new();
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SkipReason::Permission => "permission denied",
+            SkipReason::TooLarge => "too large",
+            SkipReason::Binary => "binary",
+            SkipReason::Unsupported => "unsupported type",
+        })
+    }
+}
 
-        // Text query parsed by QueryParser (supports field:value syntax for all fields)
-        if has_text_query {
-            let query_parser = QueryParser::for_index(
-                &self.index,
-                vec![self.field_content, self.field_name],
-            );
-            let parsed = query_parser.parse_query(query_str)?;
-            clauses.push((Occur::Must, parsed));
+/// Files a walk of a root left out of the index, per reason.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SkipStats {
+    pub permission: usize,
+    pub too_large: usize,
+    pub binary: usize,
+    pub unsupported: usize,
+}
+
+impl SkipStats {
+    pub fn add(&mut self, reason: SkipReason) {
+        match reason {
+            SkipReason::Permission => self.permission += 1,
+            SkipReason::TooLarge => self.too_large += 1,
+            SkipReason::Binary => self.binary += 1,
+            SkipReason::Unsupported => self.unsupported += 1,
         }
+    }
 
-        // file_type param -> TermQuery on extension field
-        if let Some(ext) = file_type {
-            let term = Term::from_field_text(self.field_extension, &ext.to_lowercase());
-            clauses.push((
-                Occur::Must,
-                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
-            ));
+    pub fn total(&self) -> usize {
+        self.permission + self.too_large + self.binary + self.unsupported
+    }
+
+    /// Counts of both.
+    pub fn merge(self, other: SkipStats) -> SkipStats {
+        SkipStats {
+            permission: self.permission + other.permission,
+            too_large: self.too_large + other.too_large,
+            binary: self.binary + other.binary,
+            unsupported: self.unsupported + other.unsupported,
         }
+    }
+}
 
-        // path_prefix param -> TermQuery per path component on directory field
-        if let Some(prefix) = path_prefix {
-            for segment in prefix.split('/').filter(|s| !s.is_empty()) {
-                let term = Term::from_field_text(self.field_directory, &segment.to_lowercase());
-                clauses.push((
-                    Occur::Must,
-                    Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
-                ));
+/// Non-zero counts, e.g. `2 permission denied, 14 unsupported type`.
+impl std::fmt::Display for SkipStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counts = [
+            (self.permission, SkipReason::Permission),
+            (self.too_large, SkipReason::TooLarge),
+            (self.binary, SkipReason::Binary),
+            (self.unsupported, SkipReason::Unsupported),
+        ];
+        let parts: Vec<String> = counts
+            .iter()
+            .filter(|(n, _)| *n > 0)
+            .map(|(n, reason)| format!("{} {}", n, reason))
+            .collect();
+        f.write_str(&parts.join(", "))
+    }
+}
+
+pub struct IndexStatus {
+    pub num_files: usize,
+    pub watched_paths: Vec<String>,
+    /// Roots in `watched_paths` indexed once without a file watcher.
+    pub unwatched_paths: Vec<String>,
+    /// Roots detached because they no longer exist.
+    pub missing_paths: Vec<String>,
+    /// On-disk location of the index; `None` when it lives in memory.
+    pub index_path: Option<String>,
+    /// Indexed file count under each watched root, in the same order as `watched_paths`.
+    pub root_counts: Vec<usize>,
+    /// Files the last walk of each watched root skipped, in the same order.
+    pub root_skips: Vec<SkipStats>,
+    pub last_indexed: Option<SystemTime>,
+    pub last_commit: Option<SystemTime>,
+    /// Commits so far; searches report the generation they saw.
+    pub generation: u64,
+    /// Progress re-indexing the previous roots after a schema change.
+    pub migration: Option<Migration>,
+    /// Segments of the last commit; many small ones slow searches until merged.
+    pub segments: usize,
+    /// Documents deleted or replaced but still taking space until their segment merges.
+    pub deleted_docs: u64,
+    /// Bytes used by the index directory; `None` for an in-memory index.
+    pub disk_bytes: Option<u64>,
+}
+
+/// Discrepancies between the index and the filesystem, found by `FileIndex::verify`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IntegrityReport {
+    /// Indexed paths whose file (or archive, for members) no longer exists.
+    pub missing: Vec<PathBuf>,
+    /// Indexed files whose content no longer hashes to the indexed version.
+    pub changed: Vec<PathBuf>,
+    /// Paths with documents in the index but no metadata, left by an earlier session or
+    /// an interrupted update. Searches find them, `read_file` and `list_files` do not.
+    pub orphaned: Vec<PathBuf>,
+    /// Indexed paths checked.
+    pub checked: usize,
+}
+
+impl IntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.changed.is_empty() && self.orphaned.is_empty()
+    }
+}
+
+/// What `file_info` reports about an indexed file, without its content.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileInfo {
+    /// The path the file is indexed under (the primary path, for aliases).
+    pub path: PathBuf,
+    /// Size in bytes on disk (of the archive, for members); `None` if it no longer exists.
+    pub size: Option<u64>,
+    pub modified: Option<SystemTime>,
+    pub extension: Option<String>,
+    /// Lines of the content `read_file` returns; `None` if it is not readable as text.
+    pub lines: Option<usize>,
+    pub indexed_at: Option<SystemTime>,
+    /// Hex blake3 hash of the indexed content, unknown for followed logs.
+    pub content_hash: Option<String>,
+    /// Whether the file on disk no longer matches the indexed content; `None` if the
+    /// two cannot be compared (unknown hash, file over the size limit).
+    pub changed: Option<bool>,
+}
+
+/// Re-indexing of the roots of an index rebuilt for a new schema version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub from_version: u32,
+    /// Roots from the manifest, re-indexed in this order.
+    pub roots: Vec<RootEntry>,
+    /// Roots re-indexed so far.
+    pub done: usize,
+    pub files_indexed: u64,
+}
+
+impl Migration {
+    pub fn is_complete(&self) -> bool {
+        self.done >= self.roots.len()
+    }
+}
+
+/// A value shared between the writer and published snapshots: reads go to the shared
+/// value, and the first write after a snapshot took it copies it (`Arc::make_mut`).
+#[derive(Default)]
+struct Shared<T>(Arc<T>);
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> std::ops::Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Clone> std::ops::DerefMut for Shared<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Shared<T>
+where
+    &'a T: IntoIterator,
+{
+    type Item = <&'a T as IntoIterator>::Item;
+    type IntoIter = <&'a T as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.as_ref().into_iter()
+    }
+}
+
+impl<T> From<T> for Shared<T> {
+    fn from(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+}
+
+/// Per-file metadata kept alongside the tantivy index.
+///
+/// The writer side mutates its own copy; a clone is published to `SearchHandle`s on
+/// every commit so readers never wait on the writer. Each map is `Shared`, so the clone
+/// only takes references and a map is copied when the writer next changes it.
+#[derive(Clone, Default)]
+struct FileMeta {
+    indexed_paths: Shared<HashSet<PathBuf>>,
+    /// Front matter tags of each indexed markdown file.
+    file_tags: Shared<HashMap<PathBuf, Vec<String>>>,
+    /// Outgoing wiki and relative links of each indexed markdown file.
+    file_links: Shared<HashMap<PathBuf, Vec<markdown::Link>>>,
+    /// Paths of files already indexed under another path (symlinks, hard links), mapped
+    /// to the indexed path.
+    aliases: Shared<HashMap<PathBuf, PathBuf>>,
+    /// Owner and permissions of each indexed file (of the archive, for members).
+    owners: Shared<HashMap<PathBuf, Owner>>,
+    /// Pseudo-extensions of indexed extensionless scripts (`py` for a python script).
+    script_extensions: Shared<HashMap<PathBuf, String>>,
+    /// Hash of the content last indexed for each file, so rewrites with identical
+    /// content (touch, metadata-only changes) are skipped.
+    content_hashes: Shared<HashMap<PathBuf, blake3::Hash>>,
+    /// When each indexed path was last (re-)indexed.
+    indexed_at: Shared<HashMap<PathBuf, SystemTime>>,
+    /// Modification time of each indexed file when it was indexed.
+    modified: Shared<HashMap<PathBuf, SystemTime>>,
+    /// Root of the project of each indexed path that is in one.
+    file_projects: Shared<HashMap<PathBuf, PathBuf>>,
+    /// Projects by root.
+    projects: Shared<HashMap<PathBuf, Project>>,
+    /// Files pinned to rank above other matches, indexed or not.
+    pinned: Shared<BTreeSet<PathBuf>>,
+}
+
+impl FileMeta {
+    /// Content of the indexed file `path`; archive members larger than `max_file_size`
+    /// once decompressed are refused.
+    fn read_file(&self, path: &str, max_file_size: u64) -> anyhow::Result<String> {
+        if let Some((archive_path, member)) = archive::split_virtual(path) {
+            let archive_path = archive_path.canonicalize()?;
+            let virtual_path = archive::member_path(&archive_path, &member);
+            if !self.indexed_paths.contains(&virtual_path) {
+                anyhow::bail!("File is not in the index: {}", virtual_path.display());
             }
+            if email::is_mbox(&archive_path) {
+                return email::read_message(&archive_path, member.parse()?);
+            }
+            if spreadsheet::is_spreadsheet(&archive_path) {
+                return spreadsheet::read_sheet(&archive_path, &member);
+            }
+            return archive::read_member(&archive_path, &member, max_file_size);
+        }
+        let requested = Path::new(path);
+        let path = requested.canonicalize()?;
+        let known = |p: &Path| self.indexed_paths.contains(p) || self.aliases.contains_key(p);
+        if !known(requested) && !known(&path) {
+            anyhow::bail!("File is not in the index: {}", path.display());
         }
+        if sqlite::is_database(&path) {
+            return sqlite::extract(&path, 0);
+        }
+        let content = std::fs::read_to_string(&path)?;
+        Ok(content)
+    }
 
-        let query = BooleanQuery::new(clauses);
-        let top_docs = searcher.search(&query, &TopDocs::with_limit(limit))?;
-
-        // Build query terms for snippet extraction (only from text query, not field filters)
-        let query_terms: Vec<String> = if has_text_query {
-            query_str
-                .split_whitespace()
-                .filter(|s| !s.contains(':'))
-                .map(|s| s.to_lowercase())
-                .collect()
+    /// Size, times, hash and staleness of the indexed file `path`, reading files up to
+    /// `max_file_size` bytes to count lines and compare hashes.
+    fn info(&self, path: &str, max_file_size: u64) -> anyhow::Result<FileInfo> {
+        let known = |p: &Path| {
+            if self.indexed_paths.contains(p) {
+                Some(p.to_path_buf())
+            } else {
+                self.aliases.get(p).cloned()
+            }
+        };
+        let requested = Path::new(path);
+        let indexed = match archive::split_virtual(path) {
+            Some((archive_path, member)) => archive_path
+                .canonicalize()
+                .ok()
+                .and_then(|a| known(&archive::member_path(&a, &member))),
+            None => requested.canonicalize().ok().and_then(|p| known(&p)),
+        };
+        let Some(indexed) = indexed.or_else(|| known(requested)) else {
+            anyhow::bail!("File is not in the index: {}", requested.display());
+        };
+
+        let disk_path = FileIndex::on_disk(&indexed);
+        let metadata = std::fs::metadata(&disk_path).ok();
+        let hash = self.content_hashes.get(&disk_path);
+        let readable = metadata.as_ref().is_some_and(|m| m.len() <= max_file_size);
+        let bytes = if readable { std::fs::read(&disk_path).ok() } else { None };
+        let changed = match (&metadata, hash, &bytes) {
+            (None, _, _) => Some(true),
+            (Some(_), Some(hash), Some(bytes)) => Some(blake3::hash(bytes) != *hash),
+            _ => None,
+        };
+        let lines = if disk_path != indexed || sqlite::is_database(&indexed) {
+            self.read_file(&indexed.to_string_lossy(), max_file_size)
+                .ok()
+                .map(|c| c.lines().count())
         } else {
-            vec![]
+            bytes
+                .as_deref()
+                .and_then(|b| std::str::from_utf8(b).ok())
+                .map(|c| c.lines().count())
         };
+        let extension = indexed
+            .extension()
+            .map(|e| e.to_string_lossy().into_owned())
+            .or_else(|| self.script_extensions.get(&indexed).cloned());
+        Ok(FileInfo {
+            size: metadata.as_ref().map(|m| m.len()),
+            modified: metadata.as_ref().and_then(|m| m.modified().ok()),
+            extension,
+            lines,
+            indexed_at: self.indexed_at.get(&indexed).copied(),
+            content_hash: hash.map(|h| h.to_hex().to_string()),
+            changed,
+            path: indexed,
+        })
+    }
 
-        let mut results = Vec::new();
-        for (score, doc_address) in top_docs {
-            let doc: TantivyDocument = searcher.doc(doc_address)?;
-            let file_path = doc
-                .get_first(self.field_path)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-            let file_name = doc
-                .get_first(self.field_name)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-            let content = doc
-                .get_first(self.field_content)
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
+    fn owner(&self, path: &str) -> Option<Owner> {
+        self.owners.get(Path::new(path)).cloned()
+    }
+
+    fn list_files(&self, extension: Option<&str>, path_prefix: Option<&str>) -> Vec<String> {
+        let mut files: Vec<String> = self
+            .indexed_paths
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .filter(|p| {
+                if let Some(ext) = extension {
+                    let path = Path::new(p);
+                    let matches = path
+                        .extension()
+                        .and_then(|e| e.to_str())
+                        .or_else(|| self.script_extensions.get(path).map(String::as_str))
+                        .map(|e| e.eq_ignore_ascii_case(ext))
+                        .unwrap_or(false);
+                    if !matches {
+                        return false;
+                    }
+                }
+                if let Some(prefix) = path_prefix {
+                    if !p.contains(prefix) {
+                        return false;
+                    }
+                }
+                true
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Projects of the indexed files `include` accepts, with their number of files, by
+    /// name.
+    fn list_projects(&self, include: impl Fn(&Path) -> bool) -> Vec<ProjectSummary> {
+        let mut files: HashMap<&Path, usize> = HashMap::new();
+        for (path, root) in &self.file_projects {
+            if include(path) {
+                *files.entry(root.as_path()).or_default() += 1;
+            }
+        }
+        let mut projects: Vec<ProjectSummary> = files
+            .into_iter()
+            .filter_map(|(root, files)| {
+                let project = self.projects.get(root)?.clone();
+                Some(ProjectSummary { project, files })
+            })
+            .collect();
+        projects.sort_by(|a, b| {
+            (&a.project.name, &a.project.root).cmp(&(&b.project.name, &b.project.root))
+        });
+        projects
+    }
+
+    /// Front matter tags of the indexed files `include` accepts, with the number of files
+    /// using each.
+    fn list_tags(&self, include: impl Fn(&Path) -> bool) -> BTreeMap<String, usize> {
+        let mut tags = BTreeMap::new();
+        for (path, file_tags) in &self.file_tags {
+            if !include(path) {
+                continue;
+            }
+            for tag in file_tags {
+                *tags.entry(tag.clone()).or_insert(0) += 1;
+            }
+        }
+        tags
+    }
+
+    /// Indexed notes linking to `note`, given as a note name (`Project Plan`) or a path.
+    ///
+    /// Wiki-links match on file name without extension, case-insensitively. Relative
+    /// links are resolved against the linking note's directory.
+    fn backlinks(&self, note: &str) -> Vec<String> {
+        let note_path = Path::new(note)
+            .canonicalize()
+            .ok()
+            .filter(|p| self.indexed_paths.contains(p));
+        let note_name = match &note_path {
+            Some(p) => p.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+            None => note.strip_suffix(".md").unwrap_or(note).to_string(),
+        };
+        let names_note = |target: &Path| {
+            target
+                .file_stem()
+                .is_some_and(|s| s.to_string_lossy().eq_ignore_ascii_case(&note_name))
+        };
+
+        let mut sources: Vec<String> = self
+            .file_links
+            .iter()
+            .filter(|(source, links)| {
+                links.iter().any(|link| match link {
+                    markdown::Link::Wiki(name) => names_note(Path::new(name)),
+                    markdown::Link::Relative(href) => {
+                        let dir = source.parent().unwrap_or(Path::new(""));
+                        let target = normalize(&dir.join(href));
+                        match &note_path {
+                            Some(p) => &target == p,
+                            None => names_note(&target),
+                        }
+                    }
+                })
+            })
+            .map(|(source, _)| source.to_string_lossy().to_string())
+            .collect();
+        sources.sort();
+        sources
+    }
+}
+
+/// Everything a search sees, published together on every commit so a search never mixes
+/// documents and metadata from different commits.
+struct Snapshot {
+    /// Number of commits before this snapshot was published; 0 for the opened index.
+    generation: u64,
+    searcher: Searcher,
+    files: FileMeta,
+}
+
+/// A search parsed for a handle, ready to collect hits and build results from.
+struct PreparedSearch {
+    query: BooleanQuery,
+    /// Words and phrases to place and highlight snippets by (not field filters).
+    query_terms: Vec<String>,
+    matching: Matching,
+    /// Key paths of `key:` terms, whose line results point at.
+    key_terms: Vec<String>,
+}
+
+/// Hits collected by the first page of `SearchIter`; later pages double in size.
+const FIRST_PAGE: usize = 10;
+const MAX_PAGE: usize = 1_000;
+
+/// Results of `SearchHandle::search_iter`, best first. Each result is built when the
+/// iterator reaches it, and hits are collected a page at a time.
+pub struct SearchIter {
+    handle: SearchHandle,
+    snapshot: Arc<Snapshot>,
+    /// `None` for a search with neither a query nor a filter, which finds nothing.
+    search: Option<PreparedSearch>,
+    options: SearchFilters,
+    /// Hits of the last page not yet built.
+    hits: VecDeque<(Score, DocAddress)>,
+    /// Hits collected so far, and the size of the next page.
+    collected: usize,
+    page: usize,
+    /// Whether no hits are left to collect.
+    exhausted: bool,
+    /// Score of the top hit, which relevance is relative to.
+    best: Option<Score>,
+    grouped: HashSet<String>,
+}
+
+impl SearchIter {
+    /// Commit generation of the index snapshot the search runs against.
+    pub fn generation(&self) -> u64 {
+        self.snapshot.generation
+    }
+
+    /// The next hit, collecting the next page when the last one is used up.
+    fn next_hit(
+        &mut self,
+        search: &PreparedSearch,
+    ) -> anyhow::Result<Option<(Score, DocAddress)>> {
+        if self.hits.is_empty() && !self.exhausted {
+            let (size, offset) = (self.page, self.collected);
+            let page = self.handle.top_hits(&self.snapshot, &search.query, size, offset)?;
+            self.exhausted = page.len() < size;
+            self.collected += page.len();
+            self.page = (self.page * 2).min(MAX_PAGE);
+            self.hits.extend(page);
+        }
+        Ok(self.hits.pop_front())
+    }
+
+    /// The next result, skipping hits `build_result` leaves out.
+    fn next_result(&mut self) -> anyhow::Result<Option<SearchResult>> {
+        let Some(search) = self.search.take() else {
+            return Ok(None);
+        };
+        let result = self.next_result_of(&search);
+        self.search = Some(search);
+        result
+    }
+
+    fn next_result_of(
+        &mut self,
+        search: &PreparedSearch,
+    ) -> anyhow::Result<Option<SearchResult>> {
+        while let Some((score, doc_address)) = self.next_hit(search)? {
+            let best = *self.best.get_or_insert(score);
+            let relevance = if best > 0.0 { score / best } else { 1.0 };
+            // Hits come best first, so the rest are below the threshold too
+            if self.options.min_score.is_some_and(|min| relevance < min) {
+                self.hits.clear();
+                self.exhausted = true;
+                break;
+            }
+            let searcher = &self.snapshot.searcher;
+            let hit = (score, relevance, doc_address);
+            let built = self.handle.build_result(
+                searcher,
+                search,
+                &self.options,
+                hit,
+                &mut self.grouped,
+                false,
+            )?;
+            let Some(mut result) = built else {
+                continue;
+            };
+            if self.options.group_by_file {
+                let count = self.handle.count_matches(searcher, search, &result.file_path)?;
+                result.match_count = Some(count);
+            }
+            return Ok(Some(result));
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for SearchIter {
+    type Item = anyhow::Result<SearchResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_result();
+        if next.is_err() {
+            // A failed search does not recover
+            self.search = None;
+        }
+        next.transpose()
+    }
+}
+
+/// Filters for `SearchHandle::search_filtered`; the default filters nothing.
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilters {
+    /// Only files with this extension.
+    pub file_type: Option<String>,
+    /// Only files below directories with these path components.
+    pub path_prefix: Option<String>,
+    /// Only files tracked by git.
+    pub tracked_only: bool,
+    /// Only log chunks overlapping this time range.
+    pub time: Option<TimeRange>,
+    /// Only files owned by this uid.
+    pub owner: Option<u32>,
+    /// Only files of this MIME type, or of any `type/*`.
+    pub mime: Option<String>,
+    /// Return up to this many matching lines per file as `SearchResult::snippets`; 0 and
+    /// 1 give only `snippet`.
+    pub snippets_per_file: usize,
+    /// Make snippets this many whole lines before and after the matching line instead of
+    /// a window of characters.
+    pub context_lines: Option<usize>,
+    /// Leave out results whose `relevance` is below this (0 to 1).
+    pub min_score: Option<f32>,
+    /// One result per file, its best hit, with `SearchResult::match_count` set.
+    pub group_by_file: bool,
+    /// Only these files (indexed paths), e.g. the files of earlier results.
+    pub within: Option<Vec<String>>,
+    /// Only files matching this filter expression.
+    pub filter: Option<Filter>,
+    /// Only files of projects with this name.
+    pub project: Option<String>,
+    /// Leave out lockfiles, vendored, minified and other generated files.
+    pub exclude_generated: bool,
+    /// Only pinned files.
+    pub pinned_only: bool,
+    /// Only results whose words and phrases match in the case typed.
+    pub case_sensitive: bool,
+    /// Whether words match whole indexed words, their starts or any part of them.
+    pub match_mode: MatchMode,
+    /// Return within about this long, with fewer results or snippets if need be.
+    pub latency_budget: Option<Duration>,
+}
+
+/// Read side of the index: searches the snapshot published by the last commit. Cheap to
+/// clone, and usable without any lock on the `FileIndex`.
+#[derive(Clone)]
+pub struct SearchHandle {
+    index: Index,
+    reader: IndexReader,
+    published: Arc<ArcSwap<Snapshot>>,
+    /// Latest published generation, for waiting on commits.
+    generations: watch::Receiver<u64>,
+    field_path: Field,
+    field_name: Field,
+    field_content: Field,
+    field_extension: Field,
+    field_directory: Field,
+    field_title: Field,
+    field_alias: Field,
+    field_subject: Field,
+    field_tracked: Field,
+    field_owner: Field,
+    field_mime: Field,
+    field_language: Field,
+    field_project: Field,
+    field_generated: Field,
+    field_revision: Field,
+    field_history: Field,
+    field_summary: Field,
+    field_commit_time: Field,
+    field_modified_at: Field,
+    field_truncated: Field,
+    field_first_line: Field,
+    field_log_start: Field,
+    field_log_end: Field,
+    field_first_row: Field,
+    field_cells: Field,
+    highlight: Highlight,
+    /// Files larger than this were skipped or indexed in part, so their hash is not
+    /// comparable with the file on disk.
+    max_file_size: u64,
+    /// Whether the index keeps directory summaries.
+    directory_summaries: bool,
+    /// Per-path score factors of `IndexConfig::ranking`.
+    ranking: Vec<RankingRule>,
+    /// Synonyms of `IndexConfig::synonyms`.
+    synonyms: Arc<Synonyms>,
+    /// Roots the handle sees files below; `None` for the whole index.
+    roots: Option<Arc<Vec<PathBuf>>>,
+    /// Outputs of recent searches, shared by all clones; `None` when turned off.
+    cache: Option<Arc<QueryCache>>,
+    /// Reads of search results, shared by all clones; `None` without usage ranking.
+    usage: Option<Arc<Usage>>,
+    /// Time per result of recent searches, for fitting them into latency budgets.
+    pace: Arc<Pace>,
+}
+
+impl std::fmt::Debug for SearchHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SearchHandle").finish_non_exhaustive()
+    }
+}
+
+impl SearchHandle {
+    /// Run `f` with a clone of this handle on tokio's blocking thread pool, so searches
+    /// and file reads from async code do not stall the executor.
+    pub async fn run<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&SearchHandle) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let handle = self.clone();
+        tokio::task::spawn_blocking(move || f(&handle))
+            .await
+            .map_err(|e| anyhow::anyhow!("Search task failed: {}", e))?
+    }
+
+    /// A handle that only sees files at or below `roots` (all of them with `None`), for
+    /// isolating client sessions that share the index.
+    pub fn scoped(&self, roots: Option<Vec<PathBuf>>) -> SearchHandle {
+        SearchHandle {
+            roots: roots.map(Arc::new),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the indexed path `path` is within this handle's roots.
+    fn in_scope(&self, path: &Path) -> bool {
+        match &self.roots {
+            None => true,
+            Some(roots) => roots.iter().any(|root| path.starts_with(root)),
+        }
+    }
+
+    /// Query matching the documents at or below this handle's roots, if it has any.
+    fn scope_clause(&self) -> Option<Box<dyn tantivy::query::Query>> {
+        use std::ops::Bound;
+        let roots = self.roots.as_ref()?;
+        let mut any_root: Vec<(Occur, Box<dyn tantivy::query::Query>)> = Vec::new();
+        for root in roots.iter() {
+            let root = root.to_string_lossy();
+            let root = root.trim_end_matches(std::path::MAIN_SEPARATOR);
+            let term = Term::from_field_text(self.field_path, root);
+            let exact = TermQuery::new(term, IndexRecordOption::Basic);
+            any_root.push((Occur::Should, Box::new(exact)));
+            // Paths below `root`: from `root/` up to (not including) the next separator byte
+            let (below, next) = (
+                format!("{}{}", root, std::path::MAIN_SEPARATOR),
+                format!("{}{}", root, (std::path::MAIN_SEPARATOR as u8 + 1) as char),
+            );
+            let range = RangeQuery::new_str_bounds(
+                "file_path".to_string(),
+                Bound::Included(&below),
+                Bound::Excluded(&next),
+            );
+            any_root.push((Occur::Should, Box::new(range)));
+        }
+        // With no roots this matches nothing
+        Some(Box::new(BooleanQuery::new(any_root)))
+    }
+
+    /// Generation of the snapshot searches currently run against.
+    pub fn generation(&self) -> u64 {
+        self.published.load().generation
+    }
+
+    /// Wait until a commit at least as new as `generation` is searchable. Returns false
+    /// if `timeout` passes first.
+    pub async fn wait_for_generation(&self, generation: u64, timeout: Duration) -> bool {
+        let mut generations = self.generations.clone();
+        let reached = tokio::time::timeout(timeout, generations.wait_for(|g| *g >= generation)).await;
+        matches!(reached, Ok(Ok(_)))
+    }
+
+    pub fn search(
+        &self,
+        query_str: &str,
+        limit: usize,
+        file_type: Option<&str>,
+        path_prefix: Option<&str>,
+    ) -> anyhow::Result<SearchOutput> {
+        let filters = SearchFilters {
+            file_type: file_type.map(str::to_string),
+            path_prefix: path_prefix.map(str::to_string),
+            ..SearchFilters::default()
+        };
+        self.search_filtered(query_str, limit, &filters)
+    }
+
+    /// Like `search`, with every filter `SearchFilters` offers.
+    pub fn search_filtered(
+        &self,
+        query_str: &str,
+        limit: usize,
+        filters: &SearchFilters,
+    ) -> anyhow::Result<SearchOutput> {
+        self.search_snapshot(&self.published.load_full(), query_str, limit, filters)
+    }
+
+    /// Paths of every file matching `query_str` and `filters`, without ranking or
+    /// snippets, and the index generation they were found at.
+    pub fn matching_paths(
+        &self,
+        query_str: &str,
+        filters: &SearchFilters,
+    ) -> anyhow::Result<(u64, HashSet<String>)> {
+        let snapshot = self.published.load_full();
+        let clauses = self.filter_clauses(&snapshot, filters);
+        let mut paths = HashSet::new();
+        if let Some(search) = self.prepare(query_str, filters, clauses)? {
+            let searcher = &snapshot.searcher;
+            for address in searcher.search(&search.query, &DocSetCollector)? {
+                let doc: TantivyDocument = searcher.doc(address)?;
+                if let Some(path) = doc.get_first(self.field_path).and_then(|v| v.as_str()) {
+                    paths.insert(path.to_string());
+                }
+            }
+        }
+        Ok((snapshot.generation, paths))
+    }
+
+    /// Run each of `queries` with the same `limit` and `filters`, concurrently and against
+    /// the same snapshot, so their outputs are consistent with each other. Outputs are in
+    /// the order of `queries`.
+    pub fn multi_search(
+        &self,
+        queries: &[String],
+        limit: usize,
+        filters: &SearchFilters,
+    ) -> Vec<anyhow::Result<SearchOutput>> {
+        let snapshot = self.published.load_full();
+        std::thread::scope(|scope| {
+            let searches: Vec<_> = queries
+                .iter()
+                .map(|query| scope.spawn(|| self.search_snapshot(&snapshot, query, limit, filters)))
+                .collect();
+            searches
+                .into_iter()
+                .map(|search| {
+                    search.join().unwrap_or_else(|_| Err(anyhow::anyhow!("Search thread panicked")))
+                })
+                .collect()
+        })
+    }
+
+    /// Like `search_filtered` with no limit, yielding results lazily, best first. A result
+    /// and its snippets are only built when the iterator reaches it, and hits are collected
+    /// in growing pages, so stopping after the first good hit saves most of the work. The
+    /// search runs against the snapshot current when it is called; `latency_budget` does
+    /// not apply.
+    pub fn search_iter(
+        &self,
+        query_str: &str,
+        filters: &SearchFilters,
+    ) -> anyhow::Result<SearchIter> {
+        let snapshot = self.published.load_full();
+        let clauses = self.filter_clauses(&snapshot, filters);
+        let search = self.prepare(query_str, filters, clauses)?;
+        metrics::counter!(telemetry::SEARCHES_TOTAL).increment(1);
+        Ok(SearchIter {
+            handle: self.clone(),
+            snapshot,
+            search,
+            options: filters.clone(),
+            hits: VecDeque::new(),
+            collected: 0,
+            page: FIRST_PAGE,
+            exhausted: false,
+            best: None,
+            grouped: HashSet::new(),
+        })
+    }
+
+    /// `search_iter` on the blocking thread pool, for async callers: results arrive on
+    /// the receiver as they are built, at most one ahead of the consumer, and dropping
+    /// the receiver stops the search. `tokio_stream::wrappers::ReceiverStream` turns it
+    /// into a `Stream`. Must be called within a tokio runtime.
+    pub fn search_stream(
+        &self,
+        query_str: &str,
+        filters: &SearchFilters,
+    ) -> mpsc::Receiver<anyhow::Result<SearchResult>> {
+        let (tx, rx) = mpsc::channel(1);
+        let (handle, query_str, filters) = (self.clone(), query_str.to_string(), filters.clone());
+        tokio::task::spawn_blocking(move || {
+            let results = match handle.search_iter(&query_str, &filters) {
+                Ok(results) => results,
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(e));
+                    return;
+                }
+            };
+            for result in results {
+                if tx.blocking_send(result).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+
+    /// `search_filtered` against `snapshot`, through the query cache.
+    fn search_snapshot(
+        &self,
+        snapshot: &Snapshot,
+        query_str: &str,
+        limit: usize,
+        filters: &SearchFilters,
+    ) -> anyhow::Result<SearchOutput> {
+        let Some(cache) = &self.cache else {
+            let clauses = self.filter_clauses(snapshot, filters);
+            return self.run_query(snapshot, query_str, limit, filters, clauses);
+        };
+        let key = format!("{:?}", (query_str, limit, filters, &self.roots));
+        if let Some(output) = cache.get(&key, snapshot.generation) {
+            metrics::counter!(telemetry::SEARCH_CACHE_HITS_TOTAL).increment(1);
+            return Ok(output);
+        }
+        let clauses = self.filter_clauses(snapshot, filters);
+        let output = self.run_query(snapshot, query_str, limit, filters, clauses)?;
+        // A repeat may have the time for the full output
+        if !output.degraded {
+            cache.insert(key, &output);
+        }
+        Ok(output)
+    }
+
+    /// Read every file of the index once, so that the first searches after opening it do
+    /// not wait on the disk. Returns the number of bytes read.
+    pub fn warm_up(&self) -> anyhow::Result<u64> {
+        use tantivy::directory::Directory;
+        let directory = self.index.directory();
+        let mut bytes = 0u64;
+        for segment in self.index.searchable_segment_metas()? {
+            for file in segment.list_files() {
+                let Ok(slice) = directory.open_read(&file) else {
+                    continue;
+                };
+                let data = slice.read_bytes()?;
+                // Memory-mapped files are only read when touched: one byte per page
+                let touched = data.as_slice().iter().step_by(4096).fold(0u8, |a, b| a ^ b);
+                std::hint::black_box(touched);
+                bytes += data.len() as u64;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// The `limit` most recently modified indexed files matching `filters`, newest first,
+    /// optionally only those modified at or after `since` (Unix seconds).
+    pub fn recent_files(
+        &self,
+        limit: usize,
+        since: Option<i64>,
+        filters: &SearchFilters,
+    ) -> anyhow::Result<Vec<RecentFile>> {
+        let snapshot = self.published.load_full();
+        let mut clauses = self.filter_clauses(&snapshot, filters);
+        clauses.push((Occur::Must, Box::new(AllQuery)));
+        if let Some(since) = since {
+            use std::ops::Bound;
+            let since = Bound::Included(u64::try_from(since).unwrap_or(0));
+            let range =
+                RangeQuery::new_u64_bounds("modified_at".to_string(), since, Bound::Unbounded);
+            clauses.push((Occur::Must, Box::new(range)));
+        }
+        let query = BooleanQuery::new(clauses);
+        let searcher = &snapshot.searcher;
+
+        // Files split into several documents take several hits, so fetch more until
+        // there are `limit` distinct files or no more documents
+        let mut fetch = limit.saturating_mul(GROUP_OVERFETCH).max(1);
+        loop {
+            let collector =
+                TopDocs::with_limit(fetch).order_by_fast_field::<u64>("modified_at", Order::Desc);
+            let hits = searcher.search(&query, &collector)?;
+            let mut seen = HashSet::new();
+            let mut files = Vec::new();
+            for (modified, address) in &hits {
+                let doc: TantivyDocument = searcher.doc(*address)?;
+                let Some(file_path) = doc.get_first(self.field_path).and_then(|v| v.as_str()) else {
+                    continue;
+                };
+                if seen.insert(file_path.to_string()) {
+                    files.push(RecentFile {
+                        file_path: file_path.to_string(),
+                        modified: SystemTime::UNIX_EPOCH + Duration::from_secs(*modified),
+                    });
+                }
+            }
+            if files.len() >= limit || hits.len() < fetch {
+                files.truncate(limit);
+                return Ok(files);
+            }
+            fetch = fetch.saturating_mul(2);
+        }
+    }
+
+    /// Extensions of the indexed files starting with `partial`, most used first, for
+    /// completing `file_type` arguments.
+    pub fn complete_file_type(&self, partial: &str) -> anyhow::Result<Vec<String>> {
+        let partial = partial.trim_start_matches('.').to_lowercase();
+        Ok(self
+            .facet_children("file_type", &Facet::root())?
+            .iter()
+            .filter_map(|facet| facet.to_path().last().map(|ext| ext.to_string()))
+            .filter(|ext| ext.starts_with(&partial))
+            .collect())
+    }
+
+    /// Indexed directories completing the path `partial`, most used first, for completing
+    /// `path_prefix` arguments. An empty `partial` gives the top-level indexed
+    /// directories, skipping the ancestors every indexed file shares.
+    pub fn complete_path_prefix(&self, partial: &str) -> anyhow::Result<Vec<String>> {
+        let (parent, name) = partial.rsplit_once('/').unwrap_or(("", partial));
+        let mut children = self.facet_children("location", &directory_facet(Path::new(parent)))?;
+        if partial.is_empty() {
+            while let [only] = children.as_slice() {
+                let below = self.facet_children("location", only)?;
+                if below.is_empty() {
+                    break;
+                }
+                children = below;
+            }
+        }
+        let name = name.to_lowercase();
+        Ok(children
+            .iter()
+            .filter(|facet| {
+                let last = facet.to_path().last().map(|n| n.to_lowercase());
+                last.is_some_and(|n| n.starts_with(&name))
+            })
+            .map(|facet| facet.to_path_string())
+            .collect())
+    }
+
+    /// Children of `parent` in the facet field `field`, over the documents this handle
+    /// sees, most documents first.
+    fn facet_children(&self, field: &str, parent: &Facet) -> anyhow::Result<Vec<Facet>> {
+        let mut clauses: Vec<(Occur, Box<dyn tantivy::query::Query>)> =
+            vec![(Occur::Must, Box::new(AllQuery))];
+        clauses.extend(self.scope_clause().map(|scope| (Occur::Must, scope)));
+        let mut collector = FacetCollector::for_field(field);
+        collector.add_facet(parent.clone());
+        let snapshot = self.published.load_full();
+        let counts = snapshot.searcher.search(&BooleanQuery::new(clauses), &collector)?;
+        let mut children: Vec<(Facet, u64)> =
+            counts.get(parent.clone()).map(|(facet, n)| (facet.clone(), n)).collect();
+        children.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(children.into_iter().map(|(facet, _)| facet).collect())
+    }
+
+    /// `query` with the scores of its matches multiplied by the factor of the first
+    /// ranking rule matching their path. Each rule gets the part of `query` over the paths
+    /// it selects and no earlier rule does, wrapped in a `BoostQuery`, so every document
+    /// is scored by exactly one part.
+    fn ranked(
+        &self,
+        query: Box<dyn tantivy::query::Query>,
+    ) -> anyhow::Result<Box<dyn tantivy::query::Query>> {
+        if self.ranking.is_empty() {
+            return Ok(query);
+        }
+        let mut parts: Vec<(Occur, Box<dyn tantivy::query::Query>)> = Vec::new();
+        let mut earlier: Vec<(Occur, Box<dyn tantivy::query::Query>)> = Vec::new();
+        for rule in &self.ranking {
+            let paths = RegexQuery::from_pattern(rule.regex(), self.field_path)?;
+            let mut part = vec![
+                (Occur::Must, query.box_clone()),
+                // Selects without adding to the score
+                (Occur::Must, Box::new(ConstScoreQuery::new(Box::new(paths.clone()), 0.0)) as _),
+            ];
+            part.extend(earlier.iter().map(|(occur, q)| (*occur, q.box_clone())));
+            let part = BoostQuery::new(Box::new(BooleanQuery::new(part)), rule.factor);
+            parts.push((Occur::Should, Box::new(part)));
+            earlier.push((Occur::MustNot, Box::new(paths)));
+        }
+        earlier.push((Occur::Must, query));
+        parts.push((Occur::Should, Box::new(BooleanQuery::new(earlier))));
+        Ok(Box::new(BooleanQuery::new(parts)))
+    }
+
+    /// Fields searched by words without a field.
+    fn text_fields(&self) -> [Field; 5] {
+        [
+            self.field_content,
+            self.field_name,
+            self.field_title,
+            self.field_alias,
+            self.field_subject,
+        ]
+    }
+
+    /// `ast` as a query whose wildcard words match as patterns, whose words and phrases
+    /// match starts (`Prefix`) or parts (`Substring`) of indexed words, and whose words with
+    /// synonyms match those too; other leaves, and words of other fields, are left to
+    /// `parser`.
+    fn text_query(
+        &self,
+        parser: &QueryParser,
+        ast: UserInputAst,
+        mode: MatchMode,
+    ) -> anyhow::Result<Box<dyn tantivy::query::Query>> {
+        match ast {
+            UserInputAst::Clause(clauses) => {
+                let clauses = clauses
+                    .into_iter()
+                    .map(|(occur, ast)| {
+                        // Like QueryParser, words without an operator are optional
+                        let occur = occur.unwrap_or(Occur::Should);
+                        Ok((occur, self.text_query(parser, ast, mode)?))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Ok(Box::new(BooleanQuery::new(clauses)))
+            }
+            UserInputAst::Boost(ast, boost) => {
+                let query = self.text_query(parser, *ast, mode)?;
+                Ok(Box::new(BoostQuery::new(query, boost as Score)))
+            }
+            UserInputAst::Leaf(leaf) => {
+                let leaf = *leaf;
+                let synonyms: Vec<UserInputLeaf> = match &leaf {
+                    // Words and phrases of the default fields match their synonyms as well
+                    UserInputLeaf::Literal(literal) if literal.field_name.is_none() => self
+                        .synonyms
+                        .expand(&literal.phrase)
+                        .into_iter()
+                        .map(|synonym| {
+                            UserInputLeaf::Literal(UserInputLiteral {
+                                field_name: None,
+                                phrase: synonym.to_string(),
+                                delimiter: Delimiter::DoubleQuotes,
+                                slop: 0,
+                                prefix: false,
+                            })
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                let query = self.leaf_query(parser, leaf, mode)?;
+                if synonyms.is_empty() {
+                    return Ok(query);
+                }
+                let mut any_synonym = vec![(Occur::Should, query)];
+                for synonym in synonyms {
+                    any_synonym.push((Occur::Should, self.leaf_query(parser, synonym, mode)?));
+                }
+                Ok(Box::new(BooleanQuery::new(any_synonym)))
+            }
+        }
+    }
+
+    /// Query for one leaf of a `text_query`.
+    fn leaf_query(
+        &self,
+        parser: &QueryParser,
+        leaf: UserInputLeaf,
+        mode: MatchMode,
+    ) -> anyhow::Result<Box<dyn tantivy::query::Query>> {
+        if let UserInputLeaf::Literal(literal) = &leaf {
+            let pattern = match literal.prefix {
+                true => format!("{}*", literal.phrase),
+                false => literal.phrase.clone(),
+            };
+            let wildcard = literal.delimiter == Delimiter::None
+                && pattern.contains(['*', '?'])
+                && pattern != "*";
+            if wildcard {
+                if let Some(query) = self.wildcard_query(literal.field_name.as_deref(), &pattern)? {
+                    return Ok(query);
+                }
+            }
+        }
+        let schema = self.index.schema();
+        let fields: Vec<Field> = match &leaf {
+            UserInputLeaf::Literal(_) if mode == MatchMode::Word => Vec::new(),
+            UserInputLeaf::Literal(literal) => self
+                .text_fields()
+                .into_iter()
+                .filter(|field| {
+                    literal
+                        .field_name
+                        .as_deref()
+                        .is_none_or(|name| schema.get_field_name(*field) == name)
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        match leaf {
+            UserInputLeaf::Literal(literal) if !fields.is_empty() => {
+                self.partial_words(&literal.phrase, &fields, mode)
+            }
+            leaf => Ok(parser.build_query_from_user_input_ast(UserInputAst::from(leaf))?),
+        }
+    }
+
+    /// Query for the wildcard `pattern` (`query::wildcard_regex`) in the field called
+    /// `field_name`, or the fields searched by default; none if no field can hold a match.
+    /// A pattern spanning several words of text fields (`test_?.py`) selects file names.
+    fn wildcard_query(
+        &self,
+        field_name: Option<&str>,
+        pattern: &str,
+    ) -> anyhow::Result<Option<Box<dyn tantivy::query::Query>>> {
+        let schema = self.index.schema();
+        let fields = match field_name {
+            Some(name) => vec![schema.get_field(name)?],
+            None => self.text_fields().to_vec(),
+        };
+        let one_word = pattern
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '*' | '?'));
+        let mut any_field: Vec<(Occur, Box<dyn tantivy::query::Query>)> = Vec::new();
+        for field in fields {
+            let tokenizer = match schema.get_field_entry(field).field_type() {
+                FieldType::Str(options) => options.get_indexing_options().map(|i| i.tokenizer()),
+                _ => None,
+            };
+            let (field, regex) = match tokenizer {
+                None => continue,
+                // Raw values keep their case
+                Some("raw") => (field, query::wildcard_regex(pattern, ".")),
+                Some(name) if one_word || name == TAG_TOKENIZER => {
+                    (field, query::wildcard_regex(&pattern.to_lowercase(), "."))
+                }
+                Some(_) if field == self.field_name => {
+                    let name = query::wildcard_regex(pattern, "[^/]");
+                    (self.field_path, format!("(?i)(.*/)?{}", name))
+                }
+                Some(_) => continue,
+            };
+            any_field.push((Occur::Should, Box::new(RegexQuery::from_pattern(&regex, field)?)));
+        }
+        Ok((!any_field.is_empty()).then(|| Box::new(BooleanQuery::new(any_field)) as _))
+    }
+
+    /// Query for the words of `phrase` in any of `fields`: the phrase with its last word a
+    /// prefix of an indexed word (`Prefix`), or for `Substring` each word inside an indexed
+    /// word, the first at its end and the last at its start (anywhere in the file for
+    /// phrases). Regex automata over the term dictionary stand in for an n-gram index.
+    fn partial_words(
+        &self,
+        phrase: &str,
+        fields: &[Field],
+        mode: MatchMode,
+    ) -> anyhow::Result<Box<dyn tantivy::query::Query>> {
+        let mut analyzer = self.index.tokenizer_for_field(self.field_content)?;
+        let mut words = Vec::new();
+        analyzer.token_stream(phrase).process(&mut |token| words.push(token.text.clone()));
+        // Tokens are lowercased runs of letters and digits, so need no regex escaping
+        let mut any_field: Vec<(Occur, Box<dyn tantivy::query::Query>)> = Vec::new();
+        for &field in fields {
+            let query: Box<dyn tantivy::query::Query> = match (mode, words.as_slice()) {
+                (_, []) => continue,
+                (MatchMode::Prefix, [word]) => {
+                    Box::new(RegexQuery::from_pattern(&format!("{}.*", word), field)?)
+                }
+                (_, [word]) => {
+                    Box::new(RegexQuery::from_pattern(&format!(".*{}.*", word), field)?)
+                }
+                (MatchMode::Prefix, words) => Box::new(PhrasePrefixQuery::new(
+                    words.iter().map(|w| Term::from_field_text(field, w)).collect(),
+                )),
+                (_, [first, middle @ .., last]) => {
+                    let mut parts: Vec<(Occur, Box<dyn tantivy::query::Query>)> = vec![
+                        (
+                            Occur::Must,
+                            Box::new(RegexQuery::from_pattern(&format!(".*{}", first), field)?),
+                        ),
+                        (
+                            Occur::Must,
+                            Box::new(RegexQuery::from_pattern(&format!("{}.*", last), field)?),
+                        ),
+                    ];
+                    for word in middle {
+                        let term = Term::from_field_text(field, word);
+                        parts.push((
+                            Occur::Must,
+                            Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)),
+                        ));
+                    }
+                    Box::new(BooleanQuery::new(parts))
+                }
+            };
+            any_field.push((Occur::Should, query));
+        }
+        Ok(Box::new(BooleanQuery::new(any_field)))
+    }
+
+    /// Query matching the documents of any of `paths` (indexed paths); none if it is empty.
+    fn any_path<'a>(&self, paths: impl Iterator<Item = &'a str>) -> Box<dyn tantivy::query::Query> {
+        let any_file: Vec<(Occur, Box<dyn tantivy::query::Query>)> = paths
+            .map(|file| {
+                let term = Term::from_field_text(self.field_path, file);
+                let query: Box<dyn tantivy::query::Query> =
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                (Occur::Should, query)
+            })
+            .collect();
+        Box::new(BooleanQuery::new(any_file))
+    }
+
+    /// Clauses restricting a query to the documents `filters` selects, never past revisions.
+    fn filter_clauses(
+        &self,
+        snapshot: &Snapshot,
+        filters: &SearchFilters,
+    ) -> Vec<(Occur, Box<dyn tantivy::query::Query>)> {
+        let SearchFilters {
+            file_type,
+            path_prefix,
+            tracked_only,
+            time,
+            owner,
+            mime,
+            within,
+            filter,
+            project,
+            exclude_generated,
+            pinned_only,
+            ..
+        } = filters;
+        let mut clauses: Vec<(Occur, Box<dyn tantivy::query::Query>)> = Vec::new();
+        if let Some(scope) = self.scope_clause() {
+            clauses.push((Occur::Must, scope));
+        }
+
+        // Files of an earlier result set
+        if let Some(files) = within {
+            clauses.push((Occur::Must, self.any_path(files.iter().map(String::as_str))));
+        }
+        if *pinned_only {
+            let pinned: Vec<String> =
+                snapshot.files.pinned.iter().map(|p| p.to_string_lossy().into_owned()).collect();
+            clauses.push((Occur::Must, self.any_path(pinned.iter().map(String::as_str))));
+        }
+
+        // file_type param -> TermQuery on extension field, or on language for a language name
+        if let Some(ext) = file_type {
+            let ext = ext.to_lowercase();
+            let term = Term::from_field_text(self.field_extension, &ext);
+            let by_extension: Box<dyn tantivy::query::Query> =
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+            if language::is_language(&ext) {
+                let term = Term::from_field_text(self.field_language, &ext);
+                let by_language: Box<dyn tantivy::query::Query> =
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                let either = vec![(Occur::Should, by_extension), (Occur::Should, by_language)];
+                clauses.push((Occur::Must, Box::new(BooleanQuery::new(either))));
+            } else {
+                clauses.push((Occur::Must, by_extension));
+            }
+        }
+
+        // path_prefix param -> TermQuery per path component on directory field
+        if let Some(prefix) = path_prefix {
+            for segment in prefix.split('/').filter(|s| !s.is_empty()) {
+                let term = Term::from_field_text(self.field_directory, &segment.to_lowercase());
+                clauses.push((
+                    Occur::Must,
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+                ));
+            }
+        }
+
+        if *tracked_only {
+            let term = Term::from_field_text(self.field_tracked, "true");
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+
+        if *exclude_generated {
+            let term = Term::from_field_text(self.field_generated, "true");
+            clauses.push((
+                Occur::MustNot,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+
+        if let Some(uid) = owner {
+            let term = Term::from_field_text(self.field_owner, &uid.to_string());
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+
+        if let Some(mime) = mime {
+            let term = Term::from_field_text(self.field_mime, &mime.trim().to_lowercase());
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+
+        // Log chunks overlapping the range: ending after `since` and starting before `until`
+        if let Some(time) = time {
+            use std::ops::Bound;
+            let since = time.since.map_or(Bound::Unbounded, Bound::Included);
+            let until = time.until.map_or(Bound::Unbounded, Bound::Included);
+            let ends_after = RangeQuery::new_i64_bounds("log_end".to_string(), since, Bound::Unbounded);
+            let starts_before =
+                RangeQuery::new_i64_bounds("log_start".to_string(), Bound::Unbounded, until);
+            clauses.push((Occur::Must, Box::new(ends_after)));
+            clauses.push((Occur::Must, Box::new(starts_before)));
+        }
+
+        if let Some(filter) = filter {
+            clauses.push((Occur::Must, self.filter_query(filter)));
+        }
+
+        if let Some(project) = project {
+            let term = Term::from_field_text(self.field_project, &project.trim().to_lowercase());
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+
+        // Past revisions are only returned by history search, directory summaries by
+        // directory search
+        for field in [self.field_history, self.field_summary] {
+            let term = Term::from_field_text(field, "true");
+            clauses.push((
+                Occur::MustNot,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+        clauses
+    }
+
+    /// Compile a filter expression into a query on the metadata fields.
+    fn filter_query(&self, filter: &Filter) -> Box<dyn tantivy::query::Query> {
+        let term_query = |field: Field, text: &str| -> Box<dyn tantivy::query::Query> {
+            let term = Term::from_field_text(field, text);
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+        };
+        let any = |queries: Vec<Box<dyn tantivy::query::Query>>| -> Box<dyn tantivy::query::Query> {
+            Box::new(BooleanQuery::new(
+                queries.into_iter().map(|q| (Occur::Should, q)).collect(),
+            ))
+        };
+        match filter {
+            Filter::And(all) => Box::new(BooleanQuery::new(
+                all.iter().map(|f| (Occur::Must, self.filter_query(f))).collect(),
+            )),
+            Filter::Or(filters) => any(filters.iter().map(|f| self.filter_query(f)).collect()),
+            Filter::Not(filter) => Box::new(BooleanQuery::new(vec![
+                (Occur::Must, Box::new(AllQuery) as Box<dyn tantivy::query::Query>),
+                (Occur::MustNot, self.filter_query(filter)),
+            ])),
+            Filter::Extension(exts) => {
+                any(exts.iter().map(|ext| term_query(self.field_extension, ext)).collect())
+            }
+            Filter::Language(languages) => any(
+                languages
+                    .iter()
+                    .map(|language| term_query(self.field_language, language))
+                    .collect(),
+            ),
+            Filter::Project(projects) => any(
+                projects
+                    .iter()
+                    .map(|project| term_query(self.field_project, project))
+                    .collect(),
+            ),
+            Filter::Path(path) => term_query(self.field_path, path),
+            Filter::PathBelow(dir) => {
+                use std::ops::Bound;
+                let (below, next) = (format!("{}/", dir), format!("{}0", dir));
+                Box::new(RangeQuery::new_str_bounds(
+                    "file_path".to_string(),
+                    Bound::Included(&below),
+                    Bound::Excluded(&next),
+                ))
+            }
+            Filter::Directory(components) => Box::new(BooleanQuery::new(
+                components
+                    .iter()
+                    .map(|c| (Occur::Must, term_query(self.field_directory, c)))
+                    .collect(),
+            )),
+            Filter::Mime(types) => {
+                any(types.iter().map(|m| term_query(self.field_mime, m)).collect())
+            }
+            Filter::Owner(uids) => any(
+                uids.iter()
+                    .map(|uid| term_query(self.field_owner, &uid.to_string()))
+                    .collect(),
+            ),
+            Filter::Generated(reasons) => any(
+                reasons
+                    .iter()
+                    .map(|reason| term_query(self.field_generated, reason))
+                    .collect(),
+            ),
+            Filter::Tracked(true) => term_query(self.field_tracked, "true"),
+            Filter::Tracked(false) => Box::new(BooleanQuery::new(vec![
+                (Occur::Must, Box::new(AllQuery) as Box<dyn tantivy::query::Query>),
+                (Occur::MustNot, term_query(self.field_tracked, "true")),
+            ])),
+            Filter::Modified(from, to) => Box::new(RangeQuery::new_u64_bounds(
+                "modified_at".to_string(),
+                *from,
+                *to,
+            )),
+        }
+    }
+
+    /// Whether the index keeps directory summaries for `search_directories`.
+    pub fn has_directory_summaries(&self) -> bool {
+        self.directory_summaries
+    }
+
+    /// Search the directory summaries: results are directories, matched by their file
+    /// names, dominant language and README head.
+    pub fn search_directories(
+        &self,
+        query_str: &str,
+        limit: usize,
+    ) -> anyhow::Result<SearchOutput> {
+        if query_str.trim().is_empty() {
+            anyhow::bail!("Directory search needs a query");
+        }
+        let term = Term::from_field_text(self.field_summary, "true");
+        let mut clauses: Vec<(Occur, Box<dyn tantivy::query::Query>)> =
+            vec![(Occur::Must, Box::new(TermQuery::new(term, IndexRecordOption::Basic)))];
+        if let Some(scope) = self.scope_clause() {
+            clauses.push((Occur::Must, scope));
+        }
+        let snapshot = self.published.load_full();
+        self.run_query(&snapshot, query_str, limit, &SearchFilters::default(), clauses)
+    }
+
+    /// Search file contents at indexed past revisions, optionally only at `revision`.
+    pub fn search_history(
+        &self,
+        query_str: &str,
+        limit: usize,
+        revision: Option<&str>,
+    ) -> anyhow::Result<SearchOutput> {
+        let mut clauses: Vec<(Occur, Box<dyn tantivy::query::Query>)> = Vec::new();
+        let term = Term::from_field_text(self.field_history, "true");
+        clauses.push((
+            Occur::Must,
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+        ));
+        if let Some(revision) = revision {
+            let term = Term::from_field_text(self.field_revision, revision);
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+        if let Some(scope) = self.scope_clause() {
+            clauses.push((Occur::Must, scope));
+        }
+        if query_str.trim().is_empty() {
+            anyhow::bail!("History search needs a query");
+        }
+        let snapshot = self.published.load_full();
+        self.run_query(&snapshot, query_str, limit, &SearchFilters::default(), clauses)
+    }
+
+    /// Combine the parsed text query with filter clauses and collect the top results of
+    /// `snapshot`.
+    fn run_query(
+        &self,
+        snapshot: &Snapshot,
+        query_str: &str,
+        limit: usize,
+        options: &SearchFilters,
+        clauses: Vec<(Occur, Box<dyn tantivy::query::Query>)>,
+    ) -> anyhow::Result<SearchOutput> {
+        let Some(search) = self.prepare(query_str, options, clauses)? else {
+            return Ok(SearchOutput {
+                results: vec![],
+                total_count: 0,
+                generation: snapshot.generation,
+                degraded: false,
+            });
+        };
+
+        let started = Instant::now();
+        let searcher = &snapshot.searcher;
+        let deadline = options.latency_budget.map(|budget| started + budget);
+        let mut degraded = false;
+        // Collect no more hits than the budget leaves time to build results for
+        let affordable = options.latency_budget.and_then(|budget| self.pace.affordable(budget));
+        let limit = match affordable {
+            Some(affordable) if affordable < limit => {
+                degraded = true;
+                affordable
+            }
+            _ => limit,
+        };
+
+        // Files split into several documents (log and table chunks) take several hits, and
+        // the index ignores case, so case-sensitive searches drop some
+        let mut fetch = limit;
+        if options.group_by_file {
+            fetch = fetch.saturating_mul(GROUP_OVERFETCH);
+        }
+        if search.matching.case_sensitive {
+            fetch = fetch.saturating_mul(CASE_OVERFETCH);
+        }
+        let top_docs = self.top_hits(snapshot, &search.query, fetch, 0)?;
+
+        // BM25 scores only compare within a query: relevance is the score relative to the
+        // best result, so the top result is always 1
+        let best = top_docs.first().map_or(0.0, |(score, _)| *score);
+        let relevance = |score: f32| if best > 0.0 { score / best } else { 1.0 };
+
+        let mut results = Vec::new();
+        let mut grouped: HashSet<String> = HashSet::new();
+        let building = Instant::now();
+        // Main snippets only, once the budget is short of full results for the rest
+        let mut lean = false;
+        for (score, doc_address) in top_docs {
+            let relevance = relevance(score);
+            // Results come best first, so the rest are below the threshold too
+            if options.min_score.is_some_and(|min| relevance < min) || results.len() == limit {
+                break;
+            }
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline && !results.is_empty() {
+                    degraded = true;
+                    break;
+                }
+                let rest = (limit - results.len()) as u32;
+                lean = lean
+                    || self.pace.per_result().is_some_and(|per| now + per * rest > deadline);
+            }
+            degraded |= lean && options.snippets_per_file > 1;
+            let hit = (score, relevance, doc_address);
+            let built = self.build_result(searcher, &search, options, hit, &mut grouped, lean)?;
+            results.extend(built);
+        }
+
+        if !lean {
+            self.pace.record(building.elapsed(), results.len());
+        }
+
+        if options.group_by_file {
+            for result in &mut results {
+                if lean || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    degraded = true;
+                    break;
+                }
+                let count = self.count_matches(searcher, &search, &result.file_path)?;
+                result.match_count = Some(count);
+            }
+        }
+
+        let total_count = results.len();
+
+        metrics::counter!(telemetry::SEARCHES_TOTAL).increment(1);
+        metrics::histogram!(telemetry::SEARCH_DURATION).record(started.elapsed().as_secs_f64());
+        metrics::histogram!(telemetry::SEARCH_HITS).record(total_count as f64);
+
+        Ok(SearchOutput {
+            results,
+            total_count,
+            generation: snapshot.generation,
+            degraded,
+        })
+    }
+
+    /// Parse `query_str` and combine it with the filter `clauses`; `None` if there is
+    /// neither a text query nor a filter.
+    fn prepare(
+        &self,
+        query_str: &str,
+        options: &SearchFilters,
+        mut clauses: Vec<(Occur, Box<dyn tantivy::query::Query>)>,
+    ) -> anyhow::Result<Option<PreparedSearch>> {
+        let query_str = &query::rewrite(query_str)?;
+        let has_text_query = !query_str.trim().is_empty();
+        let has_filters = clauses.iter().any(|(occur, _)| *occur == Occur::Must);
+        if !has_text_query && !has_filters {
+            return Ok(None);
+        }
+
+        // Text query parsed by QueryParser (supports field:value syntax for all fields)
+        if has_text_query {
+            let query_parser = QueryParser::for_index(&self.index, self.text_fields().to_vec());
+            query::check_wildcards(query_str)?;
+            let mode = options.match_mode;
+            let plain = mode == MatchMode::Word && self.synonyms.is_empty();
+            let ast = tantivy::query_grammar::parse_query(query_str)
+                .map_err(|_| QueryParserError::SyntaxError(query_str.to_string()))?;
+            let ast = query::require_fields(ast);
+            let parsed = if plain && !query_str.contains(['*', '?']) {
+                query_parser.build_query_from_user_input_ast(ast)?
+            } else {
+                self.text_query(&query_parser, ast, mode)?
+            };
+            clauses.push((Occur::Must, self.ranked(parsed)?));
+        }
+
+        let case_sensitive = options.case_sensitive && !query::text_terms(query_str).is_empty();
+        let mut query_terms = if case_sensitive {
+            query::cased_text_terms(query_str)
+        } else {
+            query::text_terms(query_str)
+        };
+        let synonyms: Vec<String> = query_terms
+            .iter()
+            .flat_map(|term| self.synonyms.expand(term))
+            .map(str::to_string)
+            .collect();
+        query_terms.extend(synonyms);
+        let key_terms = query_str
+            .split_whitespace()
+            .filter_map(|s| s.strip_prefix("key:"))
+            .map(|key| key.trim_matches('"').to_string())
+            .collect();
+        Ok(Some(PreparedSearch {
+            query: BooleanQuery::new(clauses),
+            query_terms,
+            matching: Matching {
+                case_sensitive,
+                mode: options.match_mode,
+            },
+            key_terms,
+        }))
+    }
+
+    /// The `fetch` best hits of `query` after the first `offset`, best first.
+    fn top_hits(
+        &self,
+        snapshot: &Snapshot,
+        query: &BooleanQuery,
+        fetch: usize,
+        offset: usize,
+    ) -> anyhow::Result<Vec<(Score, DocAddress)>> {
+        let searcher = &snapshot.searcher;
+        let top = TopDocs::with_limit(fetch).and_offset(offset);
+        // Matches in pinned files score `PIN_BOOST` times higher, and in often-read files
+        // up to `MAX_USAGE_BOOST` times
+        let usage = self.usage.as_ref().map(|usage| usage.boosts()).unwrap_or_default();
+        if snapshot.files.pinned.is_empty() && usage.is_empty() {
+            return Ok(searcher.search(query, &top)?);
+        }
+        let (field_path, pinned) = (self.field_path, snapshot.files.pinned.clone());
+        let boost = top.tweak_score(move |segment: &SegmentReader| {
+            let pinned: HashSet<DocId> = pinned
+                .iter()
+                .flat_map(|path| path_docs(segment, field_path, &path.to_string_lossy()))
+                .collect();
+            let used: HashMap<DocId, f32> = usage
+                .iter()
+                .flat_map(|(path, boost)| {
+                    let docs = path_docs(segment, field_path, path);
+                    docs.into_iter().map(move |doc| (doc, *boost))
+                })
+                .collect();
+            move |doc: DocId, score: Score| {
+                let mut score = score;
+                if pinned.contains(&doc) {
+                    score *= pins::PIN_BOOST;
+                }
+                score * used.get(&doc).copied().unwrap_or(1.0)
+            }
+        });
+        Ok(searcher.search(query, &boost)?)
+    }
+
+    /// Build the result of the hit at `doc_address` with its `score` and `relevance`;
+    /// `None` if it only matches in another case than a case-sensitive search asked for,
+    /// or when grouping by file and `grouped` already holds its file. `lean` leaves out
+    /// extra snippets.
+    fn build_result(
+        &self,
+        searcher: &Searcher,
+        search: &PreparedSearch,
+        options: &SearchFilters,
+        (score, relevance, doc_address): (Score, f32, DocAddress),
+        grouped: &mut HashSet<String>,
+        lean: bool,
+    ) -> anyhow::Result<Option<SearchResult>> {
+        let SearchFilters {
+            snippets_per_file,
+            context_lines,
+            ..
+        } = *options;
+        let Matching { case_sensitive, .. } = search.matching;
+        let matching = search.matching;
+        let doc: TantivyDocument = searcher.doc(doc_address)?;
+        let file_path = doc
+            .get_first(self.field_path)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        let file_name = doc
+            .get_first(self.field_name)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        let content = doc
+            .get_first(self.field_content)
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        if case_sensitive
+            && highlight::find_matches_with(content, &search.query_terms, matching).is_empty()
+            && highlight::find_matches_with(&file_name, &search.query_terms, matching).is_empty()
+        {
+            return Ok(None);
+        }
+        // The first hit of a file is its best
+        if options.group_by_file && !grouped.insert(file_path.clone()) {
+            return Ok(None);
+        }
+
+        let extension = Path::new(&file_path).extension().and_then(|e| e.to_str());
+        let key_line = extension.filter(|e| keys::is_structured(e)).and_then(|e| {
+            search.key_terms.iter().find_map(|key| keys::line_of(content, e, key))
+        });
+        let line_term;
+        let snippet_terms = match key_line.and_then(|ln| content.lines().nth(ln - 1)) {
+            Some(line) => {
+                let line = line.trim();
+                line_term = [if case_sensitive {
+                    line.to_string()
+                } else {
+                    line.to_lowercase()
+                }];
+                &line_term[..]
+            }
+            None => &search.query_terms[..],
+        };
+        // Log chunks count lines from the chunk's first line in the log
+        let first_line = doc.get_first(self.field_first_line).and_then(|v| v.as_u64());
+        let matched_line = key_line
+            .or_else(|| FileIndex::find_match_line(content, &search.query_terms, case_sensitive));
+        // Table chunks hold one data row per line
+        let first_row = doc.get_first(self.field_first_row).and_then(|v| v.as_u64());
+        // Line in the file (or data row) of line `ln` of the document
+        let number = |ln: usize| match first_row {
+            Some(f) => ln + f as usize - 1,
+            None => ln + first_line.map_or(0, |f| f as usize - 1),
+        };
+        let row = first_row.and(matched_line).map(number);
+        let line_number = matched_line.filter(|_| first_row.is_none()).map(number);
+        let (snippet, context_start) = match (context_lines, matched_line) {
+            (Some(context), Some(ln)) => {
+                let (start, text) = FileIndex::context_snippet(content, ln, context);
+                (text, Some(number(start)))
+            }
+            _ => {
+                let snippet =
+                    FileIndex::extract_snippet(content, snippet_terms, 200, case_sensitive);
+                (snippet, None)
+            }
+        };
+        let matches = highlight::find_matches_with(&snippet, snippet_terms, matching);
+        let (snippet, highlights) = self.highlight.apply(&snippet, &matches);
+        let snippets = if snippets_per_file > 1 && !lean {
+            FileIndex::line_snippets(
+                content,
+                snippet_terms,
+                snippets_per_file,
+                context_lines,
+                matching,
+            )
+            .into_iter()
+            .map(|(ln, start, text)| {
+                let matches = highlight::find_matches_with(&text, snippet_terms, matching);
+                let (text, highlights) = self.highlight.apply(&text, &matches);
+                Snippet {
+                    text,
+                    line_number: first_row.is_none().then(|| number(ln)),
+                    row: first_row.map(|_| number(ln)),
+                    context_start: context_lines.map(|_| number(start)),
+                    highlights,
+                }
+            })
+            .collect()
+        } else {
+            Vec::new()
+        };
+        let cells = doc.get_all(self.field_cells).filter_map(|v| v.as_str());
+        let cell = line_number.and_then(|ln| notebook::cell_at_line(cells, ln));
+        let section = if markdown::is_markdown(extension.unwrap_or("")) {
+            line_number.and_then(|ln| markdown::heading_at_line(content, ln))
+        } else {
+            None
+        };
+
+        let revision = doc
+            .get_first(self.field_revision)
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        let commit_time = revision.as_ref().and_then(|_| {
+            doc.get_first(self.field_commit_time)
+                .and_then(|v| v.as_str())
+                .and_then(|t| t.trim_end_matches('s').parse().ok())
+        });
+
+        let truncated = doc
+            .get_first(self.field_truncated)
+            .and_then(|v| v.as_str())
+            .is_some_and(|t| t == "true");
+        let log_time = doc
+            .get_first(self.field_log_start)
+            .and_then(|v| v.as_i64())
+            .zip(doc.get_first(self.field_log_end).and_then(|v| v.as_i64()));
+        let modified = doc
+            .get_first(self.field_modified_at)
+            .and_then(|v| v.as_u64())
+            .and_then(|secs| i64::try_from(secs).ok());
+
+        Ok(Some(SearchResult {
+            file_path,
+            file_name,
+            snippet,
+            score,
+            relevance,
+            line_number,
+            cell,
+            section,
+            revision,
+            commit_time,
+            truncated,
+            log_time,
+            row,
+            highlights,
+            snippets,
+            context_start,
+            match_count: None,
+            modified,
+        }))
+    }
+
+    /// Matching lines of the query terms of `search` in the documents of `path` matching
+    /// its query, or the number of those documents if it has no text terms.
+    fn count_matches(
+        &self,
+        searcher: &Searcher,
+        search: &PreparedSearch,
+        path: &str,
+    ) -> anyhow::Result<usize> {
+        let PreparedSearch {
+            query,
+            query_terms,
+            matching,
+            ..
+        } = search;
+        let in_file = TermQuery::new(
+            Term::from_field_text(self.field_path, path),
+            IndexRecordOption::Basic,
+        );
+        let docs = searcher.search(
+            &BooleanQuery::new(vec![
+                (Occur::Must, Box::new(query.clone())),
+                (Occur::Must, Box::new(in_file)),
+            ]),
+            &DocSetCollector,
+        )?;
+        if query_terms.is_empty() {
+            return Ok(docs.len());
+        }
+        let mut count = 0;
+        for address in docs {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let content = doc
+                .get_first(self.field_content)
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            count += content
+                .lines()
+                .filter(|line| {
+                    !highlight::find_matches_with(line, query_terms, *matching).is_empty()
+                })
+                .count();
+        }
+        Ok(count)
+    }
+
+    /// Most frequent content terms by number of files, across the index or only files
+    /// under `path_prefix` (directory components, as in `search`) and/or containing `keyword`.
+    ///
+    /// The whole index is read from the term dictionaries, which count documents: the
+    /// result is `approximate` when files are split into several or deletes are pending.
+    /// Subsets are re-tokenized from stored content, up to `MAX_ANALYZED_DOCS` documents,
+    /// and counted by file.
+    pub fn analyze_terms(
+        &self,
+        keyword: Option<&str>,
+        path_prefix: Option<&str>,
+        limit: usize,
+    ) -> anyhow::Result<TermStats> {
+        let snapshot = self.published.load_full();
+        let searcher = &snapshot.searcher;
+        let keyword = keyword.map(str::trim).filter(|k| !k.is_empty());
+        let history = Term::from_field_text(self.field_history, "true");
+        let summary = Term::from_field_text(self.field_summary, "true");
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        let mut stats = TermStats::default();
+
+        let unscoped = self.roots.is_none();
+        if keyword.is_none()
+            && path_prefix.is_none()
+            && unscoped
+            && searcher.doc_freq(&history)? == 0
+            && searcher.doc_freq(&summary)? == 0
+        {
+            for segment in searcher.segment_readers() {
+                let inverted = segment.inverted_index(self.field_content)?;
+                let mut stream = inverted.terms().stream()?;
+                while stream.advance() {
+                    if let Ok(term) = std::str::from_utf8(stream.key()) {
+                        if Self::is_interesting_term(term) {
+                            *counts.entry(term.to_string()).or_default() +=
+                                u64::from(stream.value().doc_freq);
+                        }
+                    }
+                }
+            }
+            stats.documents = searcher.num_docs();
+            let deletes = searcher.segment_readers().iter().any(|s| s.num_deleted_docs() > 0);
+            stats.approximate =
+                deletes || stats.documents != snapshot.files.indexed_paths.len() as u64;
+        } else {
+            let mut clauses: Vec<(Occur, Box<dyn tantivy::query::Query>)> = vec![
+                (
+                    Occur::MustNot,
+                    Box::new(TermQuery::new(history, IndexRecordOption::Basic)),
+                ),
+                (
+                    Occur::MustNot,
+                    Box::new(TermQuery::new(summary, IndexRecordOption::Basic)),
+                ),
+            ];
+            if let Some(keyword) = keyword {
+                let parser = QueryParser::for_index(&self.index, vec![self.field_content]);
+                clauses.push((Occur::Must, parser.parse_query(&query::rewrite(keyword)?)?));
+            }
+            if let Some(scope) = self.scope_clause() {
+                clauses.push((Occur::Must, scope));
+            }
+            for segment in path_prefix
+                .unwrap_or("")
+                .split('/')
+                .filter(|s| !s.is_empty())
+            {
+                let term = Term::from_field_text(self.field_directory, &segment.to_lowercase());
+                clauses.push((
+                    Occur::Must,
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+                ));
+            }
+            if !clauses.iter().any(|(occur, _)| *occur == Occur::Must) {
+                return Ok(stats); // a prefix of only slashes
+            }
+            let docs = searcher.search(&BooleanQuery::new(clauses), &DocSetCollector)?;
+            stats.sampled = docs.len() > MAX_ANALYZED_DOCS;
+
+            let mut analyzer = self.index.tokenizer_for_field(self.field_content)?;
+            // Co-occurring terms: leave out the keyword's own terms
+            let mut excluded = HashSet::new();
+            if let Some(keyword) = keyword {
+                analyzer.token_stream(keyword).process(&mut |t| {
+                    excluded.insert(t.text.clone());
+                });
+            }
+            // Terms per file, so the chunks of a split file count once
+            let mut files: HashMap<String, HashSet<String>> = HashMap::new();
+            for address in docs.into_iter().take(MAX_ANALYZED_DOCS) {
+                let doc: TantivyDocument = searcher.doc(address)?;
+                let text = |field| doc.get_first(field).and_then(|v| v.as_str()).unwrap_or("");
+                let seen = files.entry(text(self.field_path).to_string()).or_default();
+                analyzer.token_stream(text(self.field_content)).process(&mut |t| {
+                    if Self::is_interesting_term(&t.text) && !excluded.contains(&t.text) {
+                        seen.insert(t.text.clone());
+                    }
+                });
+            }
+            stats.documents = files.len() as u64;
+            for term in files.into_values().flatten() {
+                *counts.entry(term).or_default() += 1;
+            }
+        }
+
+        let mut terms: Vec<(String, u64)> = counts.into_iter().collect();
+        terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        terms.truncate(limit);
+        stats.terms = terms;
+        Ok(stats)
+    }
+
+    /// Complete the last word of a partially typed query from the term dictionary, most
+    /// frequent terms first. A `field:` prefix on the last word completes terms of that
+    /// field (`tag:pro` → `tag:project`); otherwise content, file name and title terms are
+    /// used. Returns whole queries with the completed word and the number of files per term.
+    ///
+    /// The dictionaries count documents, deleted ones included, per field, so they only
+    /// pick the candidates: each is then counted by file, like a search would find it.
+    pub fn suggest(&self, partial: &str, limit: usize) -> anyhow::Result<Vec<(String, u64)>> {
+        let head_len = partial.trim_end().rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let (head, word) = partial.trim_end().split_at(head_len);
+        let (field_prefix, prefix) = match word.split_once(':') {
+            Some((field, prefix)) => (Some(field), prefix),
+            None => (None, word),
+        };
+        let prefix = prefix.to_lowercase();
+        if prefix.is_empty() {
+            return Ok(Vec::new());
+        }
+        let fields = match field_prefix {
+            Some(name) => vec![self.index.schema().get_field(&query::resolve(name)?)?],
+            None => vec![self.field_content, self.field_name, self.field_title],
+        };
+
+        let snapshot = self.published.load_full();
+        let searcher = &snapshot.searcher;
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        for segment in searcher.segment_readers() {
+            for &field in &fields {
+                let inverted = segment.inverted_index(field)?;
+                let mut stream = inverted.terms().range().ge(prefix.as_bytes()).into_stream()?;
+                while stream.advance() {
+                    if !stream.key().starts_with(prefix.as_bytes()) {
+                        break;
+                    }
+                    if let Ok(term) = std::str::from_utf8(stream.key()) {
+                        *counts.entry(term.to_string()).or_default() +=
+                            u64::from(stream.value().doc_freq);
+                    }
+                }
+            }
+        }
+
+        let mut candidates: Vec<(String, u64)> = counts.into_iter().collect();
+        candidates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        // A few extra candidates make up for terms only deleted files or revisions have
+        let mut terms = Vec::new();
+        for (term, _) in candidates.into_iter().take(limit.saturating_mul(2)) {
+            let count = self.files_with_term(searcher, &fields, &term)?;
+            if count > 0 {
+                terms.push((term, count));
+            }
+        }
+        terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        terms.truncate(limit);
+        Ok(terms
+            .into_iter()
+            .map(|(term, count)| {
+                let word = match field_prefix {
+                    Some(field) => format!("{}:{}", field, term),
+                    None => term,
+                };
+                (format!("{}{}", head, word), count)
+            })
+            .collect())
+    }
+
+    /// Corrections of `query` for when it matched nothing: each word no file in scope
+    /// contains is replaced with the closest terms of the content, file name and title
+    /// dictionaries (edit distance up to 2, starting with the same letter; among equally
+    /// close terms the most common first). Empty if no word has a close term.
+    pub fn did_you_mean(&self, query: &str, limit: usize) -> anyhow::Result<Vec<String>> {
+        let snapshot = self.published.load_full();
+        let searcher = &snapshot.searcher;
+        let fields = [self.field_content, self.field_name, self.field_title];
+        let mut corrections: Vec<(String, Vec<String>)> = Vec::new();
+        for word in query::text_terms(&query::rewrite(query)?) {
+            // Phrases, wildcards and table cells are left as typed
+            let length = word.chars().count();
+            if length < 3 || !word.chars().all(char::is_alphanumeric) {
+                continue;
+            }
+            if self.has_term(searcher, &fields, &word)? {
+                continue;
+            }
+            let max_distance = if length <= 4 { 1 } else { 2 };
+            let first = word.chars().next().unwrap_or_default().to_string();
+            let mut close: HashMap<String, (usize, u64)> = HashMap::new();
+            for segment in searcher.segment_readers() {
+                for &field in &fields {
+                    let inverted = segment.inverted_index(field)?;
+                    let mut stream = inverted.terms().range().ge(first.as_bytes()).into_stream()?;
+                    while stream.advance() {
+                        if !stream.key().starts_with(first.as_bytes()) {
+                            break;
+                        }
+                        let Ok(term) = std::str::from_utf8(stream.key()) else {
+                            continue;
+                        };
+                        if term.chars().count().abs_diff(length) > max_distance {
+                            continue;
+                        }
+                        let distance = edit_distance(&word, term);
+                        if distance <= max_distance {
+                            let entry = close.entry(term.to_string()).or_insert((distance, 0));
+                            entry.1 += u64::from(stream.value().doc_freq);
+                        }
+                    }
+                }
+            }
+            let mut close: Vec<(String, (usize, u64))> = close.into_iter().collect();
+            close.sort_by(|(a, (distance_a, files_a)), (b, (distance_b, files_b))| {
+                distance_a.cmp(distance_b).then(files_b.cmp(files_a)).then(a.cmp(b))
+            });
+            let mut candidates = Vec::new();
+            for (term, _) in close {
+                if candidates.len() == limit {
+                    break;
+                }
+                // Term dictionaries cover the whole index
+                if self.roots.is_none() || self.has_term(searcher, &fields, &term)? {
+                    candidates.push(term);
+                }
+            }
+            if !candidates.is_empty() {
+                corrections.push((word, candidates));
+            }
+        }
+
+        // The i-th suggestion takes the i-th candidate of each word (or its last)
+        let count = corrections.iter().map(|(_, c)| c.len()).max().unwrap_or(0);
+        let mut suggestions: Vec<String> = Vec::new();
+        for i in 0..count {
+            let mut suggestion = query.to_string();
+            for (word, candidates) in &corrections {
+                let candidate = &candidates[i.min(candidates.len() - 1)];
+                let ranges = highlight::find_matches(&suggestion, std::slice::from_ref(word));
+                for range in ranges.into_iter().rev() {
+                    suggestion.replace_range(range, candidate);
+                }
+            }
+            if !suggestions.contains(&suggestion) {
+                suggestions.push(suggestion);
+            }
+        }
+        Ok(suggestions)
+    }
+
+    /// Files in scope with `term` in any of `fields`, each counted once however many
+    /// documents it is split into.
+    fn files_with_term(
+        &self,
+        searcher: &Searcher,
+        fields: &[Field],
+        term: &str,
+    ) -> anyhow::Result<u64> {
+        let mut files = HashSet::new();
+        for address in searcher.search(&self.term_query(fields, term), &DocSetCollector)? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            if let Some(path) = doc.get_first(self.field_path).and_then(|v| v.as_str()) {
+                files.insert(path.to_string());
+            }
+        }
+        Ok(files.len() as u64)
+    }
+
+    /// Whether any file in scope has `term` in any of `fields`.
+    fn has_term(&self, searcher: &Searcher, fields: &[Field], term: &str) -> anyhow::Result<bool> {
+        Ok(searcher.search(&self.term_query(fields, term), &tantivy::collector::Count)? > 0)
+    }
+
+    /// Live file documents in scope with `term` in any of `fields`; past revisions and
+    /// directory summaries are left out, as searches leave them out.
+    fn term_query(&self, fields: &[Field], term: &str) -> BooleanQuery {
+        let any_field: Vec<(Occur, Box<dyn tantivy::query::Query>)> = fields
+            .iter()
+            .map(|&field| {
+                let term = Term::from_field_text(field, term);
+                let query: Box<dyn tantivy::query::Query> =
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                (Occur::Should, query)
+            })
+            .collect();
+        let mut clauses = vec![(
+            Occur::Must,
+            Box::new(BooleanQuery::new(any_field)) as Box<dyn tantivy::query::Query>,
+        )];
+        for field in [self.field_history, self.field_summary] {
+            let term = Term::from_field_text(field, "true");
+            let query = TermQuery::new(term, IndexRecordOption::Basic);
+            clauses.push((Occur::MustNot, Box::new(query)));
+        }
+        clauses.extend(self.scope_clause().map(|scope| (Occur::Must, scope)));
+        BooleanQuery::new(clauses)
+    }
+
+    /// Skip one- and two-letter tokens and numbers, which dominate code and add nothing.
+    fn is_interesting_term(term: &str) -> bool {
+        term.chars().count() >= 3 && !term.chars().all(|c| c.is_ascii_digit())
+    }
+
+    /// Indexed files whose content is most similar to the indexed file at `path`, or to
+    /// `text` when no path is given. The file itself is not included.
+    pub fn similar_files(
+        &self,
+        path: Option<&str>,
+        text: Option<&str>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<SimilarFile>> {
+        let snapshot = self.published.load_full();
+        let searcher = &snapshot.searcher;
+        let (source_path, content) = match (path, text) {
+            (Some(path), _) => {
+                let path = match archive::split_virtual(path) {
+                    Some(_) => path.to_string(),
+                    None => Path::new(path).canonicalize()?.to_string_lossy().to_string(),
+                };
+                if !self.in_scope(Path::new(&path)) {
+                    anyhow::bail!("File is not in the index: {}", path);
+                }
+                let term = Term::from_field_text(self.field_path, &path);
+                let query = TermQuery::new(term, IndexRecordOption::Basic);
+                let (_, address) = searcher
+                    .search(&query, &TopDocs::with_limit(1))?
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("File is not in the index: {}", path))?;
+                let doc: TantivyDocument = searcher.doc(address)?;
+                let content = doc
+                    .get_first(self.field_content)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string();
+                (Some(path), content)
+            }
+            (None, Some(text)) => (None, text.to_string()),
+            (None, None) => anyhow::bail!("Give a path or text to compare against"),
+        };
+
+        // Defaults are tuned for large corpora; small notes and snippets need lower cut-offs
+        let more_like_this = MoreLikeThisQuery::builder()
+            .with_min_doc_frequency(1)
+            .with_min_term_frequency(1)
+            .with_min_word_length(3)
+            .with_max_query_terms(40)
+            .with_document_fields(vec![(self.field_content, vec![OwnedValue::Str(content)])]);
+        let history = Term::from_field_text(self.field_history, "true");
+        let summary = Term::from_field_text(self.field_summary, "true");
+        let mut clauses = vec![
+            (Occur::Must, Box::new(more_like_this) as Box<dyn tantivy::query::Query>),
+            (
+                Occur::MustNot,
+                Box::new(TermQuery::new(history, IndexRecordOption::Basic)),
+            ),
+            (
+                Occur::MustNot,
+                Box::new(TermQuery::new(summary, IndexRecordOption::Basic)),
+            ),
+        ];
+        clauses.extend(self.scope_clause().map(|scope| (Occur::Must, scope)));
+        let query = BooleanQuery::new(clauses);
+
+        let mut similar = Vec::new();
+        for (score, address) in searcher.search(&query, &TopDocs::with_limit(limit + 1))? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let file_path = doc
+                .get_first(self.field_path)
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            if source_path.as_deref() != Some(file_path.as_str()) {
+                similar.push(SimilarFile { file_path, score });
+            }
+        }
+        similar.truncate(limit);
+        Ok(similar)
+    }
+
+    /// Count a read of the search result `path` for usage ranking; nothing without it.
+    pub fn record_read(&self, path: &str) {
+        if let Some(usage) = &self.usage {
+            if let Err(e) = usage.record(path) {
+                tracing::warn!("Failed to save the usage counts: {}", e);
+            }
+        }
+    }
+
+    /// Save read counts `record_read` has not saved yet.
+    pub fn flush_usage(&self) -> anyhow::Result<()> {
+        match &self.usage {
+            Some(usage) => usage.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Read an indexed file, as of the last commit.
+    pub fn read_file(&self, path: &str) -> anyhow::Result<String> {
+        if !self.in_scope(Path::new(path)) {
+            anyhow::bail!("File is not in the index: {}", path);
+        }
+        self.published.load().files.read_file(path, self.max_file_size)
+    }
+
+    /// List indexed files, as of the last commit.
+    pub fn list_files(&self, extension: Option<&str>, path_prefix: Option<&str>) -> Vec<String> {
+        let mut files = self.published.load().files.list_files(extension, path_prefix);
+        files.retain(|f| self.in_scope(Path::new(f)));
+        files
+    }
+
+    /// Front matter tags, as of the last commit.
+    pub fn list_tags(&self) -> BTreeMap<String, usize> {
+        self.published.load().files.list_tags(|p| self.in_scope(p))
+    }
+
+    /// Projects of the indexed files, with their number of files, as of the last commit.
+    pub fn list_projects(&self) -> Vec<ProjectSummary> {
+        self.published.load().files.list_projects(|p| self.in_scope(p))
+    }
+
+    /// Notes linking to `note`, as of the last commit.
+    pub fn backlinks(&self, note: &str) -> Vec<String> {
+        let mut sources = self.published.load().files.backlinks(note);
+        sources.retain(|s| self.in_scope(Path::new(s)));
+        sources
+    }
+
+    /// Owner and permissions of the indexed file `path`, as of the last commit.
+    pub fn file_owner(&self, path: &str) -> Option<Owner> {
+        self.published.load().files.owner(path)
+    }
+
+    /// Modification time of the indexed file `path` when it was indexed, as of the last
+    /// commit.
+    pub fn file_modified(&self, path: &str) -> Option<SystemTime> {
+        self.published.load().files.modified.get(Path::new(path)).copied()
+    }
+
+    /// Size, times, hash and staleness of the indexed file `path`, as of the last commit.
+    pub fn file_info(&self, path: &str) -> anyhow::Result<FileInfo> {
+        if !self.in_scope(Path::new(path)) {
+            anyhow::bail!("File is not in the index: {}", path);
+        }
+        self.published.load().files.info(path, self.max_file_size)
+    }
+}
+
+pub struct FileIndex {
+    writer: IndexWriter,
+    handle: SearchHandle,
+    /// Snapshot shared with `handle`, replaced on every commit.
+    published: Arc<ArcSwap<Snapshot>>,
+    generation: u64,
+    generations: watch::Sender<u64>,
+    field_path: Field,
+    field_name: Field,
+    field_content: Field,
+    field_modified_at: Field,
+    field_extension: Field,
+    field_directory: Field,
+    field_title: Field,
+    field_tag: Field,
+    field_key: Field,
+    field_alias: Field,
+    field_subject: Field,
+    field_from: Field,
+    field_to: Field,
+    field_date: Field,
+    field_branch: Field,
+    field_tracked: Field,
+    field_owner: Field,
+    field_mime: Field,
+    field_language: Field,
+    field_project: Field,
+    field_generated: Field,
+    field_author: Field,
+    field_commit: Field,
+    field_commit_time: Field,
+    field_revision: Field,
+    field_revision_key: Field,
+    field_history: Field,
+    field_summary: Field,
+    field_truncated: Field,
+    field_first_line: Field,
+    field_log_start: Field,
+    field_log_end: Field,
+    field_first_row: Field,
+    field_columns: Field,
+    field_cells: Field,
+    field_location: Field,
+    field_file_type: Field,
+    files: FileMeta,
+    watched_roots: Vec<PathBuf>,
+    /// Roots indexed once without a file watcher, so changes only show on re-indexing.
+    unwatched_roots: HashSet<PathBuf>,
+    /// Roots detached because they disappeared, as they were indexed, for re-attaching.
+    missing_roots: Vec<RootEntry>,
+    /// Files that failed to index, for retrying and reporting.
+    retries: RetryQueue,
+    /// Directories whose summary is rewritten on the next commit.
+    stale_directories: HashSet<PathBuf>,
+    /// Project rooted at each directory looked at, if any.
+    project_dirs: HashMap<PathBuf, Option<Project>>,
+    /// Roots indexed with their own hidden-file policy instead of `config.hidden`.
+    root_policies: HashMap<PathBuf, HiddenPolicy>,
+    /// Files skipped by the last walk of each root.
+    root_skips: HashMap<PathBuf, SkipStats>,
+    /// Why the running `index_file` call left its file out, if it did.
+    last_skip: Option<SkipReason>,
+    /// Whether roots changed since the manifest was last saved.
+    roots_changed: bool,
+    /// Whether pins changed since they were last saved.
+    pins_changed: bool,
+    /// The walk `index_directory` is in, saved on commit to resume it after a crash.
+    crawl: Option<Checkpoint>,
+    /// Whether `crawl` changed since it was last saved or removed.
+    crawl_changed: bool,
+    /// The walk the previous process died in, until `index_directory` resumes it.
+    interrupted: Option<Checkpoint>,
+    /// Set when the index was rebuilt for a new schema and has roots to re-index.
+    migration: Option<Migration>,
+    /// `None` for an in-memory index.
+    index_path: Option<PathBuf>,
+    /// Exclusive ownership of `index_path` for the lifetime of this index.
+    _lock: Option<InstanceLock>,
+    /// Git repositories seen so far, dropped when their `.git` directory changes.
+    repos: Vec<git::Repo>,
+    /// Directories known not to be inside a git work tree.
+    non_repo_dirs: HashSet<PathBuf>,
+    /// Revision specs indexed for history search in every repository (opt-in).
+    history_revisions: Vec<String>,
+    /// Commit indexed for each `<repo root>@<revision>`, so unchanged revisions are skipped.
+    indexed_revisions: HashMap<String, String>,
+    /// User names recorded with file owners.
+    users: Users,
+    ocr: Option<OcrConfig>,
+    config: IndexConfig,
+    /// Documents added since the last commit.
+    pending_docs: usize,
+    /// Whether documents were added or deleted since the last commit.
+    dirty: bool,
+    /// Path each physical file is indexed under, so links to it are recorded as aliases.
+    identities: HashMap<FileId, PathBuf>,
+    /// Indexed extent of each log, for indexing only what was appended to it.
+    log_tails: HashMap<PathBuf, LogTail>,
+    last_commit_at: Instant,
+    last_indexed: Option<SystemTime>,
+    last_commit: Option<SystemTime>,
+}
+
+/// Run `f` with `state` write-locked on tokio's blocking thread pool.
+///
+/// Directory walks and commits do filesystem and tantivy work that would stall the async
+/// executor if run on it; the lock is held until `f` returns.
+pub async fn write_blocking<S, T, F>(state: Arc<tokio::sync::RwLock<S>>, f: F) -> anyhow::Result<T>
+where
+    S: Send + Sync + 'static,
+    F: FnOnce(&mut S) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let mut guard = state.write_owned().await;
+    tokio::task::spawn_blocking(move || f(&mut guard))
+        .await
+        .map_err(|e| anyhow::anyhow!("Indexing task failed: {}", e))
+}
+
+/// The first and last `budget / 2` bytes of the `len`-byte file at `path`, cut at line
+/// boundaries and joined by a marker line; `None` if they are not UTF-8 text.
+fn read_head_and_tail(path: &Path, len: u64, budget: u64) -> std::io::Result<Option<String>> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let half = budget / 2;
+    let mut file = std::fs::File::open(path)?;
+    let mut head = vec![0; half as usize];
+    file.read_exact(&mut head)?;
+    let mut tail = Vec::with_capacity(half as usize);
+    file.seek(SeekFrom::Start(len.saturating_sub(half)))?;
+    file.read_to_end(&mut tail)?;
+
+    // Whole lines only, so no line or multi-byte character is split
+    let head = match head.iter().rposition(|&b| b == b'\n') {
+        Some(i) => &head[..=i],
+        None => &head[..],
+    };
+    let tail = match tail.iter().position(|&b| b == b'\n') {
+        Some(i) => &tail[i + 1..],
+        None => &tail[..],
+    };
+    let (Ok(head), Ok(tail)) = (std::str::from_utf8(head), std::str::from_utf8(tail)) else {
+        return Ok(None);
+    };
+    let skipped = len.saturating_sub((head.len() + tail.len()) as u64);
+    Ok(Some(format!("{}[... {} bytes not indexed ...]\n{}", head, skipped, tail)))
+}
+
+/// Copy of `meta.json` taken on open and after every commit, to recover an index whose
+/// `meta.json` was lost or damaged. Best effort: a merge finishing after the copy may
+/// delete segments it lists.
+const META_BACKUP: &str = "meta.json.last-good";
+
+fn backup_meta(dir: &Path) {
+    if let Err(e) = std::fs::copy(dir.join("meta.json"), dir.join(META_BACKUP)) {
+        tracing::warn!("Failed to back up index metadata in {}: {}", dir.display(), e);
+    }
+}
+
+/// Open the index at `dir` and load its segments, as the reader will.
+fn open_index(dir: &Path) -> tantivy::Result<Index> {
+    let index = Index::open_in_dir(dir)?;
+    index
+        .reader_builder()
+        .reload_policy(ReloadPolicy::Manual)
+        .try_into()
+        .map(|_: IndexReader| index)
+}
+
+/// Open the index at `dir`, falling back to the `meta.json` saved after the last good
+/// commit. If that fails too, the damaged `meta.json` is put back and the first error
+/// returned.
+fn open_or_recover(dir: &Path) -> tantivy::Result<Index> {
+    let err = match open_index(dir) {
+        Ok(index) => return Ok(index),
+        Err(e) => e,
+    };
+    let meta = dir.join("meta.json");
+    let backup = dir.join(META_BACKUP);
+    if !backup.exists() {
+        return Err(err);
+    }
+    let damaged = std::fs::read(&meta).ok();
+    let recovered = std::fs::copy(&backup, &meta)
+        .map_err(|e| tantivy::TantivyError::SystemError(e.to_string()))
+        .and_then(|_| open_index(dir));
+    match recovered {
+        Ok(index) => {
+            tracing::warn!(
+                "Recovered index at {} from the metadata of an earlier commit ({})",
+                dir.display(),
+                err
+            );
+            Ok(index)
+        }
+        Err(_) => {
+            let restored = match damaged {
+                Some(bytes) => std::fs::write(&meta, bytes),
+                None => std::fs::remove_file(&meta),
+            };
+            if let Err(e) = restored {
+                tracing::warn!("Could not restore {}: {}", meta.display(), e);
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Modification time of a file in whole seconds, as stored in `modified_at`.
+fn modified_secs(metadata: &std::fs::Metadata) -> Option<u64> {
+    let modified = metadata.modified().ok()?;
+    Some(modified.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// Total size of the files below `dir`.
+fn dir_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Move the unreadable index at `dir` to a sibling `*.corrupt-<unix seconds>` directory.
+fn quarantine(dir: &Path) -> anyhow::Result<PathBuf> {
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".corrupt-{}", secs));
+    let target = dir.with_file_name(name);
+    std::fs::rename(dir, &target)?;
+    Ok(target)
+}
+
+/// Index location used before indexes moved to the per-user data directory.
+pub fn legacy_index_path() -> PathBuf {
+    std::env::temp_dir().join("localfiles_index")
+}
+
+/// Move an index left at the old temp location `legacy` to `target` if `target` has none
+/// yet. Call with `target` locked.
+///
+/// Returns whether an old index was found. One that is not a directory owned by this
+/// user (the temp dir is shared), or that cannot be moved (a different filesystem), is
+/// left in place and a warning is logged.
+pub fn migrate_legacy_index(legacy: &Path, target: &Path) -> bool {
+    let Ok(metadata) = std::fs::symlink_metadata(legacy) else {
+        return false;
+    };
+    if !metadata.is_dir() || !legacy.join("schema_version").exists() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        if Some(metadata.uid()) != owner::current_uid() {
+            tracing::warn!(
+                "Ignoring old index at {}: owned by another user",
+                legacy.display()
+            );
+            return true;
+        }
+    }
+    if target.exists() {
+        tracing::warn!(
+            "Ignoring old index at {}; it can be deleted (now using {})",
+            legacy.display(),
+            target.display()
+        );
+        return true;
+    }
+    let moved = target
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::rename(legacy, target));
+    match moved {
+        Ok(()) => tracing::info!("Moved old index from {} to {}", legacy.display(), target.display()),
+        Err(e) => tracing::warn!(
+            "Could not move old index from {} to {}: {}; it will be rebuilt",
+            legacy.display(),
+            target.display(),
+            e
+        ),
+    }
+    true
+}
+
+impl FileIndex {
+    pub fn builder() -> IndexBuilder {
+        IndexBuilder::default()
+    }
+
+    pub fn new(index_path: Option<PathBuf>) -> anyhow::Result<Self> {
+        Self::with_config(index_path, IndexConfig::default())
+    }
+
+    pub fn with_config(index_path: Option<PathBuf>, config: IndexConfig) -> anyhow::Result<Self> {
+        let index_path = match (config.in_memory, index_path) {
+            (true, _) => None,
+            (false, Some(index_path)) => Some(index_path),
+            (false, None) => Some(paths::default_index_path(&[])?),
+        };
+        // Lock before touching the directory: migration deletes it and recovery moves it
+        let lock = index_path.as_deref().map(InstanceLock::acquire).transpose()?;
+        if let Some(index_path) = index_path.as_deref().filter(|_| config.migrate_legacy) {
+            migrate_legacy_index(&legacy_index_path(), index_path);
+        }
+
+        // Schema version migration: delete stale index if version mismatches, then
+        // re-index the roots from its manifest
+        let mut migration = None;
+        if let Some(index_path) = index_path.as_ref().filter(|p| p.exists()) {
+            let version_file = index_path.join("schema_version");
+            // A missing version file means an old schema
+            let version = std::fs::read_to_string(&version_file)
+                .map_or(0, |v| v.trim().parse::<u32>().unwrap_or(0));
+            if version != SCHEMA_VERSION {
+                std::fs::remove_dir_all(index_path)?;
+                let roots = manifest::load(index_path).unwrap_or_else(|e| {
+                    tracing::warn!("Failed to read the roots of the old index: {}", e);
+                    Vec::new()
+                });
+                tracing::info!(
+                    "Schema version changed from {} to {}, recreating index at {} \
+                     and re-indexing {} roots",
+                    version,
+                    SCHEMA_VERSION,
+                    index_path.display(),
+                    roots.len()
+                );
+                migration = (!roots.is_empty()).then_some(Migration {
+                    from_version: version,
+                    roots,
+                    done: 0,
+                    files_indexed: 0,
+                });
+            }
+        }
+        // A checkpoint next to a deleted index is of no use
+        let interrupted = match index_path.as_deref().filter(|_| migration.is_none()) {
+            Some(index_path) => checkpoint::load(index_path).unwrap_or_else(|e| {
+                tracing::warn!("Failed to read the indexing checkpoint: {}", e);
+                None
+            }),
+            None => None,
+        };
+
+        let mut schema_builder = Schema::builder();
+        let field_path = schema_builder.add_text_field("file_path", STRING | STORED);
+        let field_name = schema_builder.add_text_field("file_name", TEXT | STORED);
+        let field_content = schema_builder.add_text_field("content", TEXT | STORED);
+        // Modification time in Unix seconds, for ordering by recency
+        let field_modified_at =
+            schema_builder.add_u64_field("modified_at", INDEXED | STORED | FAST);
+        let field_extension = schema_builder.add_text_field("extension", TEXT | STORED);
+        let field_directory = schema_builder.add_text_field("directory", TEXT | STORED);
+        let field_title = schema_builder.add_text_field("title", TEXT | STORED);
+        let tag_options = TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(TAG_TOKENIZER)
+                    .set_index_option(IndexRecordOption::Basic),
+            )
+            .set_stored();
+        let field_tag = schema_builder.add_text_field("tag", tag_options);
+        // Dotted key paths of JSON, YAML and TOML files (`key:server.port`)
+        let key_options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(TAG_TOKENIZER)
+                .set_index_option(IndexRecordOption::Basic),
+        );
+        let field_key = schema_builder.add_text_field("key", key_options);
+        let field_alias = schema_builder.add_text_field("alias", TEXT | STORED);
+        let field_subject = schema_builder.add_text_field("subject", TEXT | STORED);
+        let field_from = schema_builder.add_text_field("from", TEXT | STORED);
+        let field_to = schema_builder.add_text_field("to", TEXT | STORED);
+        let field_date = schema_builder.add_text_field("date", STRING | STORED);
+        let field_branch = schema_builder.add_text_field("branch", STRING | STORED);
+        let field_tracked = schema_builder.add_text_field("tracked", STRING | STORED);
+        // Owner uid, for `owner` filters
+        let field_owner = schema_builder.add_text_field("owner", STRING);
+        // MIME type and its `type/*` wildcard, for `mime` filters
+        let field_mime = schema_builder.add_text_field("mime", STRING | STORED);
+        // Detected programming language, for `language:` queries and `file_type` filters
+        let field_language = schema_builder.add_text_field("language", STRING | STORED);
+        // Lowercase name of the file's project, for `project` filters
+        let field_project = schema_builder.add_text_field("project", STRING);
+        // "true" and the reason for lockfiles, vendored and minified files, for
+        // `exclude_generated` and `generated` filters
+        let field_generated = schema_builder.add_text_field("generated", STRING | STORED);
+        let field_author = schema_builder.add_text_field("author", TEXT | STORED);
+        let field_commit = schema_builder.add_text_field("commit", STRING | STORED);
+        let field_commit_time = schema_builder.add_text_field("commit_time", STRING | STORED);
+        let field_revision = schema_builder.add_text_field("revision", STRING | STORED);
+        let field_revision_key = schema_builder.add_text_field("revision_key", STRING);
+        let field_history = schema_builder.add_text_field("history", STRING);
+        // Directory summary documents, for `search_directories`
+        let field_summary = schema_builder.add_text_field("summary", STRING);
+        let field_truncated = schema_builder.add_text_field("truncated", STRING | STORED);
+        // Log chunks: first line number and timestamp range
+        let field_first_line = schema_builder.add_u64_field("first_line", STORED);
+        let field_log_start = schema_builder.add_i64_field("log_start", INDEXED | STORED | FAST);
+        let field_log_end = schema_builder.add_i64_field("log_end", INDEXED | STORED | FAST);
+        // Table chunks: first data row and cells by column (`columns.status:failed`)
+        let field_first_row = schema_builder.add_u64_field("first_row", STORED);
+        let field_columns = schema_builder.add_json_field("columns", TEXT);
+        // Notebooks: `<cell index>:<first line>` of each cell in the indexed text
+        let field_cells = schema_builder.add_text_field("cells", STORED);
+        // Directory components and extension as facets, for completing tool arguments
+        let field_location = schema_builder.add_facet_field("location", FacetOptions::default());
+        let field_file_type = schema_builder.add_facet_field("file_type", FacetOptions::default());
+        let schema = schema_builder.build();
+
+        let index = match &index_path {
+            None => Index::create_in_ram(schema.clone()),
+            Some(index_path) if index_path.exists() => match open_or_recover(index_path) {
+                Ok(idx) => {
+                    backup_meta(index_path);
+                    idx
+                }
+                Err(e) if config.rebuild_corrupt => {
+                    let moved = quarantine(index_path)?;
+                    tracing::warn!(
+                        "Index at {} cannot be opened ({}); moved it to {} and starting a new one",
+                        index_path.display(),
+                        e,
+                        moved.display()
+                    );
+                    std::fs::create_dir_all(index_path)?;
+                    Index::create_in_dir(index_path, schema.clone())?
+                }
+                Err(e) => anyhow::bail!(
+                    "Index at {} cannot be opened: {}. Nothing was changed; set \
+                     LOCALFILES_REBUILD_CORRUPT=1 to move it aside and start a new index",
+                    index_path.display(),
+                    e
+                ),
+            },
+            Some(index_path) => {
+                std::fs::create_dir_all(index_path)?;
+                Index::create_in_dir(index_path, schema.clone())?
+            }
+        };
+
+        index.tokenizers().register(
+            TAG_TOKENIZER,
+            TextAnalyzer::builder(RawTokenizer::default())
+                .filter(LowerCaser)
+                .build(),
+        );
+
+        // Write schema version file
+        if let Some(index_path) = &index_path {
+            std::fs::write(index_path.join("schema_version"), SCHEMA_VERSION.to_string())?;
+        }
+
+        let writer = match config.writer_threads {
+            Some(threads) => index.writer_with_num_threads(threads, config.writer_heap_bytes)?,
+            None => index.writer(config.writer_heap_bytes)?,
+        };
+        if !config.directory_summaries {
+            // Summaries left from a run with them on; dropped by the first commit
+            writer.delete_term(Term::from_field_text(field_summary, "true"));
+        }
+        let reader = index
+            .reader_builder()
+            // Commits become visible only when `commit` publishes a new snapshot
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+
+        let pinned = match index_path.as_deref().map(pins::load).transpose() {
+            Ok(pinned) => pinned.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("Failed to read the pinned files: {}", e);
+                BTreeSet::new()
+            }
+        };
+        let files = FileMeta {
+            pinned: pinned.into(),
+            ..FileMeta::default()
+        };
+        let published = Arc::new(ArcSwap::from_pointee(Snapshot {
+            generation: 0,
+            searcher: reader.searcher(),
+            files: files.clone(),
+        }));
+        let (generations, generations_rx) = watch::channel(0);
+        let handle = SearchHandle {
+            index,
+            reader,
+            published: published.clone(),
+            generations: generations_rx,
+            field_path,
+            field_name,
+            field_content,
+            field_extension,
+            field_directory,
+            field_title,
+            field_alias,
+            field_subject,
+            field_tracked,
+            field_owner,
+            field_mime,
+            field_language,
+            field_project,
+            field_generated,
+            field_revision,
+            field_history,
+            field_summary,
+            field_commit_time,
+            field_modified_at,
+            field_truncated,
+            field_first_line,
+            field_log_start,
+            field_log_end,
+            field_first_row,
+            field_cells,
+            highlight: config.highlight.clone(),
+            max_file_size: config.max_file_size,
+            directory_summaries: config.directory_summaries,
+            ranking: config.ranking.clone(),
+            synonyms: Arc::new(config.synonyms.clone()),
+            roots: None,
+            cache: (config.query_cache > 0).then(|| Arc::new(QueryCache::new(config.query_cache))),
+            usage: config
+                .usage_ranking
+                .then(|| Arc::new(Usage::open(index_path.as_deref()))),
+            pace: Arc::default(),
+        };
+
+        Ok(Self {
+            writer,
+            handle,
+            published,
+            generation: 0,
+            generations,
+            field_path,
+            field_name,
+            field_content,
+            field_modified_at,
+            field_extension,
+            field_directory,
+            field_title,
+            field_tag,
+            field_key,
+            field_alias,
+            field_subject,
+            field_from,
+            field_to,
+            field_date,
+            field_branch,
+            field_tracked,
+            field_owner,
+            field_mime,
+            field_language,
+            field_project,
+            field_generated,
+            field_author,
+            field_commit,
+            field_commit_time,
+            field_revision,
+            field_revision_key,
+            field_history,
+            field_summary,
+            field_truncated,
+            field_first_line,
+            field_log_start,
+            field_log_end,
+            field_first_row,
+            field_columns,
+            field_cells,
+            field_location,
+            field_file_type,
+            files,
+            watched_roots: Vec::new(),
+            unwatched_roots: HashSet::new(),
+            missing_roots: Vec::new(),
+            retries: RetryQueue::default(),
+            stale_directories: HashSet::new(),
+            project_dirs: HashMap::new(),
+            root_policies: HashMap::new(),
+            root_skips: HashMap::new(),
+            last_skip: None,
+            roots_changed: false,
+            pins_changed: false,
+            crawl: None,
+            crawl_changed: false,
+            interrupted,
+            migration,
+            index_path,
+            _lock: lock,
+            repos: Vec::new(),
+            non_repo_dirs: HashSet::new(),
+            history_revisions: Vec::new(),
+            indexed_revisions: HashMap::new(),
+            ocr: None,
+            users: Users::load(),
+            config,
+            pending_docs: 0,
+            dirty: false,
+            identities: HashMap::new(),
+            log_tails: HashMap::new(),
+            last_commit_at: Instant::now(),
+            last_indexed: None,
+            last_commit: None,
+        })
+    }
+
+    pub fn config(&self) -> &IndexConfig {
+        &self.config
+    }
+
+    /// Run images and scanned PDFs through external OCR commands.
+    pub fn set_ocr(&mut self, ocr: OcrConfig) {
+        self.ocr = Some(ocr);
+    }
+
+    /// Also index repositories at these revisions (`tags`, `v1.0`, `main~50`, ...) when
+    /// they are added, for `search_history`.
+    pub fn set_history_revisions(&mut self, revisions: Vec<String>) {
+        self.history_revisions = revisions;
+    }
+
+    /// Whether images are indexed with the text an OCR command recognizes in them.
+    pub fn has_ocr(&self) -> bool {
+        self.ocr.is_some()
+    }
+
+    /// Whether repositories are also indexed at past revisions.
+    pub fn has_history(&self) -> bool {
+        !self.history_revisions.is_empty()
+    }
+
+    /// Use `policy` instead of the configured one for `root` and everything below it.
+    /// Takes effect on the next `index_directory(root)`, which also removes files the new
+    /// policy excludes.
+    pub fn set_hidden_policy(&mut self, root: &Path, policy: HiddenPolicy) {
+        self.root_policies.insert(root.to_path_buf(), policy);
+        self.roots_changed = true;
+    }
+
+    /// Roots as saved in the manifest: the current ones, then those still waiting to be
+    /// re-indexed after a schema change.
+    pub fn roots(&self) -> Vec<RootEntry> {
+        let mut roots: Vec<RootEntry> = self
+            .watched_roots
+            .iter()
+            .map(|root| RootEntry {
+                path: root.clone(),
+                watched: self.is_watched(root),
+                policy: self.root_policies.get(root).cloned(),
+            })
+            .collect();
+        if let Some(migration) = &self.migration {
+            for entry in &migration.roots[migration.done.min(migration.roots.len())..] {
+                if self.covering_root(&entry.path).is_none() {
+                    roots.push(entry.clone());
+                }
+            }
+        }
+        roots
+    }
+
+    /// The re-indexing of the previous roots after a schema change, if there was one.
+    pub fn migration(&self) -> Option<&Migration> {
+        self.migration.as_ref()
+    }
+
+    /// The walk the previous process was killed in, until the root is indexed again.
+    pub fn interrupted_crawl(&self) -> Option<&Checkpoint> {
+        self.interrupted.as_ref()
+    }
+
+    /// Record that the next root of the migration was re-indexed with `files` files.
+    pub fn migrated_root_done(&mut self, files: u64) {
+        if let Some(migration) = &mut self.migration {
+            migration.done += 1;
+            migration.files_indexed += files;
+        }
+    }
+
+    /// Record whether the root `root` has a file watcher, for `status`.
+    pub fn set_watched(&mut self, root: &Path, watched: bool) {
+        let changed = if watched {
+            self.unwatched_roots.remove(root)
+        } else {
+            self.unwatched_roots.insert(root.to_path_buf())
+        };
+        self.roots_changed |= changed;
+    }
+
+    /// Whether the root `root` has a file watcher; roots are watched unless indexed with
+    /// `set_watched(root, false)`.
+    pub fn is_watched(&self, root: &Path) -> bool {
+        !self.unwatched_roots.contains(root)
+    }
+
+    /// The policy for the watched root `root`.
+    pub fn hidden_policy(&self, root: &Path) -> &HiddenPolicy {
+        self.root_policies.get(root).unwrap_or(&self.config.hidden)
+    }
+
+    /// Whether a file at `path` would be indexed: below a watched root, not excluded by
+    /// its policy, outside `.git` and of an indexed type. Checked before `write_file`.
+    pub fn accepts(&self, path: &Path) -> bool {
+        self.covering_root(path).is_some()
+            && !git::is_git_internal(path)
+            && !self.excluded(path)
+            && self.wants(path)
+    }
+
+    /// Whether the file at `path` is indexed under that path. Checked before `delete_file`.
+    pub fn is_indexed(&self, path: &Path) -> bool {
+        self.files.indexed_paths.contains(path)
+    }
+
+    /// Pin the indexed file `path` (or the file a link at `path` is indexed as), so its
+    /// matches rank above others from the next commit. Returns the pinned path.
+    pub fn pin(&mut self, path: &Path) -> anyhow::Result<PathBuf> {
+        let path = match self.files.aliases.get(path) {
+            Some(primary) => primary.clone(),
+            None if self.is_indexed(path) => path.to_path_buf(),
+            None => anyhow::bail!("File is not in the index: {}", path.display()),
+        };
+        self.pins_changed |= self.files.pinned.insert(path.clone());
+        Ok(path)
+    }
+
+    /// Unpin `path`, or the file a link at `path` is indexed as. Returns whether it was
+    /// pinned.
+    pub fn unpin(&mut self, path: &Path) -> bool {
+        let path = self.files.aliases.get(path).cloned().unwrap_or_else(|| path.to_path_buf());
+        let removed = self.files.pinned.remove(&path);
+        self.pins_changed |= removed;
+        removed
+    }
+
+    /// Pinned files, sorted, including ones no longer indexed.
+    pub fn pinned(&self) -> impl Iterator<Item = &Path> {
+        self.files.pinned.iter().map(PathBuf::as_path)
+    }
+
+    /// Whether `path` lies below a watched root whose policy excludes it.
+    fn excluded(&self, path: &Path) -> bool {
+        let path = Self::on_disk(path);
+        let Some(root) = self.covering_root(&path) else {
+            return false;
+        };
+        path.strip_prefix(root)
+            .is_ok_and(|relative| self.hidden_policy(root).excludes(relative))
+    }
+
+    pub fn index_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        if git::is_git_internal(path) {
+            // A commit, checkout or staging change: reload the repository on next use, and
+            // re-index its files on their next event even if their content is unchanged
+            let (stale, repos): (Vec<_>, Vec<_>) = std::mem::take(&mut self.repos)
+                .into_iter()
+                .partition(|repo| path.starts_with(repo.root()));
+            self.repos = repos;
+            for repo in stale {
+                self.files.content_hashes.retain(|p, _| !p.starts_with(repo.root()));
+            }
+            return Ok(());
+        }
+        if self.excluded(path) {
+            return Ok(());
+        }
+        if archive::is_archive(path) {
+            return self.index_archive(path);
+        }
+        if email::is_mbox(path) {
+            return self.index_mbox(path);
+        }
+        if spreadsheet::is_spreadsheet(path) {
+            return self.index_spreadsheet(path);
+        }
+        if self.ocr.as_ref().is_some_and(|o| o.handles(path)) {
+            return self.index_ocr(path);
+        }
+        if self.config.sqlite_rows.is_some() && sqlite::is_database(path) {
+            return self.index_database(path);
+        }
+        if !self.wants(path) {
+            return self.skip(path, SkipReason::Unsupported);
+        }
+
+        let metadata = std::fs::metadata(path)?;
+        let truncated = metadata.len() > self.config.max_file_size;
+        if truncated && self.config.large_files == LargeFiles::Skip {
+            return self.skip(path, SkipReason::TooLarge);
+        }
+
+        // The same file reached through a symlink or hard link: index it once
+        if let Some(id) = FileId::of(path, &metadata) {
+            match self.identities.get(&id) {
+                Some(primary) if primary != path && self.files.indexed_paths.contains(primary) => {
+                    let primary = primary.clone();
+                    if self.files.aliases.insert(path.to_path_buf(), primary.clone()).is_none() {
+                        self.dirty = true;
+                    }
+                    return self.index_file(&primary);
+                }
+                _ => {
+                    // No longer a link to another indexed file (e.g. a retargeted symlink)
+                    if self.files.aliases.remove(path).is_some() {
+                        self.dirty = true;
+                    }
+                    self.identities.insert(id, path.to_path_buf());
+                }
+            }
+        }
+
+        // A log that only grew: index the appended lines
+        if logs::is_log(path) && !truncated && self.append_log_tail(path, &metadata)? {
+            return Ok(());
+        }
+
+        if !metadata.is_file() {
+            return Ok(());
+        }
+        let content = if truncated {
+            read_head_and_tail(path, metadata.len(), self.config.max_file_size)?
+        } else {
+            match std::fs::read_to_string(path) {
+                Ok(content) => Some(content),
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => None,
+                // Locked, or being replaced: worth another attempt
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let Some(content) = content else {
+            return self.skip(path, SkipReason::Binary);
+        };
+        let hash = blake3::hash(content.as_bytes());
+        // A chown or chmod changes the indexed owner even if the content is the same
+        let owner = Owner::of(&metadata, &self.users);
+        if self.files.owners.get(path) == owner.as_ref() && self.is_unchanged(path, &hash) {
+            return Ok(());
+        }
+
+        // Upsert: remove existing then add
+        self.delete_documents(path)?;
+        if logs::is_log(path) {
+            let chunks = logs::chunks(&content, logs::CHUNK_LINES);
+            // Follow the log from its last complete line
+            if !truncated && (content.is_empty() || content.ends_with('\n')) {
+                let last_time = chunks.iter().rev().find_map(|c| c.end);
+                let tail = LogTail::new(content.as_bytes(), content.lines().count(), last_time);
+                self.log_tails.insert(path.to_path_buf(), tail);
+            }
+            self.add_log_chunks(path, chunks, &metadata, truncated)?;
+        } else if tabular::is_tabular(path) && !truncated {
+            self.add_table_chunks(path, &content, &metadata)?;
+        } else {
+            self.add_document(path, content, &metadata, truncated)?;
+        }
+        self.files.content_hashes.insert(path.to_path_buf(), hash);
+        Ok(())
+    }
+
+    /// Index the lines appended to a followed log since it was last indexed. Returns
+    /// false if the log is not followed or was rewritten rather than appended to, so it
+    /// has to be indexed in full.
+    fn append_log_tail(
+        &mut self,
+        path: &Path,
+        metadata: &std::fs::Metadata,
+    ) -> anyhow::Result<bool> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let Some(tail) = self.log_tails.get(path) else {
+            return Ok(false);
+        };
+        if metadata.len() < tail.offset {
+            return Ok(false);
+        }
+        let mut file = std::fs::File::open(path)?;
+        let fingerprint_start = tail.offset - tail.fingerprint.len() as u64;
+        file.seek(SeekFrom::Start(fingerprint_start))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        if !bytes.starts_with(&tail.fingerprint) {
+            return Ok(false);
+        }
+        // Only complete lines; a partly written last line is indexed once it is finished
+        let appended = &bytes[tail.fingerprint.len()..];
+        let Some(end) = appended.iter().rposition(|&b| b == b'\n') else {
+            return Ok(true);
+        };
+        let Ok(text) = std::str::from_utf8(&appended[..=end]) else {
+            return Ok(false);
+        };
+
+        let chunks = logs::chunks_from(text, logs::CHUNK_LINES, tail.lines + 1, tail.last_time);
+        let last_time = chunks.iter().rev().find_map(|c| c.end).or(tail.last_time);
+        let indexed = &bytes[..tail.fingerprint.len() + end + 1];
+        let mut next = LogTail::new(indexed, tail.lines + text.lines().count(), last_time);
+        next.offset += fingerprint_start;
+        self.log_tails.insert(path.to_path_buf(), next);
+        self.files.content_hashes.remove(path);
+        tracing::trace!("Indexing {} appended bytes of {}", end + 1, path.display());
+        self.add_log_chunks(path, chunks, metadata, false)?;
+        Ok(true)
+    }
+
+    /// Index a table's data rows in chunks, one document each, with cells by column.
+    fn add_table_chunks(
+        &mut self,
+        path: &Path,
+        content: &str,
+        metadata: &std::fs::Metadata,
+    ) -> anyhow::Result<()> {
+        let table = tabular::parse(content, tabular::delimiter(path).unwrap_or(','));
+        let cells: Vec<Vec<(&String, &String)>> = table
+            .rows
+            .iter()
+            .map(|row| table.columns.iter().zip(row).filter(|(_, cell)| !cell.is_empty()).collect())
+            .collect();
+        // One `column: cell | ...` line per row
+        let lines: Vec<String> = cells
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|(column, cell)| format!("{}: {}", column, cell))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+                    .replace(['\r', '\n'], " ")
+            })
+            .collect();
+        let objects: Vec<BTreeMap<String, OwnedValue>> = cells
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|(column, cell)| (column.clone(), OwnedValue::Str(cell.clone())))
+                    .collect()
+            })
+            .collect();
+        self.add_row_chunks(path, path, metadata, &lines, &objects)
+    }
+
+    /// Index the rows of a table or sheet in chunks of `ROWS_PER_CHUNK` lines, one
+    /// document each, so the matching line gives the row. `cells` holds each row's cells by
+    /// column, or is empty. Name, extension and directory are those of `source`.
+    fn add_row_chunks(
+        &mut self,
+        path: &Path,
+        source: &Path,
+        metadata: &std::fs::Metadata,
+        lines: &[String],
+        cells: &[BTreeMap<String, OwnedValue>],
+    ) -> anyhow::Result<()> {
+        let modified = metadata
+            .modified()
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let file_name = source.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let extension = source
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let directory = source
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+        for (i, rows) in lines.chunks(tabular::ROWS_PER_CHUNK).enumerate() {
+            if rows.iter().all(|row| row.is_empty()) {
+                continue;
+            }
+            let mut document = doc!(
+                self.field_path => path.to_string_lossy().to_string(),
+                self.field_name => file_name.clone(),
+                self.field_content => rows.join("\n"),
+                self.field_modified_at => modified.as_secs(),
+                self.field_extension => extension.clone(),
+                self.field_directory => directory.clone(),
+                self.field_first_row => (i * tabular::ROWS_PER_CHUNK + 1) as u64,
+            );
+            let first = i * tabular::ROWS_PER_CHUNK;
+            for row in cells.iter().skip(first).take(rows.len()) {
+                document.add_object(self.field_columns, row.clone());
+            }
+            self.add_owner(&mut document, metadata);
+            self.add_facets(&mut document, source, &extension);
+            self.add_project(&mut document, path);
+            self.add_mime(&mut document, mime::detect(source, ""));
+            self.writer.add_document(document)?;
+            self.pending_docs += 1;
+        }
+        self.dirty = true;
+        self.mark_indexed(path, metadata);
+        self.last_indexed = Some(SystemTime::now());
+        Ok(())
+    }
+
+    /// Index a log as chunks of lines, one document each, with their time ranges.
+    fn add_log_chunks(
+        &mut self,
+        path: &Path,
+        chunks: Vec<logs::Chunk>,
+        metadata: &std::fs::Metadata,
+        truncated: bool,
+    ) -> anyhow::Result<()> {
+        let modified = metadata
+            .modified()
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let directory = path
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+        for chunk in chunks {
+            let mut document = doc!(
+                self.field_path => path.to_string_lossy().to_string(),
+                self.field_name => file_name.clone(),
+                self.field_content => chunk.text,
+                self.field_modified_at => modified.as_secs(),
+                self.field_extension => "log",
+                self.field_directory => directory.clone(),
+                self.field_first_line => chunk.first_line as u64,
+            );
+            if let Some(start) = chunk.start {
+                document.add_i64(self.field_log_start, start);
+            }
+            if let Some(end) = chunk.end {
+                document.add_i64(self.field_log_end, end);
+            }
+            if truncated {
+                document.add_text(self.field_truncated, "true");
+            }
+            self.add_owner(&mut document, metadata);
+            self.add_facets(&mut document, path, "log");
+            self.add_project(&mut document, path);
+            self.add_mime(&mut document, "text/plain");
+            self.writer.add_document(document)?;
+            self.pending_docs += 1;
+        }
+        self.dirty = true;
+        self.mark_indexed(path, metadata);
+        self.last_indexed = Some(SystemTime::now());
+        Ok(())
+    }
+
+    /// Record `path` as indexed, owned by the owner of the file `metadata` describes.
+    fn mark_indexed(&mut self, path: &Path, metadata: &std::fs::Metadata) {
+        self.files.indexed_paths.insert(path.to_path_buf());
+        self.touch_directory(path);
+        self.files.indexed_at.insert(path.to_path_buf(), SystemTime::now());
+        if let Ok(modified) = metadata.modified() {
+            self.files.modified.insert(path.to_path_buf(), modified);
+        }
+        match Owner::of(metadata, &self.users) {
+            Some(owner) => self.files.owners.insert(path.to_path_buf(), owner),
+            None => self.files.owners.remove(path),
+        };
+    }
+
+    /// Have the next commit rewrite the summary of the directory holding `path`.
+    fn touch_directory(&mut self, path: &Path) {
+        if self.config.directory_summaries {
+            if let Some(dir) = Self::on_disk(path).parent() {
+                self.stale_directories.insert(dir.to_path_buf());
+            }
+        }
+    }
+
+    /// Rewrite the summary documents of stale directories from their indexed files.
+    fn write_directory_summaries(&mut self) -> anyhow::Result<()> {
+        let stale = std::mem::take(&mut self.stale_directories);
+        let mut files: HashMap<&Path, Vec<PathBuf>> = HashMap::new();
+        for path in &self.files.indexed_paths {
+            let file = Self::on_disk(path);
+            if let Some(dir) = file.parent().and_then(|dir| stale.get(dir)) {
+                files.entry(dir.as_path()).or_default().push(file);
+            }
+        }
+        for dir in &stale {
+            let dir_str = dir.to_string_lossy().to_string();
+            self.writer
+                .delete_term(Term::from_field_text(self.field_path, &dir_str));
+            let Some(paths) = files.get_mut(dir.as_path()) else {
+                continue;
+            };
+            paths.sort();
+            paths.dedup();
+            let mut readme = None;
+            let entries: Vec<(String, Option<&'static str>)> = paths
+                .iter()
+                .map(|path| {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                    if readme.is_none() && summary::is_readme(&name) {
+                        readme = summary::readme_head(path);
+                    }
+                    let extension = path
+                        .extension()
+                        .map(|e| e.to_string_lossy().to_lowercase())
+                        .or_else(|| self.files.script_extensions.get(path).cloned())
+                        .unwrap_or_default();
+                    (name, language::detect(path, &extension, ""))
+                })
+                .collect();
+            let name = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let mut document = doc!(
+                self.field_path => dir_str.clone(),
+                self.field_name => name,
+                self.field_content => summary::summary_text(dir, &entries, readme.as_deref()),
+                self.field_directory => dir_str,
+                self.field_summary => "true",
+            );
+            if let Some(language) = summary::dominant_language(&entries) {
+                document.add_text(self.field_language, language);
+            }
+            self.writer.add_document(document)?;
+            self.pending_docs += 1;
+        }
+        Ok(())
+    }
+
+    /// Tag `document`, indexed under `path`, with the project of its nearest enclosing
+    /// project root.
+    fn add_project(&mut self, document: &mut TantivyDocument, path: &Path) {
+        let file = Self::on_disk(path);
+        // A marker added or edited since its directory was looked at
+        if file.file_name().is_some_and(|name| project::is_marker(&name.to_string_lossy())) {
+            if let Some(dir) = file.parent() {
+                self.project_dirs.remove(dir);
+            }
+        }
+        let found = file.ancestors().skip(1).find_map(|dir| {
+            self.project_dirs
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| project::detect(dir))
+                .clone()
+        });
+        match found {
+            Some(project) => {
+                document.add_text(self.field_project, project.name.to_lowercase());
+                self.files.file_projects.insert(path.to_path_buf(), project.root.clone());
+                self.files.projects.insert(project.root.clone(), project);
+            }
+            None => {
+                self.files.file_projects.remove(path);
+            }
+        }
+    }
+
+    /// Index the owner uid of the file `metadata` describes, for `owner` filters.
+    fn add_owner(&self, document: &mut TantivyDocument, metadata: &std::fs::Metadata) {
+        if let Some(owner) = Owner::of(metadata, &self.users) {
+            document.add_text(self.field_owner, owner.uid.to_string());
+        }
+    }
+
+    /// Index the directory and extension of `path` as facets, for completing `path_prefix`
+    /// and `file_type` arguments.
+    fn add_facets(&self, document: &mut TantivyDocument, path: &Path, extension: &str) {
+        if let Some(directory) = path.parent().map(directory_facet) {
+            if !directory.is_root() {
+                document.add_facet(self.field_location, directory);
+            }
+        }
+        if !extension.is_empty() {
+            document.add_facet(self.field_file_type, Facet::from_path([extension]));
+        }
+    }
+
+    /// Index `mime` and its `type/*` wildcard, for `mime` filters.
+    fn add_mime(&self, document: &mut TantivyDocument, mime: &str) {
+        document.add_text(self.field_mime, mime);
+        document.add_text(self.field_mime, mime::wildcard(mime));
+    }
+
+    /// Whether `path` is indexed with content hashing to `hash`.
+    fn is_unchanged(&self, path: &Path, hash: &blake3::Hash) -> bool {
+        let unchanged = self.files.content_hashes.get(path) == Some(hash);
+        if unchanged {
+            tracing::trace!("Content unchanged, skipping {}", path.display());
+        }
+        unchanged
+    }
+
+    /// Index the text members of a `.zip`/`.tar.gz` archive under virtual paths
+    /// such as `archive.zip!/src/main.rs`.
+    fn index_archive(&mut self, path: &Path) -> anyhow::Result<()> {
+        let metadata = std::fs::metadata(path)?;
+        let members = archive::read_members(path, self.config.max_file_size, |p| self.wants(p))?;
+
+        // Upsert: drop every member from the previous version of the archive
+        self.delete_documents(path)?;
+        for member in members {
+            let virtual_path = archive::member_path(path, &member.name);
+            self.add_document(&virtual_path, member.content, &metadata, false)?;
+        }
+        Ok(())
+    }
+
+    /// Index each sheet of a spreadsheet under a virtual path such as `budget.xlsx!/Q3`.
+    fn index_spreadsheet(&mut self, path: &Path) -> anyhow::Result<()> {
+        let metadata = std::fs::metadata(path)?;
+        if metadata.len() > self.config.max_file_size {
+            return self.skip(path, SkipReason::TooLarge);
+        }
+        let hash = blake3::hash(&std::fs::read(path)?);
+        if self.is_unchanged(path, &hash) {
+            return Ok(());
+        }
+        let sheets = spreadsheet::read_sheets(path)?;
+
+        // Upsert: drop every sheet of the previous version of the workbook
+        self.delete_documents(path)?;
+        for sheet in sheets {
+            let virtual_path = archive::member_path(path, &sheet.name);
+            self.add_row_chunks(&virtual_path, path, &metadata, &sheet.rows, &[])?;
+        }
+        self.files.content_hashes.insert(path.to_path_buf(), hash);
+        Ok(())
+    }
+
+    /// Index the text recognized by the configured OCR command.
+    fn index_ocr(&mut self, path: &Path) -> anyhow::Result<()> {
+        let metadata = std::fs::metadata(path)?;
+        if metadata.len() > self.config.max_file_size {
+            return self.skip(path, SkipReason::TooLarge);
+        }
+        // Hash the image itself: OCR is the expensive part
+        let hash = blake3::hash(&std::fs::read(path)?);
+        if self.is_unchanged(path, &hash) {
+            return Ok(());
+        }
+        let Some(ocr) = &self.ocr else {
+            return Ok(());
+        };
+        let text = ocr.extract(path)?;
+
+        self.delete_documents(path)?;
+        self.add_document(path, text, &metadata, false)?;
+        self.files.content_hashes.insert(path.to_path_buf(), hash);
+        Ok(())
+    }
+
+    /// Index the tables, columns and (if configured) text rows of a SQLite database.
+    fn index_database(&mut self, path: &Path) -> anyhow::Result<()> {
+        let metadata = std::fs::metadata(path)?;
+        if metadata.len() > self.config.max_file_size {
+            return self.skip(path, SkipReason::TooLarge);
+        }
+        if !sqlite::has_header(path) {
+            return self.skip(path, SkipReason::Unsupported);
+        }
+        let hash = blake3::hash(&std::fs::read(path)?);
+        if self.is_unchanged(path, &hash) {
+            return Ok(());
+        }
+        let text = sqlite::extract(path, self.config.sqlite_rows.unwrap_or(0))?;
+
+        self.delete_documents(path)?;
+        self.add_document(path, text, &metadata, false)?;
+        self.files.content_hashes.insert(path.to_path_buf(), hash);
+        Ok(())
+    }
+
+    /// Index each message of an mbox file under a virtual path such as `inbox.mbox!/3`.
+    fn index_mbox(&mut self, path: &Path) -> anyhow::Result<()> {
+        let metadata = std::fs::metadata(path)?;
+        if metadata.len() > self.config.max_file_size {
+            return self.skip(path, SkipReason::TooLarge);
+        }
+        let content = match std::fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(_) => return Ok(()),
+        };
+        let hash = blake3::hash(content.as_bytes());
+        if self.is_unchanged(path, &hash) {
+            return Ok(());
+        }
+
+        self.delete_documents(path)?;
+        for (i, message) in email::split_mbox(&content).into_iter().enumerate() {
+            let virtual_path = archive::member_path(path, &(i + 1).to_string());
+            self.add_document(&virtual_path, message, &metadata, false)?;
+        }
+        self.files.content_hashes.insert(path.to_path_buf(), hash);
+        Ok(())
+    }
+
+    fn add_document(
+        &mut self,
+        path: &Path,
+        content: String,
+        metadata: &std::fs::Metadata,
+        truncated: bool,
+    ) -> anyhow::Result<()> {
+        let Some((content, cells)) = Self::extract_text(path, content) else {
+            return Ok(()); // skip files whose format could not be parsed
+        };
+        let modified = metadata
+            .modified()
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut file_name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let file_path_str = path.to_string_lossy().to_string();
+        let mut extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        // Extensionless scripts are filed under their interpreter's usual extension
+        if extension.is_empty() {
+            if let Some(script) = mime::script_extension(&content) {
+                self.files.script_extensions.insert(path.to_path_buf(), script.clone());
+                extension = script;
+            }
+        }
+        let directory = path
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let in_mbox =
+            archive::split_virtual(&file_path_str).is_some_and(|(a, _)| email::is_mbox(&a));
+        let mime = if in_mbox { "message/rfc822" } else { mime::detect(path, &content) };
+        let language = language::detect(path, &extension, &content);
+        let generated = generated::detect(path, &content);
+        let (content, message) = if in_mbox || email::is_eml(path) {
+            let mut message = email::parse(&content);
+            if !message.subject.is_empty() {
+                file_name = message.subject.clone();
+            }
+            (std::mem::take(&mut message.body), Some(message))
+        } else {
+            (content, None)
+        };
+
+        let front_matter = if markdown::is_markdown(&extension) {
+            let links = markdown::links(&content);
+            if !links.is_empty() {
+                self.files.file_links.insert(path.to_path_buf(), links);
+            }
+            markdown::front_matter(&content)
+        } else {
+            None
+        };
+
+        let mut key_paths: Vec<String> = if keys::is_structured(&extension) && !truncated {
+            keys::key_paths(&content, &extension).into_iter().map(|(path, _)| path).collect()
+        } else {
+            Vec::new()
+        };
+        key_paths.sort();
+        key_paths.dedup();
+
+        let mut document = doc!(
+            self.field_path => file_path_str,
+            self.field_name => file_name,
+            self.field_content => content,
+            self.field_modified_at => modified.as_secs(),
+            self.field_extension => extension.clone(),
+            self.field_directory => directory,
+        );
+        self.add_facets(&mut document, path, &extension);
+        self.add_project(&mut document, path);
+        if let Some(language) = language {
+            document.add_text(self.field_language, language);
+        }
+        if let Some(reason) = generated {
+            document.add_text(self.field_generated, "true");
+            document.add_text(self.field_generated, reason);
+        }
+        if let Some(fm) = front_matter {
+            if let Some(title) = &fm.title {
+                document.add_text(self.field_title, title);
+            }
+            for tag in &fm.tags {
+                document.add_text(self.field_tag, tag);
+            }
+            for alias in &fm.aliases {
+                document.add_text(self.field_alias, alias);
+            }
+            if !fm.tags.is_empty() {
+                self.files.file_tags.insert(path.to_path_buf(), fm.tags);
+            }
+        }
+        for key in &key_paths {
+            document.add_text(self.field_key, key);
+        }
+        if truncated {
+            document.add_text(self.field_truncated, "true");
+        }
+        for cell in &cells {
+            document.add_text(self.field_cells, cell);
+        }
+        if let Some(message) = message {
+            document.add_text(self.field_subject, &message.subject);
+            document.add_text(self.field_from, &message.from);
+            document.add_text(self.field_to, &message.to);
+            document.add_text(self.field_date, &message.date);
+        }
+        if let Some(info) = self.git_info(&Self::on_disk(path)) {
+            if let Some(branch) = &info.branch {
+                document.add_text(self.field_branch, branch);
+            }
+            document.add_text(self.field_tracked, if info.tracked { "true" } else { "false" });
+            if let Some(commit) = &info.last_commit {
+                document.add_text(self.field_author, &commit.author);
+                document.add_text(self.field_commit, &commit.hash);
+                document.add_text(self.field_commit_time, format!("{}s", commit.time));
+            }
+        }
+        self.add_owner(&mut document, metadata);
+        self.add_mime(&mut document, mime);
+        self.writer.add_document(document)?;
+        self.pending_docs += 1;
+        self.dirty = true;
+        self.mark_indexed(path, metadata);
+        self.last_indexed = Some(SystemTime::now());
+        Ok(())
+    }
+
+    /// Git metadata for `path` if it is inside a work tree, loading the repository once.
+    fn git_info(&mut self, path: &Path) -> Option<git::FileInfo> {
+        let dir = path.parent()?;
+        self.repo_for(dir).map(|repo| repo.file_info(path))
+    }
+
+    /// The repository whose work tree contains `dir`, loading it on first use.
+    fn repo_for(&mut self, dir: &Path) -> Option<&git::Repo> {
+        if let Some(i) = self.repos.iter().position(|r| dir.starts_with(r.root())) {
+            return Some(&self.repos[i]);
+        }
+        if self.non_repo_dirs.contains(dir) {
+            return None;
+        }
+        match git::Repo::discover(dir) {
+            Some(repo) => {
+                self.repos.push(repo);
+                self.repos.last()
+            }
+            None => {
+                self.non_repo_dirs.insert(dir.to_path_buf());
+                None
+            }
+        }
+    }
+
+    /// Index the configured past revisions of the repository at `root`, replacing any
+    /// previously indexed revision whose commit has moved.
+    fn index_history(&mut self, root: &Path) -> anyhow::Result<()> {
+        for spec in git::expand_revisions(root, &self.history_revisions)? {
+            let key = format!("{}@{}", root.display(), spec);
+            let revision = match git::read_revision(root, &spec, self.config.max_file_size, |p| self.wants(p)) {
+                Ok(revision) => revision,
+                Err(e) => {
+                    tracing::warn!("Skipping revision {} of {}: {}", spec, root.display(), e);
+                    continue;
+                }
+            };
+            if self.indexed_revisions.get(&key) == Some(&revision.commit.hash) {
+                continue;
+            }
+            self.writer
+                .delete_term(Term::from_field_text(self.field_revision_key, &key));
+            self.dirty = true;
+            for file in revision.files {
+                let path = root.join(&file.path);
+                let Some((content, cells)) = Self::extract_text(&path, file.content) else {
+                    continue;
+                };
+                let extension = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or("")
+                    .to_lowercase();
+                let mut document = doc!(
+                    // Distinct from the working copy's path, which upserts delete by
+                    self.field_path => format!("{}@{}", path.display(), revision.name),
+                    self.field_name => path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                    self.field_content => content,
+                    self.field_extension => extension,
+                    self.field_directory => path.parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default(),
+                    self.field_revision => revision.name.clone(),
+                    self.field_revision_key => key.clone(),
+                    self.field_history => "true",
+                    self.field_author => revision.commit.author.clone(),
+                    self.field_commit => revision.commit.hash.clone(),
+                    self.field_commit_time => format!("{}s", revision.commit.time),
+                );
+                for cell in &cells {
+                    document.add_text(self.field_cells, cell);
+                }
+                self.writer.add_document(document)?;
+                self.pending_docs += 1;
+            }
+            tracing::info!("Indexed {} at {} for history search", root.display(), spec);
+            self.indexed_revisions.insert(key, revision.commit.hash);
+        }
+        Ok(())
+    }
+
+    pub fn remove_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        if self.files.aliases.remove(path).is_some() {
+            self.dirty = true;
+            return Ok(());
+        }
+        self.delete_documents(path)?;
+        self.retries.forget(path);
+        self.identities.retain(|_, p| p != path);
+
+        // Links to the removed path that still exist are indexed under their own path
+        let aliases: Vec<PathBuf> = self
+            .files
+            .aliases
+            .iter()
+            .filter(|(_, primary)| primary.as_path() == path)
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        for alias in aliases {
+            self.files.aliases.remove(&alias);
+            if alias.exists() {
+                self.index_file(&alias)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the documents indexed under `path`, keeping its aliases for a re-add.
+    fn delete_documents(&mut self, path: &Path) -> anyhow::Result<()> {
+        let path_str = path.to_string_lossy().to_string();
+        self.writer
+            .delete_term(Term::from_field_text(self.field_path, &path_str));
+        self.dirty = true;
+        self.files.content_hashes.remove(path);
+        self.log_tails.remove(path);
+        self.files.indexed_paths.remove(path);
+        self.touch_directory(path);
+        self.files.indexed_at.remove(path);
+        self.files.modified.remove(path);
+        self.files.file_tags.remove(path);
+        self.files.file_links.remove(path);
+        self.files.owners.remove(path);
+        self.files.script_extensions.remove(path);
+        self.files.file_projects.remove(path);
+        if path.file_name().is_some_and(|name| project::is_marker(&name.to_string_lossy())) {
+            if let Some(dir) = path.parent() {
+                self.project_dirs.remove(dir);
+            }
+        }
+
+        if archive::is_archive(path) || email::is_mbox(path) || spreadsheet::is_spreadsheet(path) {
+            let prefix = archive::member_prefix(path);
+            let members: Vec<PathBuf> = self
+                .files.indexed_paths
+                .iter()
+                .filter(|p| p.to_string_lossy().starts_with(&prefix))
+                .cloned()
+                .collect();
+            for member in members {
+                self.delete_documents(&member)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn index_directory(&mut self, dir: &Path) -> anyhow::Result<u64> {
+        let mut count = 0u64;
+        let mut skips = SkipStats::default();
+        let policy = self.hidden_policy(dir).clone();
+        let prioritized = self.config.crawl_order == CrawlOrder::Priority;
+        let resumed = match &self.interrupted {
+            Some(interrupted) if interrupted.root.path == dir => self.interrupted.take(),
+            _ => None,
+        };
+        let done = resumed.map(|checkpoint| checkpoint.done).unwrap_or_default();
+        let mut committed = HashMap::new();
+        if !done.is_empty() {
+            tracing::info!(
+                "Resuming the interrupted indexing of {}: {} directories were already indexed",
+                dir.display(),
+                done.len()
+            );
+            committed = self.committed_files(&done)?;
+        }
+        self.crawl = Some(Checkpoint {
+            root: RootEntry {
+                path: dir.to_path_buf(),
+                watched: self.is_watched(dir),
+                policy: self.root_policies.get(dir).cloned(),
+            },
+            done: done.clone(),
+        });
+        self.crawl_changed = true;
+        let mut queued = Vec::new();
+        // Walk order: directories entered and not yet left, by depth
+        let mut open: Vec<PathBuf> = Vec::new();
+        for entry in WalkDir::new(dir)
+            .follow_links(true)
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || policy.allows(e.file_name(), e.file_type().is_dir()))
+        {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    // Counted once per unreadable directory, whatever it holds
+                    let kind = e.io_error().map(|e| e.kind());
+                    if kind == Some(std::io::ErrorKind::PermissionDenied) {
+                        tracing::debug!("Skipping {}: {}", e.path().unwrap_or(dir).display(), e);
+                        skips.add(SkipReason::Permission);
+                    }
+                    continue;
+                }
+            };
+            if !prioritized {
+                // Everything in a directory is indexed once the walk leaves it
+                for left in open.split_off(entry.depth().min(open.len())) {
+                    self.crawled(left);
+                }
+                if entry.file_type().is_dir() {
+                    open.push(entry.path().to_path_buf());
+                }
+            }
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            // Files of completed directories are kept as indexed unless they changed since
+            if let Some((modified, paths)) = committed.remove(entry.path()) {
+                let metadata = entry.metadata().ok();
+                if let Some(metadata) = metadata.filter(|m| modified_secs(m) == Some(modified)) {
+                    for path in paths {
+                        self.mark_indexed(&path, &metadata);
+                    }
+                    continue;
+                }
+                // Drop every document of the old version, archive members included
+                for path in paths {
+                    self.delete_documents(&path)?;
+                }
+            }
+            if prioritized {
+                queued.push(crawl::Queued {
+                    depth: entry.depth(),
+                    modified: entry.metadata().ok().and_then(|m| m.modified().ok()),
+                    path: entry.into_path(),
+                });
+            } else {
+                self.index_walked(entry.path(), &mut count, &mut skips)?;
+            }
+        }
+        for left in open {
+            self.crawled(left);
+        }
+        // Indexed in a completed directory, but deleted (or excluded) since
+        for (_, paths) in committed.into_values() {
+            for path in paths {
+                self.delete_documents(&path)?;
+            }
+        }
+        crawl::prioritize(&mut queued, SystemTime::now());
+        let early_commit = queued.len() > crawl::EARLY_COMMIT_FILES;
+        // Files of each directory still to index, to mark it done after its last one
+        let mut remaining: HashMap<&Path, usize> = HashMap::new();
+        for file in &queued {
+            if let Some(parent) = file.path.parent() {
+                *remaining.entry(parent).or_default() += 1;
+            }
+        }
+        for (i, file) in queued.iter().enumerate() {
+            self.index_walked(&file.path, &mut count, &mut skips)?;
+            // The top of a large tree becomes searchable before its deep directories
+            if early_commit && i + 1 == crawl::EARLY_COMMIT_FILES && self.dirty {
+                self.commit()?;
+            }
+            if let Some(parent) = file.path.parent() {
+                let left = remaining.get_mut(parent).expect("counted above");
+                *left -= 1;
+                if *left == 0 {
+                    self.crawled(parent.to_path_buf());
+                }
+            }
+        }
+        self.crawl = None;
+        self.crawl_changed = true;
+        self.root_skips.retain(|root, _| !root.starts_with(dir));
+        self.root_skips.insert(dir.to_path_buf(), skips);
+        // Files indexed under an earlier, more permissive policy
+        let excluded: Vec<PathBuf> = self
+            .files
+            .indexed_paths
+            .iter()
+            .filter(|p| {
+                Self::on_disk(p)
+                    .strip_prefix(dir)
+                    .is_ok_and(|relative| policy.excludes(relative))
+            })
+            .cloned()
+            .collect();
+        for path in excluded {
+            self.remove_file(&path)?;
+        }
+        if !self.history_revisions.is_empty() {
+            if let Some(root) = self.repo_for(dir).map(|r| r.root().to_path_buf()) {
+                if let Err(e) = self.index_history(&root) {
+                    tracing::warn!("Failed to index history of {}: {}", root.display(), e);
+                }
+            }
+        }
+        if !self.watched_roots.contains(&dir.to_path_buf()) {
+            self.roots_changed = true;
+            // A new root replaces any roots nested inside it
+            self.watched_roots.retain(|r| !r.starts_with(dir));
+            self.root_policies.retain(|r, _| !r.starts_with(dir) || r == dir);
+            self.unwatched_roots.retain(|r| !r.starts_with(dir) || r == dir);
+            self.missing_roots.retain(|r| !r.path.starts_with(dir));
+            self.watched_roots.push(dir.to_path_buf());
+        }
+        Ok(count)
+    }
+
+    /// Committed documents of the files directly in the directories `done`, by file on disk:
+    /// the modification time in seconds they were indexed with and their indexed paths
+    /// (one per member for containers).
+    fn committed_files(
+        &self,
+        done: &BTreeSet<PathBuf>,
+    ) -> anyhow::Result<HashMap<PathBuf, (u64, Vec<PathBuf>)>> {
+        let searcher = self.handle.reader.searcher();
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, Box::new(AllQuery)),
+            (
+                Occur::MustNot,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(self.field_history, "true"),
+                    IndexRecordOption::Basic,
+                )),
+            ),
+            (
+                Occur::MustNot,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(self.field_summary, "true"),
+                    IndexRecordOption::Basic,
+                )),
+            ),
+        ]);
+        let mut files: HashMap<PathBuf, (u64, Vec<PathBuf>)> = HashMap::new();
+        for address in searcher.search(&query, &DocSetCollector)? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let Some(path) = doc.get_first(self.field_path).and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let path = PathBuf::from(path);
+            let file = Self::on_disk(&path);
+            if !file.parent().is_some_and(|parent| done.contains(parent)) {
+                continue;
+            }
+            let modified = doc.get_first(self.field_modified_at).and_then(|v| v.as_u64());
+            let entry = files.entry(file).or_insert((modified.unwrap_or(0), Vec::new()));
+            // Chunked files have several documents under one path
+            if !entry.1.contains(&path) {
+                entry.1.push(path);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Record that the files of the directory `dir` are all indexed, for the checkpoint.
+    fn crawled(&mut self, dir: PathBuf) {
+        if let Some(crawl) = &mut self.crawl {
+            crawl.done.insert(dir);
+            self.crawl_changed = true;
+        }
+    }
+
+    /// Index a file found by walking a root, counting it or why it was skipped.
+    fn index_walked(
+        &mut self,
+        path: &Path,
+        count: &mut u64,
+        skips: &mut SkipStats,
+    ) -> anyhow::Result<()> {
+        match self.index_or_retry(path) {
+            Ok(Some(reason)) => skips.add(reason),
+            Ok(None) => {
+                *count += 1;
+                self.commit_if_due()?;
+            }
+            Err(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Roots that no longer exist, deleted or on a volume that was unmounted.
+    pub fn vanished_roots(&self) -> Vec<PathBuf> {
+        self.watched_roots.iter().filter(|root| !root.exists()).cloned().collect()
+    }
+
+    /// Drop the root `root` and the files indexed under it, keeping it in
+    /// `missing_roots` until it is indexed again. Returns the number of paths removed.
+    pub fn detach_root(&mut self, root: &Path) -> anyhow::Result<usize> {
+        let stale: Vec<PathBuf> = self
+            .files
+            .indexed_paths
+            .iter()
+            .chain(self.files.aliases.keys())
+            .filter(|p| p.starts_with(root))
+            .cloned()
+            .collect();
+        for path in &stale {
+            self.remove_file(path)?;
+        }
+        self.missing_roots.retain(|r| r.path != root);
+        self.missing_roots.push(RootEntry {
+            path: root.to_path_buf(),
+            watched: self.is_watched(root),
+            policy: self.root_policies.remove(root),
+        });
+        self.watched_roots.retain(|r| r != root);
+        self.unwatched_roots.remove(root);
+        self.root_skips.remove(root);
+        self.roots_changed = true;
+        Ok(stale.len())
+    }
+
+    /// Files the last walk of `root` skipped, per reason.
+    pub fn skipped(&self, root: &Path) -> SkipStats {
+        self.root_skips.get(root).copied().unwrap_or_default()
+    }
+
+    /// Roots detached by `detach_root`, as they were indexed, until indexed again.
+    pub fn missing_roots(&self) -> &[RootEntry] {
+        &self.missing_roots
+    }
+
+    /// Delete every document and forget the roots, pins, failed files and read counts,
+    /// then scrub what was stored on disk about them (see `purge`): the index's old
+    /// segments and the files next to it, its OCR cache included. The index stays open,
+    /// empty, for new roots. Returns the paths scrubbed.
+    ///
+    /// The files are scrubbed and the deletion committed before anything is forgotten,
+    /// so a purge failing on disk leaves the in-memory state describing what is still
+    /// stored.
+    pub fn purge(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        let holding = self.index_path.as_deref().map(purge::holding_path);
+        // Segment files are deleted on commit, so keep links to scrub them through
+        if let Some(index_path) = &self.index_path {
+            purge::hold(index_path)?;
+        }
+        // The files holding roots and pins are scrubbed rather than saved empty
+        let sidecars = self.index_path.as_deref().map(purge::sidecar_paths).unwrap_or_default();
+        let mut scrubbed = Vec::new();
+        for path in sidecars.into_iter().filter(|path| Some(path) != holding.as_ref()) {
+            if purge::scrub(&path)? > 0 {
+                scrubbed.push(path);
+            }
+        }
+        self.writer.delete_all_documents()?;
+        self.writer.commit()?;
+
+        self.files = FileMeta::default();
+        self.watched_roots.clear();
+        self.unwatched_roots.clear();
+        self.missing_roots.clear();
+        self.retries = RetryQueue::default();
+        self.stale_directories.clear();
+        self.project_dirs.clear();
+        self.root_policies.clear();
+        self.root_skips.clear();
+        self.migration = None;
+        self.repos.clear();
+        self.non_repo_dirs.clear();
+        self.indexed_revisions.clear();
+        self.identities.clear();
+        self.log_tails.clear();
+        self.last_indexed = None;
+        self.crawl = None;
+        self.interrupted = None;
+        if let Some(usage) = &self.handle.usage {
+            usage.clear();
+        }
+        if let Some(cache) = &self.handle.cache {
+            cache.clear();
+        }
+        self.roots_changed = false;
+        self.pins_changed = false;
+        self.crawl_changed = false;
+        // Publish the empty index to searches
+        self.dirty = true;
+        self.commit()?;
+
+        if let Some(holding) = holding {
+            if purge::scrub(&holding)? > 0 {
+                scrubbed.push(holding);
+            }
+        }
+        Ok(scrubbed)
+    }
+
+    /// The watched root that `path` is equal to or nested inside, if any.
+    pub fn covering_root(&self, path: &Path) -> Option<&Path> {
+        self.watched_roots
+            .iter()
+            .find(|root| path.starts_with(root))
+            .map(PathBuf::as_path)
+    }
+
+    /// Watched roots strictly inside `dir`, which indexing `dir` would absorb.
+    pub fn nested_roots(&self, dir: &Path) -> Vec<PathBuf> {
+        self.watched_roots
+            .iter()
+            .filter(|root| root.starts_with(dir) && root.as_path() != dir)
+            .cloned()
+            .collect()
+    }
+
+    /// Apply a watcher event: re-index created and modified files, drop removed ones.
+    /// Call `commit` after a batch of events to make the changes searchable.
+    pub fn apply(&mut self, event: &FileEvent) -> anyhow::Result<()> {
+        match event {
+            FileEvent::Created(p) | FileEvent::Modified(p) => self.index_or_retry(p).map(|_| ()),
+            FileEvent::Removed(p) => self.remove_file(p),
+        }
+    }
+
+    /// Re-sync a path with what is on disk, without registering it as a watched root.
+    ///
+    /// Used when watcher events were lost: files are re-indexed, directories are walked,
+    /// and indexed paths at or below `path` that no longer exist are removed.
+    pub fn rescan_path(&mut self, path: &Path) -> anyhow::Result<()> {
+        let stale: Vec<PathBuf> = self
+            .files.indexed_paths
+            .iter()
+            .chain(self.files.aliases.keys())
+            .filter(|p| p.starts_with(path) && !Self::on_disk(p).exists())
+            .cloned()
+            .collect();
+        for p in stale {
+            self.remove_file(&p)?;
+        }
+
+        if path.is_dir() {
+            let policy = match self.covering_root(path) {
+                Some(root) => self.hidden_policy(root).clone(),
+                None => self.config.hidden.clone(),
+            };
+            for entry in WalkDir::new(path)
+                .follow_links(true)
+                .into_iter()
+                .filter_entry(|e| e.depth() == 0 || policy.allows(e.file_name(), e.file_type().is_dir()))
+                .filter_map(|e| e.ok())
+            {
+                if entry.file_type().is_file() {
+                    let _ = self.index_or_retry(entry.path());
+                }
+            }
+        } else if path.is_file() {
+            self.index_or_retry(path)?;
+        }
+        Ok(())
+    }
+
+    /// Index `path` like `index_file`, returning why it was left out, if it was. A file
+    /// that cannot be read for lack of permission is skipped rather than failing.
+    pub fn index_or_skip(&mut self, path: &Path) -> anyhow::Result<Option<SkipReason>> {
+        self.last_skip = None;
+        match self.index_file(path) {
+            Ok(()) => Ok(self.last_skip.take()),
+            Err(e) if is_permission_denied(&e) => {
+                tracing::debug!("Skipping {}: {}", path.display(), e);
+                Ok(Some(SkipReason::Permission))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Record that `index_file` left `path` out for `reason`.
+    fn skip(&mut self, path: &Path, reason: SkipReason) -> anyhow::Result<()> {
+        tracing::trace!("Skipping {}: {}", path.display(), reason);
+        self.last_skip = Some(reason);
+        Ok(())
+    }
+
+    /// Index `path` like `index_or_skip`, queueing another attempt with backoff if that
+    /// fails while it exists.
+    fn index_or_retry(&mut self, path: &Path) -> anyhow::Result<Option<SkipReason>> {
+        let result = self.index_or_skip(path);
+        match &result {
+            Err(e) if path.exists() => {
+                tracing::debug!("Failed to index {}, will retry: {}", path.display(), e);
+                self.retries.failed(path, e.to_string(), Instant::now());
+            }
+            _ => self.retries.forget(path),
+        }
+        result
+    }
+
+    /// Files due for another attempt at indexing, for the caller to index again with
+    /// `apply`.
+    pub fn take_due_retries(&mut self) -> Vec<PathBuf> {
+        self.retries.take_due(Instant::now())
+    }
+
+    /// When the next file that failed to index is due for another attempt.
+    pub fn next_retry(&self) -> Option<Instant> {
+        self.retries.next_due()
+    }
+
+    /// Files that failed every attempt to index them.
+    pub fn failed_files(&self) -> Vec<FailedFile> {
+        self.retries.failed_files()
+    }
+
+    /// Cross-check the committed index against the filesystem. Content is compared for
+    /// files whose hash is known, which is every file indexed by this process except
+    /// followed logs and truncated files.
+    pub fn verify(&self) -> anyhow::Result<IntegrityReport> {
+        let mut report = IntegrityReport::default();
+        for path in &self.files.indexed_paths {
+            report.checked += 1;
+            if !Self::on_disk(path).exists() {
+                report.missing.push(path.clone());
+            }
+        }
+        for (path, hash) in &self.files.content_hashes {
+            let Ok(metadata) = std::fs::metadata(path) else {
+                continue;
+            };
+            if metadata.len() > self.config.max_file_size {
+                continue;
+            }
+            // Unreadable files count as changed: re-indexing them drops their documents
+            let same = std::fs::read(path).is_ok_and(|bytes| blake3::hash(&bytes) == *hash);
+            if !same {
+                report.changed.push(path.clone());
+            }
+        }
+
+        let snapshot = self.published.load_full();
+        let searcher = &snapshot.searcher;
+        let mut candidates = HashSet::new();
+        for segment in searcher.segment_readers() {
+            let inverted = segment.inverted_index(self.field_path)?;
+            let mut stream = inverted.terms().stream()?;
+            while stream.advance() {
+                if let Ok(path) = std::str::from_utf8(stream.key()) {
+                    if !self.files.indexed_paths.contains(Path::new(path)) {
+                        candidates.insert(path.to_string());
+                    }
+                }
+            }
+        }
+        // The term dictionary keeps deleted documents' paths until segments merge
+        let history = Term::from_field_text(self.field_history, "true");
+        let summary = Term::from_field_text(self.field_summary, "true");
+        for path in candidates {
+            let query = BooleanQuery::new(vec![
+                (
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(self.field_path, &path),
+                        IndexRecordOption::Basic,
+                    )),
+                ),
+                (
+                    Occur::MustNot,
+                    Box::new(TermQuery::new(history.clone(), IndexRecordOption::Basic)),
+                ),
+                (
+                    Occur::MustNot,
+                    Box::new(TermQuery::new(summary.clone(), IndexRecordOption::Basic)),
+                ),
+            ]);
+            if searcher.search(&query, &tantivy::collector::Count)? > 0 {
+                report.orphaned.push(PathBuf::from(path));
+            }
+        }
+        report.missing.sort();
+        report.changed.sort();
+        report.orphaned.sort();
+        Ok(report)
+    }
+
+    /// Fix what `verify` found: drop missing files, re-index changed ones, and replace
+    /// orphaned documents by a fresh index of their file if it still exists. Returns the
+    /// number of paths repaired; call `commit` afterwards.
+    pub fn repair(&mut self, report: &IntegrityReport) -> anyhow::Result<usize> {
+        let mut repaired = 0;
+        for path in &report.missing {
+            self.remove_file(path)?;
+            repaired += 1;
+        }
+        for path in &report.changed {
+            match self.index_file(path) {
+                Ok(()) => repaired += 1,
+                Err(e) => tracing::warn!("Failed to re-index {}: {}", path.display(), e),
+            }
+        }
+        for path in &report.orphaned {
+            self.delete_documents(path)?;
+            let file = Self::on_disk(path);
+            if file.exists() {
+                // Re-add every member of a container, not just the orphaned one
+                self.files.content_hashes.remove(&file);
+                if let Err(e) = self.index_file(&file) {
+                    tracing::warn!("Failed to re-index {}: {}", file.display(), e);
+                }
+            }
+            repaired += 1;
+        }
+        Ok(repaired)
+    }
+
+    pub fn commit(&mut self) -> anyhow::Result<()> {
+        let started = Instant::now();
+        if !self.stale_directories.is_empty() {
+            self.write_directory_summaries()?;
+        }
+        self.writer.commit()?;
+        if let Some(index_path) = &self.index_path {
+            backup_meta(index_path);
+            if std::mem::take(&mut self.roots_changed) {
+                if let Err(e) = manifest::save(index_path, &self.roots()) {
+                    tracing::warn!("Failed to save the index roots: {}", e);
+                }
+            }
+            if std::mem::take(&mut self.pins_changed) {
+                if let Err(e) = pins::save(index_path, &self.files.pinned) {
+                    tracing::warn!("Failed to save the pinned files: {}", e);
+                }
+            }
+            if std::mem::take(&mut self.crawl_changed) {
+                let saved = match &self.crawl {
+                    Some(crawl) => checkpoint::save(index_path, crawl),
+                    None => checkpoint::remove(index_path),
+                };
+                if let Err(e) = saved {
+                    tracing::warn!("Failed to save the indexing checkpoint: {}", e);
+                }
+            }
+        }
+        self.handle.reader.reload()?;
+        self.generation += 1;
+        self.published.store(Arc::new(Snapshot {
+            generation: self.generation,
+            searcher: self.handle.reader.searcher(),
+            files: self.files.clone(),
+        }));
+        self.generations.send_replace(self.generation);
+        self.pending_docs = 0;
+        self.dirty = false;
+        self.last_commit_at = Instant::now();
+        self.last_commit = Some(SystemTime::now());
+        metrics::histogram!(telemetry::COMMIT_DURATION).record(started.elapsed().as_secs_f64());
+        metrics::gauge!(telemetry::INDEXED_FILES).set(self.files.indexed_paths.len() as f64);
+        if let Some(index_path) = &self.index_path {
+            metrics::gauge!(telemetry::INDEX_SIZE_BYTES).set(dir_size(index_path) as f64);
+        }
+        Ok(())
+    }
+
+    /// Whether anything was added or removed since the last commit.
+    pub fn has_uncommitted_changes(&self) -> bool {
+        self.dirty
+    }
+
+    /// Commit if `commit_every_docs` documents are pending, or if there are uncommitted
+    /// changes and `commit_interval` has passed since the last commit. Returns whether
+    /// it committed.
+    ///
+    /// Use this rather than `commit` for frequent small updates, so a stream of single
+    /// file changes does not produce a segment per change.
+    pub fn commit_if_due(&mut self) -> anyhow::Result<bool> {
+        let due = self.pending_docs >= self.config.commit_every_docs
+            || (self.dirty && self.last_commit_at.elapsed() >= self.config.commit_interval);
+        if due {
+            self.commit()?;
+        }
+        Ok(due)
+    }
+
+    /// When uncommitted changes become due under the commit policy; `None` if there are
+    /// none.
+    pub fn next_commit_due(&self) -> Option<Instant> {
+        self.dirty
+            .then(|| self.last_commit_at + self.config.commit_interval)
+    }
+
+    pub fn search(
+        &self,
+        query_str: &str,
+        limit: usize,
+        file_type: Option<&str>,
+        path_prefix: Option<&str>,
+    ) -> anyhow::Result<SearchOutput> {
+        self.handle.search(query_str, limit, file_type, path_prefix)
+    }
+
+    /// A cloneable handle for searching and listing without access to the writer.
+    pub fn search_history(
+        &self,
+        query_str: &str,
+        limit: usize,
+        revision: Option<&str>,
+    ) -> anyhow::Result<SearchOutput> {
+        self.handle.search_history(query_str, limit, revision)
+    }
+
+    /// Number of commits so far; the generation searches see after the last commit.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn search_handle(&self) -> SearchHandle {
+        self.handle.clone()
+    }
+
+    pub fn read_file(&self, path: &str) -> anyhow::Result<String> {
+        self.files.read_file(path, self.config.max_file_size)
+    }
+
+    pub fn list_files(&self, extension: Option<&str>, path_prefix: Option<&str>) -> Vec<String> {
+        self.files.list_files(extension, path_prefix)
+    }
+
+    /// Front matter tags across all indexed files with the number of files using each.
+    pub fn list_tags(&self) -> BTreeMap<String, usize> {
+        self.files.list_tags(|_| true)
+    }
+
+    /// Indexed notes linking to `note`, given as a note name (`Project Plan`) or a path.
+    pub fn backlinks(&self, note: &str) -> Vec<String> {
+        self.files.backlinks(note)
+    }
+
+    /// Owner and permissions of the indexed file `path` (unix only).
+    pub fn file_owner(&self, path: &str) -> Option<Owner> {
+        self.files.owner(path)
+    }
+
+    /// Size, times, hash and staleness of the indexed file `path`.
+    pub fn file_info(&self, path: &str) -> anyhow::Result<FileInfo> {
+        self.files.info(path, self.config.max_file_size)
+    }
+
+    pub fn status(&self) -> IndexStatus {
+        let snapshot = self.published.load_full();
+        let searcher = &snapshot.searcher;
+        IndexStatus {
+            num_files: self.files.indexed_paths.len(),
+            watched_paths: self.watched_roots.iter().map(|p| p.display().to_string()).collect(),
+            unwatched_paths: self
+                .watched_roots
+                .iter()
+                .filter(|root| !self.is_watched(root))
+                .map(|p| p.display().to_string())
+                .collect(),
+            missing_paths: self
+                .missing_roots
+                .iter()
+                .map(|root| root.path.display().to_string())
+                .collect(),
+            index_path: self.index_path.as_ref().map(|p| p.display().to_string()),
+            root_counts: self
+                .watched_roots
+                .iter()
+                .map(|root| {
+                    self.files.indexed_paths.iter().filter(|p| p.starts_with(root)).count()
+                })
+                .collect(),
+            root_skips: self.watched_roots.iter().map(|root| self.skipped(root)).collect(),
+            last_indexed: self.last_indexed,
+            last_commit: self.last_commit,
+            generation: self.generation,
+            migration: self.migration.clone(),
+            segments: searcher.segment_readers().len(),
+            deleted_docs: searcher
+                .segment_readers()
+                .iter()
+                .map(|segment| u64::from(segment.num_deleted_docs()))
+                .sum(),
+            disk_bytes: self.index_path.as_deref().map(dir_size),
+        }
+    }
+
+    /// Reduce structured formats to the text worth indexing, with the stored start of each
+    /// notebook cell; `None` if parsing fails.
+    fn extract_text(path: &Path, content: String) -> Option<(String, Vec<String>)> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if extension.eq_ignore_ascii_case("ipynb") {
+            let nb = notebook::extract(&content).ok()?;
+            return Some((nb.text, nb.cells.into_iter().map(notebook::encode_cell).collect()));
+        }
+        Some((content, Vec::new()))
+    }
+
+    /// The file on disk backing an indexed path: the archive for archive members.
+    fn on_disk(path: &Path) -> PathBuf {
+        match archive::split_virtual(&path.to_string_lossy()) {
+            Some((archive_path, _)) => archive_path,
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Whether `path` has one of the configured extensions (by default, a supported one).
+    fn wants(&self, path: &Path) -> bool {
+        let Some(extensions) = &self.config.extensions else {
+            return Self::is_supported(path) || Self::script_extension(path).is_some();
+        };
+        let matches = |name: Option<&std::ffi::OsStr>| {
+            name.and_then(|n| n.to_str())
+                .is_some_and(|n| extensions.iter().any(|e| e.eq_ignore_ascii_case(n)))
+        };
+        matches(path.extension())
+            || matches(path.file_name())
+            || Self::script_extension(path).is_some_and(|s| matches(Some(s.as_ref())))
+    }
+
+    /// Pseudo-extension of an extensionless script, from its `#!` line.
+    fn script_extension(path: &Path) -> Option<String> {
+        if path.extension().is_some() {
+            return None;
+        }
+        mime::file_script_extension(path)
+    }
+
+    fn is_supported(path: &Path) -> bool {
+        // Check known extensionless filenames
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            let lower = name.to_lowercase();
+            if lower == "makefile" || lower == "dockerfile" {
+                return true;
+            }
+        }
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| SUPPORTED_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+            .unwrap_or(false)
+    }
+
+    fn find_match_line(
+        content: &str,
+        query_terms: &[String],
+        case_sensitive: bool,
+    ) -> Option<usize> {
+        let pos = FileIndex::first_match(content, query_terms, case_sensitive)?;
+        // Count newlines before the match position (1-indexed)
+        Some(content[..pos].matches('\n').count() + 1)
+    }
+
+    /// Position of the first of `query_terms`, in their order, found in `content`; wildcard
+    /// words are looked for by their text before the first wildcard.
+    fn first_match(content: &str, query_terms: &[String], case_sensitive: bool) -> Option<usize> {
+        let literal = |term: &String| term.split(['*', '?']).next().unwrap_or("").to_string();
+        if case_sensitive {
+            return query_terms.iter().find_map(|term| content.find(&literal(term)));
+        }
+        let content_lower = content.to_lowercase();
+        query_terms
+            .iter()
+            .find_map(|term| content_lower.find(&literal(term).to_lowercase()))
+    }
+
+    /// Snippets of up to `max` lines of `content` matching `query_terms`, as the 1-based
+    /// matching line, the snippet's first line and its text. Without `context` a snippet
+    /// is the line, cut around the match if long; with it, the lines around the match,
+    /// and matches already shown in the previous snippet start no new one.
+    fn line_snippets(
+        content: &str,
+        query_terms: &[String],
+        max: usize,
+        context: Option<usize>,
+        matching: Matching,
+    ) -> Vec<(usize, usize, String)> {
+        let mut snippets: Vec<(usize, usize, String)> = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            if snippets.len() == max {
+                break;
+            }
+            let ln = i + 1;
+            if highlight::find_matches_with(line, query_terms, matching).is_empty() {
+                continue;
+            }
+            match context {
+                Some(context) => {
+                    if snippets.last().is_some_and(|(prev, _, _)| ln <= prev + context) {
+                        continue;
+                    }
+                    let (start, text) = FileIndex::context_snippet(content, ln, context);
+                    snippets.push((ln, start, text));
+                }
+                None => {
+                    let text = match line.trim() {
+                        short if short.len() <= 200 => short.to_string(),
+                        _ => {
+                            let case_sensitive = matching.case_sensitive;
+                            FileIndex::extract_snippet(line, query_terms, 200, case_sensitive)
+                        }
+                    };
+                    snippets.push((ln, ln, text));
+                }
+            }
+        }
+        snippets
+    }
+
+    /// Lines `line - context` to `line + context` (1-based, clamped to `content`), with
+    /// the number of the first.
+    fn context_snippet(content: &str, line: usize, context: usize) -> (usize, String) {
+        let start = line.saturating_sub(context).max(1);
+        let lines: Vec<&str> = content
+            .lines()
+            .skip(start - 1)
+            .take(line - start + 1 + context)
+            .collect();
+        (start, lines.join("\n"))
+    }
+
+    fn extract_snippet(
+        content: &str,
+        query_terms: &[String],
+        window: usize,
+        case_sensitive: bool,
+    ) -> String {
+        let best_pos = FileIndex::first_match(content, query_terms, case_sensitive).unwrap_or(0);
+        let start = best_pos.saturating_sub(window / 2);
+        let end = (best_pos + window / 2).min(content.len());
+
+        // Align to char boundaries
+        let start = {
+            let mut s = start;
+            while s > 0 && !content.is_char_boundary(s) {
+                s -= 1;
+            }
+            s
+        };
+        let end = {
+            let mut e = end.min(content.len());
+            while e < content.len() && !content.is_char_boundary(e) {
+                e += 1;
+            }
+            e
+        };
+
+        let snippet = &content[start..end];
+        format!("...{}...", snippet.trim())
+    }
+}
+
+/// Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Facet of the directory `directory`, one level per component (`/home/me/notes`).
+fn directory_facet(directory: &Path) -> Facet {
+    Facet::from_path(directory.components().filter_map(|c| match c {
+        std::path::Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+        _ => None,
+    }))
+}
+
+/// Whether `e` is an I/O error for lack of permission.
+fn is_permission_denied(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied)
+}
+
+/// Documents of `segment` indexed under `path`.
+fn path_docs(segment: &SegmentReader, field_path: Field, path: &str) -> Vec<DocId> {
+    let mut docs = Vec::new();
+    let Ok(inverted) = segment.inverted_index(field_path) else {
+        return docs;
+    };
+    let term = Term::from_field_text(field_path, path);
+    let Ok(Some(mut postings)) = inverted.read_postings(&term, IndexRecordOption::Basic) else {
+        return docs;
+    };
+    let mut doc = postings.doc();
+    while doc != TERMINATED {
+        docs.push(doc);
+        doc = postings.advance();
+    }
+    docs
+}
+
+/// Resolve `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn test_index(dir: &TempDir) -> FileIndex {
+        let index_path = dir.path().join("index");
+        FileIndex::new(Some(index_path)).expect("failed to create test index")
+    }
+
+    fn write_fixture(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    // -- Index creation & migration --
+
+    #[test]
+    fn test_new_creates_index() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        let _idx = FileIndex::new(Some(index_path.clone())).unwrap();
+        let version = fs::read_to_string(index_path.join("schema_version")).unwrap();
+        assert_eq!(version.trim(), "19");
+    }
+
+    #[test]
+    fn test_schema_migration_keeps_roots() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        write_fixture(&root, "a.txt", "migrated");
+        let index_path = dir.path().join("index");
+        let mut idx = FileIndex::new(Some(index_path.clone())).unwrap();
+        let policy = HiddenPolicy {
+            hidden: false,
+            skip_dirs: Vec::new(),
+        };
+        idx.set_hidden_policy(&root, policy.clone());
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        assert!(idx.migration().is_none());
+        drop(idx);
+
+        fs::write(index_path.join("schema_version"), "1").unwrap();
+        let mut idx = FileIndex::new(Some(index_path.clone())).unwrap();
+        assert_eq!(idx.search("migrated", 10, None, None).unwrap().results.len(), 0);
+        let migration = idx.status().migration.unwrap();
+        assert_eq!((migration.from_version, migration.done), (1, 0));
+        assert_eq!(migration.roots[0].policy, Some(policy));
+        // Roots still waiting stay in the manifest
+        assert_eq!(idx.roots(), migration.roots);
+
+        let files = idx.index_directory(&root).unwrap();
+        idx.migrated_root_done(files);
+        idx.commit().unwrap();
+        let migration = idx.migration().unwrap();
+        assert!(migration.is_complete());
+        assert_eq!(migration.files_indexed, 1);
+        assert_eq!(manifest::load(&index_path).unwrap()[0].path, root);
+    }
+
+    #[test]
+    fn test_corrupt_index_recovered_or_quarantined() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        let mut idx = FileIndex::new(Some(index_path.clone())).unwrap();
+        idx.index_file(&write_fixture(fixtures.path(), "a.txt", "recoverable")).unwrap();
+        idx.commit().unwrap();
+        drop(idx);
+
+        // A damaged meta.json is replaced by the copy from the last commit
+        fs::write(index_path.join("meta.json"), "{ not json").unwrap();
+        let idx = FileIndex::new(Some(index_path.clone())).unwrap();
+        assert_eq!(idx.search("recoverable", 10, None, None).unwrap().results.len(), 1);
+        drop(idx);
+
+        // Without a usable copy, opening fails and leaves the index alone
+        fs::write(index_path.join("meta.json"), "{ not json").unwrap();
+        fs::remove_file(index_path.join(META_BACKUP)).unwrap();
+        let err = FileIndex::new(Some(index_path.clone())).err().unwrap().to_string();
+        assert!(err.contains("LOCALFILES_REBUILD_CORRUPT"));
+        assert_eq!(fs::read_to_string(index_path.join("meta.json")).unwrap(), "{ not json");
+
+        let config = IndexConfig {
+            rebuild_corrupt: true,
+            ..IndexConfig::default()
+        };
+        let idx = FileIndex::with_config(Some(index_path.clone()), config).unwrap();
+        assert_eq!(idx.search("recoverable", 10, None, None).unwrap().results.len(), 0);
+        let quarantined: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with("index.corrupt-"))
+            .collect();
+        assert_eq!(quarantined.len(), 1);
+        assert!(quarantined[0].path().join("meta.json").exists());
+    }
+
+    #[test]
+    fn test_commit_shares_file_meta_with_the_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let a = write_fixture(fixtures.path(), "a.md", "alpha");
+        idx.index_file(&a).unwrap();
+        idx.commit().unwrap();
+        let published = idx.published.load_full();
+        assert!(Arc::ptr_eq(&published.files.indexed_paths.0, &idx.files.indexed_paths.0));
+
+        // The writer's next change copies the map and leaves the snapshot as it was
+        let b = write_fixture(fixtures.path(), "b.md", "beta");
+        idx.index_file(&b).unwrap();
+        assert!(!Arc::ptr_eq(&published.files.indexed_paths.0, &idx.files.indexed_paths.0));
+        assert_eq!(published.files.indexed_paths.len(), 1);
+        assert!(Arc::ptr_eq(&published.files.pinned.0, &idx.files.pinned.0));
+    }
+
+    #[test]
+    fn test_new_opens_existing_index() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        let _idx1 = FileIndex::new(Some(index_path.clone())).unwrap();
+        drop(_idx1);
+        let _idx2 = FileIndex::new(Some(index_path)).unwrap();
+    }
+
+    #[test]
+    fn test_schema_version_migration() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        let _idx = FileIndex::new(Some(index_path.clone())).unwrap();
+        drop(_idx);
+        // Overwrite version to trigger migration
+        fs::write(index_path.join("schema_version"), "1").unwrap();
+        let _idx2 = FileIndex::new(Some(index_path.clone())).unwrap();
+        let version = fs::read_to_string(index_path.join("schema_version")).unwrap();
+        assert_eq!(version.trim(), "19");
+    }
+
+    #[test]
+    fn test_legacy_index_moves_only_when_owned_directory() {
+        let dir = TempDir::new().unwrap();
+        let legacy = dir.path().join("localfiles_index");
+        let target = dir.path().join("data/indexes/abc");
+        assert!(!migrate_legacy_index(&legacy, &target));
+
+        // A symlink planted at the old location is not followed
+        let elsewhere = dir.path().join("elsewhere");
+        fs::create_dir(&elsewhere).unwrap();
+        fs::write(elsewhere.join("schema_version"), "18").unwrap();
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&elsewhere, &legacy).unwrap();
+            assert!(!migrate_legacy_index(&legacy, &target));
+            assert!(!target.exists());
+            fs::remove_file(&legacy).unwrap();
+        }
+
+        fs::rename(&elsewhere, &legacy).unwrap();
+        assert!(migrate_legacy_index(&legacy, &target));
+        assert!(target.join("schema_version").exists());
+        assert!(!legacy.exists());
+
+        // With an index at the target the old one is left alone
+        fs::create_dir(&legacy).unwrap();
+        fs::write(legacy.join("schema_version"), "18").unwrap();
+        assert!(migrate_legacy_index(&legacy, &target));
+        assert!(legacy.exists());
+    }
+
+    // -- is_supported --
+
+    #[test]
+    fn test_is_supported_common_extensions() {
+        for ext in &["rs", "py", "js", "md", "yaml"] {
+            let p = PathBuf::from(format!("test.{}", ext));
+            assert!(FileIndex::is_supported(&p), "expected {} to be supported", ext);
+        }
+    }
+
+    #[test]
+    fn test_is_supported_makefile_dockerfile() {
+        assert!(FileIndex::is_supported(Path::new("Makefile")));
+        assert!(FileIndex::is_supported(Path::new("Dockerfile")));
+    }
+
+    #[test]
+    fn test_is_supported_unsupported() {
+        for ext in &["png", "jpg", "exe"] {
+            let p = PathBuf::from(format!("test.{}", ext));
+            assert!(!FileIndex::is_supported(&p), "expected {} to be unsupported", ext);
+        }
+    }
+
+    #[test]
+    fn test_is_supported_no_extension() {
+        assert!(!FileIndex::is_supported(Path::new("README")));
+    }
+
+    #[test]
+    fn test_is_supported_case_insensitive() {
+        assert!(FileIndex::is_supported(Path::new("test.RS")));
+        assert!(FileIndex::is_supported(Path::new("test.Py")));
+    }
+
+    // -- index_file --
+
+    #[test]
+    fn test_index_file_basic() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let f = write_fixture(fixtures.path(), "hello.rs", "fn main() {}");
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.status().num_files, 1);
+
+        // Modification time as a number, for sorting and formatting
+        let mtime = fs::metadata(&f).unwrap().modified().unwrap();
+        let secs = mtime.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() as i64;
+        let results = idx.search("main", 10, None, None).unwrap().results;
+        assert_eq!(results[0].modified, Some(secs));
+        let path = f.to_string_lossy();
+        assert_eq!(idx.search_handle().file_modified(&path), Some(mtime));
+    }
+
+    #[test]
+    fn test_index_file_unsupported_skipped() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let f = write_fixture(fixtures.path(), "image.png", "not really an image");
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.status().num_files, 0);
+    }
+
+    #[test]
+    fn test_builder_extensions_and_max_file_size() {
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::builder()
+            .in_memory()
+            .extensions([".PNG", "md"])
+            .max_file_size(100)
+            .build()
+            .unwrap();
+        let png = write_fixture(fixtures.path(), "scan.png", "png_token");
+        let rs = write_fixture(fixtures.path(), "main.rs", "rs_token");
+        let big = write_fixture(fixtures.path(), "big.md", &"big_token ".repeat(20));
+        for f in [&png, &rs, &big] {
+            idx.index_file(f).unwrap();
+        }
+        idx.commit().unwrap();
+        assert_eq!(idx.list_files(None, None), vec![png.to_string_lossy().to_string()]);
+        assert!(idx.status().index_path.is_none());
+    }
+
+    #[test]
+    fn test_log_chunks_searchable_by_time_range() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut log = String::new();
+        for minute in 0..logs::CHUNK_LINES * 2 {
+            let level = if minute % logs::CHUNK_LINES == 5 {
+                "ERROR database"
+            } else {
+                "INFO ok"
+            };
+            let (hour, minute) = (minute / 60, minute % 60);
+            log.push_str(&format!("2024-07-01T{:02}:{:02}:00Z {}\n", hour, minute, level));
+        }
+        let f = write_fixture(fixtures.path(), "app.log", &log);
+        let mut idx = test_index(&dir);
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.list_files(None, None).len(), 1);
+
+        let all = idx.search("ERROR database", 10, None, None).unwrap();
+        assert_eq!(all.total_count, 2);
+        let mut lines: Vec<usize> = all.results.iter().filter_map(|r| r.line_number).collect();
+        lines.sort();
+        assert_eq!(lines, vec![6, logs::CHUNK_LINES + 6]);
+
+        let since = logs::parse_time_bound("2024-07-01T03:30:00Z").unwrap();
+        let range = TimeRange {
+            since: Some(since),
+            until: None,
+        };
+        let late = idx
+            .search_handle()
+            .search_filtered(
+                "ERROR database",
+                10,
+                &SearchFilters {
+                    time: Some(range),
+                    ..SearchFilters::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(late.total_count, 1);
+        assert_eq!(late.results[0].line_number, Some(logs::CHUNK_LINES + 6));
+        assert!(late.results[0].log_time.is_some_and(|(start, _)| start >= since - 3_600));
+
+        // Removing the log drops every chunk
+        idx.remove_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.search("database", 10, None, None).unwrap().total_count, 0);
+    }
+
+    #[test]
+    fn test_group_by_file_collapses_chunks() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut log = String::new();
+        for line in 0..logs::CHUNK_LINES * 2 {
+            let level = if [3, 7, logs::CHUNK_LINES + 3].contains(&line) {
+                "ERROR disk"
+            } else {
+                "INFO ok"
+            };
+            log.push_str(&format!("{}\n", level));
+        }
+        let log = write_fixture(fixtures.path(), "app.log", &log);
+        let notes = write_fixture(fixtures.path(), "notes.txt", "disk ERROR seen once");
+        let mut idx = test_index(&dir);
+        idx.index_file(&log).unwrap();
+        idx.index_file(&notes).unwrap();
+        idx.commit().unwrap();
+
+        let filters = SearchFilters {
+            group_by_file: true,
+            ..SearchFilters::default()
+        };
+        let output = idx.search_handle().search_filtered("error", 10, &filters).unwrap();
+        let mut counts: Vec<(String, Option<usize>)> = output
+            .results
+            .into_iter()
+            .map(|r| (r.file_name, r.match_count))
+            .collect();
+        counts.sort();
+        let expected = vec![
+            ("app.log".to_string(), Some(3)),
+            ("notes.txt".to_string(), Some(1)),
+        ];
+        assert_eq!(counts, expected);
+        assert_eq!(idx.search("error", 10, None, None).unwrap().total_count, 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_owner_recorded_and_filterable() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let f = write_fixture(fixtures.path(), "mine.txt", "owned_token");
+        fs::set_permissions(&f, fs::Permissions::from_mode(0o640)).unwrap();
+        let mut idx = test_index(&dir);
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+
+        let me = crate::owner::current_uid().unwrap();
+        let path = f.display().to_string();
+        let owner = idx.search_handle().file_owner(&path).unwrap();
+        assert_eq!(owner.uid, me);
+        assert_eq!(owner.permissions(), "rw-r-----");
+
+        let filtered = |uid| {
+            let filters = SearchFilters {
+                owner: Some(uid),
+                ..SearchFilters::default()
+            };
+            idx.search_handle().search_filtered("owned_token", 10, &filters).unwrap().total_count
+        };
+        assert_eq!(filtered(me), 1);
+        assert_eq!(filtered(me + 1), 0);
+
+        // A chmod alone re-records the permissions
+        fs::set_permissions(&f, fs::Permissions::from_mode(0o600)).unwrap();
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.file_owner(&path).unwrap().permissions(), "rw-------");
+    }
+
+    #[test]
+    fn test_scoped_handle_sees_only_its_roots() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        let mine = root.join("alice");
+        let mine = write_fixture(&mine, "plan.md", "---\ntags: [roadmap]\n---\nquarterly roadmap");
+        let theirs = root.join("alicia");
+        let theirs = write_fixture(&theirs, "notes.md", "---\ntags: [secret]\n---\nsecret roadmap");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let all = idx.search_handle();
+        let scoped = all.scoped(Some(vec![root.join("alice")]));
+        let mine = mine.to_string_lossy().to_string();
+        let theirs = theirs.to_string_lossy().to_string();
+
+        assert_eq!(all.search("roadmap", 10, None, None).unwrap().total_count, 2);
+        let results = scoped.search("roadmap", 10, None, None).unwrap().results;
+        assert_eq!(results.iter().map(|r| &r.file_path).collect::<Vec<_>>(), vec![&mine]);
+        assert_eq!(scoped.list_files(None, None), vec![mine.clone()]);
+        assert!(scoped.read_file(&mine).is_ok());
+        assert!(scoped.read_file(&theirs).is_err() && scoped.file_info(&theirs).is_err());
+        assert_eq!(scoped.list_tags().into_keys().collect::<Vec<_>>(), vec!["roadmap"]);
+        assert!(scoped.suggest("secr", 5).unwrap().is_empty());
+        assert!(scoped.similar_files(Some(&theirs), None, 5).is_err());
+        let stats = scoped.analyze_terms(None, None, 50).unwrap();
+        assert!(stats.terms.iter().all(|(term, _)| term != "secret"));
+        assert!(all.scoped(Some(Vec::new())).list_files(None, None).is_empty());
+        let nothing = all.scoped(Some(Vec::new()));
+        assert_eq!(nothing.search("roadmap", 10, None, None).unwrap().total_count, 0);
+    }
+
+    #[test]
+    fn test_recent_files() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let rows: String = (0..250).map(|i| format!("{},row\n", i)).collect();
+        let files = [
+            write_fixture(fixtures.path(), "old.md", "old notes"),
+            write_fixture(fixtures.path(), "data.csv", &format!("id,value\n{}", rows)),
+            write_fixture(fixtures.path(), "new.md", "new notes"),
+        ];
+        for (secs, file) in [1_000, 2_000, 3_000].into_iter().zip(&files) {
+            let file = std::fs::File::options().write(true).open(file).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+        }
+        idx.index_directory(fixtures.path()).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+        let paths = |recent: Vec<RecentFile>| -> Vec<String> {
+            recent.into_iter().map(|f| f.file_path).collect()
+        };
+        let names = |files: &[&PathBuf]| -> Vec<String> {
+            files.iter().map(|f| f.to_string_lossy().to_string()).collect()
+        };
+
+        // The three chunks of the table count once
+        let recent = handle.recent_files(10, None, &SearchFilters::default()).unwrap();
+        assert_eq!(recent[0].modified, SystemTime::UNIX_EPOCH + Duration::from_secs(3_000));
+        assert_eq!(paths(recent), names(&[&files[2], &files[1], &files[0]]));
+        let recent = handle.recent_files(2, None, &SearchFilters::default()).unwrap();
+        assert_eq!(paths(recent), names(&[&files[2], &files[1]]));
+
+        let markdown = SearchFilters {
+            file_type: Some("md".to_string()),
+            ..SearchFilters::default()
+        };
+        let recent = handle.recent_files(10, None, &markdown).unwrap();
+        assert_eq!(paths(recent), names(&[&files[2], &files[0]]));
+        let recent = handle.recent_files(10, Some(1_500), &SearchFilters::default()).unwrap();
+        assert_eq!(paths(recent), names(&[&files[2], &files[1]]));
+    }
+
+    #[test]
+    fn test_cached_search_sees_new_commits() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        idx.index_file(&write_fixture(fixtures.path(), "a.md", "alpha")).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+        assert!(handle.warm_up().unwrap() > 0);
+        assert_eq!(handle.search("alpha", 10, None, None).unwrap().total_count, 1);
+        assert_eq!(handle.search("alpha", 10, None, None).unwrap().total_count, 1);
+
+        idx.index_file(&write_fixture(fixtures.path(), "b.md", "alpha beta")).unwrap();
+        idx.commit().unwrap();
+        let output = handle.search("alpha", 10, None, None).unwrap();
+        assert_eq!((output.total_count, output.generation), (2, idx.generation()));
+    }
+
+    #[test]
+    fn test_multi_search_keeps_query_order() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(fixtures.path(), "a.md", "alpha shared");
+        write_fixture(fixtures.path(), "b.rs", "beta shared");
+        idx.index_directory(fixtures.path()).unwrap();
+        idx.commit().unwrap();
+        let queries = ["shared", "beta", "missing", "content:("].map(str::to_string);
+        let outputs = idx.search_handle().multi_search(&queries, 10, &SearchFilters::default());
+        let counts: Vec<Option<usize>> =
+            outputs.iter().map(|o| o.as_ref().ok().map(|o| o.total_count)).collect();
+        assert_eq!(counts, [Some(2), Some(1), Some(0), None]);
+        assert!(outputs[..3].iter().all(|o| o.as_ref().unwrap().generation == idx.generation()));
+    }
+
+    #[test]
+    fn test_filter_expression() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root.join("notes"), "a.md", "shared note");
+        write_fixture(&root.join("src"), "lib.rs", "shared code");
+        write_fixture(&root.join("src"), "Cargo.toml", "shared = true");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+        let count = |query: &str, expression: &str| {
+            let filters = SearchFilters {
+                filter: Some(Filter::parse(expression).unwrap()),
+                ..SearchFilters::default()
+            };
+            handle.search_filtered(query, 10, &filters).unwrap().total_count
+        };
+
+        assert_eq!(count("", "ext in [rs, toml] AND path startswith src"), 2);
+        assert_eq!(count("shared", "NOT ext = md"), 2);
+        assert_eq!(count("shared", &format!("path startswith \"{}/src\"", root.display())), 2);
+        assert_eq!(count("shared", "ext = md OR modified < 2024-01-01"), 1);
+        assert_eq!(count("shared", "modified >= 2024-01-01 AND tracked = false"), 3);
+    }
+
+    #[test]
+    fn test_file_type_names_a_language() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(fixtures.path(), "main.py", "import shared");
+        write_fixture(fixtures.path(), "stubs.pyi", "def shared() -> None: ...");
+        write_fixture(fixtures.path(), "util.h", "namespace shared {\nint add(int, int);\n}\n");
+        write_fixture(fixtures.path(), "Makefile", "shared:\n\tcc -o shared util.c\n");
+        idx.index_directory(fixtures.path()).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+        let count = |query: &str, file_type: Option<&str>| {
+            handle.search(query, 10, file_type, None).unwrap().total_count
+        };
+
+        assert_eq!(count("shared", Some("python")), 2);
+        // Extensions stay literal
+        assert_eq!(count("shared", Some("py")), 1);
+        assert_eq!(count("shared", Some("make")), 1);
+        assert_eq!(count("shared AND language:cpp", None), 1);
+        assert_eq!(count("shared AND lang:c", None), 0);
+    }
+
+    #[test]
+    fn test_directory_summaries() {
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = FileIndex::builder().in_memory().directory_summaries(true).build().unwrap();
+        let readme = write_fixture(&root.join("auth"), "README.md", "# Authentication service\n");
+        write_fixture(&root.join("auth"), "login.rs", "fn login() {}");
+        write_fixture(&root.join("auth"), "session.rs", "fn session() {}");
+        write_fixture(&root.join("billing"), "invoice.py", "def total(): pass");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+        let dirs = |query: &str| -> Vec<String> {
+            let output = handle.search_directories(query, 10).unwrap();
+            output.results.into_iter().map(|r| r.file_path).collect()
+        };
+        let auth = root.join("auth").to_string_lossy().to_string();
+        let billing = root.join("billing").to_string_lossy().to_string();
+
+        assert_eq!(dirs("authentication"), [auth.as_str()]);
+        assert_eq!(dirs("invoice"), [billing]);
+        assert_eq!(dirs("language:rust"), [auth.as_str()]);
+        // Summaries stay out of file searches
+        assert_eq!(handle.search("authentication", 10, None, None).unwrap().total_count, 1);
+        assert_eq!(handle.search("session", 10, None, None).unwrap().total_count, 1);
+
+        idx.remove_file(&readme).unwrap();
+        idx.commit().unwrap();
+        assert!(dirs("authentication").is_empty());
+        assert_eq!(dirs("login"), [auth]);
+        assert!(!test_index(&TempDir::new().unwrap()).search_handle().has_directory_summaries());
+    }
+
+    #[test]
+    fn test_projects() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root.join("engine"), "Cargo.toml", "[package]\nname = \"engine-core\"\n");
+        write_fixture(&root.join("engine/src"), "lib.rs", "shared code");
+        write_fixture(&root.join("web"), "package.json", "{\"name\": \"Web-App\"}");
+        write_fixture(&root.join("web"), "index.js", "shared script");
+        write_fixture(&root.join("notes"), "todo.md", "shared note");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+        let count = |filters: SearchFilters| {
+            handle.search_filtered("shared", 10, &filters).unwrap().total_count
+        };
+
+        let in_project = |name: &str| SearchFilters {
+            project: Some(name.to_string()),
+            ..SearchFilters::default()
+        };
+        assert_eq!(count(in_project("engine-core")), 1);
+        assert_eq!(count(in_project("web-app")), 1);
+        let expression = SearchFilters {
+            filter: Some(Filter::parse("NOT project in [engine-core, web-app]").unwrap()),
+            ..SearchFilters::default()
+        };
+        assert_eq!(count(expression), 1);
+        let listed: Vec<(String, &str, usize)> = handle
+            .list_projects()
+            .into_iter()
+            .map(|p| (p.project.name, p.project.kind, p.files))
+            .collect();
+        assert_eq!(
+            listed,
+            [("Web-App".to_string(), "npm", 2), ("engine-core".to_string(), "cargo", 2)]
+        );
+
+        // A manifest added later makes its directory a project
+        let manifest =
+            write_fixture(&root.join("notes"), "Cargo.toml", "[package]\nname = \"notes\"\n");
+        idx.index_file(&manifest).unwrap();
+        idx.commit().unwrap();
+        let names: Vec<String> =
+            idx.search_handle().list_projects().into_iter().map(|p| p.project.name).collect();
+        assert_eq!(names, ["Web-App", "engine-core", "notes"]);
+    }
+
+    #[test]
+    fn test_exclude_generated() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root, "app.js", "function render(widget) { return widget; }");
+        write_fixture(&root, "app.min.js", "function render(a){return a}");
+        write_fixture(&root, "package-lock.json", "{\"render\": \"1.0.0\"}");
+        write_fixture(&root.join("vendor/lib"), "render.js", "function render() {}");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+        let count = |filters: SearchFilters| {
+            handle.search_filtered("render", 10, &filters).unwrap().total_count
+        };
+
+        assert_eq!(count(SearchFilters::default()), 4);
+        let sources = SearchFilters {
+            exclude_generated: true,
+            ..SearchFilters::default()
+        };
+        assert_eq!(count(sources), 1);
+        let expression = |expression: &str| SearchFilters {
+            filter: Some(Filter::parse(expression).unwrap()),
+            ..SearchFilters::default()
+        };
+        assert_eq!(count(expression("generated = true")), 3);
+        assert_eq!(count(expression("generated in [lockfile, minified]")), 2);
+        assert_eq!(count(expression("generated = false")), 1);
+    }
+
+    #[test]
+    fn test_ranking_rules_scale_scores() {
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let rules = "docs/**=2.0, vendor/**=0.5, **/release.md=10";
+        let config = IndexConfig {
+            ranking: crate::ranking::parse_rules(rules).unwrap(),
+            ..IndexConfig::default()
+        };
+        let mut idx = FileIndex::builder().config(config).in_memory().build().unwrap();
+        for dir in ["docs", "notes", "vendor/lib"] {
+            write_fixture(&root.join(dir), "release.md", "the release checklist");
+        }
+        write_fixture(&root, "other.md", "release");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+
+        let output = idx.search("checklist", 10, None, None).unwrap();
+        assert_eq!(output.total_count, 3);
+        let score = |dir: &str| {
+            let path = root.join(dir).join("release.md").to_string_lossy().into_owned();
+            output.results.iter().find(|r| r.file_path == path).unwrap().score
+        };
+        // The first matching rule applies: notes/ is only selected by the last
+        assert!((score("docs") / score("vendor/lib") - 4.0).abs() < 1e-3);
+        assert!((score("notes") / score("docs") - 5.0).abs() < 1e-3);
+        assert!(output.results[0].file_path.contains("notes"));
+    }
+
+    #[test]
+    fn test_purge_empties_index_and_scrubs_files() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        let index_path = dir.path().join("index");
+        let ocr_cache = crate::ocr::cache_path(&index_path);
+        idx.set_ocr(OcrConfig::new(Some(ocr_cache.clone())).with_command("png", "cat"));
+        // OCR output cached by another index is not this index's to scrub
+        let other_cache = dir.path().join("other.ocr");
+        fs::create_dir(&other_cache).unwrap();
+        fs::write(other_cache.join("cached.txt"), "other text").unwrap();
+        let plan = write_fixture(&root, "plan.md", "quarterly roadmap");
+        write_fixture(&root, "scan.png", "scanned invoice");
+        idx.index_directory(&root).unwrap();
+        idx.pin(&plan).unwrap();
+        idx.commit().unwrap();
+        assert!(manifest::manifest_path(&index_path).exists());
+        assert_eq!(fs::read_dir(&ocr_cache).unwrap().count(), 1);
+
+        let scrubbed = idx.purge().unwrap();
+        assert!(scrubbed.contains(&manifest::manifest_path(&index_path)));
+        assert!(scrubbed.contains(&pins::pins_path(&index_path)));
+        assert!(scrubbed.contains(&ocr_cache) && !ocr_cache.exists());
+        assert!(other_cache.join("cached.txt").exists());
+        assert!(!purge::holding_path(&index_path).exists());
+        assert_eq!(idx.search("invoice", 10, None, None).unwrap().total_count, 0);
+        assert_eq!(idx.search("roadmap", 10, None, None).unwrap().total_count, 0);
+        assert!(idx.roots().is_empty());
+        assert_eq!(idx.pinned().count(), 0);
+        assert!(idx.status().watched_paths.is_empty());
+
+        // The emptied index keeps working, and reopens empty
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.search("roadmap", 10, None, None).unwrap().total_count, 1);
+        drop(idx);
+        let idx = test_index(&dir);
+        assert_eq!(idx.search("roadmap", 10, None, None).unwrap().total_count, 1);
+    }
+
+    #[test]
+    fn test_pinned_files_rank_first_and_persist() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root, "chatter.md", "deploy deploy deploy the deploy checklist");
+        let guide = write_fixture(&root, "guide.md", "how we deploy, and much else besides");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let top = |idx: &FileIndex| {
+            let output = idx.search("deploy", 10, None, None).unwrap();
+            output.results[0].file_path.clone()
+        };
+        assert!(top(&idx).ends_with("chatter.md"));
+        assert!(idx.pin(&root.join("missing.md")).is_err());
+
+        assert_eq!(idx.pin(&guide).unwrap(), guide);
+        idx.commit().unwrap();
+        assert!(top(&idx).ends_with("guide.md"));
+        let pinned_only = SearchFilters {
+            pinned_only: true,
+            ..SearchFilters::default()
+        };
+        let output = idx.search_handle().search_filtered("deploy", 10, &pinned_only).unwrap();
+        assert_eq!(output.total_count, 1);
+        drop(idx);
+
+        let mut idx = test_index(&dir);
+        assert_eq!(idx.pinned().collect::<Vec<_>>(), [guide.as_path()]);
+        assert!(idx.unpin(&guide));
+        assert!(!idx.unpin(&guide));
+        idx.commit().unwrap();
+        assert_eq!(pins::load(&dir.path().join("index")).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_case_sensitive_search() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root, "lower.md", "an error occurred\nanother error here");
+        write_fixture(&root, "type.rs", "// no error here\nfn parse() -> Result<(), Error> {}");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.search("Error", 10, None, None).unwrap().total_count, 2);
+
+        let filters = SearchFilters {
+            case_sensitive: true,
+            ..SearchFilters::default()
+        };
+        let output = idx.search_handle().search_filtered("Error", 10, &filters).unwrap();
+        assert_eq!(output.total_count, 1);
+        let result = &output.results[0];
+        assert!(result.file_path.ends_with("type.rs"));
+        assert_eq!(result.line_number, Some(2));
+        assert!(result.snippet.contains("**Error**"));
+        assert!(!result.snippet.contains("**error**"));
+        let lower = idx.search_handle().search_filtered("error", 10, &filters).unwrap();
+        assert_eq!(lower.total_count, 2);
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root, "deploy.md", "kubernetes deployment checklist");
+        write_fixture(&root, "notes.md", "deployment notes, and the kubelet");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+
+        assert_eq!(idx.search("kubernets deploymnet", 10, None, None).unwrap().total_count, 0);
+        let suggestions = handle.did_you_mean("kubernets deploymnet", 3).unwrap();
+        assert_eq!(suggestions, ["kubernetes deployment"]);
+        // Known words and fields are kept
+        let suggestions = handle.did_you_mean("ext:md +checklst", 3).unwrap();
+        assert_eq!(suggestions, ["ext:md +checklist"]);
+        assert!(handle.did_you_mean("kubernetes", 3).unwrap().is_empty());
+        assert!(handle.did_you_mean("zzyzx", 3).unwrap().is_empty());
+
+        assert_eq!(edit_distance("kubelet", "kubernets"), 3);
+        assert_eq!(edit_distance("checklst", "checklist"), 1);
+        assert_eq!(edit_distance("", "ab"), 2);
+    }
+
+    #[test]
+    fn test_read_results_rank_higher_with_usage_ranking() {
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let config = IndexConfig {
+            usage_ranking: true,
+            query_cache: 0,
+            ..IndexConfig::default()
+        };
+        let mut idx = FileIndex::builder().config(config).in_memory().build().unwrap();
+        let alpha = write_fixture(&root, "alpha.md", "deploy the notes");
+        write_fixture(&root, "beta.md", "deploy plans");
+        write_fixture(&root, "gamma.md", "deploy deploy deploy the deploy checklist");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+        let ranked = || {
+            let output = handle.search("deploy", 10, None, None).unwrap();
+            output.results.iter().map(|r| r.file_name.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(ranked(), ["gamma.md", "beta.md", "alpha.md"]);
+
+        for _ in 0..3 {
+            handle.record_read(&alpha.to_string_lossy());
+        }
+        // The boost lifts a comparable match, but not past a clearly better one
+        assert_eq!(ranked(), ["gamma.md", "alpha.md", "beta.md"]);
+    }
+
+    #[test]
+    fn test_synonyms_expand_queries() {
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let config = IndexConfig {
+            synonyms: Synonyms::parse("k8s, kubernetes\ngcp, google cloud").unwrap(),
+            ..IndexConfig::default()
+        };
+        let mut idx = FileIndex::builder().config(config).in_memory().build().unwrap();
+        write_fixture(&root, "cluster.md", "Deploying to Kubernetes");
+        write_fixture(&root, "k8s.md", "k8s notes");
+        write_fixture(&root, "hosting.md", "We run on Google Cloud");
+        write_fixture(&root, "weather.md", "a cloud over google headquarters");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let search = |query: &str| idx.search(query, 10, None, None).unwrap();
+
+        let output = search("k8s");
+        assert_eq!(output.total_count, 2);
+        let cluster = output.results.iter().find(|r| r.file_name == "cluster.md").unwrap();
+        assert!(cluster.snippet.contains("**Kubernetes**"));
+        assert_eq!(search("kubernetes").total_count, 2);
+        // Multi-word synonyms match as phrases
+        let output = search("gcp");
+        assert_eq!(output.total_count, 1);
+        assert_eq!(output.results[0].file_name, "hosting.md");
+        assert_eq!(search("\"google cloud\"").total_count, 1);
+        // Only words of the default fields are expanded
+        assert_eq!(search("name:k8s").total_count, 1);
+    }
+
+    #[test]
+    fn test_wildcard_queries() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root, "settings.md", "Load the configuration first.");
+        write_fixture(&root, "test_1.py", "assert conf");
+        write_fixture(&root, "test_10.py", "assert True");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let search = |query: &str| idx.search(query, 10, None, None);
+
+        let output = search("conf*").unwrap();
+        assert_eq!(output.total_count, 2);
+        let settings = output.results.iter().find(|r| r.file_name == "settings.md").unwrap();
+        assert!(settings.snippet.contains("**configuration**"));
+        assert_eq!(search("co?f").unwrap().total_count, 1);
+        assert_eq!(search("name:test_?.py").unwrap().total_count, 1);
+        assert_eq!(search("TEST_?.PY").unwrap().total_count, 1);
+        assert_eq!(search("test_*.py AND assert").unwrap().total_count, 2);
+        assert_eq!(search("conf* -ext:md").unwrap().total_count, 1);
+
+        let err = search("*conf").err().unwrap();
+        assert!(err.downcast_ref::<query::BadWildcard>().is_some());
+        assert_eq!(search("*").unwrap().total_count, 3);
+    }
+
+    #[test]
+    fn test_prefix_and_substring_matches() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root, "users.py", "def get_user(id):\n    return db.getuser(id)");
+        write_fixture(&root, "other.py", "def get_group(id): pass");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let search = |query: &str, match_mode| {
+            let filters = SearchFilters {
+                match_mode,
+                ..SearchFilters::default()
+            };
+            idx.search_handle().search_filtered(query, 10, &filters).unwrap()
+        };
+        assert_eq!(search("get_us", MatchMode::Word).total_count, 0);
+
+        let output = search("get_us", MatchMode::Prefix);
+        assert_eq!(output.total_count, 1);
+        assert!(output.results[0].snippet.contains("**get_us**er"));
+        assert_eq!(search("get", MatchMode::Prefix).total_count, 2);
+        assert_eq!(search("ext:py AND getu", MatchMode::Prefix).total_count, 1);
+
+        assert_eq!(search("user", MatchMode::Word).total_count, 1);
+        let output = search("etuse", MatchMode::Substring);
+        assert_eq!(output.total_count, 1);
+        assert_eq!(output.results[0].line_number, Some(2));
+        assert_eq!(search("t_gro", MatchMode::Substring).total_count, 1);
+    }
+
+    #[test]
+    fn test_accepts_only_indexable_paths() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root, "notes/a.md", "first");
+        idx.index_directory(&root).unwrap();
+
+        assert!(idx.accepts(&root.join("notes/new.md")));
+        assert!(idx.accepts(&root.join("journal/2024/today.txt")));
+        assert!(!idx.accepts(&root.join("tool.exe")));
+        assert!(!idx.accepts(&root.join(".git/config")));
+        assert!(!idx.accepts(&root.join("node_modules/pkg/index.js")));
+        assert!(!idx.accepts(Path::new("/etc/motd.txt")));
+
+        assert!(idx.is_indexed(&root.join("notes/a.md")));
+        assert!(!idx.is_indexed(&root.join("notes/new.md")));
+        assert!(!idx.is_indexed(&root.join("notes")));
+    }
+
+    #[test]
+    fn test_complete_arguments() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root.join("notes"), "a.md", "first note");
+        write_fixture(&root.join("notes"), "b.md", "second note");
+        write_fixture(&root.join("notes"), "c.txt", "plain text");
+        write_fixture(&root.join("code"), "main.rs", "fn main() {}");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+        let dir_of = |name: &str| root.join(name).to_string_lossy().to_string();
+
+        assert_eq!(handle.complete_file_type("").unwrap(), ["md", "rs", "txt"]);
+        assert_eq!(handle.complete_file_type(".T").unwrap(), ["txt"]);
+        // The directories every file is under are skipped
+        assert_eq!(handle.complete_path_prefix("").unwrap(), [dir_of("notes"), dir_of("code")]);
+        let partial = format!("{}/N", root.display());
+        assert_eq!(handle.complete_path_prefix(&partial).unwrap(), [dir_of("notes")]);
+        let scoped = handle.scoped(Some(vec![root.join("code")]));
+        assert_eq!(scoped.complete_file_type("").unwrap(), ["rs"]);
+    }
+
+    #[test]
+    fn test_mime_filter_and_extensionless_scripts() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let json = write_fixture(fixtures.path(), "export.txt", "{\"kind\": \"mime_token\"}");
+        let text = write_fixture(fixtures.path(), "notes.txt", "mime_token in prose");
+        let script = "#!/usr/bin/env python3\nmime_token()\n";
+        let script = write_fixture(fixtures.path(), "deploy", script);
+        // Extensionless without a `#!` line: not indexed
+        let license = write_fixture(fixtures.path(), "LICENSE", "mime_token");
+        let mut idx = test_index(&dir);
+        idx.index_directory(fixtures.path()).unwrap();
+        idx.commit().unwrap();
+        let mut files = idx.list_files(None, None);
+        files.sort();
+        let mut expected: Vec<String> =
+            [&json, &text, &script].iter().map(|p| p.display().to_string()).collect();
+        expected.sort();
+        assert_eq!(files, expected);
+        assert!(!files.contains(&license.display().to_string()));
+
+        let search = |mime: &str| {
+            let filters = SearchFilters {
+                mime: Some(mime.to_string()),
+                ..SearchFilters::default()
+            };
+            let output = idx.search_handle().search_filtered("mime_token", 10, &filters).unwrap();
+            output.results.into_iter().map(|r| r.file_name).collect::<Vec<_>>()
+        };
+        assert_eq!(search("application/json"), vec!["export.txt"]);
+        assert_eq!(search("text/x-python"), vec!["deploy"]);
+        assert_eq!(search("text/*").len(), 2);
+        // The script is filed under its interpreter's extension
+        let py = idx.search("mime_token", 10, Some("py"), None).unwrap();
+        assert_eq!(py.results[0].file_name, "deploy");
+    }
+
+    #[test]
+    fn test_script_pseudo_extensions() {
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::builder().in_memory().extensions(["py", "lua"]).build().unwrap();
+        let python = write_fixture(fixtures.path(), "deploy", "#!/usr/bin/env python3\nrun()\n");
+        let lua = write_fixture(fixtures.path(), "build", "#!/usr/bin/lua5.4\nscript_token()\n");
+        // Not in the configured extensions
+        let shell = write_fixture(fixtures.path(), "backup", "#!/bin/bash\nscript_token\n");
+        for f in [&python, &lua, &shell] {
+            idx.index_file(f).unwrap();
+        }
+        idx.commit().unwrap();
+
+        let listed = |ext: &str| idx.list_files(Some(ext), None);
+        assert_eq!(listed("py"), vec![python.to_string_lossy().to_string()]);
+        assert_eq!(listed("lua"), vec![lua.to_string_lossy().to_string()]);
+        assert!(listed("sh").is_empty());
+        let found = idx.search("script_token", 10, Some("lua"), None).unwrap();
+        assert_eq!(found.results.len(), 1);
+        assert_eq!(found.results[0].file_name, "build");
+    }
+
+    #[test]
+    fn test_key_paths_searchable_with_line() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let yaml = write_fixture(
+            fixtures.path(),
+            "deploy.yaml",
+            "# web\nspec:\n  containers:\n    - name: web\n      image: nginx\n",
+        );
+        let toml = "name = \"x\"\n\n[server]\nport = 80\n";
+        let toml = write_fixture(fixtures.path(), "app.toml", toml);
+        let json = "{\n  \"server\": {\"host\": \"a\"}\n}";
+        let json = write_fixture(fixtures.path(), "app.json", json);
+        let mut idx = test_index(&dir);
+        for f in [&yaml, &toml, &json] {
+            idx.index_file(f).unwrap();
+        }
+        idx.commit().unwrap();
+
+        let image = idx.search("key:spec.containers.image", 10, None, None).unwrap();
+        assert_eq!(image.total_count, 1);
+        assert_eq!(image.results[0].line_number, Some(5));
+        assert!(image.results[0].snippet.contains("image: nginx"));
+
+        let port = idx.search("key:Server.Port", 10, None, None).unwrap();
+        assert_eq!(port.total_count, 1);
+        assert_eq!(port.results[0].line_number, Some(4));
+
+        // Every file that sets a key under `server`
+        let server = idx.search("key:server", 10, None, None).unwrap();
+        assert_eq!(server.total_count, 2);
+        let port = idx.search("key:port", 10, None, None).unwrap();
+        assert_eq!(port.total_count, 0);
+    }
+
+    #[test]
+    fn test_table_rows_searchable_by_column() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut csv = String::from("Order ID,Status,Note\n");
+        for id in 1..=tabular::ROWS_PER_CHUNK + 10 {
+            let status = if id == 7 || id == tabular::ROWS_PER_CHUNK + 3 {
+                "failed"
+            } else {
+                "shipped"
+            };
+            csv.push_str(&format!("{},{},\"customer said failed, maybe\"\n", id, status));
+        }
+        let f = write_fixture(fixtures.path(), "orders.csv", &csv);
+        let tsv = write_fixture(fixtures.path(), "people.tsv", "name\trole\nalice\tadmin\n");
+        let mut idx = test_index(&dir);
+        idx.index_file(&f).unwrap();
+        idx.index_file(&tsv).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.list_files(None, None).len(), 2);
+
+        let failed = idx.search("columns.status:failed", 10, None, None).unwrap();
+        assert_eq!(failed.total_count, 2);
+        let mut rows: Vec<usize> = failed.results.iter().filter_map(|r| r.row).collect();
+        rows.sort();
+        assert_eq!(rows, vec![7, tabular::ROWS_PER_CHUNK + 3]);
+        assert!(failed.results.iter().all(|r| r.line_number.is_none()));
+
+        let order = idx.search("columns.order_id:42", 10, None, None).unwrap();
+        assert_eq!(order.results[0].row, Some(42));
+        let admin = idx.search("columns.role:admin", 10, None, None).unwrap();
+        assert_eq!(admin.results[0].row, Some(1));
+        let none = idx.search("columns.role:alice", 10, None, None).unwrap();
+        assert_eq!(none.total_count, 0);
+
+        idx.remove_file(&f).unwrap();
+        idx.commit().unwrap();
+        let none = idx.search("columns.status:failed", 10, None, None).unwrap();
+        assert_eq!(none.total_count, 0);
+    }
+
+    #[test]
+    fn test_appended_log_lines_indexed_without_reindexing() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let f = write_fixture(fixtures.path(), "app.log", "2024-07-01T09:00:00Z first_token\n");
+        let mut idx = test_index(&dir);
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+
+        // Append, with a partly written last line
+        use std::io::Write;
+        let mut file = fs::OpenOptions::new().append(true).open(&f).unwrap();
+        file.write_all(b"\tat appended_token\n2024-07-01T09:01:00Z partial").unwrap();
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.search("first_token", 10, None, None).unwrap().total_count, 1);
+        let appended = idx.search("appended_token", 10, None, None).unwrap();
+        assert_eq!(appended.results[0].line_number, Some(2));
+        assert_eq!(appended.results[0].log_time, Some((1_719_824_400, 1_719_824_400)));
+        assert_eq!(idx.search("partial", 10, None, None).unwrap().total_count, 0);
+
+        file.write_all(b"_token\n").unwrap();
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        let finished = idx.search("partial_token", 10, None, None).unwrap();
+        assert_eq!(finished.results[0].line_number, Some(3));
+
+        // A rewrite is indexed in full, replacing every chunk
+        fs::write(&f, "2024-07-02T09:00:00Z rewritten_token\n").unwrap();
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.search("first_token", 10, None, None).unwrap().total_count, 0);
+        assert_eq!(idx.search("rewritten_token", 10, None, None).unwrap().total_count, 1);
+    }
+
+    #[test]
+    fn test_large_files_truncated_to_head_and_tail() {
+        let fixtures = TempDir::new().unwrap();
+        let mut content = String::from("head_token\n");
+        content.push_str(&"filler line\n".repeat(100));
+        content.push_str("tail_token\n");
+        let log = write_fixture(fixtures.path(), "big.log", &content);
+
+        let mut skipping = FileIndex::builder().in_memory().max_file_size(200).build().unwrap();
+        skipping.index_file(&log).unwrap();
+        skipping.commit().unwrap();
+        assert_eq!(skipping.status().num_files, 0);
+
+        let mut idx = FileIndex::builder()
+            .in_memory()
+            .max_file_size(200)
+            .large_files(LargeFiles::Truncate)
+            .build()
+            .unwrap();
+        idx.index_file(&log).unwrap();
+        idx.commit().unwrap();
+        for term in ["head_token", "tail_token"] {
+            let output = idx.search(term, 10, None, None).unwrap();
+            assert_eq!(output.total_count, 1);
+            assert!(output.results[0].truncated);
+        }
+        assert_eq!(LargeFiles::parse("Truncate").unwrap(), LargeFiles::Truncate);
+        assert!(LargeFiles::parse("chunk").is_err());
+    }
+
+    #[test]
+    fn test_hidden_policy_skips_junk_and_per_root_overrides() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path();
+        write_fixture(root, "notes.md", "visible_token");
+        write_fixture(root, ".github/ci.yml", "visible_token");
+        write_fixture(root, ".venv/lib/site.py", "junk_token");
+        write_fixture(root, "web/node_modules/pkg/index.js", "junk_token");
+        let mut idx = test_index(&dir);
+        idx.index_directory(root).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.list_files(None, None).len(), 2);
+        assert_eq!(idx.search("junk_token", 10, None, None).unwrap().total_count, 0);
+
+        // Watcher events below a skipped directory are ignored too
+        let late = write_fixture(root, ".venv/lib/late.py", "junk_token");
+        idx.index_file(&late).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.list_files(None, None).len(), 2);
+
+        // A root's own policy: no dotfiles, but node_modules allowed; re-indexing drops
+        // files the new policy excludes
+        idx.set_hidden_policy(
+            root,
+            HiddenPolicy {
+                hidden: false,
+                skip_dirs: HiddenPolicy::parse_skip_dirs("none"),
+            },
+        );
+        idx.index_directory(root).unwrap();
+        idx.commit().unwrap();
+        let files = idx.list_files(None, None);
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().any(|f| f.ends_with("notes.md")));
+        assert!(files.iter().any(|f| f.ends_with("index.js")));
+    }
+
+    #[test]
+    fn test_index_file_binary_skipped() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let f = fixtures.path().join("binary.rs");
+        // Invalid UTF-8 bytes cause read_to_string to fail, so the file is skipped
+        fs::write(&f, b"hello\xff\xfeworld").unwrap();
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.status().num_files, 0);
+    }
+
+    #[test]
+    fn test_index_file_upsert() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let f = write_fixture(fixtures.path(), "data.rs", "old_unique_content");
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+
+        // Overwrite with new content
+        fs::write(&f, "new_unique_content").unwrap();
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+
+        let old = idx.search("old_unique_content", 10, None, None).unwrap();
+        assert_eq!(old.results.len(), 0);
+        let new = idx.search("new_unique_content", 10, None, None).unwrap();
+        assert_eq!(new.results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_async_wrappers_run_off_the_executor() {
+        let fixtures = TempDir::new().unwrap();
+        write_fixture(fixtures.path(), "a.rs", "async_token");
+        let idx = Arc::new(tokio::sync::RwLock::new(FileIndex::builder().in_memory().build().unwrap()));
+        let root = fixtures.path().to_path_buf();
+        let count = write_blocking(idx.clone(), move |idx| {
+            let count = idx.index_directory(&root)?;
+            idx.commit()?;
+            anyhow::Ok(count)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(count, 1);
+
+        let handle = idx.read().await.search_handle();
+        let output = handle
+            .run(|h| h.search("async_token", 10, None, None))
+            .await
+            .unwrap();
+        assert_eq!(output.total_count, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links_indexed_once_and_readable_by_alias() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let real = write_fixture(&root, "real.md", "linked_token");
+        std::os::unix::fs::symlink(&real, root.join("link.md")).unwrap();
+        fs::hard_link(&real, root.join("hard.md")).unwrap();
+        let mut idx = test_index(&dir);
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+
+        assert_eq!(idx.list_files(None, None).len(), 1);
+        assert_eq!(idx.search("linked_token", 10, None, None).unwrap().total_count, 1);
+        for name in ["real.md", "link.md", "hard.md"] {
+            let content = idx.read_file(&root.join(name).to_string_lossy()).unwrap();
+            assert_eq!(content, "linked_token");
+        }
+
+        // Removing the indexed path keeps the file indexed under a remaining link
+        let primary = PathBuf::from(&idx.list_files(None, None)[0]);
+        fs::remove_file(&primary).unwrap();
+        idx.remove_file(&primary).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.list_files(None, None).len(), 1);
+        assert_ne!(PathBuf::from(&idx.list_files(None, None)[0]), primary);
+        assert_eq!(idx.search("linked_token", 10, None, None).unwrap().total_count, 1);
+    }
+
+    #[test]
+    fn test_index_file_skips_unchanged_content() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let f = write_fixture(fixtures.path(), "data.rs", "same_content");
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert!(!idx.has_uncommitted_changes());
+
+        // Rewrite with identical content, as `touch` or an editor save would
+        fs::write(&f, "same_content").unwrap();
+        idx.index_file(&f).unwrap();
+        assert!(!idx.has_uncommitted_changes());
+        assert_eq!(idx.pending_docs, 0);
+
+        fs::write(&f, "changed_content").unwrap();
+        idx.index_file(&f).unwrap();
+        assert!(idx.has_uncommitted_changes());
+
+        // Re-adding after removal indexes again
+        idx.remove_file(&f).unwrap();
+        idx.commit().unwrap();
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.search("changed_content", 10, None, None).unwrap().total_count, 1);
+    }
+
+    // -- index_directory --
+
+    #[test]
+    fn test_index_directory_recursive() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(fixtures.path(), "a.rs", "aaa");
+        write_fixture(fixtures.path(), "sub/b.py", "bbb");
+        write_fixture(fixtures.path(), "sub/deep/c.js", "ccc");
+        let count = idx.index_directory(fixtures.path()).unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_index_directory_skips_unsupported() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(fixtures.path(), "good.rs", "code");
+        write_fixture(fixtures.path(), "bad.png", "pixels");
+        write_fixture(fixtures.path(), "also_good.md", "docs");
+        let _count = idx.index_directory(fixtures.path()).unwrap();
+        // count includes all files walked (supported or not) since index_file returns Ok(())
+        // but num_files only tracks actually indexed ones
+        idx.commit().unwrap();
+        assert_eq!(idx.status().num_files, 2);
+    }
+
+    #[test]
+    fn test_status_reports_segments_and_size() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let a = write_fixture(fixtures.path(), "a.txt", "first");
+        idx.index_file(&a).unwrap();
+        idx.index_file(&write_fixture(fixtures.path(), "b.txt", "other")).unwrap();
+        idx.commit().unwrap();
+        fs::write(&a, "second").unwrap();
+        idx.index_file(&a).unwrap();
+        idx.commit().unwrap();
+        let status = idx.status();
+        // The first version stays on disk until its segment is merged away
+        assert_eq!((status.segments, status.deleted_docs), (2, 1));
+        assert!(status.disk_bytes.unwrap() > 0);
+        let in_memory = FileIndex::builder().in_memory().build().unwrap();
+        assert_eq!(in_memory.status().disk_bytes, None);
+    }
+
+    #[test]
+    fn test_index_directory_adds_watched_root() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(fixtures.path(), "a.rs", "code");
+        idx.index_directory(fixtures.path()).unwrap();
+        let status = idx.status();
+        assert!(status.watched_paths.contains(&fixtures.path().display().to_string()));
+    }
 
-            let snippet = Self::extract_snippet(content, &query_terms, 200);
-            let line_number = Self::find_match_line(content, &query_terms);
+    #[test]
+    fn test_index_directory_merges_nested_roots() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let sub = fixtures.path().join("src");
+        write_fixture(&sub, "a.rs", "aaa");
+        idx.index_directory(&sub).unwrap();
+        assert_eq!(idx.covering_root(&sub.join("a.rs")), Some(sub.as_path()));
+        assert_eq!(idx.nested_roots(fixtures.path()), vec![sub.clone()]);
 
-            results.push(SearchResult {
-                file_path,
-                file_name,
-                snippet,
-                score,
-                line_number,
-            });
-        }
+        idx.index_directory(fixtures.path()).unwrap();
+        assert_eq!(
+            idx.status().watched_paths,
+            vec![fixtures.path().display().to_string()]
+        );
+        assert!(idx.nested_roots(fixtures.path()).is_empty());
+    }
 
-        let total_count = results.len();
+    #[test]
+    fn test_verify_and_repair() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(&root, "a.txt", "alpha");
+        let b = write_fixture(&root, "b.txt", "bravo");
+        let c = write_fixture(&root, "c.txt", "charlie");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let report = idx.verify().unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.checked, 3);
 
-        Ok(SearchOutput {
-            results,
-            total_count,
-        })
-    }
+        // Changes the watcher never saw
+        std::fs::write(&b, "bravissimo").unwrap();
+        std::fs::remove_file(&c).unwrap();
+        let report = idx.verify().unwrap();
+        assert_eq!((report.changed.clone(), report.missing.clone()), (vec![b.clone()], vec![c]));
+        assert_eq!(idx.repair(&report).unwrap(), 2);
+        idx.commit().unwrap();
+        assert!(idx.verify().unwrap().is_clean());
+        assert_eq!(idx.search("bravissimo", 10, None, None).unwrap().results.len(), 1);
 
-    pub fn read_file(&self, path: &str) -> anyhow::Result<String> {
-        let path = std::path::Path::new(path).canonicalize()?;
-        if !self.indexed_paths.contains(&path) {
-            anyhow::bail!("File is not in the index: {}", path.display());
-        }
-        let content = std::fs::read_to_string(&path)?;
-        Ok(content)
+        // A new session has the documents but none of their metadata
+        drop(idx);
+        let mut idx = test_index(&dir);
+        let report = idx.verify().unwrap();
+        assert_eq!(report.orphaned, vec![root.join("a.txt"), b]);
+        idx.repair(&report).unwrap();
+        idx.commit().unwrap();
+        assert!(idx.verify().unwrap().is_clean());
+        assert_eq!(idx.list_files(None, None).len(), 2);
+        assert_eq!(idx.search("alpha", 10, None, None).unwrap().results.len(), 1);
     }
 
-    pub fn list_files(&self, extension: Option<&str>, path_prefix: Option<&str>) -> Vec<String> {
-        let mut files: Vec<String> = self
-            .indexed_paths
-            .iter()
-            .map(|p| p.to_string_lossy().to_string())
-            .filter(|p| {
-                if let Some(ext) = extension {
-                    let matches = Path::new(p)
-                        .extension()
-                        .and_then(|e| e.to_str())
-                        .map(|e| e.eq_ignore_ascii_case(ext))
-                        .unwrap_or(false);
-                    if !matches {
-                        return false;
-                    }
-                }
-                if let Some(prefix) = path_prefix {
-                    if !p.contains(prefix) {
-                        return false;
-                    }
-                }
-                true
-            })
-            .collect();
-        files.sort();
-        files
-    }
+    #[test]
+    fn test_file_info() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = test_index(&dir);
+        let notes = write_fixture(&root, "notes.md", "# Notes\n\nfirst\nsecond\n");
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let path = notes.to_string_lossy().to_string();
+        let info = idx.search_handle().file_info(&path).unwrap();
+        assert_eq!(info.path, notes);
+        assert_eq!(info.size, Some(22));
+        assert_eq!((info.extension.as_deref(), info.lines), (Some("md"), Some(4)));
+        assert!(info.indexed_at.is_some() && info.modified.is_some());
+        let hash = blake3::hash(b"# Notes\n\nfirst\nsecond\n").to_hex().to_string();
+        assert_eq!(info.content_hash, Some(hash));
+        assert_eq!(info.changed, Some(false));
 
-    pub fn status(&self) -> IndexStatus {
-        IndexStatus {
-            num_files: self.indexed_paths.len(),
-            watched_paths: self.watched_roots.iter().map(|p| p.display().to_string()).collect(),
-            index_path: self.index_path.display().to_string(),
-        }
+        std::fs::write(&notes, "rewritten").unwrap();
+        assert_eq!(idx.search_handle().file_info(&path).unwrap().changed, Some(true));
+        std::fs::remove_file(&notes).unwrap();
+        let info = idx.search_handle().file_info(&path).unwrap();
+        assert_eq!((info.size, info.changed), (None, Some(true)));
+        assert!(idx.file_info(&root.join("other.md").to_string_lossy()).is_err());
     }
 
-    fn is_supported(path: &Path) -> bool {
-        // Check known extensionless filenames
-        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-            let lower = name.to_lowercase();
-            if lower == "makefile" || lower == "dockerfile" {
-                return true;
-            }
-        }
-        path.extension()
-            .and_then(|e| e.to_str())
-            .map(|e| SUPPORTED_EXTENSIONS.contains(&e.to_lowercase().as_str()))
-            .unwrap_or(false)
-    }
+    #[test]
+    fn test_unwatched_roots_in_status() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let sub = fixtures.path().join("docs");
+        write_fixture(&sub, "a.md", "notes");
+        idx.index_directory(&sub).unwrap();
+        idx.set_watched(&sub, false);
+        assert!(!idx.is_watched(&sub));
+        let status = idx.status();
+        assert_eq!(status.unwatched_paths, vec![sub.display().to_string()]);
+        assert_eq!(status.watched_paths, status.unwatched_paths);
 
-    fn find_match_line(content: &str, query_terms: &[String]) -> Option<usize> {
-        let content_lower = content.to_lowercase();
-        for term in query_terms {
-            if let Some(pos) = content_lower.find(&term.to_lowercase()) {
-                // Count newlines before the match position (1-indexed)
-                let line = content[..pos].matches('\n').count() + 1;
-                return Some(line);
-            }
-        }
-        None
+        // Absorbing the root into a new one drops its record
+        idx.index_directory(fixtures.path()).unwrap();
+        assert!(idx.status().unwatched_paths.is_empty());
+        idx.set_watched(fixtures.path(), false);
+        idx.set_watched(fixtures.path(), true);
+        assert!(idx.is_watched(fixtures.path()));
     }
 
-    fn extract_snippet(content: &str, query_terms: &[String], window: usize) -> String {
-        let content_lower = content.to_lowercase();
-        let mut best_pos = 0;
-        for term in query_terms {
-            if let Some(pos) = content_lower.find(&term.to_lowercase()) {
-                best_pos = pos;
-                break;
-            }
-        }
-        let start = best_pos.saturating_sub(window / 2);
-        let end = (best_pos + window / 2).min(content.len());
+    #[test]
+    fn test_vanished_root_is_detached_and_reattached() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let sub = fixtures.path().join("usb");
+        write_fixture(&sub, "a.md", "removable notes");
+        idx.index_directory(&sub).unwrap();
+        idx.set_watched(&sub, false);
+        idx.commit().unwrap();
+        assert!(idx.vanished_roots().is_empty());
 
-        // Align to char boundaries
-        let start = {
-            let mut s = start;
-            while s > 0 && !content.is_char_boundary(s) {
-                s -= 1;
-            }
-            s
-        };
-        let end = {
-            let mut e = end.min(content.len());
-            while e < content.len() && !content.is_char_boundary(e) {
-                e += 1;
-            }
-            e
-        };
+        std::fs::remove_dir_all(&sub).unwrap();
+        assert_eq!(idx.vanished_roots(), vec![sub.clone()]);
+        assert_eq!(idx.detach_root(&sub).unwrap(), 1);
+        idx.commit().unwrap();
+        let status = idx.status();
+        assert!(status.watched_paths.is_empty());
+        assert_eq!(status.missing_paths, vec![sub.display().to_string()]);
+        assert_eq!(status.num_files, 0);
+        assert_eq!(idx.search("removable", 10, None, None).unwrap().total_count, 0);
+        assert!(!idx.missing_roots()[0].watched);
 
-        let snippet = &content[start..end];
-        format!("...{}...", snippet.trim())
+        write_fixture(&sub, "a.md", "removable notes");
+        idx.index_directory(&sub).unwrap();
+        assert!(idx.missing_roots().is_empty());
+        assert_eq!(idx.status().watched_paths, vec![sub.display().to_string()]);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use tempfile::TempDir;
 
-    fn test_index(dir: &TempDir) -> FileIndex {
-        let index_path = dir.path().join("index");
-        FileIndex::new(Some(index_path)).expect("failed to create test index")
+    #[test]
+    fn test_index_directory_commits_in_batches() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let config = IndexConfig {
+            writer_threads: Some(2),
+            writer_heap_bytes: 40_000_000,
+            commit_every_docs: 2,
+            ..IndexConfig::default()
+        };
+        let mut idx = FileIndex::with_config(Some(dir.path().join("index")), config).unwrap();
+        for i in 0..5 {
+            write_fixture(fixtures.path(), &format!("f{}.rs", i), "batch_token");
+        }
+        idx.index_directory(fixtures.path()).unwrap();
+        // Four of five files were committed mid-walk without an explicit commit
+        assert!(idx.status().last_commit.is_some());
+        assert_eq!(idx.search_handle().list_files(None, None).len(), 4);
     }
 
-    fn write_fixture(dir: &Path, name: &str, content: &str) -> PathBuf {
-        let path = dir.join(name);
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).unwrap();
-        }
-        fs::write(&path, content).unwrap();
-        path
+    #[test]
+    fn test_commit_if_due_defers_small_changes() {
+        let fixtures = TempDir::new().unwrap();
+        let config = IndexConfig {
+            commit_interval: Duration::from_secs(3600),
+            ..IndexConfig::default()
+        };
+        let mut idx = FileIndex::builder().config(config).in_memory().build().unwrap();
+        assert_eq!(idx.next_commit_due(), None);
+        let f = write_fixture(fixtures.path(), "a.rs", "deferred_token");
+        idx.commit().unwrap();
+        idx.index_file(&f).unwrap();
+
+        // Interval not yet elapsed since the last commit
+        assert!(!idx.commit_if_due().unwrap());
+        assert!(idx.next_commit_due().unwrap() > Instant::now());
+        assert_eq!(idx.generation(), 1);
+
+        idx.config.commit_interval = Duration::ZERO;
+        assert!(idx.commit_if_due().unwrap());
+        assert_eq!(idx.next_commit_due(), None);
+        assert_eq!(idx.search_handle().list_files(None, None).len(), 1);
     }
 
-    // -- Index creation & migration --
+    // -- rescan_path --
 
     #[test]
-    fn test_new_creates_index() {
+    fn test_rescan_path_syncs_directory() {
         let dir = TempDir::new().unwrap();
-        let index_path = dir.path().join("index");
-        let _idx = FileIndex::new(Some(index_path.clone())).unwrap();
-        let version = fs::read_to_string(index_path.join("schema_version")).unwrap();
-        assert_eq!(version.trim(), "2");
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let gone = write_fixture(fixtures.path(), "sub/gone.rs", "old");
+        idx.index_file(&gone).unwrap();
+        fs::remove_file(&gone).unwrap();
+        write_fixture(fixtures.path(), "sub/new.rs", "fresh");
+        idx.rescan_path(&fixtures.path().join("sub")).unwrap();
+        idx.commit().unwrap();
+        let files = idx.list_files(None, None);
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with("new.rs"));
+        assert!(idx.status().watched_paths.is_empty());
     }
 
+    // -- archives --
+
+    #[cfg(feature = "archives")]
     #[test]
-    fn test_new_opens_existing_index() {
+    fn test_index_zip_members() {
+        use std::io::Write;
         let dir = TempDir::new().unwrap();
-        let index_path = dir.path().join("index");
-        let _idx1 = FileIndex::new(Some(index_path.clone())).unwrap();
-        drop(_idx1);
-        let _idx2 = FileIndex::new(Some(index_path)).unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let archive_path = fixtures.path().canonicalize().unwrap().join("snap.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&archive_path).unwrap());
+        let opts = zip::write::SimpleFileOptions::default();
+        zip.start_file("src/main.rs", opts).unwrap();
+        zip.write_all(b"fn archived_token() {}").unwrap();
+        zip.start_file("logo.png", opts).unwrap();
+        zip.write_all(b"pixels").unwrap();
+        zip.finish().unwrap();
+
+        idx.index_file(&archive_path).unwrap();
+        idx.commit().unwrap();
+        let virtual_path = format!("{}!/src/main.rs", archive_path.display());
+        assert_eq!(idx.list_files(None, None), vec![virtual_path.clone()]);
+        let res = idx.search("archived_token", 10, None, None).unwrap();
+        assert_eq!(res.results[0].file_path, virtual_path);
+        assert_eq!(idx.read_file(&virtual_path).unwrap(), "fn archived_token() {}");
+
+        idx.remove_file(&archive_path).unwrap();
+        assert!(idx.list_files(None, None).is_empty());
     }
 
+    #[cfg(feature = "archives")]
     #[test]
-    fn test_schema_version_migration() {
+    fn test_index_tar_gz_members() {
         let dir = TempDir::new().unwrap();
-        let index_path = dir.path().join("index");
-        let _idx = FileIndex::new(Some(index_path.clone())).unwrap();
-        drop(_idx);
-        // Overwrite version to trigger migration
-        fs::write(index_path.join("schema_version"), "1").unwrap();
-        let _idx2 = FileIndex::new(Some(index_path.clone())).unwrap();
-        let version = fs::read_to_string(index_path.join("schema_version")).unwrap();
-        assert_eq!(version.trim(), "2");
-    }
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let archive_path = fixtures.path().canonicalize().unwrap().join("snap.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            fs::File::create(&archive_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        let body = b"fn tarred_token() {}";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(body.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "src/lib.rs", &body[..]).unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
 
-    // -- is_supported --
+        idx.index_file(&archive_path).unwrap();
+        idx.commit().unwrap();
+        let virtual_path = format!("{}!/src/lib.rs", archive_path.display());
+        assert_eq!(idx.list_files(None, None), vec![virtual_path.clone()]);
+        let res = idx.search("tarred_token", 10, None, None).unwrap();
+        assert_eq!(res.results[0].file_path, virtual_path);
+        assert_eq!(idx.read_file(&virtual_path).unwrap(), "fn tarred_token() {}");
+        assert!(idx.file_info(&virtual_path).unwrap().size.is_some());
+    }
 
     #[test]
-    fn test_is_supported_common_extensions() {
-        for ext in &["rs", "py", "js", "md", "yaml"] {
-            let p = PathBuf::from(format!("test.{}", ext));
-            assert!(FileIndex::is_supported(&p), "expected {} to be supported", ext);
-        }
+    fn test_bang_directory_is_not_an_archive() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let odd = fixtures.path().canonicalize().unwrap().join("draft!");
+        fs::create_dir(&odd).unwrap();
+        let file = write_fixture(&odd, "notes.md", "exclaimed_token");
+
+        idx.index_file(&file).unwrap();
+        idx.commit().unwrap();
+        let path = file.display().to_string();
+        assert_eq!(idx.read_file(&path).unwrap(), "exclaimed_token");
+        let info = idx.file_info(&path).unwrap();
+        assert_eq!(info.size, Some("exclaimed_token".len() as u64));
+        assert_eq!(info.changed, Some(false));
     }
 
+    #[cfg(feature = "sqlite")]
     #[test]
-    fn test_is_supported_makefile_dockerfile() {
-        assert!(FileIndex::is_supported(Path::new("Makefile")));
-        assert!(FileIndex::is_supported(Path::new("Dockerfile")));
+    fn test_index_sqlite_schema_and_rows() {
+        let fixtures = TempDir::new().unwrap();
+        let db_path = fixtures.path().canonicalize().unwrap().join("app.db");
+        let db = rusqlite::Connection::open(&db_path).unwrap();
+        db.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT, name TEXT);
+             INSERT INTO users (email, name) VALUES ('alice@example.com', 'Alice');
+             INSERT INTO users (email, name) VALUES ('bob@example.com', 'Bob');",
+        )
+        .unwrap();
+        drop(db);
+        let not_sqlite = write_fixture(fixtures.path(), "other.db", "berkeley_token");
+
+        let mut idx = FileIndex::builder().in_memory().sqlite_rows(1).build().unwrap();
+        idx.index_file(&db_path).unwrap();
+        idx.index_file(&not_sqlite).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.list_files(None, None), vec![db_path.display().to_string()]);
+
+        let email = idx.search("key:users.email", 10, None, None).unwrap();
+        assert_eq!(email.total_count, 1);
+        assert_eq!(email.results[0].line_number, Some(3));
+        // Only the first row is indexed
+        assert_eq!(idx.search("alice", 10, None, None).unwrap().total_count, 1);
+        assert_eq!(idx.search("bob", 10, None, None).unwrap().total_count, 0);
+        let schema = idx.read_file(&db_path.display().to_string()).unwrap();
+        assert!(schema.contains("column users.email (TEXT)"));
+        assert!(!schema.contains("alice"));
+
+        // Without a row limit configured, databases are not indexed
+        let mut off = FileIndex::builder().in_memory().build().unwrap();
+        off.index_file(&db_path).unwrap();
+        off.commit().unwrap();
+        assert!(off.list_files(None, None).is_empty());
     }
 
+    #[cfg(all(feature = "spreadsheets", feature = "archives"))]
     #[test]
-    fn test_is_supported_unsupported() {
-        for ext in &["png", "jpg", "exe"] {
-            let p = PathBuf::from(format!("test.{}", ext));
-            assert!(!FileIndex::is_supported(&p), "expected {} to be unsupported", ext);
+    fn test_index_spreadsheet_sheets_and_rows() {
+        use std::io::Write;
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let book = fixtures.path().canonicalize().unwrap().join("budget.xlsx");
+        // A minimal workbook: one sheet with inline strings and a number
+        let mut zip = zip::ZipWriter::new(fs::File::create(&book).unwrap());
+        let opts = zip::write::SimpleFileOptions::default();
+        let files = [
+            (
+                "xl/workbook.xml",
+                r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"
+                    xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+                    <sheets><sheet name="Q3" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+                    <Relationship Id="rId1" Target="worksheets/sheet1.xml"
+                    Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet"/>
+                    </Relationships>"#,
+            ),
+            (
+                "xl/worksheets/sheet1.xml",
+                r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+                    <sheetData>
+                    <row r="1"><c r="A1" t="inlineStr"><is><t>Item</t></is></c></row>
+                    <row r="3"><c r="A3" t="inlineStr"><is><t>travel_budget</t></is></c>
+                    <c r="B3"><v>1200</v></c></row>
+                    </sheetData></worksheet>"#,
+            ),
+        ];
+        for (name, xml) in files {
+            zip.start_file(name, opts).unwrap();
+            zip.write_all(xml.as_bytes()).unwrap();
         }
-    }
+        zip.finish().unwrap();
 
-    #[test]
-    fn test_is_supported_no_extension() {
-        assert!(!FileIndex::is_supported(Path::new("README")));
-    }
+        let mut idx = test_index(&dir);
+        idx.index_file(&book).unwrap();
+        idx.commit().unwrap();
+        let sheet = format!("{}!/Q3", book.display());
+        assert_eq!(idx.list_files(None, None), vec![sheet.clone()]);
 
-    #[test]
-    fn test_is_supported_case_insensitive() {
-        assert!(FileIndex::is_supported(Path::new("test.RS")));
-        assert!(FileIndex::is_supported(Path::new("test.Py")));
+        let res = idx.search("travel_budget", 10, None, None).unwrap();
+        assert_eq!(res.results[0].file_path, sheet);
+        assert_eq!(res.results[0].row, Some(3));
+        assert_eq!(res.results[0].file_name, "budget.xlsx");
+        assert_eq!(idx.read_file(&sheet).unwrap(), "Item\n\ntravel_budget | 1200");
+
+        idx.remove_file(&book).unwrap();
+        assert!(idx.list_files(None, None).is_empty());
     }
 
-    // -- index_file --
+    // -- notebooks --
 
     #[test]
-    fn test_index_file_basic() {
+    fn test_index_notebook_maps_cells() {
         let dir = TempDir::new().unwrap();
         let fixtures = TempDir::new().unwrap();
         let mut idx = test_index(&dir);
-        let f = write_fixture(fixtures.path(), "hello.rs", "fn main() {}");
+        let nb = r##"{"cells": [
+            {"cell_type": "markdown", "source": ["# Analysis\n"]},
+            {"cell_type": "code", "source": ["df = load()\n", "notebook_token(df)"],
+             "outputs": [{"data": {"image/png": "base64blobtoken"}}]}
+        ]}"##;
+        let f = write_fixture(fixtures.path(), "analysis.ipynb", nb);
         idx.index_file(&f).unwrap();
         idx.commit().unwrap();
-        assert_eq!(idx.status().num_files, 1);
+        let res = idx.search("notebook_token", 10, None, None).unwrap();
+        assert_eq!(res.results.len(), 1);
+        assert_eq!(res.results[0].cell, Some(1));
+        assert_eq!(idx.search("base64blobtoken", 10, None, None).unwrap().results.len(), 0);
+        // Cell boundaries are not part of the indexed text
+        assert!(!res.results[0].snippet.contains("[cell"), "{}", res.results[0].snippet);
+        assert_eq!(idx.search("cell", 10, None, None).unwrap().results.len(), 0);
     }
 
+    // -- markdown --
+
     #[test]
-    fn test_index_file_unsupported_skipped() {
+    fn test_markdown_front_matter_tags_and_sections() {
         let dir = TempDir::new().unwrap();
         let fixtures = TempDir::new().unwrap();
         let mut idx = test_index(&dir);
-        let f = write_fixture(fixtures.path(), "image.png", "not really an image");
-        idx.index_file(&f).unwrap();
+        let note = "---\ntitle: Garden Plans\ntags: [Outdoors, project/alpha]\n---\n\
+                    # Spring\nplant seeds\n## Tools\nshovel_token\n";
+        let f1 = write_fixture(fixtures.path(), "garden.md", note);
+        let f2 = write_fixture(fixtures.path(), "other.md", "---\ntags: outdoors\n---\nhike\n");
+        idx.index_file(&f1).unwrap();
+        idx.index_file(&f2).unwrap();
         idx.commit().unwrap();
-        assert_eq!(idx.status().num_files, 0);
+
+        let res = idx.search("tag:\"project/alpha\"", 10, None, None).unwrap();
+        assert_eq!(res.results.len(), 1);
+        assert_eq!(idx.search("tag:OUTDOORS", 10, None, None).unwrap().results.len(), 2);
+        assert_eq!(idx.search("title:garden", 10, None, None).unwrap().results.len(), 1);
+        // Field aliases; unknown fields are errors naming the valid ones
+        assert_eq!(idx.search("Tags:outdoors", 10, None, None).unwrap().results.len(), 2);
+        let err = idx.search("tagz:outdoors", 10, None, None).err().unwrap();
+        assert!(err.downcast_ref::<query::UnknownField>().is_some());
+
+        let res = idx.search("shovel_token", 10, None, None).unwrap();
+        assert_eq!(res.results[0].section.as_deref(), Some("Tools"));
+
+        let tags = idx.list_tags();
+        assert_eq!(tags.get("outdoors"), Some(&2));
+        assert_eq!(tags.get("project/alpha"), Some(&1));
     }
 
     #[test]
-    fn test_index_file_binary_skipped() {
+    fn test_backlinks_wiki_and_relative() {
         let dir = TempDir::new().unwrap();
         let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
         let mut idx = test_index(&dir);
-        let f = fixtures.path().join("binary.rs");
-        // Invalid UTF-8 bytes cause read_to_string to fail, so the file is skipped
-        fs::write(&f, b"hello\xff\xfeworld").unwrap();
-        idx.index_file(&f).unwrap();
-        idx.commit().unwrap();
-        assert_eq!(idx.status().num_files, 0);
+        let target = write_fixture(&root, "notes/Project Plan.md", "# Plan\n");
+        let a = write_fixture(&root, "daily/a.md", "Reviewed [[project plan|the plan]].");
+        let b = write_fixture(&root, "daily/b.md", "See [plan](../notes/Project%20Plan.md).");
+        let c = write_fixture(&root, "daily/c.md", "Links to [[Something Else]].");
+        for f in [&target, &a, &b, &c] {
+            idx.index_file(f).unwrap();
+        }
+
+        let expected = vec![a.display().to_string(), b.display().to_string()];
+        assert_eq!(idx.backlinks("Project Plan"), expected);
+        assert_eq!(idx.backlinks(target.to_str().unwrap()), expected);
+        assert!(idx.backlinks("Nobody").is_empty());
     }
 
+    // -- email --
+
     #[test]
-    fn test_index_file_upsert() {
+    fn test_index_mbox_messages() {
         let dir = TempDir::new().unwrap();
         let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
         let mut idx = test_index(&dir);
-        let f = write_fixture(fixtures.path(), "data.rs", "old_unique_content");
+        let mbox = "From alice@example.com Mon Jul  1 09:00:00 2024\n\
+                    From: Alice <alice@example.com>\nSubject: Time off\n\nvacation next week\n\n\
+                    From bob@example.com Mon Jul  1 10:00:00 2024\n\
+                    From: Bob <bob@example.com>\nSubject: Trip\n\nvacation photos\n";
+        let f = write_fixture(&root, "inbox.mbox", mbox);
         idx.index_file(&f).unwrap();
         idx.commit().unwrap();
 
-        // Overwrite with new content
-        fs::write(&f, "new_unique_content").unwrap();
-        idx.index_file(&f).unwrap();
-        idx.commit().unwrap();
+        let res = idx.search("from:alice vacation", 10, None, None).unwrap();
+        assert_eq!(res.results.len(), 1);
+        assert_eq!(res.results[0].file_name, "Time off");
+        assert_eq!(res.results[0].file_path, format!("{}!/1", f.display()));
+        let message = idx.read_file(&res.results[0].file_path).unwrap();
+        assert!(message.contains("Subject: Time off"));
 
-        let old = idx.search("old_unique_content", 10, None, None).unwrap();
-        assert_eq!(old.results.len(), 0);
-        let new = idx.search("new_unique_content", 10, None, None).unwrap();
-        assert_eq!(new.results.len(), 1);
+        idx.remove_file(&f).unwrap();
+        assert!(idx.list_files(None, None).is_empty());
     }
 
-    // -- index_directory --
+    // -- ocr --
 
     #[test]
-    fn test_index_directory_recursive() {
+    fn test_index_file_with_ocr() {
         let dir = TempDir::new().unwrap();
         let fixtures = TempDir::new().unwrap();
         let mut idx = test_index(&dir);
-        write_fixture(fixtures.path(), "a.rs", "aaa");
-        write_fixture(fixtures.path(), "sub/b.py", "bbb");
-        write_fixture(fixtures.path(), "sub/deep/c.js", "ccc");
-        let count = idx.index_directory(fixtures.path()).unwrap();
-        assert_eq!(count, 3);
+        let f = write_fixture(fixtures.path(), "receipt.png", "scanned_receipt_token");
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.status().num_files, 0);
+
+        idx.set_ocr(OcrConfig::new(Some(dir.path().join("ocr"))).with_command("png", "cat"));
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        let res = idx.search("scanned_receipt_token", 10, None, None).unwrap();
+        assert_eq!(res.results.len(), 1);
     }
 
+    // -- search handle --
+
     #[test]
-    fn test_index_directory_skips_unsupported() {
+    fn test_search_handle_sees_commits_only() {
         let dir = TempDir::new().unwrap();
         let fixtures = TempDir::new().unwrap();
         let mut idx = test_index(&dir);
-        write_fixture(fixtures.path(), "good.rs", "code");
-        write_fixture(fixtures.path(), "bad.png", "pixels");
-        write_fixture(fixtures.path(), "also_good.md", "docs");
-        let _count = idx.index_directory(fixtures.path()).unwrap();
-        // count includes all files walked (supported or not) since index_file returns Ok(())
-        // but num_files only tracks actually indexed ones
+        let handle = idx.search_handle();
+        let f = write_fixture(fixtures.path(), "a.rs", "handle_token");
+        idx.index_file(&f).unwrap();
+        assert!(handle.list_files(None, None).is_empty());
         idx.commit().unwrap();
-        assert_eq!(idx.status().num_files, 2);
+        assert_eq!(handle.list_files(None, None).len(), 1);
+        assert_eq!(handle.search("handle_token", 10, None, None).unwrap().results.len(), 1);
     }
 
-    #[test]
-    fn test_index_directory_adds_watched_root() {
+    #[tokio::test]
+    async fn test_generations_and_wait() {
         let dir = TempDir::new().unwrap();
         let fixtures = TempDir::new().unwrap();
         let mut idx = test_index(&dir);
-        write_fixture(fixtures.path(), "a.rs", "code");
-        idx.index_directory(fixtures.path()).unwrap();
-        let status = idx.status();
-        assert!(status.watched_paths.contains(&fixtures.path().display().to_string()));
+        let handle = idx.search_handle();
+        assert_eq!(handle.generation(), 0);
+
+        // Removed and not yet re-added: searches still see the last commit
+        let f = write_fixture(fixtures.path(), "a.rs", "generation_token");
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        idx.remove_file(&f).unwrap();
+        let output = handle.search("generation_token", 10, None, None).unwrap();
+        assert_eq!((output.total_count, output.generation), (1, 1));
+
+        let timeout = Duration::from_millis(50);
+        assert!(handle.wait_for_generation(1, timeout).await);
+        assert!(!handle.wait_for_generation(2, timeout).await);
+        let waiter = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.wait_for_generation(2, Duration::from_secs(5)).await }
+        });
+        idx.commit().unwrap();
+        assert!(waiter.await.unwrap());
+        assert_eq!(idx.status().generation, 2);
     }
 
     // -- search: keyword --
@@ -651,6 +7441,60 @@ mod tests {
         assert_eq!(res.results.len(), 0);
     }
 
+    #[test]
+    fn test_search_relevance_and_min_score() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let strong = write_fixture(fixtures.path(), "strong.txt", "quota quota quota exceeded");
+        let weak = "quota mentioned once among many other unrelated words in a longer text";
+        let weak = write_fixture(fixtures.path(), "weak.txt", weak);
+        idx.index_file(&strong).unwrap();
+        idx.index_file(&weak).unwrap();
+        idx.commit().unwrap();
+
+        let res = idx.search("quota", 10, None, None).unwrap();
+        assert_eq!(res.results[0].file_name, "strong.txt");
+        assert_eq!(res.results[0].relevance, 1.0);
+        let weak_relevance = res.results[1].relevance;
+        assert!(weak_relevance > 0.0 && weak_relevance < 1.0);
+
+        let filters = SearchFilters {
+            min_score: Some((1.0 + weak_relevance) / 2.0),
+            ..SearchFilters::default()
+        };
+        let res = idx.search_handle().search_filtered("quota", 10, &filters).unwrap();
+        assert_eq!(res.results.len(), 1);
+        assert_eq!(res.total_count, 1);
+    }
+
+    #[test]
+    fn test_search_within_files() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let mut paths = Vec::new();
+        for (name, content) in [("a.rs", "retry timeout"), ("b.rs", "retry"), ("c.rs", "timeout")] {
+            let f = write_fixture(fixtures.path(), name, content);
+            idx.index_file(&f).unwrap();
+            paths.push(f.display().to_string());
+        }
+        idx.commit().unwrap();
+
+        let search = |query: &str| {
+            let filters = SearchFilters {
+                within: Some(paths[..2].to_vec()),
+                ..SearchFilters::default()
+            };
+            let output = idx.search_handle().search_filtered(query, 10, &filters).unwrap();
+            let mut names: Vec<String> = output.results.into_iter().map(|r| r.file_name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(search("timeout"), vec!["a.rs"]);
+        assert_eq!(search(""), vec!["a.rs", "b.rs"]);
+    }
+
     // -- search: field-based --
 
     #[test]
@@ -830,17 +7674,594 @@ mod tests {
         idx.commit().unwrap();
         let status = idx.status();
         assert!(status.num_files >= 1);
-        assert!(!status.index_path.is_empty());
+        assert!(status.index_path.is_some_and(|p| !p.is_empty()));
         assert!(!status.watched_paths.is_empty());
     }
 
+    #[test]
+    fn test_second_index_on_same_path_fails_without_deleting() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        let mut idx = FileIndex::new(Some(index_path.clone())).unwrap();
+        let f = write_fixture(fixtures.path(), "kept.rs", "kept_token");
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+
+        // Even with a stale schema version, the second process must not recreate the index
+        fs::write(index_path.join("schema_version"), "1").unwrap();
+        let err = FileIndex::new(Some(index_path.clone())).err().unwrap();
+        assert!(err.downcast_ref::<crate::instance::IndexInUse>().is_some());
+        assert!(index_path.exists());
+        assert_eq!(idx.search("kept_token", 10, None, None).unwrap().total_count, 1);
+    }
+
+    #[test]
+    fn test_index_directory_skips_git_internals() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(fixtures.path(), "src/lib.rs", "visible_token");
+        write_fixture(fixtures.path(), ".git/description", "hidden_token");
+        write_fixture(fixtures.path(), ".git/logs/HEAD.log", "hidden_token");
+        idx.index_directory(fixtures.path()).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.list_files(None, None).len(), 1);
+        assert_eq!(idx.search("hidden_token", 10, None, None).unwrap().total_count, 0);
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn test_git_metadata_tracked_only_and_author() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let repo = git2::Repository::init(&root).unwrap();
+        write_fixture(&root, "tracked.rs", "shared_token");
+        write_fixture(&root, "scratch.rs", "shared_token");
+        let mut git_index = repo.index().unwrap();
+        git_index.add_path(Path::new("tracked.rs")).unwrap();
+        git_index.write().unwrap();
+        let tree = repo.find_tree(git_index.write_tree().unwrap()).unwrap();
+        let alice = git2::Signature::now("Alice", "alice@example.com").unwrap();
+        repo.commit(Some("HEAD"), &alice, &alice, "add tracked", &tree, &[])
+            .unwrap();
+
+        let mut idx = test_index(&dir);
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+        assert_eq!(handle.search("shared_token", 10, None, None).unwrap().total_count, 2);
+        let tracked = handle
+            .search_filtered(
+                "shared_token",
+                10,
+                &SearchFilters {
+                    tracked_only: true,
+                    ..SearchFilters::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(tracked.total_count, 1);
+        assert_eq!(tracked.results[0].file_name, "tracked.rs");
+        let by_author = handle.search("author:alice", 10, None, None).unwrap();
+        assert_eq!(by_author.total_count, 1);
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn test_history_search_finds_removed_content() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let repo = git2::Repository::init(&root).unwrap();
+        let signature = git2::Signature::now("Alice", "alice@example.com").unwrap();
+        let commit_file = |content: &str, parents: &[&git2::Commit]| {
+            write_fixture(&root, "config.toml", content);
+            let mut git_index = repo.index().unwrap();
+            git_index.add_path(Path::new("config.toml")).unwrap();
+            git_index.write().unwrap();
+            let tree = repo.find_tree(git_index.write_tree().unwrap()).unwrap();
+            let oid = repo
+                .commit(Some("HEAD"), &signature, &signature, "update", &tree, parents)
+                .unwrap();
+            repo.find_commit(oid).unwrap()
+        };
+        let first = commit_file("legacy_timeout = 30", &[]);
+        repo.tag_lightweight("v1.0", first.as_object(), false).unwrap();
+        commit_file("timeout = 30", &[&first]);
+
+        let mut idx = test_index(&dir);
+        idx.set_history_revisions(vec!["tags".to_string()]);
+        idx.index_directory(&root).unwrap();
+        idx.commit().unwrap();
+
+        // The working copy no longer has the key, and history docs stay out of normal search
+        assert_eq!(idx.search("legacy_timeout", 10, None, None).unwrap().total_count, 0);
+        let history = idx.search_history("legacy_timeout", 10, None).unwrap();
+        assert_eq!(history.total_count, 1);
+        assert_eq!(history.results[0].revision.as_deref(), Some("v1.0"));
+        assert!(history.results[0].file_path.ends_with("config.toml@v1.0"));
+        assert!(history.results[0].commit_time.is_some());
+        assert_eq!(idx.search_history("legacy_timeout", 10, Some("v2.0")).unwrap().total_count, 0);
+        // The working copy is still listed once
+        assert_eq!(idx.list_files(None, None).len(), 1);
+    }
+
+    #[test]
+    fn test_similar_files_by_path_and_text() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let parser = write_fixture(
+            fixtures.path(),
+            "parser.rs",
+            "fn parse_token(lexer: &mut Lexer) -> Token { lexer.next_token() }",
+        );
+        write_fixture(
+            fixtures.path(),
+            "parser_test.rs",
+            "fn test_parse_token() { let mut lexer = Lexer::new(); parse_token(&mut lexer); }",
+        );
+        write_fixture(fixtures.path(), "readme.md", "Installation instructions for the project");
+        idx.index_directory(fixtures.path()).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+
+        let similar = handle
+            .similar_files(Some(&parser.to_string_lossy()), None, 5)
+            .unwrap();
+        assert_eq!(similar.len(), 1);
+        assert!(similar[0].file_path.ends_with("parser_test.rs"));
+
+        let by_text = handle.similar_files(None, Some("project installation"), 5).unwrap();
+        assert!(by_text[0].file_path.ends_with("readme.md"));
+        assert!(handle.similar_files(None, None, 5).is_err());
+    }
+
+    #[test]
+    fn test_analyze_terms_whole_index_subtree_and_keyword() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(fixtures.path(), "src/a.rs", "widget render widget 42");
+        write_fixture(fixtures.path(), "src/b.rs", "widget layout");
+        write_fixture(fixtures.path(), "docs/c.md", "layout guide");
+        idx.index_directory(fixtures.path()).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+
+        let all = handle.analyze_terms(None, None, 10).unwrap();
+        assert!(!all.approximate);
+        assert_eq!(all.documents, 3);
+        assert_eq!(all.terms[0], ("layout".to_string(), 2));
+        assert_eq!(all.terms[1], ("widget".to_string(), 2));
+        assert!(all.terms.iter().all(|(t, _)| t != "42"));
+
+        let src = handle.analyze_terms(None, Some("src"), 10).unwrap();
+        assert_eq!(src.documents, 2);
+        assert_eq!(src.terms[0], ("widget".to_string(), 2));
+
+        let with_layout = handle.analyze_terms(Some("layout"), None, 10).unwrap();
+        assert_eq!(with_layout.documents, 2);
+        assert!(with_layout.terms.iter().all(|(t, _)| t != "layout"));
+        assert!(with_layout.terms.contains(&("widget".to_string(), 1)));
+        assert!(with_layout.terms.contains(&("guide".to_string(), 1)));
+    }
+
+    #[test]
+    fn test_analyze_terms_counts_split_files_once() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        let log: String = (0..logs::CHUNK_LINES * 2)
+            .map(|i| format!("2024-07-01T{:02}:{:02}:00Z INFO replica lag\n", i / 60, i % 60))
+            .collect();
+        write_fixture(fixtures.path(), "logs/app.log", &log);
+        write_fixture(fixtures.path(), "logs/notes.md", "replica setup");
+        idx.index_directory(fixtures.path()).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+
+        // The log's two chunks are two documents in the term dictionaries
+        let all = handle.analyze_terms(None, None, 10).unwrap();
+        assert!(all.approximate);
+        assert_eq!(all.documents, 3);
+        assert_eq!(all.terms[0], ("replica".to_string(), 3));
+
+        let subtree = handle.analyze_terms(None, Some("logs"), 1000).unwrap();
+        assert!(!subtree.approximate);
+        assert_eq!(subtree.documents, 2);
+        assert_eq!(subtree.terms[0], ("replica".to_string(), 2));
+        assert!(subtree.terms.contains(&("lag".to_string(), 1)));
+    }
+
+    #[test]
+    fn test_suggest_completes_last_word_by_frequency() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        write_fixture(fixtures.path(), "a.md", "---\ntags: [project]\n---\nconfigure configuration");
+        write_fixture(fixtures.path(), "b.rs", "configuration loader");
+        idx.index_directory(fixtures.path()).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+
+        let suggestions = handle.suggest("load Config", 5).unwrap();
+        assert_eq!(suggestions[0], ("load configuration".to_string(), 2));
+        assert_eq!(suggestions[1], ("load configure".to_string(), 1));
+        assert_eq!(
+            handle.suggest("tag:pro", 5).unwrap(),
+            vec![("tag:project".to_string(), 1)]
+        );
+        assert!(handle.suggest("", 5).unwrap().is_empty());
+        assert!(handle.suggest("nosuchfield:x", 5).is_err());
+    }
+
+    #[test]
+    fn test_suggest_counts_each_live_file_once() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        // Two chunks, and the word in the file name as well as the content
+        let log: String = (0..logs::CHUNK_LINES * 2)
+            .map(|i| format!("2024-07-01T{:02}:{:02}:00Z replicator ok\n", i / 60, i % 60))
+            .collect();
+        write_fixture(fixtures.path(), "replicator.log", &log);
+        let gone = write_fixture(fixtures.path(), "old.md", "replicated replicated");
+        idx.index_directory(fixtures.path()).unwrap();
+        idx.commit().unwrap();
+        idx.remove_file(&gone).unwrap();
+        idx.commit().unwrap();
+
+        let handle = idx.search_handle();
+        assert_eq!(handle.suggest("replic", 5).unwrap(), vec![("replicator".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_in_memory_index_writes_nothing_to_disk() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let config = IndexConfig {
+            in_memory: true,
+            ..IndexConfig::default()
+        };
+        let index_path = dir.path().join("index");
+        let mut idx = FileIndex::with_config(Some(index_path.clone()), config).unwrap();
+        let f = write_fixture(fixtures.path(), "ram.rs", "ephemeral_token");
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(idx.search("ephemeral_token", 10, None, None).unwrap().total_count, 1);
+        assert!(idx.status().index_path.is_none());
+        assert!(!index_path.exists());
+    }
+
+    #[test]
+    fn test_status_timestamps_and_root_counts() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = test_index(&dir);
+        assert!(idx.status().last_indexed.is_none());
+        assert!(idx.status().last_commit.is_none());
+        write_fixture(fixtures.path(), "a.rs", "aaa");
+        write_fixture(fixtures.path(), "sub/b.rs", "bbb");
+        idx.index_directory(fixtures.path()).unwrap();
+        assert!(idx.status().last_indexed.is_some());
+        assert!(idx.status().last_commit.is_none());
+        idx.commit().unwrap();
+        let status = idx.status();
+        assert!(status.last_commit.is_some());
+        assert_eq!(status.root_counts, vec![2]);
+    }
+
+    #[test]
+    fn test_interrupted_walk_resumes_after_completed_directories() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let index_path = dir.path().join("index");
+        let config = IndexConfig {
+            commit_every_docs: 1,
+            ..IndexConfig::default()
+        };
+        let mut idx = FileIndex::with_config(Some(index_path.clone()), config).unwrap();
+        let one = write_fixture(&root, "a/one.md", "alpha");
+        let same = write_fixture(&root, "a/same.md", "gamma");
+        let gone = write_fixture(&root, "a/gone.md", "delta");
+        let two = write_fixture(&root, "b/two.md", "beta");
+        idx.index_directory(&root).unwrap();
+        // Saved by the commits during the walk, removed by the one after it
+        let saved = checkpoint::load(&index_path).unwrap().unwrap();
+        assert_eq!((saved.root.path.as_path(), saved.done.len()), (root.as_path(), 1));
+        idx.commit().unwrap();
+        assert_eq!(checkpoint::load(&index_path).unwrap(), None);
+        drop(idx);
+
+        // Killed after finishing `a`; then `one.md` is edited and `gone.md` deleted
+        let done = BTreeSet::from([root.join("a")]);
+        checkpoint::save(&index_path, &Checkpoint { done, ..saved }).unwrap();
+        fs::write(&one, "alphanew").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        fs::File::options().write(true).open(&one).unwrap().set_modified(later).unwrap();
+        fs::remove_file(&gone).unwrap();
+        fs::write(&two, "betanew").unwrap();
+        let mut idx = FileIndex::new(Some(index_path.clone())).unwrap();
+        assert_eq!(idx.interrupted_crawl().unwrap().root.path, root);
+        // `same.md` is unchanged, so only `one.md` and `two.md` are read again
+        assert_eq!(idx.index_directory(&root).unwrap(), 2);
+        idx.commit().unwrap();
+        assert_eq!(idx.search("betanew", 10, None, None).unwrap().total_count, 1);
+        assert_eq!(idx.search("alphanew", 10, None, None).unwrap().total_count, 1);
+        assert_eq!(idx.search("alpha", 10, None, None).unwrap().total_count, 0);
+        assert_eq!(idx.search("delta", 10, None, None).unwrap().total_count, 0);
+        assert_eq!(idx.search("gamma", 10, None, None).unwrap().total_count, 1);
+        let listed = idx.list_files(None, None);
+        assert!(listed.contains(&same.to_string_lossy().to_string()), "{:?}", listed);
+        assert!(idx.interrupted_crawl().is_none());
+        assert_eq!(checkpoint::load(&index_path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_priority_crawl_commits_shallow_files_early() {
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let config = IndexConfig {
+            commit_every_docs: usize::MAX,
+            commit_interval: Duration::from_secs(3600),
+            in_memory: true,
+            ..IndexConfig::default()
+        };
+        let mut idx = FileIndex::builder().config(config).build().unwrap();
+        write_fixture(&root, "a/b/c/d/deep.md", "abyssal");
+        for i in 0..crawl::EARLY_COMMIT_FILES {
+            write_fixture(&root, &format!("dir/{}.md", i), "filler");
+        }
+        write_fixture(&root, "top.md", "overview");
+
+        let count = idx.index_directory(&root).unwrap();
+        assert_eq!(count, crawl::EARLY_COMMIT_FILES as u64 + 2);
+        // Committed before the deepest file was indexed
+        assert_eq!(idx.generation(), 1);
+        assert_eq!(idx.search("overview", 10, None, None).unwrap().total_count, 1);
+        assert_eq!(idx.search("abyssal", 10, None, None).unwrap().total_count, 0);
+        idx.commit().unwrap();
+        assert_eq!(idx.search("abyssal", 10, None, None).unwrap().total_count, 1);
+    }
+
+    #[test]
+    fn test_skipped_files_counted_per_reason() {
+        let fixtures = TempDir::new().unwrap();
+        let root = fixtures.path().canonicalize().unwrap();
+        let mut idx = FileIndex::builder().in_memory().max_file_size(100).build().unwrap();
+        write_fixture(&root, "notes.md", "kept");
+        write_fixture(&root, "photo.png", "not an image, but skipped by type");
+        write_fixture(&root, "huge.txt", &"x".repeat(200));
+        fs::write(root.join("blob.txt"), [0xff, 0xfe, 0x00, 0x9f]).unwrap();
+        assert_eq!(idx.index_directory(&root).unwrap(), 1);
+        let skipped = idx.skipped(&root);
+        assert_eq!((skipped.unsupported, skipped.too_large, skipped.binary), (1, 1, 1));
+        assert_eq!(skipped.to_string(), "1 too large, 1 binary, 1 unsupported type");
+        assert_eq!(idx.status().root_skips, vec![skipped]);
+        assert_eq!(idx.index_or_skip(&root.join("huge.txt")).unwrap(), Some(SkipReason::TooLarge));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let secret = write_fixture(&root, "secret.md", "private");
+            fs::set_permissions(&secret, fs::Permissions::from_mode(0o000)).unwrap();
+            // Permissions do not apply to root
+            if fs::read(&secret).is_err() {
+                idx.index_directory(&root).unwrap();
+                assert_eq!(idx.skipped(&root).permission, 1);
+                assert!(idx.failed_files().is_empty());
+            }
+            fs::set_permissions(&secret, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+    }
+
     // -- extract_snippet --
 
+    #[test]
+    fn test_snippet_highlights_terms_and_phrases() {
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::builder().in_memory().build().unwrap();
+        let text = "Retrying after Connection-Refused from db01; connection pool drained\n";
+        let f = write_fixture(fixtures.path(), "app.txt", text);
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+
+        let output = idx.search("\"connection refused\" OR pool -debug", 10, None, None).unwrap();
+        let result = &output.results[0];
+        let expected = "...Retrying after **Connection-Refused** from db01; connection \
+                        **pool** drained...";
+        assert_eq!(result.snippet, expected);
+        let matched: Vec<&str> =
+            result.highlights.iter().map(|r| &result.snippet[r.clone()]).collect();
+        assert_eq!(matched, vec!["Connection-Refused", "pool"]);
+
+        let builder = FileIndex::builder().in_memory().highlight(Highlight::none());
+        let mut plain = builder.build().unwrap();
+        plain.index_file(&f).unwrap();
+        plain.commit().unwrap();
+        let output = plain.search("pool", 10, None, None).unwrap();
+        assert_eq!(output.results[0].snippet, format!("...{}...", text.trim()));
+        assert_eq!(output.results[0].highlights, vec![59..63]);
+    }
+
+    #[test]
+    fn test_snippets_per_file_with_line_numbers() {
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::builder().in_memory().build().unwrap();
+        let text = "start\ntimeout on a\nok\nok\nTimeout on b\nok\nok\nok\ntimeout on c\n";
+        let f = write_fixture(fixtures.path(), "run.txt", text);
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+
+        let search = |snippets_per_file| {
+            let filters = SearchFilters {
+                snippets_per_file,
+                ..SearchFilters::default()
+            };
+            let output = idx.search_handle().search_filtered("timeout", 10, &filters).unwrap();
+            output.results.into_iter().next().unwrap()
+        };
+        let one = search(1);
+        assert!(one.snippets.is_empty());
+        assert_eq!(one.line_number, Some(2));
+
+        let snippets: Vec<(Option<usize>, String)> =
+            search(2).snippets.into_iter().map(|s| (s.line_number, s.text)).collect();
+        let expected = vec![
+            (Some(2), "**timeout** on a".to_string()),
+            (Some(5), "**Timeout** on b".to_string()),
+        ];
+        assert_eq!(snippets, expected);
+        assert_eq!(search(10).snippets.len(), 3);
+    }
+
+    #[test]
+    fn test_latency_budget_cuts_results_and_snippets() {
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::builder().in_memory().build().unwrap();
+        for i in 0..20 {
+            let text = format!("deadline {}\nok\ndeadline again\n", i);
+            let f = write_fixture(fixtures.path(), &format!("{}.txt", i), &text);
+            idx.index_file(&f).unwrap();
+        }
+        idx.commit().unwrap();
+
+        let handle = idx.search_handle();
+        let search = |budget| {
+            let filters = SearchFilters {
+                snippets_per_file: 2,
+                latency_budget: Some(budget),
+                ..SearchFilters::default()
+            };
+            handle.search_filtered("deadline", 20, &filters).unwrap()
+        };
+        // Nothing measured yet: one result is built, then the spent budget ends the search
+        let spent = search(Duration::ZERO);
+        assert!(spent.degraded);
+        assert_eq!(spent.results.len(), 1);
+        assert_eq!(spent.results[0].snippets.len(), 2);
+
+        // Measured: only one result is collected, and without its extra snippets
+        let spent = search(Duration::ZERO);
+        assert!(spent.degraded);
+        assert_eq!(spent.results.len(), 1);
+        assert!(spent.results[0].snippets.is_empty());
+
+        let ample = search(Duration::from_secs(600));
+        assert!(!ample.degraded);
+        assert_eq!(ample.results.len(), 20);
+        assert!(ample.results.iter().all(|r| r.snippets.len() == 2));
+    }
+
+    #[test]
+    fn test_search_iter_builds_results_lazily() {
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::builder().in_memory().build().unwrap();
+        for i in 1..=25 {
+            let text = format!("{}\nfiller\n", "stream ".repeat(i));
+            let f = write_fixture(fixtures.path(), &format!("{}.txt", i), &text);
+            idx.index_file(&f).unwrap();
+        }
+        let f = write_fixture(fixtures.path(), "log.txt", "stream one\nok\nstream two\n");
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+
+        let paths = |results: Vec<SearchResult>| {
+            results.into_iter().map(|r| (r.file_path, r.match_count)).collect::<Vec<_>>()
+        };
+        for filters in [
+            SearchFilters::default(),
+            SearchFilters {
+                group_by_file: true,
+                min_score: Some(0.5),
+                ..SearchFilters::default()
+            },
+        ] {
+            let all = handle.search_filtered("stream", 100, &filters).unwrap();
+            let iter = handle.search_iter("stream", &filters).unwrap();
+            assert_eq!(iter.generation(), all.generation);
+            let streamed: Vec<_> = iter.collect::<anyhow::Result<_>>().unwrap();
+            assert_eq!(paths(streamed), paths(all.results));
+        }
+
+        // Taking the top result collects only the first page
+        let mut iter = handle.search_iter("stream", &SearchFilters::default()).unwrap();
+        assert!(iter.next().unwrap().unwrap().file_path.ends_with("25.txt"));
+        assert_eq!(iter.collected, FIRST_PAGE);
+        assert_eq!(iter.by_ref().count(), 25);
+        assert_eq!(iter.collected, 26);
+        assert!(handle.search_iter("", &SearchFilters::default()).unwrap().next().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_search_stream_sends_results_until_dropped() {
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::builder().in_memory().build().unwrap();
+        for i in 0..3 {
+            let f = write_fixture(fixtures.path(), &format!("{}.txt", i), "brook");
+            idx.index_file(&f).unwrap();
+        }
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+
+        let mut results = handle.search_stream("brook", &SearchFilters::default());
+        let mut count = 0;
+        while let Some(result) = results.recv().await {
+            assert!(result.unwrap().file_path.ends_with(".txt"));
+            count += 1;
+        }
+        assert_eq!(count, 3);
+
+        let mut failed = handle.search_stream("brook AND (", &SearchFilters::default());
+        assert!(failed.recv().await.unwrap().is_err());
+        assert!(failed.recv().await.is_none());
+    }
+
+    #[test]
+    fn test_context_lines_snippets() {
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::builder().in_memory().build().unwrap();
+        let text = "fn a() {\n    let x = 1;\n    panic_token(x);\n}\n\n\
+                    fn b() {\n    panic_token();\n}\n";
+        let f = write_fixture(fixtures.path(), "lib.rs", text);
+        idx.index_file(&f).unwrap();
+        idx.commit().unwrap();
+
+        let search = |snippets_per_file| {
+            let filters = SearchFilters {
+                snippets_per_file,
+                context_lines: Some(1),
+                ..SearchFilters::default()
+            };
+            let output = idx.search_handle().search_filtered("panic_token", 10, &filters).unwrap();
+            output.results.into_iter().next().unwrap()
+        };
+        let result = search(1);
+        assert_eq!(result.line_number, Some(3));
+        assert_eq!(result.context_start, Some(2));
+        assert_eq!(result.snippet, "    let x = 1;\n    **panic_token**(x);\n}");
+
+        let snippets: Vec<(Option<usize>, Option<usize>)> = search(5)
+            .snippets
+            .iter()
+            .map(|s| (s.line_number, s.context_start))
+            .collect();
+        assert_eq!(snippets, vec![(Some(3), Some(2)), (Some(7), Some(6))]);
+
+        // The first line has no lines before it
+        assert_eq!(FileIndex::context_snippet("a\nb\nc", 1, 1), (1, "a\nb".to_string()));
+    }
+
     #[test]
     fn test_extract_snippet_centered() {
         let content = "aaaa bbbb cccc target_word dddd eeee ffff";
         let terms = vec!["target_word".to_string()];
-        let snippet = FileIndex::extract_snippet(content, &terms, 30);
+        let snippet = FileIndex::extract_snippet(content, &terms, 30, false);
         assert!(snippet.contains("target_word"));
     }
 
@@ -848,7 +8269,7 @@ mod tests {
     fn test_extract_snippet_at_start() {
         let content = "target_word is at the very beginning of this text";
         let terms = vec!["target_word".to_string()];
-        let snippet = FileIndex::extract_snippet(content, &terms, 40);
+        let snippet = FileIndex::extract_snippet(content, &terms, 40, false);
         assert!(snippet.contains("target_word"));
     }
 
@@ -857,7 +8278,7 @@ mod tests {
         // Multi-byte chars (emoji) near window boundary — ensure no panic
         let content = "🎉🎊🎈 target_word 🎉🎊🎈";
         let terms = vec!["target_word".to_string()];
-        let snippet = FileIndex::extract_snippet(content, &terms, 60);
+        let snippet = FileIndex::extract_snippet(content, &terms, 60, false);
         assert!(snippet.contains("target_word"));
     }
 
@@ -867,20 +8288,20 @@ mod tests {
     fn test_find_match_line_found() {
         let content = "line1\nline2\ntarget";
         let terms = vec!["target".to_string()];
-        assert_eq!(FileIndex::find_match_line(content, &terms), Some(3));
+        assert_eq!(FileIndex::find_match_line(content, &terms, false), Some(3));
     }
 
     #[test]
     fn test_find_match_line_first_line() {
         let content = "target on first line\nsecond line";
         let terms = vec!["target".to_string()];
-        assert_eq!(FileIndex::find_match_line(content, &terms), Some(1));
+        assert_eq!(FileIndex::find_match_line(content, &terms, false), Some(1));
     }
 
     #[test]
     fn test_find_match_line_not_found() {
         let content = "nothing here";
         let terms = vec!["absent".to_string()];
-        assert_eq!(FileIndex::find_match_line(content, &terms), None);
+        assert_eq!(FileIndex::find_match_line(content, &terms, false), None);
     }
 }
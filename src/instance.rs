@@ -0,0 +1,114 @@
+//! Coordination between server processes sharing an index directory.
+//!
+//! Each on-disk index has a sibling `<index>.lock` file. The process that holds an
+//! exclusive lock on it owns the index; the lock is released when the process exits,
+//! even if it crashes. The owner writes its pid into the file so others can name it.
+
+use std::fmt;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Another process already owns the index.
+#[derive(Debug)]
+pub struct IndexInUse {
+    pub index_path: PathBuf,
+    /// Pid recorded by the owning process, if readable.
+    pub pid: Option<u32>,
+}
+
+impl fmt::Display for IndexInUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Index at {} is in use by another localfiles process", self.index_path.display())?;
+        if let Some(pid) = self.pid {
+            write!(f, " (pid {})", pid)?;
+        }
+        write!(
+            f,
+            ". Stop that process, start from a different directory (or LOCALFILES_BASE_DIR), \
+             or pass --in-memory"
+        )
+    }
+}
+
+impl std::error::Error for IndexInUse {}
+
+/// Exclusive ownership of an index directory, held until dropped.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+    path: PathBuf,
+}
+
+/// Lock file guarding `index_path`. It sits next to the index so it survives the index
+/// directory being deleted and recreated on schema changes.
+pub fn lock_path(index_path: &Path) -> PathBuf {
+    let name = index_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "index".to_string());
+    index_path.with_file_name(format!("{}.lock", name))
+}
+
+impl InstanceLock {
+    /// Take ownership of `index_path`, failing with [`IndexInUse`] if another process has it.
+    pub fn acquire(index_path: &Path) -> anyhow::Result<Self> {
+        let path = lock_path(index_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut contents = String::new();
+                let pid = file
+                    .read_to_string(&mut contents)
+                    .ok()
+                    .and_then(|_| contents.trim().parse().ok());
+                return Err(IndexInUse {
+                    index_path: index_path.to_path_buf(),
+                    pid,
+                }
+                .into());
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+        file.flush()?;
+        Ok(Self { _file: file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_second_lock_reports_owner_until_released() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        let lock = InstanceLock::acquire(&index_path).unwrap();
+        assert_eq!(lock.path(), dir.path().join("index.lock"));
+
+        let err = InstanceLock::acquire(&index_path).unwrap_err();
+        let in_use = err.downcast_ref::<IndexInUse>().expect("IndexInUse error");
+        assert_eq!(in_use.pid, Some(std::process::id()));
+        assert!(err.to_string().contains("in use by another localfiles process"));
+
+        drop(lock);
+        assert!(InstanceLock::acquire(&index_path).is_ok());
+    }
+}
@@ -0,0 +1,371 @@
+//! Key paths of JSON, YAML and TOML files (and of SQLite schemas, see `sqlite`).
+//!
+//! Every key is indexed under its dotted path from the document root in the `key` field,
+//! so `key:server.port` finds files that set `port` inside `server`. Array items add no
+//! segment: `image` in `spec: {containers: [{image: ...}]}` is `spec.containers.image`.
+//! The parsers are line-oriented and forgiving, recording the line each key is on so
+//! results can point at it; syntax they do not understand is skipped rather than
+//! rejected.
+
+use crate::sqlite;
+
+/// Whether files with `extension` have their key paths indexed.
+pub fn is_structured(extension: &str) -> bool {
+    matches!(
+        extension.to_lowercase().as_str(),
+        "json" | "yaml" | "yml" | "toml"
+    ) || sqlite::is_database_extension(extension)
+}
+
+/// Dotted key paths in `content` with the 1-based line each key is on, in file order.
+pub fn key_paths(content: &str, extension: &str) -> Vec<(String, usize)> {
+    match extension.to_lowercase().as_str() {
+        "json" => json_keys(content),
+        "yaml" | "yml" => yaml_keys(content),
+        "toml" => toml_keys(content),
+        ext if sqlite::is_database_extension(ext) => sqlite::schema_keys(content),
+        _ => Vec::new(),
+    }
+}
+
+/// Line of the first occurrence of `key` (compared case-insensitively) in `content`.
+pub fn line_of(content: &str, extension: &str, key: &str) -> Option<usize> {
+    key_paths(content, extension)
+        .into_iter()
+        .find(|(path, _)| path.eq_ignore_ascii_case(key))
+        .map(|(_, line)| line)
+}
+
+enum Frame {
+    /// An object, with its current key and whether the next string is a key.
+    Object {
+        key: Option<String>,
+        expect_key: bool,
+    },
+    Array,
+}
+
+fn json_keys(content: &str) -> Vec<(String, usize)> {
+    let mut keys = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut line = 1;
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '{' => stack.push(Frame::Object {
+                key: None,
+                expect_key: true,
+            }),
+            '[' => stack.push(Frame::Array),
+            '}' | ']' => {
+                stack.pop();
+            }
+            ',' => {
+                if let Some(Frame::Object { expect_key, .. }) = stack.last_mut() {
+                    *expect_key = true;
+                }
+            }
+            '"' => {
+                let mut s = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(c) => s.push(c),
+                            None => break,
+                        },
+                        '\n' => {
+                            line += 1;
+                            s.push(c);
+                        }
+                        c => s.push(c),
+                    }
+                }
+                if let Some(Frame::Object { key, expect_key }) = stack.last_mut() {
+                    if *expect_key {
+                        *key = Some(s);
+                        *expect_key = false;
+                        keys.push((json_path(&stack), line));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    keys
+}
+
+fn json_path(stack: &[Frame]) -> String {
+    stack
+        .iter()
+        .filter_map(|frame| match frame {
+            Frame::Object { key, .. } => key.as_deref(),
+            Frame::Array => None,
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn yaml_keys(content: &str) -> Vec<(String, usize)> {
+    let mut keys = Vec::new();
+    // (indent, key) of the mappings enclosing the current line
+    let mut stack: Vec<(usize, String)> = Vec::new();
+    // Indent of a key whose value is a `|` or `>` block scalar
+    let mut block: Option<usize> = None;
+    for (i, raw) in content.lines().enumerate() {
+        let trimmed = raw.trim_start();
+        let mut indent = raw.len() - trimmed.len();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(block_indent) = block {
+            if indent > block_indent {
+                continue;
+            }
+            block = None;
+        }
+        if trimmed.starts_with('#') {
+            continue;
+        }
+        if indent == 0 && (trimmed.starts_with("---") || trimmed.starts_with("...")) {
+            stack.clear();
+            continue;
+        }
+        // A `- ` list item's mapping starts after the dash
+        let mut rest = trimmed;
+        while let Some(item) = rest.strip_prefix('-') {
+            if !item.is_empty() && !item.starts_with(' ') {
+                break;
+            }
+            let item = item.trim_start();
+            indent += rest.len() - item.len();
+            rest = item;
+        }
+        let Some((key, value)) = yaml_key(rest) else {
+            continue;
+        };
+        while stack.last().is_some_and(|(level, _)| *level >= indent) {
+            stack.pop();
+        }
+        stack.push((indent, key));
+        let path = stack
+            .iter()
+            .map(|(_, k)| k.as_str())
+            .collect::<Vec<_>>()
+            .join(".");
+        keys.push((path, i + 1));
+        let value = value.split(" #").next().unwrap_or("").trim();
+        if value.starts_with('|') || value.starts_with('>') {
+            block = Some(indent);
+        }
+    }
+    keys
+}
+
+/// Split a `key: value` line into its unquoted key and value.
+fn yaml_key(line: &str) -> Option<(String, &str)> {
+    let (key, value) = match line.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = line[1..].find(quote)? + 1;
+            let value = line[end + 1..].strip_prefix(':')?;
+            (line[1..end].to_string(), value)
+        }
+        '{' | '[' | '&' | '*' | '!' | '|' | '>' | '%' | '@' | '`' => return None,
+        _ => {
+            let colon = line
+                .match_indices(':')
+                .map(|(i, _)| i)
+                .find(|&i| line[i + 1..].is_empty() || line[i + 1..].starts_with([' ', '\t']))?;
+            let key = line[..colon].trim_end();
+            if key.contains(" #") {
+                return None;
+            }
+            (key.to_string(), &line[colon + 1..])
+        }
+    };
+    if !value.is_empty() && !value.starts_with([' ', '\t']) {
+        return None;
+    }
+    Some((key, value))
+}
+
+fn toml_keys(content: &str) -> Vec<(String, usize)> {
+    let mut keys = Vec::new();
+    let mut table: Vec<String> = Vec::new();
+    // Closing delimiter of a multi-line string or array being skipped
+    let mut open: Option<&str> = None;
+    let mut depth = 0usize;
+    for (i, raw) in content.lines().enumerate() {
+        let line = raw.trim();
+        match open {
+            Some("]") => {
+                depth = bracket_depth(line, depth);
+                if depth == 0 {
+                    open = None;
+                }
+                continue;
+            }
+            Some(close) => {
+                if line.contains(close) {
+                    open = None;
+                }
+                continue;
+            }
+            None => {}
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            let header = line.trim_start_matches('[');
+            let header = header.split(']').next().unwrap_or("");
+            table = toml_dotted(header);
+            let path = table.join(".");
+            if !path.is_empty() {
+                keys.push((path, i + 1));
+            }
+            continue;
+        }
+        let Some((key, value)) = split_toml_assignment(line) else {
+            continue;
+        };
+        let mut path = table.clone();
+        path.extend(toml_dotted(key));
+        keys.push((path.join("."), i + 1));
+        let value = value.trim();
+        for delimiter in ["\"\"\"", "'''"] {
+            if let Some(after) = value.strip_prefix(delimiter) {
+                if !after.contains(delimiter) {
+                    open = Some(delimiter);
+                }
+            }
+        }
+        if value.starts_with('[') {
+            depth = bracket_depth(value, 0);
+            if depth > 0 {
+                open = Some("]");
+            }
+        }
+    }
+    keys
+}
+
+/// Split `key = value` at the first `=` outside quotes.
+fn split_toml_assignment(line: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '=') => return Some((&line[..i], &line[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Segments of a dotted TOML key such as `a."b.c".d`, unquoted.
+fn toml_dotted(key: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut segment = String::new();
+    let mut quote = None;
+    for c in key.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '.') => segments.push(std::mem::take(&mut segment).trim().to_string()),
+            (None, c) if c.is_whitespace() => {}
+            (_, c) => segment.push(c),
+        }
+    }
+    segments.push(segment.trim().to_string());
+    segments.retain(|s| !s.is_empty());
+    segments
+}
+
+/// Bracket nesting after `line`, starting from `depth`, ignoring brackets in strings.
+fn bracket_depth(line: &str, mut depth: usize) -> usize {
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (None, '#') => break,
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '[') => depth += 1,
+            (None, ']') => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected(paths: &[(&str, usize)]) -> Vec<(String, usize)> {
+        paths
+            .iter()
+            .map(|(path, line)| (path.to_string(), *line))
+            .collect()
+    }
+
+    #[test]
+    fn test_key_paths_json_yaml_toml() {
+        let json =
+            "{\n  \"server\": {\n    \"port\": 8080,\n    \"hosts\": [\"a\", \"b\"]\n  },\n  \
+                    \"spec\": {\"containers\": [{\"image\": \"nginx\"}]}\n}";
+        assert_eq!(
+            key_paths(json, "json"),
+            expected(&[
+                ("server", 2),
+                ("server.port", 3),
+                ("server.hosts", 4),
+                ("spec", 6),
+                ("spec.containers", 6),
+                ("spec.containers.image", 6),
+            ])
+        );
+
+        let yaml = "# config\nserver:\n  port: 8080 # default\n  url: http://x\nspec:\n  \
+                    containers:\n    - name: web\n      image: nginx\n  script: |\n    a: b\n  \
+                    \"quoted key\": 1\n---\nother: true\n";
+        assert_eq!(
+            key_paths(yaml, "yaml"),
+            expected(&[
+                ("server", 2),
+                ("server.port", 3),
+                ("server.url", 4),
+                ("spec", 5),
+                ("spec.containers", 6),
+                ("spec.containers.name", 7),
+                ("spec.containers.image", 8),
+                ("spec.script", 9),
+                ("spec.quoted key", 11),
+                ("other", 13),
+            ])
+        );
+
+        let toml = "title = \"x\"\n[server]\nport = 8080\nhosts = [\n  \"a = b\",\n]\n\
+                    [[spec.containers]]\nimage = \"nginx\"\nnotes = \"\"\"\nkey = no\n\"\"\"\n\
+                    db.\"pool.size\" = 4\n";
+        assert_eq!(
+            key_paths(toml, "toml"),
+            expected(&[
+                ("title", 1),
+                ("server", 2),
+                ("server.port", 3),
+                ("server.hosts", 4),
+                ("spec.containers", 7),
+                ("spec.containers.image", 8),
+                ("spec.containers.notes", 9),
+                ("spec.containers.db.pool.size", 12),
+            ])
+        );
+        assert_eq!(line_of(toml, "toml", "Server.Port"), Some(3));
+        assert!(key_paths("port: 1", "txt").is_empty());
+    }
+}
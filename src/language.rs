@@ -0,0 +1,152 @@
+//! Programming languages of indexed files, for `language` searches and `file_type`
+//! filters that name a language.
+//!
+//! Most files get their language from the extension, so `.py` and `.pyi` are both
+//! `python`. A few build files are known by name (`Makefile` is `make`), extensionless
+//! scripts go by the extension their `#!` line files them under, and `.h` headers are C
+//! unless they use C++ or Objective-C constructs. Files in no known language, plain text
+//! included, have none.
+
+use std::path::Path;
+
+/// Languages by lowercase extension.
+const BY_EXTENSION: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("pyi", "python"),
+    ("pyw", "python"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("cjs", "javascript"),
+    ("jsx", "javascript"),
+    ("ts", "typescript"),
+    ("mts", "typescript"),
+    ("cts", "typescript"),
+    ("tsx", "typescript"),
+    ("c", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("cxx", "cpp"),
+    ("hh", "cpp"),
+    ("hpp", "cpp"),
+    ("hxx", "cpp"),
+    ("m", "objective-c"),
+    ("mm", "objective-c"),
+    ("cs", "csharp"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("kts", "kotlin"),
+    ("scala", "scala"),
+    ("go", "go"),
+    ("rb", "ruby"),
+    ("php", "php"),
+    ("pl", "perl"),
+    ("pm", "perl"),
+    ("lua", "lua"),
+    ("swift", "swift"),
+    ("dart", "dart"),
+    ("zig", "zig"),
+    ("hs", "haskell"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("erl", "erlang"),
+    ("clj", "clojure"),
+    ("r", "r"),
+    ("jl", "julia"),
+    ("sh", "shell"),
+    ("bash", "shell"),
+    ("zsh", "shell"),
+    ("ps1", "powershell"),
+    ("sql", "sql"),
+    ("mk", "make"),
+    ("cmake", "cmake"),
+    ("html", "html"),
+    ("htm", "html"),
+    ("css", "css"),
+    ("scss", "scss"),
+    ("md", "markdown"),
+    ("markdown", "markdown"),
+    ("json", "json"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("toml", "toml"),
+    ("xml", "xml"),
+];
+
+/// Languages of files known by their name.
+const BY_NAME: &[(&str, &str)] = &[
+    ("makefile", "make"),
+    ("gnumakefile", "make"),
+    ("cmakelists.txt", "cmake"),
+    ("dockerfile", "dockerfile"),
+    ("containerfile", "dockerfile"),
+    ("rakefile", "ruby"),
+    ("gemfile", "ruby"),
+    ("vagrantfile", "ruby"),
+];
+
+/// Text that only appears in C++ headers.
+const CPP_MARKERS: &[&str] =
+    &["namespace ", "class ", "template <", "template<", "std::", "public:"];
+
+/// Language of the file at `path` with text `content`, indexed under `extension` (an
+/// extensionless script's is that of its interpreter).
+pub fn detect(path: &Path, extension: &str, content: &str) -> Option<&'static str> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if let Some((_, language)) = BY_NAME.iter().find(|(known, _)| *known == name) {
+        return Some(language);
+    }
+    if extension == "h" {
+        return Some(header_language(content));
+    }
+    BY_EXTENSION
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, language)| *language)
+}
+
+/// Whether `name` is a language some files are detected as.
+pub fn is_language(name: &str) -> bool {
+    BY_EXTENSION
+        .iter()
+        .chain(BY_NAME)
+        .any(|(_, language)| *language == name)
+}
+
+/// Language of a `.h` header: C++ or Objective-C if it uses their constructs, else C.
+fn header_language(content: &str) -> &'static str {
+    let mut language = "c";
+    for line in content.lines().map(str::trim_start) {
+        if line.starts_with("@interface") || line.starts_with("@protocol") {
+            return "objective-c";
+        }
+        if CPP_MARKERS.iter().any(|marker| line.contains(marker)) {
+            language = "cpp";
+        }
+    }
+    language
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_by_name_extension_and_content() {
+        assert_eq!(detect(Path::new("stubs.pyi"), "pyi", ""), Some("python"));
+        assert_eq!(detect(Path::new("/src/Makefile"), "", ""), Some("make"));
+        assert_eq!(detect(Path::new("CMakeLists.txt"), "txt", ""), Some("cmake"));
+        // Extensionless scripts arrive with their interpreter's extension
+        assert_eq!(detect(Path::new("deploy"), "sh", "#!/bin/bash\n"), Some("shell"));
+        assert_eq!(detect(Path::new("notes.txt"), "txt", "plain"), None);
+
+        assert_eq!(detect(Path::new("util.h"), "h", "int add(int a, int b);\n"), Some("c"));
+        let cpp = "#pragma once\nnamespace util {\nint add(int, int);\n}\n";
+        assert_eq!(detect(Path::new("util.h"), "h", cpp), Some("cpp"));
+        let objc = "#import <Foundation/Foundation.h>\n@interface Greeter : NSObject\n@end\n";
+        assert_eq!(detect(Path::new("Greeter.h"), "h", objc), Some("objective-c"));
+
+        assert!(is_language("python") && is_language("make"));
+        assert!(!is_language("py"));
+    }
+}
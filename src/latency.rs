@@ -0,0 +1,77 @@
+//! Latency budgets for searches, for interactive callers such as editor integrations
+//! that would rather get a fast partial answer than a complete slow one.
+//!
+//! With `SearchFilters::latency_budget` set, a search collects no more hits than the
+//! budget leaves time to build results for, at the cost per result measured by earlier
+//! searches on the handle (`Pace`). While building results it watches the clock: once the
+//! time left would not cover full results for the rest, it stops adding extra snippets
+//! and match counts, and once the budget is spent it stops adding results, keeping at
+//! least one. `SearchOutput::degraded` says whether the budget changed the output.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Weight of the newest measurement in the moving average, as a divisor.
+const SMOOTHING: u64 = 8;
+
+/// Moving average of the time one search result takes to build, snippets included,
+/// shared by the clones of a `SearchHandle`.
+#[derive(Debug, Default)]
+pub struct Pace {
+    /// Nanoseconds per result; 0 until the first measurement.
+    nanos: AtomicU64,
+}
+
+impl Pace {
+    /// Time one result takes to build, if any search measured it yet.
+    pub fn per_result(&self) -> Option<Duration> {
+        match self.nanos.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+
+    /// Fold in that building `results` results took `elapsed`.
+    pub fn record(&self, elapsed: Duration, results: usize) {
+        if results == 0 {
+            return;
+        }
+        let sample = (elapsed.as_nanos() / results as u128).clamp(1, u64::MAX as u128) as u64;
+        // Racing updates lose a sample, which an average can afford
+        let average = match self.nanos.load(Ordering::Relaxed) {
+            0 => sample,
+            old => old - old / SMOOTHING + sample / SMOOTHING,
+        };
+        self.nanos.store(average.max(1), Ordering::Relaxed);
+    }
+
+    /// Results that can be built in `left`, at least one; `None` until measured.
+    pub fn affordable(&self, left: Duration) -> Option<usize> {
+        let per_result = self.per_result()?;
+        let fit = left.as_nanos() / per_result.as_nanos();
+        Some((fit as usize).max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pace_averages_and_affords() {
+        let pace = Pace::default();
+        assert_eq!(pace.per_result(), None);
+        assert_eq!(pace.affordable(Duration::from_millis(50)), None);
+        pace.record(Duration::from_millis(10), 0);
+        assert_eq!(pace.per_result(), None);
+
+        pace.record(Duration::from_millis(20), 10);
+        assert_eq!(pace.per_result(), Some(Duration::from_millis(2)));
+        assert_eq!(pace.affordable(Duration::from_millis(50)), Some(25));
+        assert_eq!(pace.affordable(Duration::ZERO), Some(1));
+
+        // A slower search moves the average an eighth of the way
+        pace.record(Duration::from_millis(10), 1);
+        assert_eq!(pace.per_result(), Some(Duration::from_micros(3_000)));
+    }
+}
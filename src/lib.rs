@@ -1,2 +1,54 @@
+//! Local full-text file search: a tantivy index of text files kept in sync by a file
+//! watcher, served over MCP by the `localfiles` binary.
+//!
+//! To use the index from your own application, start from [`prelude`] and
+//! [`indexer::FileIndex::builder`].
+
+pub mod archive;
+pub mod audit;
+pub mod budget;
+pub mod checkpoint;
+pub mod crawl;
+pub mod email;
+pub mod filter;
+pub mod format;
+pub mod generated;
+pub mod git;
+pub mod health;
+pub mod highlight;
 pub mod indexer;
+pub mod instance;
+pub mod keys;
+pub mod language;
+pub mod latency;
+pub mod limits;
+pub mod logs;
+pub mod manifest;
+pub mod markdown;
+pub mod mime;
+pub mod notebook;
+pub mod ocr;
+pub mod outline;
+pub mod owner;
+pub mod paths;
+pub mod pins;
+pub mod prelude;
+pub mod project;
+pub mod purge;
+pub mod query;
+pub mod query_cache;
+pub mod query_log;
+pub mod ranking;
+pub mod result_sets;
+pub mod retry;
+pub mod saved;
+pub mod scope;
+pub mod spreadsheet;
+pub mod sqlite;
+pub mod summary;
+pub mod synonyms;
+pub mod tabular;
+pub mod telemetry;
+pub mod usage;
 pub mod watcher;
+pub mod write;
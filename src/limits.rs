@@ -0,0 +1,352 @@
+//! Limits applied to tool calls before they run.
+//!
+//! Protects the server from runaway clients: per-tool rate limits (token buckets, e.g.
+//! `index_paths=10/m`), a cap on query length and a cap on paths per `index_paths` call.
+//! Configured with `LOCALFILES_RATE_LIMITS`, `LOCALFILES_MAX_QUERY_LEN` and
+//! `LOCALFILES_MAX_INDEX_PATHS`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Comma-separated `tool=calls/unit` entries; `*` sets the default, `none` disables.
+pub const RATE_LIMITS_ENV: &str = "LOCALFILES_RATE_LIMITS";
+pub const MAX_QUERY_LEN_ENV: &str = "LOCALFILES_MAX_QUERY_LEN";
+pub const MAX_INDEX_PATHS_ENV: &str = "LOCALFILES_MAX_INDEX_PATHS";
+
+const DEFAULT_RATE_LIMITS: &str = "index_paths=10/m,*=20/s";
+const DEFAULT_MAX_QUERY_LEN: usize = 1_024;
+const DEFAULT_MAX_INDEX_PATHS: usize = 32;
+
+/// Free-text arguments whose length is capped; for lists of strings, each one's.
+const QUERY_ARGUMENTS: &[&str] = &["query", "text", "keyword", "note", "filter", "queries"];
+
+/// At most `calls` per `per`, allowing bursts of up to `calls`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub calls: u32,
+    pub per: Duration,
+}
+
+impl Rate {
+    /// Parse `20/s`, `10/m` or `100/h`.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let (calls, unit) = spec
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| anyhow::anyhow!("Expected calls/unit, e.g. 20/s: {}", spec))?;
+        let calls: u32 = calls.trim().parse()?;
+        let per = match unit.trim() {
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_secs(60),
+            "h" => Duration::from_secs(3600),
+            other => anyhow::bail!("Unknown rate unit '{}' (use s, m or h)", other),
+        };
+        if calls == 0 {
+            anyhow::bail!("Rate must allow at least one call: {}", spec);
+        }
+        Ok(Self { calls, per })
+    }
+
+    fn per_second(&self) -> f64 {
+        f64::from(self.calls) / self.per.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self.per.as_secs() {
+            1 => "s",
+            60 => "m",
+            _ => "h",
+        };
+        write!(f, "{}/{}", self.calls, unit)
+    }
+}
+
+/// Why a tool call was rejected.
+#[derive(Debug, PartialEq)]
+pub enum Violation {
+    RateLimited {
+        tool: String,
+        rate: Rate,
+        retry_after: Duration,
+    },
+    QueryTooLong {
+        argument: String,
+        len: usize,
+        max: usize,
+    },
+    TooManyPaths {
+        count: usize,
+        max: usize,
+    },
+}
+
+impl Violation {
+    /// Machine-readable kind, returned in the error data.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Violation::RateLimited { .. } => "rate_limited",
+            Violation::QueryTooLong { .. } => "query_too_long",
+            Violation::TooManyPaths { .. } => "too_many_paths",
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::RateLimited {
+                tool,
+                rate,
+                retry_after,
+            } => write!(
+                f,
+                "Rate limit for {} exceeded ({}); retry in {}ms",
+                tool,
+                rate,
+                retry_after.as_millis()
+            ),
+            Violation::QueryTooLong { argument, len, max } => write!(
+                f,
+                "'{}' is {} characters long; the maximum is {}",
+                argument, len, max
+            ),
+            Violation::TooManyPaths { count, max } => write!(
+                f,
+                "{} paths given; at most {} can be indexed per call",
+                count, max
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+#[derive(Debug)]
+pub struct Limits {
+    rates: HashMap<String, Rate>,
+    default_rate: Option<Rate>,
+    pub max_query_len: usize,
+    pub max_index_paths: usize,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            rates: HashMap::new(),
+            default_rate: None,
+            max_query_len: DEFAULT_MAX_QUERY_LEN,
+            max_index_paths: DEFAULT_MAX_INDEX_PATHS,
+            buckets: Mutex::new(HashMap::new()),
+        }
+        .with_rates(DEFAULT_RATE_LIMITS)
+        .expect("default rate limits parse")
+    }
+}
+
+impl Limits {
+    /// Replace the rate limits with `tool=rate` entries (`*` for every other tool);
+    /// `none` removes all rate limits.
+    pub fn with_rates(mut self, spec: &str) -> anyhow::Result<Self> {
+        self.rates.clear();
+        self.default_rate = None;
+        if spec.trim().eq_ignore_ascii_case("none") {
+            return Ok(self);
+        }
+        for entry in spec.split(',').filter(|e| !e.trim().is_empty()) {
+            let (tool, rate) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Expected tool=rate in rate limits: {}", entry))?;
+            let rate = Rate::parse(rate)?;
+            match tool.trim() {
+                "*" => self.default_rate = Some(rate),
+                tool => {
+                    self.rates.insert(tool.to_string(), rate);
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Defaults overridden by the environment.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut limits = Self::default();
+        if let Ok(spec) = std::env::var(RATE_LIMITS_ENV) {
+            limits = limits
+                .with_rates(&spec)
+                .map_err(|e| anyhow::anyhow!("Invalid {}: {}", RATE_LIMITS_ENV, e))?;
+        }
+        if let Ok(max) = std::env::var(MAX_QUERY_LEN_ENV) {
+            limits.max_query_len = max
+                .trim()
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid {}: {}", MAX_QUERY_LEN_ENV, e))?;
+        }
+        if let Ok(max) = std::env::var(MAX_INDEX_PATHS_ENV) {
+            limits.max_index_paths = max
+                .trim()
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid {}: {}", MAX_INDEX_PATHS_ENV, e))?;
+        }
+        Ok(limits)
+    }
+
+    /// Same limits with full buckets, for a new client session.
+    pub fn new_session(&self) -> Self {
+        Self {
+            rates: self.rates.clone(),
+            default_rate: self.default_rate,
+            max_query_len: self.max_query_len,
+            max_index_paths: self.max_index_paths,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check a call to `tool` with `arguments`, consuming one call of its rate limit.
+    pub fn check(
+        &self,
+        tool: &str,
+        arguments: Option<&serde_json::Map<String, serde_json::Value>>,
+    ) -> Result<(), Violation> {
+        if let Some(arguments) = arguments {
+            for &argument in QUERY_ARGUMENTS {
+                let texts: Vec<(String, &str)> = match arguments.get(argument) {
+                    Some(serde_json::Value::String(text)) => vec![(argument.to_string(), text)],
+                    Some(serde_json::Value::Array(items)) => items
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, item)| {
+                            Some((format!("{}[{}]", argument, i), item.as_str()?))
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                for (argument, text) in texts {
+                    let len = text.chars().count();
+                    if len > self.max_query_len {
+                        return Err(Violation::QueryTooLong {
+                            argument,
+                            len,
+                            max: self.max_query_len,
+                        });
+                    }
+                }
+            }
+            if tool == "index_paths" {
+                if let Some(paths) = arguments.get("paths").and_then(|v| v.as_array()) {
+                    if paths.len() > self.max_index_paths {
+                        return Err(Violation::TooManyPaths {
+                            count: paths.len(),
+                            max: self.max_index_paths,
+                        });
+                    }
+                }
+            }
+        }
+
+        let Some(rate) = self.rates.get(tool).copied().or(self.default_rate) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets.entry(tool.to_string()).or_insert(Bucket {
+            tokens: f64::from(rate.calls),
+            refilled: now,
+        });
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() * rate.per_second();
+        bucket.tokens = (bucket.tokens + refill).min(f64::from(rate.calls));
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Violation::RateLimited {
+                tool: tool.to_string(),
+                rate,
+                retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / rate.per_second()),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn args(value: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_parse_rates() {
+        let limits = Limits::default().with_rates("search=5/s, *=100/m").unwrap();
+        assert_eq!(limits.rates["search"], Rate::parse("5/s").unwrap());
+        assert_eq!(limits.default_rate.unwrap().per, Duration::from_secs(60));
+        assert!(Limits::default().with_rates("search=5").is_err());
+        assert!(Limits::default().with_rates("search=0/s").is_err());
+        assert!(Limits::default().with_rates("none").unwrap().default_rate.is_none());
+    }
+
+    #[test]
+    fn test_rate_limit_bursts_then_throttles() {
+        let limits = Limits::default().with_rates("search=2/h").unwrap();
+        assert!(limits.check("search", None).is_ok());
+        assert!(limits.check("search", None).is_ok());
+        let err = limits.check("search", None).unwrap_err();
+        assert_eq!(err.kind(), "rate_limited");
+        // Other tools have no limit with this spec
+        assert!(limits.check("status", None).is_ok());
+        // A new session starts with a full bucket
+        assert!(limits.new_session().check("search", None).is_ok());
+    }
+
+    #[test]
+    fn test_size_caps() {
+        let mut limits = Limits::default().with_rates("none").unwrap();
+        limits.max_query_len = 5;
+        limits.max_index_paths = 1;
+        assert_eq!(
+            limits.check("search", Some(&args(json!({ "query": "too long" })))),
+            Err(Violation::QueryTooLong {
+                argument: "query".to_string(),
+                len: 8,
+                max: 5
+            })
+        );
+        assert!(limits.check("search", Some(&args(json!({ "query": "short" })))).is_ok());
+        // Every query of a multi_search, and filter expressions, are capped as well
+        let multi = json!({ "queries": ["short", "too long"] });
+        assert_eq!(
+            limits.check("multi_search", Some(&args(multi))),
+            Err(Violation::QueryTooLong {
+                argument: "queries[1]".to_string(),
+                len: 8,
+                max: 5
+            })
+        );
+        let filter = json!({ "query": "a", "filter": "ext = rs" });
+        assert_eq!(
+            limits
+                .check("search", Some(&args(filter)))
+                .unwrap_err()
+                .kind(),
+            "query_too_long"
+        );
+        assert_eq!(
+            limits
+                .check("index_paths", Some(&args(json!({ "paths": ["/a", "/b"] }))))
+                .unwrap_err()
+                .kind(),
+            "too_many_paths"
+        );
+    }
+}
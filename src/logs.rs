@@ -0,0 +1,220 @@
+//! Line-oriented indexing of `.log` files.
+//!
+//! A log is indexed as chunks of `CHUNK_LINES` lines, each its own document with the
+//! log's path, its first line number and the range of timestamps found at the start of
+//! its lines. Search results then point at the matching part of a large log, and
+//! `since`/`until` restrict a search to chunks overlapping a time range.
+//!
+//! Timestamps are ISO 8601 / RFC 3339 style (`2024-07-01T09:00:00Z`,
+//! `2024-07-01 09:00:00.123`, optionally in `[brackets]`). Times without a UTC offset
+//! are taken as UTC. Lines without a timestamp (stack traces, continuation lines)
+//! belong to the time of the line before them.
+
+use std::path::Path;
+
+/// Lines per indexed chunk.
+pub const CHUNK_LINES: usize = 200;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// 1-based line number of the chunk's first line in the log.
+    pub first_line: usize,
+    pub text: String,
+    /// Earliest and latest timestamps in the chunk, in seconds since the Unix epoch.
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+}
+
+/// A time range to restrict log searches to; either bound may be open.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimeRange {
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+/// Whether `path` is indexed as a log.
+pub fn is_log(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("log"))
+}
+
+/// Split `content` into chunks of `lines_per_chunk` lines with their time ranges.
+pub fn chunks(content: &str, lines_per_chunk: usize) -> Vec<Chunk> {
+    chunks_from(content, lines_per_chunk, 1, None)
+}
+
+/// Like `chunks` for text appended to a log: numbering starts at `first_line`, and lines
+/// before the first timestamp take the time `current` of the last line already indexed.
+pub fn chunks_from(
+    content: &str,
+    lines_per_chunk: usize,
+    first_line: usize,
+    mut current: Option<i64>,
+) -> Vec<Chunk> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut chunks = Vec::new();
+    // `current`: time of the last timestamped line, carried into chunks that start with
+    // continuation lines
+    for (i, group) in lines.chunks(lines_per_chunk.max(1)).enumerate() {
+        let mut start = current;
+        let mut end = current;
+        for line in group {
+            if let Some(t) = parse_timestamp(line) {
+                start = Some(start.map_or(t, |s| s.min(t)));
+                end = Some(end.map_or(t, |e| e.max(t)));
+                current = Some(t);
+            }
+        }
+        chunks.push(Chunk {
+            first_line: first_line + i * lines_per_chunk.max(1),
+            text: group.join("\n"),
+            start,
+            end,
+        });
+    }
+    chunks
+}
+
+/// The timestamp at the start of `line`, in seconds since the Unix epoch.
+pub fn parse_timestamp(line: &str) -> Option<i64> {
+    let line = line.trim_start().trim_start_matches('[');
+    let (date, rest) = (line.get(..10)?, line.get(10..)?);
+    let days = parse_date(date)?;
+    let rest = rest.strip_prefix(['T', ' '])?;
+    let (h, m, s) = (
+        rest.get(0..2)?.parse::<i64>().ok()?,
+        rest.get(3..5)?.parse::<i64>().ok()?,
+        rest.get(6..8)?.parse::<i64>().ok()?,
+    );
+    if rest.get(2..3)? != ":" || rest.get(5..6)? != ":" || h > 23 || m > 59 || s > 60 {
+        return None;
+    }
+    // Skip fractional seconds, then apply an offset if one follows
+    let rest = rest[8..].trim_start_matches(['.', ',']);
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+    let offset = parse_offset(rest).unwrap_or(0);
+    Some(days * 86_400 + h * 3_600 + m * 60 + s - offset)
+}
+
+/// Parse a `since`/`until` bound: a timestamp as in logs, a `YYYY-MM-DD` date (midnight
+/// UTC) or seconds since the Unix epoch.
+pub fn parse_time_bound(value: &str) -> anyhow::Result<i64> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<i64>() {
+        return Ok(secs);
+    }
+    if let Some(t) = parse_timestamp(value) {
+        return Ok(t);
+    }
+    match parse_date(value) {
+        Some(days) if value.len() == 10 => Ok(days * 86_400),
+        _ => anyhow::bail!(
+            "Invalid time '{}' (use e.g. 2024-07-01, 2024-07-01T09:00:00Z or Unix seconds)",
+            value
+        ),
+    }
+}
+
+/// Format seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_timestamp(secs: i64) -> String {
+    let time = secs.rem_euclid(86_400);
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        format_date(secs),
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60
+    )
+}
+
+/// Format seconds since the Unix epoch as a UTC `YYYY-MM-DD` date.
+pub fn format_date(secs: i64) -> String {
+    // Civil-from-days (Howard Hinnant's algorithm)
+    let z = secs.div_euclid(86_400) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Days since the Unix epoch of a `YYYY-MM-DD` date.
+fn parse_date(date: &str) -> Option<i64> {
+    let bytes = date.as_bytes();
+    if bytes.len() < 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return None;
+    }
+    let year: i64 = date.get(0..4)?.parse().ok()?;
+    let month: i64 = date.get(5..7)?.parse().ok()?;
+    let day: i64 = date.get(8..10)?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days-from-civil (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146_097 + doe - 719_468)
+}
+
+/// Seconds east of UTC for `Z`, `+02:00` or `-0530` at the start of `s`.
+fn parse_offset(s: &str) -> Option<i64> {
+    if s.starts_with('Z') {
+        return Some(0);
+    }
+    let sign = match s.get(0..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits: String = s[1..].chars().filter(|c| *c != ':').take(4).collect();
+    if digits.len() != 4 {
+        return None;
+    }
+    let h: i64 = digits[0..2].parse().ok()?;
+    let m: i64 = digits[2..4].parse().ok()?;
+    Some(sign * (h * 3_600 + m * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("2024-07-01T09:00:00Z ERROR boom"), Some(1_719_824_400));
+        assert_eq!(parse_timestamp("[2024-07-01 09:00:00.123] INFO"), Some(1_719_824_400));
+        assert_eq!(parse_timestamp("2024-07-01T11:00:00+02:00 x"), Some(1_719_824_400));
+        assert_eq!(parse_timestamp("    at com.example.Main"), None);
+        assert_eq!(parse_timestamp("2024-13-01T09:00:00Z"), None);
+        assert_eq!(parse_time_bound("2024-07-01").unwrap(), 1_719_792_000);
+        assert_eq!(parse_time_bound("1719824400").unwrap(), 1_719_824_400);
+        assert!(parse_time_bound("yesterday").is_err());
+        assert_eq!(format_timestamp(1_719_824_400), "2024-07-01T09:00:00Z");
+        assert_eq!(format_date(-1), "1969-12-31");
+    }
+
+    #[test]
+    fn test_chunks_carry_time_into_continuation_lines() {
+        let log = "2024-07-01T09:00:00Z start\n\
+                   2024-07-01T09:05:00Z ERROR database\n\
+                   \tat Db.connect\n\
+                   \tat Main.run\n\
+                   2024-07-01T10:00:00Z done";
+        let chunks = chunks(log, 2);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].first_line, 3);
+        // Only continuation lines: the time of the last timestamped line before them
+        assert_eq!(chunks[1].start, Some(1_719_824_700));
+        assert_eq!(chunks[1].end, Some(1_719_824_700));
+        assert_eq!(chunks[2].end, Some(1_719_828_000));
+    }
+}
@@ -1,92 +1,523 @@
+mod daemon;
 mod server;
+use localfiles::audit::{self, AuditLog};
+use localfiles::crawl;
+use localfiles::highlight;
 use localfiles::indexer;
+use localfiles::limits::Limits;
+use localfiles::ocr;
+use localfiles::paths;
+use localfiles::purge;
+use localfiles::ranking;
+use localfiles::scope::SessionScope;
+use localfiles::sqlite;
+use localfiles::synonyms;
+use localfiles::telemetry;
+use localfiles::usage;
 use localfiles::watcher;
+use localfiles::write;
 
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
 use rmcp::transport::stdio;
 use rmcp::ServiceExt;
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
 
-use server::{FileSearchServer, SharedState};
-use watcher::FileEvent;
+use server::{AppState, Capabilities, FileSearchServer, SharedState};
+use watcher::{FileEvent, PendingEvents};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // All tracing to stderr — stdout is reserved for MCP stdio protocol
-    tracing_subscriber::fmt()
-        .with_env_filter("localfiles=info")
-        .with_writer(std::io::stderr)
-        .init();
+    // All tracing to stderr — stdout is reserved for MCP stdio protocol.
+    // RUST_LOG overrides the default filter; LOCALFILES_LOG_FORMAT=json emits one JSON object per line.
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("localfiles=info"));
+    let json = std::env::var("LOCALFILES_LOG_FORMAT").is_ok_and(|v| v.eq_ignore_ascii_case("json"));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    if json {
+        subscriber.json().with_current_span(true).with_span_list(false).init();
+    } else {
+        subscriber.init();
+    }
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let has_flag = |flag: &str| args.iter().any(|a| a == flag);
+
+    // Thin frontend: relay stdio to the shared daemon, starting it if needed
+    if has_flag("--proxy") {
+        return daemon::proxy(&daemon::socket_path()?, &paths::default_base_dir()).await;
+    }
+
+    // Indexes live in the per-user data dir, keyed by the workspace the server was started from;
+    // the daemon serves every workspace from one machine-wide index
+    let daemon_mode = has_flag("--daemon");
+    let index_path = if daemon_mode {
+        paths::default_index_path(&[])?
+    } else {
+        paths::default_index_path(&[paths::default_base_dir()])?
+    };
+
+    // Scrub the index, the files next to it (OCR cache included) and the audit logs, then exit
+    if has_flag("--purge-index") {
+        let extra = AuditLog::path_from_env()?.map(|p| audit::log_files(&p)).unwrap_or_default();
+        let purged = purge::purge_index(&index_path, &extra)?;
+        println!("Purged {} ({} paths scrubbed)", index_path.display(), purged.len());
+        for path in purged {
+            println!("  {}", path.display());
+        }
+        return Ok(());
+    }
+
+    // Optional Prometheus exporter
+    if let Some(addr) = telemetry::init()? {
+        tracing::info!("Serving metrics on http://{}/metrics", addr);
+    }
 
     // Create the file index
-    let index = indexer::FileIndex::new(None)?;
+    let mut config = indexer::IndexConfig::default();
+    if let Some(threads) = env_parse("LOCALFILES_WRITER_THREADS")? {
+        config.writer_threads = Some(threads);
+    }
+    if let Some(mb) = env_parse::<usize>("LOCALFILES_WRITER_HEAP_MB")? {
+        config.writer_heap_bytes = mb * 1_000_000;
+    }
+    if let Some(docs) = env_parse("LOCALFILES_COMMIT_EVERY_DOCS")? {
+        config.commit_every_docs = docs;
+    }
+    if let Some(ms) = env_parse("LOCALFILES_COMMIT_INTERVAL_MS")? {
+        config.commit_interval = std::time::Duration::from_millis(ms);
+    }
+    if let Some(mb) = env_parse::<u64>("LOCALFILES_MAX_FILE_MB")? {
+        config.max_file_size = mb * 1024 * 1024;
+    }
+    // `truncate` indexes the start and end of larger files (e.g. logs) instead of skipping them
+    if let Ok(mode) = std::env::var("LOCALFILES_LARGE_FILES") {
+        config.large_files = indexer::LargeFiles::parse(&mode)
+            .map_err(|e| anyhow::anyhow!("Invalid LOCALFILES_LARGE_FILES: {}", e))?;
+    }
+    // Dotfiles and junk directories skipped in watched roots (overridable per index_paths call)
+    if let Ok(v) = std::env::var("LOCALFILES_INDEX_HIDDEN") {
+        config.hidden.hidden = v == "1" || v.eq_ignore_ascii_case("true");
+    }
+    if let Ok(spec) = std::env::var("LOCALFILES_SKIP_DIRS") {
+        config.hidden.skip_dirs = indexer::HiddenPolicy::parse_skip_dirs(&spec);
+    }
+    // SQLite databases: `schema`, or rows of text per table to index as well
+    if let Ok(spec) = std::env::var(sqlite::SQLITE_ENV) {
+        config.sqlite_rows = Some(
+            sqlite::parse_rows(&spec)
+                .map_err(|e| anyhow::anyhow!("Invalid {}: {}", sqlite::SQLITE_ENV, e))?,
+        );
+    }
+    // Markers around matches in snippets: `off`, `**`, or `<mark> </mark>`
+    if let Ok(spec) = std::env::var(highlight::HIGHLIGHT_ENV) {
+        config.highlight = highlight::Highlight::parse(&spec)
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", highlight::HIGHLIGHT_ENV, e))?;
+    }
+    // Outputs of recent searches reused until the next commit; 0 turns the cache off
+    if let Some(entries) = env_parse("LOCALFILES_QUERY_CACHE")? {
+        config.query_cache = entries;
+    }
+    // One summary document per directory, for search_directories
+    if let Ok(v) = std::env::var("LOCALFILES_DIRECTORY_SUMMARIES") {
+        config.directory_summaries = v == "1" || v.eq_ignore_ascii_case("true");
+    }
+    // Per-path score factors, e.g. `docs/**=2.0,vendor/**=0.3`
+    if let Ok(spec) = std::env::var(ranking::RANKING_ENV) {
+        config.ranking = ranking::parse_rules(&spec)
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", ranking::RANKING_ENV, e))?;
+    }
+    // Groups of equivalent words and phrases, one per line of the file it names
+    if let Ok(path) = std::env::var(synonyms::SYNONYMS_ENV) {
+        config.synonyms = synonyms::Synonyms::load(std::path::Path::new(&path))
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", synonyms::SYNONYMS_ENV, e))?;
+    }
+    // `walk` indexes files in directory walk order instead of shallow and recent first
+    if let Ok(order) = std::env::var(crawl::CRAWL_ORDER_ENV) {
+        config.crawl_order = crawl::CrawlOrder::parse(&order)
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", crawl::CRAWL_ORDER_ENV, e))?;
+    }
+    // Small persistent boost for search results that get read, counted per file
+    if let Ok(v) = std::env::var(usage::USAGE_ENV) {
+        config.usage_ranking = v == "1" || v.eq_ignore_ascii_case("true");
+    }
+    // Rate limits and size caps for tool calls, parsed up front so bad values fail fast
+    let limits = Limits::from_env()?;
+    // Optional audit log of file accesses (LOCALFILES_AUDIT_LOG)
+    let audit = AuditLog::from_env()?;
+    if let Some(audit) = &audit {
+        tracing::info!("Writing audit log to {}", audit.path().display());
+    }
+    // An index that cannot be opened or recovered is only replaced when asked to; the old
+    // one is kept in a `.corrupt-<timestamp>` directory next to it
+    config.rebuild_corrupt = has_flag("--rebuild-corrupt")
+        || std::env::var("LOCALFILES_REBUILD_CORRUPT").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+    // `--in-memory` or LOCALFILES_IN_MEMORY=1 keeps the index in RAM for ephemeral sessions
+    config.in_memory = has_flag("--in-memory")
+        || std::env::var("LOCALFILES_IN_MEMORY").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+    // An index left at the old temp location is moved here once the index is locked
+    config.migrate_legacy = true;
+    // OCR output is cached next to the index, unless nothing may be written to disk
+    let ocr_cache = (!config.in_memory).then(|| ocr::cache_path(&index_path));
+    let mut index = indexer::FileIndex::with_config(Some(index_path), config)?;
+    if let Ok(spec) = std::env::var(ocr::OCR_ENV) {
+        let mut ocr = ocr::OcrConfig::parse(&spec, ocr_cache)?;
+        if let Ok(secs) = std::env::var(ocr::OCR_TIMEOUT_ENV) {
+            ocr = ocr.with_timeout(std::time::Duration::from_secs(secs.trim().parse()?));
+        }
+        index.set_ocr(ocr);
+        tracing::info!("OCR enabled: {}", spec);
+    }
+
+    if let Ok(spec) = std::env::var("LOCALFILES_HISTORY") {
+        let revisions: Vec<String> = spec
+            .split(',')
+            .map(|r| r.trim().to_string())
+            .filter(|r| !r.is_empty())
+            .collect();
+        if !cfg!(feature = "git") {
+            tracing::warn!("LOCALFILES_HISTORY is ignored without --features git");
+        } else if !revisions.is_empty() {
+            tracing::info!("History search enabled for revisions: {}", revisions.join(", "));
+            index.set_history_revisions(revisions);
+        }
+    }
+
+    // Read side of the index, used by search tools without taking the state lock
+    let search = index.search_handle();
+    // LOCALFILES_WARM_UP=1 reads the index files in the background, so the first
+    // searches do not wait on cold memory maps
+    let warm_up = std::env::var("LOCALFILES_WARM_UP")
+        .is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+    if warm_up {
+        let search = search.clone();
+        tokio::spawn(async move {
+            match search.run(|search| search.warm_up()).await {
+                Ok(bytes) => tracing::info!("Warmed up the index ({} bytes read)", bytes),
+                Err(e) => tracing::warn!("Failed to warm up the index: {}", e),
+            }
+        });
+    }
 
     // Create the file watcher
-    let (watcher_handle, mut event_rx) = watcher::new_watcher()?;
+    let (mut watcher_handle, mut event_rx, overflow) = watcher::new_watcher()?;
+    if let Some(ms) = env_parse("LOCALFILES_POLL_INTERVAL_MS")? {
+        watcher_handle.set_poll_interval(std::time::Duration::from_millis(ms));
+    }
+    // Poll directories the OS has no native watches left for (inotify's max_user_watches)
+    if let Ok(v) = std::env::var("LOCALFILES_WATCH_LIMIT_FALLBACK") {
+        watcher_handle.set_limit_fallback(v == "1" || v.eq_ignore_ascii_case("true"));
+    }
+
+    let queue = Arc::new(watcher::QueueStats::default());
+    let deferred_commit = Arc::new(tokio::sync::Notify::new());
 
     // Shared state for MCP handler + background task
     let state = Arc::new(RwLock::new(SharedState {
         index,
         watcher: watcher_handle,
+        overflow: overflow.clone(),
+        queue: queue.clone(),
+        saved: Default::default(),
+        deferred_commit: deferred_commit.clone(),
     }));
 
     // Spawn background task: debounced file event processing
     let state_bg = state.clone();
     tokio::spawn(async move {
-        let mut pending: Vec<FileEvent> = Vec::new();
+        let mut pending = PendingEvents::default();
+        // When changes left uncommitted by the commit policy must be committed
+        let mut commit_due: Option<tokio::time::Instant> = None;
+        // When the next file that failed to index is due for another attempt
+        let mut retry_due: Option<tokio::time::Instant> = None;
         loop {
-            // Wait for the first event
-            let event = event_rx.recv().await;
-            match event {
-                None => break, // channel closed
-                Some(e) => pending.push(e),
+            // Wait for the first event, for events that overflowed the channel, or for
+            // deferred changes or retries to become due
+            tokio::select! {
+                event = event_rx.recv() => match event {
+                    None => break, // channel closed
+                    Some(e) => {
+                        pending.push(e);
+                        queue.set_pending(pending.len() + event_rx.len());
+                    }
+                },
+                _ = overflow.notified() => {}
+                _ = deferred_commit.notified() => {
+                    // Changes left by `index_paths` for the commit policy, or files to retry
+                    commit_due = commit_deferred(&state_bg).await;
+                    retry_due = next_retry(&state_bg).await;
+                    continue;
+                }
+                _ = tokio::time::sleep_until(retry_due.unwrap_or_else(tokio::time::Instant::now)),
+                    if retry_due.is_some() =>
+                {
+                    // Index the due files again with the next batch
+                    let due = state_bg.write().await.index.take_due_retries();
+                    for path in due {
+                        pending.push_if_absent(FileEvent::Modified(path));
+                    }
+                    retry_due = None;
+                }
+                _ = tokio::time::sleep_until(commit_due.unwrap_or_else(tokio::time::Instant::now)),
+                    if commit_due.is_some() =>
+                {
+                    commit_due = commit_deferred(&state_bg).await;
+                    continue;
+                }
             }
 
             // Debounce: collect events for 500ms
             let deadline =
                 tokio::time::Instant::now() + tokio::time::Duration::from_millis(500);
-            loop {
-                match tokio::time::timeout_at(deadline, event_rx.recv()).await {
-                    Ok(Some(e)) => pending.push(e),
-                    _ => break,
-                }
+            while let Ok(Some(e)) = tokio::time::timeout_at(deadline, event_rx.recv()).await {
+                pending.push(e);
+                queue.set_pending(pending.len() + event_rx.len());
             }
 
             // Process batch under a single write lock
-            let mut s = state_bg.write().await;
-            for event in pending.drain(..) {
-                match event {
-                    FileEvent::Created(p) | FileEvent::Modified(p) => {
-                        if let Err(e) = s.index.index_file(&p) {
-                            tracing::warn!("Failed to re-index {}: {}", p.display(), e);
-                        }
+            let span = tracing::info_span!(
+                "index_batch",
+                events = pending.len(),
+                overflowed = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+            );
+            let started = std::time::Instant::now();
+            let events = pending.take();
+            let overflow_batch = overflow.clone();
+            let batch_span = span.clone();
+            // Re-indexing and committing run on the blocking pool, off the async executor
+            let removals = events.iter().any(|e| matches!(e, FileEvent::Removed(_)));
+            let processed = indexer::write_blocking(state_bg.clone(), move |s| {
+                let _guard = batch_span.enter();
+                let mut processed = events.len();
+                for event in events {
+                    if let Err(e) = s.index.apply(&event) {
+                        tracing::warn!("Failed to apply event for {}: {}", event.path().display(), e);
                     }
-                    FileEvent::Removed(p) => {
-                        if let Err(e) = s.index.remove_file(&p) {
-                            tracing::warn!(
-                                "Failed to remove {} from index: {}",
-                                p.display(),
-                                e
-                            );
+                }
+
+                // Events lost to a full channel: rescan the affected paths from disk
+                let overflowed = overflow_batch.take_paths();
+                if !overflowed.is_empty() {
+                    tracing::warn!(
+                        "Watcher channel overflowed, rescanning {} paths",
+                        overflowed.len()
+                    );
+                    processed += overflowed.len();
+                    batch_span.record("overflowed", overflowed.len());
+                    for p in overflowed {
+                        if let Err(e) = s.index.rescan_path(&p) {
+                            tracing::warn!("Failed to rescan {}: {}", p.display(), e);
                         }
                     }
                 }
-            }
-            if let Err(e) = s.index.commit() {
-                tracing::warn!("Failed to commit after watcher batch: {}", e);
+
+                // A removed directory may have been a root, or contained one
+                if removals {
+                    if let Err(e) = server::check_roots(s, false) {
+                        tracing::warn!("Failed to detach missing roots: {}", e);
+                    }
+                }
+
+                let (committed, due) = commit_if_due(s);
+                (processed, committed, due, s.index.next_retry())
+            })
+            .instrument(span.clone())
+            .await;
+            let processed = match processed {
+                Ok((processed, committed, due, retry)) => {
+                    if committed {
+                        check_saved_searches(&state_bg).instrument(span.clone()).await;
+                    }
+                    commit_due = due.map(tokio::time::Instant::from_std);
+                    retry_due = retry.map(tokio::time::Instant::from_std);
+                    processed
+                }
+                Err(e) => {
+                    tracing::error!(parent: &span, "Watcher batch failed: {}", e);
+                    0
+                }
+            };
+            let _guard = span.enter();
+            queue.finish_batch(processed);
+            span.record("duration_ms", started.elapsed().as_millis() as u64);
+            tracing::debug!("Applied {} watcher events", processed);
+        }
+    });
+
+    // Roots can also vanish without events (an unmounted volume), so check every 30s;
+    // missing roots are re-attached when they reappear unless LOCALFILES_REATTACH_ROOTS=0
+    let reattach_roots = std::env::var("LOCALFILES_REATTACH_ROOTS")
+        .map_or(true, |v| v == "1" || v.eq_ignore_ascii_case("true"));
+    let state_roots = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            let checked = indexer::write_blocking(state_roots.clone(), move |s| {
+                if server::check_roots(s, reattach_roots)? {
+                    s.index.commit()?;
+                }
+                anyhow::Ok(())
+            })
+            .await;
+            if let Err(e) = checked.and_then(|checked| checked) {
+                tracing::warn!("Failed to check indexed roots: {}", e);
             }
         }
     });
 
+    // After a schema change, re-index the roots the old index covered
+    let migrated = state.read().await.index.migration().map(|m| m.roots.clone());
+    if let Some(roots) = migrated {
+        tokio::spawn(server::reindex_roots(state.clone(), roots));
+    }
+
+    // Resume indexing a root where a killed process left off
+    let interrupted = state.read().await.index.interrupted_crawl().map(|c| c.root.clone());
+    if let Some(root) = interrupted {
+        tokio::spawn(server::reindex_roots(state.clone(), vec![root]));
+    }
+
+    // write_file and append_file are off unless LOCALFILES_WRITE_TOOLS=1, delete_file
+    // unless built with `trash` and LOCALFILES_DELETE_TOOL=1, purge_index unless
+    // LOCALFILES_PURGE_TOOL=1
+    let capabilities = Capabilities {
+        write: std::env::var(write::WRITE_TOOLS_ENV)
+            .is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")),
+        delete: cfg!(feature = "trash")
+            && std::env::var(write::DELETE_TOOL_ENV)
+                .is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")),
+        purge: std::env::var(purge::PURGE_TOOL_ENV)
+            .is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")),
+        ..Capabilities::of(&state.read().await.index)
+    };
+    // Index the workspace roots clients advertise (on unless LOCALFILES_CLIENT_ROOTS=0)
+    let client_roots = std::env::var("LOCALFILES_CLIENT_ROOTS")
+        .map_or(true, |v| v == "1" || v.eq_ignore_ascii_case("true"));
+    // With LOCALFILES_SESSION_ISOLATION=1 each session only sees the roots it indexed,
+    // plus the comma-separated LOCALFILES_SHARED_ROOTS
+    let scope = if std::env::var("LOCALFILES_SESSION_ISOLATION")
+        .is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+    {
+        let base_dir = paths::default_base_dir();
+        let shared: Vec<PathBuf> = std::env::var("LOCALFILES_SHARED_ROOTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let path = paths::expand(p, &base_dir);
+                path.canonicalize().unwrap_or(path)
+            })
+            .collect();
+        tracing::info!("Session isolation enabled ({} shared roots)", shared.len());
+        SessionScope::isolated(shared)
+    } else {
+        SessionScope::unrestricted()
+    };
+    let server = FileSearchServer::new(state.clone(), search)
+        .with_limits(limits)
+        .with_audit_log(audit)
+        .with_capabilities(capabilities)
+        .with_client_roots(client_roots)
+        .with_reattach_roots(reattach_roots)
+        .with_scope(scope);
+    if daemon_mode {
+        return daemon::serve(server, &daemon::socket_path()?).await;
+    }
+
     // Start MCP server on stdio
     tracing::info!("localfiles MCP server starting on stdio");
-    let server = FileSearchServer::new(state);
     let service = server.serve(stdio()).await.inspect_err(|e| {
         tracing::error!("Failed to start MCP server: {}", e);
     })?;
 
     service.waiting().await?;
 
+    // Commit changes the commit policy was still holding back
+    let mut s = state.write().await;
+    if s.index.has_uncommitted_changes() {
+        s.index.commit()?;
+    }
+    s.index.search_handle().flush_usage()?;
+
     Ok(())
 }
+
+/// Commit changes held back by the commit policy if they are due, off the executor.
+/// Returns when to try again.
+async fn commit_deferred(state: &AppState) -> Option<tokio::time::Instant> {
+    let (committed, due) = indexer::write_blocking(state.clone(), commit_if_due)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Deferred commit failed: {}", e);
+            (false, None)
+        });
+    if committed {
+        check_saved_searches(state).await;
+    }
+    due.map(tokio::time::Instant::from_std)
+}
+
+/// Queue files that newly match saved searches. The searches run on the blocking pool
+/// without holding the state; only recording what they found takes the write lock.
+async fn check_saved_searches(state: &AppState) {
+    let (handle, runs) = {
+        let s = state.read().await;
+        (s.index.search_handle(), s.saved.runs())
+    };
+    if runs.is_empty() {
+        return;
+    }
+    let found = tokio::task::spawn_blocking(move || {
+        runs.iter().filter_map(|run| run.matches(&handle)).collect::<Vec<_>>()
+    })
+    .await;
+    match found {
+        Ok(found) => {
+            let mut s = state.write().await;
+            for run in found {
+                s.saved.record(run);
+            }
+        }
+        Err(e) => tracing::error!("Saved search task failed: {}", e),
+    }
+}
+
+/// When the next file that failed to index is due for another attempt.
+async fn next_retry(state: &AppState) -> Option<tokio::time::Instant> {
+    state.read().await.index.next_retry().map(tokio::time::Instant::from_std)
+}
+
+/// Commit under the commit policy. Returns whether anything was committed, for
+/// re-running saved searches, and when the changes still uncommitted become due.
+fn commit_if_due(s: &mut SharedState) -> (bool, Option<std::time::Instant>) {
+    let committed = match s.index.commit_if_due() {
+        Ok(committed) => committed,
+        Err(e) => {
+            tracing::warn!("Failed to commit watcher changes: {}", e);
+            false
+        }
+    };
+    (committed, s.index.next_commit_due())
+}
+
+/// Parse an optional numeric setting from the environment.
+fn env_parse<T: std::str::FromStr>(name: &str) -> anyhow::Result<Option<T>>
+where
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(v) if !v.trim().is_empty() => v
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", name, e)),
+        _ => Ok(None),
+    }
+}
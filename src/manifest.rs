@@ -0,0 +1,87 @@
+//! The roots an on-disk index was built from.
+//!
+//! Saved next to the index as `<index>.roots.json` whenever the set of roots changes, so
+//! it survives the index directory being deleted for a new schema version. The server
+//! then re-indexes those roots instead of coming back with an empty index.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::indexer::HiddenPolicy;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RootEntry {
+    pub path: PathBuf,
+    /// False for roots indexed once with `index_paths` `watch: false`.
+    pub watched: bool,
+    /// The root's own hidden-file policy, if `index_paths` overrode the configured one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<HiddenPolicy>,
+}
+
+/// Manifest of the index at `index_path`.
+pub fn manifest_path(index_path: &Path) -> PathBuf {
+    let name = index_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "index".to_string());
+    index_path.with_file_name(format!("{}.roots.json", name))
+}
+
+/// Roots recorded for the index at `index_path`; none if it has no manifest yet.
+pub fn load(index_path: &Path) -> anyhow::Result<Vec<RootEntry>> {
+    let path = manifest_path(index_path);
+    match std::fs::read_to_string(&path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace the manifest of the index at `index_path` with `roots`.
+pub fn save(index_path: &Path, roots: &[RootEntry]) -> anyhow::Result<()> {
+    write_json_atomic(&manifest_path(index_path), &roots)
+}
+
+/// Replace the file at `path` with `value` as JSON. Writes a sibling `.tmp` file then
+/// renames it over `path`, so a crash never leaves a truncated file.
+pub fn write_json_atomic(path: &Path, value: &impl Serialize) -> anyhow::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, serde_json::to_vec(value)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_and_load_roots() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        assert!(load(&index_path).unwrap().is_empty());
+        let roots = vec![
+            RootEntry {
+                path: PathBuf::from("/home/me/notes"),
+                watched: true,
+                policy: None,
+            },
+            RootEntry {
+                path: PathBuf::from("/srv/archive"),
+                watched: false,
+                policy: Some(HiddenPolicy {
+                    hidden: false,
+                    skip_dirs: vec!["build".to_string()],
+                }),
+            },
+        ];
+        save(&index_path, &roots).unwrap();
+        assert_eq!(manifest_path(&index_path), dir.path().join("index.roots.json"));
+        assert_eq!(load(&index_path).unwrap(), roots);
+    }
+}
@@ -0,0 +1,203 @@
+//! Markdown front matter, heading and link helpers.
+//!
+//! Front matter is the YAML block between `---` lines at the top of a note. Only the
+//! keys used by note-taking tools are read (`title`, `tags`, `aliases`), in either the
+//! inline (`tags: [a, b]`) or block list (`tags:\n  - a`) form.
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    pub aliases: Vec<String>,
+}
+
+/// Whether `extension` is a markdown extension.
+pub fn is_markdown(extension: &str) -> bool {
+    matches!(extension.to_lowercase().as_str(), "md" | "markdown")
+}
+
+/// Parse the front matter at the top of `content`, if any.
+pub fn front_matter(content: &str) -> Option<FrontMatter> {
+    let mut lines = content.lines();
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+
+    let mut fm = FrontMatter::default();
+    let mut current: Option<&str> = None;
+    for line in lines {
+        if line.trim_end() == "---" || line.trim_end() == "..." {
+            return Some(fm);
+        }
+        if let Some(item) = line.trim_start().strip_prefix("- ") {
+            if line.starts_with(|c: char| c.is_whitespace() || c == '-') {
+                if let Some(key) = current {
+                    fm.push(key, item);
+                }
+                continue;
+            }
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        let value = value.trim();
+        current = Some(match key {
+            "title" | "tags" | "tag" | "aliases" | "alias" => key,
+            _ => "",
+        });
+        if let Some(list) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            for item in list.split(',') {
+                fm.push(key, item);
+            }
+        } else if !value.is_empty() {
+            if key == "tags" || key == "tag" {
+                // `tags: a b` and `tags: a, b` are both common
+                for item in value.split([',', ' ']) {
+                    fm.push(key, item);
+                }
+            } else {
+                fm.push(key, value);
+            }
+        }
+    }
+    None // unterminated block is not front matter
+}
+
+impl FrontMatter {
+    fn push(&mut self, key: &str, value: &str) {
+        let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+        if value.is_empty() {
+            return;
+        }
+        match key {
+            "title" => self.title = Some(value.to_string()),
+            "tags" | "tag" => self.tags.push(value.trim_start_matches('#').to_lowercase()),
+            "aliases" | "alias" => self.aliases.push(value.to_string()),
+            _ => {}
+        }
+    }
+}
+
+/// Headings outside code fences as `(1-based line, level, text)`.
+pub fn headings(content: &str) -> Vec<(usize, usize, String)> {
+    let mut in_fence = false;
+    let mut headings = Vec::new();
+    for (i, l) in content.lines().enumerate() {
+        let trimmed = l.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        let level = l.chars().take_while(|&c| c == '#').count();
+        if (1..=6).contains(&level) && l[level..].starts_with(' ') {
+            headings.push((i + 1, level, l[level..].trim().to_string()));
+        }
+    }
+    headings
+}
+
+/// Text of the closest heading at or above 1-based `line`.
+pub fn heading_at_line(content: &str, line: usize) -> Option<String> {
+    headings(content)
+        .into_iter()
+        .take_while(|(l, _, _)| *l <= line)
+        .last()
+        .map(|(_, _, text)| text)
+}
+
+/// An outgoing link from a markdown note.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Link {
+    /// `[[Note Name]]`, with any `#heading` or `|alias` removed.
+    Wiki(String),
+    /// `[text](relative/path.md)`, with any `#anchor` removed.
+    Relative(String),
+}
+
+/// Extract wiki-links and relative markdown links from `content`.
+pub fn links(content: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else { break };
+        let target = after[..end].split(['|', '#']).next().unwrap_or("").trim();
+        if !target.is_empty() {
+            links.push(Link::Wiki(target.to_string()));
+        }
+        rest = &after[end + 2..];
+    }
+
+    let mut rest = content;
+    while let Some(start) = rest.find("](") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find(')') else { break };
+        let href = after[..end].split('#').next().unwrap_or("").trim();
+        let external = href.contains("://") || href.starts_with("mailto:");
+        if !href.is_empty() && !external {
+            links.push(Link::Relative(href.replace("%20", " ")));
+        }
+        rest = &after[end + 1..];
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_front_matter_inline_and_block_lists() {
+        let note = "---\ntitle: \"Weekly Review\"\ntags: [Planning, #work]\naliases:\n  - review\n  - retro\n---\n# Body\n";
+        let fm = front_matter(note).unwrap();
+        assert_eq!(fm.title.as_deref(), Some("Weekly Review"));
+        assert_eq!(fm.tags, vec!["planning", "work"]);
+        assert_eq!(fm.aliases, vec!["review", "retro"]);
+    }
+
+    #[test]
+    fn test_front_matter_absent_or_unterminated() {
+        assert!(front_matter("# Just a heading\n").is_none());
+        assert!(front_matter("---\ntitle: x\n").is_none());
+    }
+
+    #[test]
+    fn test_heading_at_line_skips_code_fences() {
+        let note = "# Intro\ntext\n## Setup\n```sh\n# not a heading\n```\nmatch here\n";
+        assert_eq!(heading_at_line(note, 7).as_deref(), Some("Setup"));
+        assert_eq!(heading_at_line(note, 2).as_deref(), Some("Intro"));
+        assert_eq!(heading_at_line("no headings", 1), None);
+    }
+
+    #[test]
+    fn test_headings_levels_and_lines() {
+        let note = "# Title\n\n## Part\n```\n## code\n```\n### Detail\n#hashtag\n";
+        assert_eq!(
+            headings(note),
+            vec![
+                (1, 1, "Title".to_string()),
+                (3, 2, "Part".to_string()),
+                (7, 3, "Detail".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_links_wiki_and_relative() {
+        let note = "See [[Project Plan|the plan]] and [[Ideas#Later]].\n\
+                    Also [notes](../notes/My%20Note.md#top), [site](https://example.com).";
+        assert_eq!(
+            links(note),
+            vec![
+                Link::Wiki("Project Plan".to_string()),
+                Link::Wiki("Ideas".to_string()),
+                Link::Relative("../notes/My Note.md".to_string()),
+            ]
+        );
+    }
+}
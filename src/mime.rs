@@ -0,0 +1,180 @@
+//! MIME types of indexed files, from their content as well as their extension.
+//!
+//! Specific extensions decide the type (`.rs` is `text/x-rust`). Files with a generic or
+//! no extension are sniffed: a `#!` line names the interpreter of a script, and text
+//! that parses as JSON, or starts like XML or HTML, gets that type. Everything else is
+//! `text/plain`. The `mime` field holds the type and its `type/*` wildcard, so filters
+//! can ask for `application/json` or for any `text/*`.
+
+use std::path::Path;
+
+/// Types by lowercase extension. `txt`, `log` and the like are missing on purpose: their
+/// content is sniffed.
+const BY_EXTENSION: &[(&str, &str)] = &[
+    ("rs", "text/x-rust"),
+    ("py", "text/x-python"),
+    ("js", "text/javascript"),
+    ("jsx", "text/javascript"),
+    ("ts", "text/x-typescript"),
+    ("tsx", "text/x-typescript"),
+    ("json", "application/json"),
+    ("toml", "application/toml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("html", "text/html"),
+    ("css", "text/css"),
+    ("scss", "text/x-scss"),
+    ("sh", "text/x-shellscript"),
+    ("bash", "text/x-shellscript"),
+    ("zsh", "text/x-shellscript"),
+    ("c", "text/x-c"),
+    ("h", "text/x-c"),
+    ("cpp", "text/x-c++"),
+    ("hpp", "text/x-c++"),
+    ("java", "text/x-java"),
+    ("go", "text/x-go"),
+    ("rb", "text/x-ruby"),
+    ("php", "text/x-php"),
+    ("sql", "application/sql"),
+    ("xml", "application/xml"),
+    ("csv", "text/csv"),
+    ("tsv", "text/tab-separated-values"),
+    ("md", "text/markdown"),
+    ("markdown", "text/markdown"),
+    ("ipynb", "application/x-ipynb+json"),
+    ("eml", "message/rfc822"),
+    ("mbox", "application/mbox"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("webp", "image/webp"),
+    ("sqlite", "application/vnd.sqlite3"),
+    ("sqlite3", "application/vnd.sqlite3"),
+    ("db", "application/vnd.sqlite3"),
+    ("db3", "application/vnd.sqlite3"),
+    ("xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+    ("xlsm", "application/vnd.ms-excel.sheet.macroenabled.12"),
+    ("xls", "application/vnd.ms-excel"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+];
+
+/// Script types by interpreter, with the extension such scripts usually have.
+const INTERPRETERS: &[(&str, &str, &str)] = &[
+    ("python", "text/x-python", "py"),
+    ("sh", "text/x-shellscript", "sh"),
+    ("bash", "text/x-shellscript", "sh"),
+    ("zsh", "text/x-shellscript", "sh"),
+    ("dash", "text/x-shellscript", "sh"),
+    ("ksh", "text/x-shellscript", "sh"),
+    ("node", "text/javascript", "js"),
+    ("deno", "text/x-typescript", "ts"),
+    ("ruby", "text/x-ruby", "rb"),
+    ("perl", "text/x-perl", "pl"),
+    ("php", "text/x-php", "php"),
+];
+
+/// MIME type of the file at `path` with text `content`.
+pub fn detect(path: &Path, content: &str) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    if let Some((_, mime)) = BY_EXTENSION.iter().find(|(ext, _)| *ext == extension) {
+        return mime;
+    }
+    if let Some((mime, _)) = script(content) {
+        return mime;
+    }
+    let start = content.trim_start();
+    let head: String = start.chars().take(64).collect::<String>().to_lowercase();
+    if (start.starts_with('{') || start.starts_with('['))
+        && serde_json::from_str::<serde::de::IgnoredAny>(content).is_ok()
+    {
+        "application/json"
+    } else if head.starts_with("<?xml") {
+        "application/xml"
+    } else if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        "text/html"
+    } else {
+        "text/plain"
+    }
+}
+
+/// The `type/*` wildcard matching `mime`.
+pub fn wildcard(mime: &str) -> String {
+    format!("{}/*", mime.split('/').next().unwrap_or(mime))
+}
+
+/// Pseudo-extension of a script from the interpreter on its `#!` line: the usual
+/// extension for known interpreters (`py` for `python3`), otherwise the interpreter's
+/// name (`lua`, `awk`).
+pub fn script_extension(content: &str) -> Option<String> {
+    let name = interpreter(content)?;
+    match INTERPRETERS.iter().find(|(interpreter, _, _)| *interpreter == name) {
+        Some((_, _, extension)) => Some(extension.to_string()),
+        None => Some(name.to_lowercase()),
+    }
+}
+
+/// `script_extension` of the file at `path`, reading only its first line.
+pub fn file_script_extension(path: &Path) -> Option<String> {
+    use std::io::Read;
+
+    let mut head = Vec::with_capacity(256);
+    std::fs::File::open(path).ok()?.take(256).read_to_end(&mut head).ok()?;
+    if !head.starts_with(b"#!") {
+        return None;
+    }
+    script_extension(&String::from_utf8_lossy(&head))
+}
+
+/// Type of a script by its interpreter, if the interpreter is a known one.
+fn script(content: &str) -> Option<(&'static str, &'static str)> {
+    let name = interpreter(content)?;
+    INTERPRETERS
+        .iter()
+        .find(|(interpreter, _, _)| *interpreter == name)
+        .map(|(_, mime, extension)| (*mime, *extension))
+}
+
+/// Interpreter named on the `#!` line of `content`, without its version: `bash` for
+/// `#!/bin/bash`, `python` for `#!/usr/bin/env python3.12`, `node` for
+/// `#!/usr/bin/env -S node --flag`.
+fn interpreter(content: &str) -> Option<&str> {
+    let line = content.strip_prefix("#!")?.lines().next()?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_by_extension_and_content() {
+        assert_eq!(detect(Path::new("main.rs"), "{}"), "text/x-rust");
+        assert_eq!(detect(Path::new("data.txt"), " {\"a\": [1, 2]}\n"), "application/json");
+        assert_eq!(detect(Path::new("notes.txt"), "{ not json"), "text/plain");
+        assert_eq!(detect(Path::new("page.txt"), "<!DOCTYPE html><p>"), "text/html");
+        let script = "#!/usr/bin/env python3\nprint()";
+        assert_eq!(detect(Path::new("deploy"), script), "text/x-python");
+        assert_eq!(detect(Path::new("run"), "#!/bin/bash -e\n"), "text/x-shellscript");
+        let node = "#!/usr/bin/env -S node --no-warnings\n";
+        assert_eq!(script_extension(node).as_deref(), Some("js"));
+        assert_eq!(script_extension("#!/usr/bin/lua5.4\n").as_deref(), Some("lua"));
+        assert_eq!(detect(Path::new("tool"), "#!/usr/bin/lua5.4\n"), "text/plain");
+        assert_eq!(script_extension("#!\n"), None);
+        assert_eq!(wildcard("text/x-python"), "text/*");
+    }
+}
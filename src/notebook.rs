@@ -0,0 +1,115 @@
+//! Text extraction for Jupyter notebooks (`.ipynb`).
+//!
+//! Only code and markdown cell sources are indexed; outputs, metadata and embedded
+//! images are dropped. Cells are separated by a blank line, and the line each one starts
+//! on is kept apart from the text so that a matching line can be mapped back to its cell.
+
+/// The indexed text of a notebook and where its cells start.
+pub struct Extracted {
+    pub text: String,
+    /// Notebook cell index and the 1-based line of `text` it starts on, in order.
+    pub cells: Vec<(usize, usize)>,
+}
+
+/// Extract the code and markdown cell sources of a notebook.
+pub fn extract(json: &str) -> anyhow::Result<Extracted> {
+    let notebook: serde_json::Value = serde_json::from_str(json)?;
+    let cells = notebook
+        .get("cells")
+        .and_then(|c| c.as_array())
+        .ok_or_else(|| anyhow::anyhow!("Notebook has no cells array"))?;
+
+    let mut out = Extracted {
+        text: String::new(),
+        cells: Vec::new(),
+    };
+    let mut line = 1;
+    for (i, cell) in cells.iter().enumerate() {
+        let kind = cell.get("cell_type").and_then(|t| t.as_str()).unwrap_or("");
+        if kind != "code" && kind != "markdown" {
+            continue;
+        }
+        // `source` is either a single string or a list of lines
+        let source = match cell.get("source") {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(serde_json::Value::Array(lines)) => {
+                lines.iter().filter_map(|l| l.as_str()).collect()
+            }
+            _ => String::new(),
+        };
+        let source = source.trim_end();
+        out.cells.push((i, line));
+        out.text.push_str(source);
+        out.text.push_str("\n\n");
+        line += source.matches('\n').count() + 2;
+    }
+    Ok(out)
+}
+
+/// Stored form of a cell start: `<cell index>:<line>`.
+pub fn encode_cell((index, line): (usize, usize)) -> String {
+    format!("{}:{}", index, line)
+}
+
+/// Index of the cell containing 1-based `line`, given the stored cell starts.
+pub fn cell_at_line<'a>(cells: impl IntoIterator<Item = &'a str>, line: usize) -> Option<usize> {
+    cells
+        .into_iter()
+        .filter_map(|cell| {
+            let (index, start) = cell.split_once(':')?;
+            Some((index.parse().ok()?, start.parse::<usize>().ok()?))
+        })
+        .take_while(|&(_, start)| start <= line)
+        .last()
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r##"{
+        "cells": [
+            {"cell_type": "markdown", "source": ["# Title\n", "Intro text"]},
+            {"cell_type": "raw", "source": "ignored"},
+            {"cell_type": "code", "source": "import numpy\nplot_token()",
+             "outputs": [{"data": {"image/png": "iVBORw0KGgo="}}]}
+        ],
+        "metadata": {}
+    }"##;
+
+    #[test]
+    fn test_extract_keeps_only_sources() {
+        let text = extract(NOTEBOOK).unwrap().text;
+        assert!(text.contains("Intro text"));
+        assert!(text.contains("plot_token()"));
+        assert!(!text.contains("ignored"));
+        assert!(!text.contains("iVBORw0KGgo"));
+    }
+
+    #[test]
+    fn test_cell_at_line() {
+        let nb = extract(NOTEBOOK).unwrap();
+        assert_eq!(
+            nb.text,
+            "# Title\nIntro text\n\nimport numpy\nplot_token()\n\n"
+        );
+        assert_eq!(nb.cells, vec![(0, 1), (2, 4)]);
+        let cells: Vec<String> = nb.cells.into_iter().map(encode_cell).collect();
+        let cells = || cells.iter().map(String::as_str);
+        let line = nb
+            .text
+            .lines()
+            .position(|l| l.contains("plot_token"))
+            .unwrap()
+            + 1;
+        assert_eq!(cell_at_line(cells(), line), Some(2));
+        assert_eq!(cell_at_line(cells(), 2), Some(0));
+    }
+
+    #[test]
+    fn test_extract_rejects_non_notebook() {
+        assert!(extract("not json").is_err());
+        assert!(extract("{}").is_err());
+    }
+}
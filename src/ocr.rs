@@ -0,0 +1,247 @@
+//! Pluggable OCR for images and scanned PDFs.
+//!
+//! An external command is configured per extension, e.g. `tesseract {} -` for `png`.
+//! `{}` is replaced by the file path (the path is appended if there is no `{}`), and the
+//! command's stdout is indexed as the file's text. Output is cached by content hash so
+//! unchanged files are not run through OCR again, in a directory next to the index
+//! (`cache_path`) that purging the index scrubs. The cache holds the text of private
+//! documents, so its directory is readable by its owner only.
+//!
+//! Indexing waits for the command, so one that hangs is killed after a timeout
+//! (`OCR_TIMEOUT_ENV`, 60 seconds by default) and the file fails to index like any other.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Environment variable holding OCR commands, e.g. `png,jpg=tesseract {} -;pdf=pdf-ocr {}`.
+pub const OCR_ENV: &str = "LOCALFILES_OCR";
+
+/// Environment variable holding the seconds an OCR command may run per file.
+pub const OCR_TIMEOUT_ENV: &str = "LOCALFILES_OCR_TIMEOUT";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Where OCR output for the index at `index_path` is cached: `<index>.ocr` next to it.
+pub fn cache_path(index_path: &Path) -> PathBuf {
+    let name = index_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "index".to_string());
+    index_path.with_file_name(format!("{}.ocr", name))
+}
+
+pub struct OcrConfig {
+    /// Command line per lowercase extension.
+    commands: HashMap<String, String>,
+    /// `None` runs the command every time, for indexes that keep nothing on disk.
+    cache_dir: Option<PathBuf>,
+    /// How long a command may run before it is killed.
+    timeout: Duration,
+}
+
+impl OcrConfig {
+    pub fn new(cache_dir: Option<PathBuf>) -> Self {
+        Self {
+            commands: HashMap::new(),
+            cache_dir,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Kill commands still running after `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run `command` for files with `extension`.
+    pub fn with_command(mut self, extension: &str, command: &str) -> Self {
+        self.commands
+            .insert(extension.trim_start_matches('.').to_lowercase(), command.to_string());
+        self
+    }
+
+    /// Parse `ext[,ext]=command` entries separated by `;`.
+    pub fn parse(spec: &str, cache_dir: Option<PathBuf>) -> anyhow::Result<Self> {
+        let mut config = Self::new(cache_dir);
+        for entry in spec.split(';').filter(|e| !e.trim().is_empty()) {
+            let (extensions, command) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Expected ext=command in OCR spec: {}", entry))?;
+            if command.trim().is_empty() {
+                anyhow::bail!("Empty OCR command for {}", extensions);
+            }
+            for ext in extensions.split(',').filter(|e| !e.trim().is_empty()) {
+                config = config.with_command(ext.trim(), command.trim());
+            }
+        }
+        Ok(config)
+    }
+
+    /// Directory of the cached OCR output, if cached.
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache_dir.as_deref()
+    }
+
+    /// Whether an OCR command is configured for `path`.
+    pub fn handles(&self, path: &Path) -> bool {
+        self.command_for(path).is_some()
+    }
+
+    /// Extract text from `path`, using the cached output when the content is unchanged.
+    pub fn extract(&self, path: &Path) -> anyhow::Result<String> {
+        let command = self
+            .command_for(path)
+            .ok_or_else(|| anyhow::anyhow!("No OCR command for {}", path.display()))?;
+        let Some(cache_dir) = &self.cache_dir else {
+            return run(command, path, self.timeout);
+        };
+        let bytes = std::fs::read(path)?;
+        let cache_file = cache_dir.join(format!("{}.txt", blake3::hash(&bytes).to_hex()));
+        if let Ok(text) = std::fs::read_to_string(&cache_file) {
+            return Ok(text);
+        }
+
+        let text = run(command, path, self.timeout)?;
+        create_private_dir(cache_dir)?;
+        std::fs::write(&cache_file, &text)?;
+        Ok(text)
+    }
+
+    fn command_for(&self, path: &Path) -> Option<&str> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        self.commands.get(&ext).map(String::as_str)
+    }
+}
+
+/// Create `dir` and its missing parents, accessible by this user only on unix.
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+fn run(command: &str, path: &Path, timeout: Duration) -> anyhow::Result<String> {
+    let path_arg = path.to_string_lossy();
+    let mut args: Vec<String> = command
+        .split_whitespace()
+        .map(|a| a.replace("{}", &path_arg))
+        .collect();
+    if !command.contains("{}") {
+        args.push(path_arg.to_string());
+    }
+    let (program, rest) = args
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("Empty OCR command"))?;
+    let mut child = Command::new(program)
+        .args(rest)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Drained on their own threads so a chatty command cannot block on a full pipe
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("OCR command `{}` timed out after {:?}", command, timeout);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = stderr.join().unwrap_or_default();
+        anyhow::bail!(
+            "OCR command `{}` failed: {}",
+            command,
+            String::from_utf8_lossy(&stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// Read `pipe` to the end on a separate thread.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_spec() {
+        let config = OcrConfig::parse("png,JPG=tesseract {} -;pdf=pdf-ocr", None).unwrap();
+        assert!(config.handles(Path::new("scan.png")));
+        assert!(config.handles(Path::new("photo.jpg")));
+        assert_eq!(config.command_for(Path::new("doc.PDF")), Some("pdf-ocr"));
+        assert!(!config.handles(Path::new("notes.md")));
+        assert!(OcrConfig::parse("png", None).is_err());
+        let cache = cache_path(Path::new("/data/indexes/ab12"));
+        assert_eq!(cache, Path::new("/data/indexes/ab12.ocr"));
+    }
+
+    #[test]
+    fn test_extract_caches_by_content() {
+        let dir = TempDir::new().unwrap();
+        let image = dir.path().join("scan.png");
+        std::fs::write(&image, "recognized text").unwrap();
+        let config = OcrConfig::new(Some(dir.path().join("cache"))).with_command("png", "cat {}");
+        assert_eq!(config.extract(&image).unwrap(), "recognized text");
+        assert_eq!(std::fs::read_dir(dir.path().join("cache")).unwrap().count(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(dir.path().join("cache")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        // A cache hit does not run the command again
+        let failing = OcrConfig::new(Some(dir.path().join("cache"))).with_command("png", "false");
+        assert_eq!(failing.extract(&image).unwrap(), "recognized text");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hung_command_is_killed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let image = dir.path().join("scan.png");
+        std::fs::write(&image, "stuck").unwrap();
+        let script = dir.path().join("hang.sh");
+        std::fs::write(&script, "#!/bin/sh\nsleep 30\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = OcrConfig::new(None)
+            .with_command("png", &script.to_string_lossy())
+            .with_timeout(Duration::from_millis(200));
+
+        let started = Instant::now();
+        let err = config.extract(&image).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,208 @@
+//! Structural outlines of single files.
+//!
+//! Markdown outlines are its headings. With the `tree-sitter` feature, Rust, Python,
+//! JavaScript, TypeScript and Go files are parsed and their definitions (functions,
+//! types, classes, impls, ...) are listed, nested under their enclosing definition.
+
+use std::path::Path;
+
+use crate::markdown;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineEntry {
+    /// 1-based line where the heading or definition starts.
+    pub line: usize,
+    /// Nesting depth, 0 for top level.
+    pub depth: usize,
+    /// `heading`, or the definition kind such as `fn`, `struct` or `class`.
+    pub kind: String,
+    pub name: String,
+}
+
+/// Outline of `content`, read from `path`. Fails for file types without outline support.
+pub fn outline(path: &Path, content: &str) -> anyhow::Result<Vec<OutlineEntry>> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    if markdown::is_markdown(&extension) {
+        return Ok(markdown::headings(content)
+            .into_iter()
+            .map(|(line, level, name)| OutlineEntry {
+                line,
+                depth: level - 1,
+                kind: "heading".to_string(),
+                name,
+            })
+            .collect());
+    }
+    code_outline(&extension, content)
+}
+
+#[cfg(feature = "tree-sitter")]
+fn code_outline(extension: &str, content: &str) -> anyhow::Result<Vec<OutlineEntry>> {
+    let (language, kinds): (tree_sitter::Language, &[(&str, &str)]) = match extension {
+        "rs" => (tree_sitter_rust::LANGUAGE.into(), RUST_KINDS),
+        "py" => (tree_sitter_python::LANGUAGE.into(), PYTHON_KINDS),
+        "js" | "jsx" => (tree_sitter_javascript::LANGUAGE.into(), JS_KINDS),
+        "ts" => (tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(), TS_KINDS),
+        "tsx" => (tree_sitter_typescript::LANGUAGE_TSX.into(), TS_KINDS),
+        "go" => (tree_sitter_go::LANGUAGE.into(), GO_KINDS),
+        _ => anyhow::bail!("No outline available for .{} files", extension),
+    };
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&language)?;
+    let tree = parser
+        .parse(content, None)
+        .ok_or_else(|| anyhow::anyhow!("Failed to parse file"))?;
+
+    let mut entries = Vec::new();
+    collect(tree.root_node(), content.as_bytes(), kinds, 0, &mut entries);
+    Ok(entries)
+}
+
+#[cfg(not(feature = "tree-sitter"))]
+fn code_outline(extension: &str, _content: &str) -> anyhow::Result<Vec<OutlineEntry>> {
+    anyhow::bail!(
+        "No outline available for .{} files (code outlines require building with --features tree-sitter)",
+        extension
+    )
+}
+
+/// Definition node kinds per language, with the label shown in the outline.
+#[cfg(feature = "tree-sitter")]
+const RUST_KINDS: &[(&str, &str)] = &[
+    ("function_item", "fn"),
+    ("function_signature_item", "fn"),
+    ("struct_item", "struct"),
+    ("enum_item", "enum"),
+    ("union_item", "union"),
+    ("trait_item", "trait"),
+    ("impl_item", "impl"),
+    ("mod_item", "mod"),
+    ("const_item", "const"),
+    ("static_item", "static"),
+    ("type_item", "type"),
+    ("macro_definition", "macro"),
+];
+
+#[cfg(feature = "tree-sitter")]
+const PYTHON_KINDS: &[(&str, &str)] = &[
+    ("function_definition", "def"),
+    ("class_definition", "class"),
+];
+
+#[cfg(feature = "tree-sitter")]
+const JS_KINDS: &[(&str, &str)] = &[
+    ("function_declaration", "function"),
+    ("generator_function_declaration", "function"),
+    ("class_declaration", "class"),
+    ("method_definition", "method"),
+];
+
+#[cfg(feature = "tree-sitter")]
+const TS_KINDS: &[(&str, &str)] = &[
+    ("function_declaration", "function"),
+    ("generator_function_declaration", "function"),
+    ("class_declaration", "class"),
+    ("abstract_class_declaration", "class"),
+    ("method_definition", "method"),
+    ("interface_declaration", "interface"),
+    ("type_alias_declaration", "type"),
+    ("enum_declaration", "enum"),
+];
+
+#[cfg(feature = "tree-sitter")]
+const GO_KINDS: &[(&str, &str)] = &[
+    ("function_declaration", "func"),
+    ("method_declaration", "method"),
+    ("type_spec", "type"),
+];
+
+#[cfg(feature = "tree-sitter")]
+fn collect(
+    node: tree_sitter::Node,
+    source: &[u8],
+    kinds: &[(&str, &str)],
+    depth: usize,
+    entries: &mut Vec<OutlineEntry>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let Some((_, label)) = kinds.iter().find(|(kind, _)| *kind == child.kind()) else {
+            collect(child, source, kinds, depth, entries);
+            continue;
+        };
+        let text = |field: &str| {
+            child
+                .child_by_field_name(field)
+                .and_then(|n| n.utf8_text(source).ok())
+                .map(str::to_string)
+        };
+        let name = if child.kind() == "impl_item" {
+            // `impl Trait for Type` or `impl Type`
+            match (text("trait"), text("type")) {
+                (Some(t), Some(ty)) => format!("{} for {}", t, ty),
+                (_, ty) => ty.unwrap_or_default(),
+            }
+        } else {
+            text("name").unwrap_or_default()
+        };
+        entries.push(OutlineEntry {
+            line: child.start_position().row + 1,
+            depth,
+            kind: label.to_string(),
+            name,
+        });
+        collect(child, source, kinds, depth + 1, entries);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_outline() {
+        let entries = outline(Path::new("notes.md"), "# Top\ntext\n## Sub\n").unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                OutlineEntry {
+                    line: 1,
+                    depth: 0,
+                    kind: "heading".to_string(),
+                    name: "Top".to_string()
+                },
+                OutlineEntry {
+                    line: 3,
+                    depth: 1,
+                    kind: "heading".to_string(),
+                    name: "Sub".to_string()
+                },
+            ]
+        );
+        assert!(outline(Path::new("data.csv"), "a,b").is_err());
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    #[test]
+    fn test_rust_outline_nests_impl_methods() {
+        let source = "struct Point;\n\nimpl Display for Point {\n    fn fmt(&self) {}\n}\n\nfn main() {}\n";
+        let summary: Vec<(usize, usize, String)> = outline(Path::new("lib.rs"), source)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.line, e.depth, format!("{} {}", e.kind, e.name)))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, 0, "struct Point".to_string()),
+                (3, 0, "impl Display for Point".to_string()),
+                (4, 1, "fn fmt".to_string()),
+                (7, 0, "fn main".to_string()),
+            ]
+        );
+    }
+}
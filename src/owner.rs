@@ -0,0 +1,142 @@
+//! File owners and permission bits, for `owner` filters and `list_files` details.
+//!
+//! Only unix files have an owner. Elsewhere no file has one, so owner filters match
+//! nothing. User names come from `/etc/passwd` and are recorded when a file is indexed.
+
+use std::collections::HashMap;
+
+/// Owner and permissions of an indexed file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: u32,
+    /// Name of `uid` when it is listed in `/etc/passwd`.
+    pub user: Option<String>,
+    /// Permission bits, e.g. `0o644`.
+    pub mode: u32,
+}
+
+impl Owner {
+    /// Owner of a file with `metadata`, on unix.
+    #[cfg(unix)]
+    pub fn of(metadata: &std::fs::Metadata, users: &Users) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        Some(Self {
+            uid: metadata.uid(),
+            user: users.name(metadata.uid()).map(str::to_string),
+            mode: metadata.mode() & 0o7777,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn of(_metadata: &std::fs::Metadata, _users: &Users) -> Option<Self> {
+        None
+    }
+
+    /// The user name, or the uid if it has none.
+    pub fn name(&self) -> String {
+        self.user.clone().unwrap_or_else(|| self.uid.to_string())
+    }
+
+    /// Permission bits as `ls -l` shows them, e.g. `rw-r-----`.
+    pub fn permissions(&self) -> String {
+        let mut out = String::with_capacity(9);
+        for shift in [6, 3, 0] {
+            let bits = self.mode >> shift;
+            out.push(if bits & 4 != 0 { 'r' } else { '-' });
+            out.push(if bits & 2 != 0 { 'w' } else { '-' });
+            out.push(if bits & 1 != 0 { 'x' } else { '-' });
+        }
+        out
+    }
+}
+
+/// User names by uid.
+#[derive(Debug, Clone, Default)]
+pub struct Users {
+    names: HashMap<u32, String>,
+}
+
+impl Users {
+    /// Read `/etc/passwd`; no names are known if it cannot be read.
+    pub fn load() -> Self {
+        std::fs::read_to_string("/etc/passwd")
+            .map(|passwd| Self::parse(&passwd))
+            .unwrap_or_default()
+    }
+
+    /// Parse `name:password:uid:...` lines.
+    pub fn parse(passwd: &str) -> Self {
+        let names = passwd
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut fields = line.split(':');
+                let name = fields.next()?;
+                let uid = fields.nth(1)?.parse().ok()?;
+                Some((uid, name.to_string()))
+            })
+            .collect();
+        Self { names }
+    }
+
+    pub fn name(&self, uid: u32) -> Option<&str> {
+        self.names.get(&uid).map(String::as_str)
+    }
+
+    /// Resolve an owner filter: `me` (the user running the server), a user name or a uid.
+    pub fn resolve(&self, owner: &str) -> anyhow::Result<u32> {
+        let owner = owner.trim();
+        if owner.eq_ignore_ascii_case("me") {
+            return current_uid()
+                .ok_or_else(|| anyhow::anyhow!("File owners are only available on unix"));
+        }
+        if let Ok(uid) = owner.parse() {
+            return Ok(uid);
+        }
+        self.names
+            .iter()
+            .find(|(_, name)| name.as_str() == owner)
+            .map(|(uid, _)| *uid)
+            .ok_or_else(|| anyhow::anyhow!("Unknown user '{}'", owner))
+    }
+}
+
+/// Effective uid of this process, on unix.
+pub fn current_uid() -> Option<u32> {
+    #[cfg(unix)]
+    {
+        // SAFETY: geteuid has no preconditions and cannot fail
+        Some(unsafe { libc::geteuid() })
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_users_and_permissions() {
+        let users = Users::parse(
+            "# comment\nroot:x:0:0::/root:/bin/sh\nalice:x:1000:1000::/home/alice:/bin/sh\n",
+        );
+        assert_eq!(users.name(1000), Some("alice"));
+        assert_eq!(users.resolve("alice").unwrap(), 1000);
+        assert_eq!(users.resolve("0").unwrap(), 0);
+        assert!(users.resolve("mallory").is_err());
+        #[cfg(unix)]
+        assert_eq!(users.resolve("me").unwrap(), current_uid().unwrap());
+
+        let owner = Owner {
+            uid: 1000,
+            user: None,
+            mode: 0o640,
+        };
+        assert_eq!(owner.permissions(), "rw-r-----");
+        assert_eq!(owner.name(), "1000");
+    }
+}
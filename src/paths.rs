@@ -0,0 +1,262 @@
+//! Expansion of user-supplied path arguments.
+//!
+//! Tool arguments and environment settings may use `~`, `$HOME`, the variables listed in
+//! `LOCALFILES_PATH_VARS` and paths relative to a base directory (`LOCALFILES_BASE_DIR`,
+//! else the working directory). Other variables are left as written, so a client cannot
+//! read the server's environment through paths, and messages about expanded paths show
+//! them as written (`shown`). Also resolves the per-user data directory that holds
+//! indexes by default.
+
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the base directory for relative paths.
+pub const BASE_DIR_ENV: &str = "LOCALFILES_BASE_DIR";
+/// Comma-separated names of environment variables that paths may use besides `HOME`.
+pub const PATH_VARS_ENV: &str = "LOCALFILES_PATH_VARS";
+
+/// Base directory for relative paths: `LOCALFILES_BASE_DIR`, else the working directory.
+pub fn default_base_dir() -> PathBuf {
+    match std::env::var(BASE_DIR_ENV) {
+        Ok(dir) if !dir.trim().is_empty() => {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            expand(dir.trim(), &cwd)
+        }
+        _ => std::env::current_dir().unwrap_or_default(),
+    }
+}
+
+fn home_dir() -> Option<String> {
+    home_in(&env_var)
+}
+
+/// Home directory according to the environment `env`.
+fn home_in(env: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    env("HOME").or_else(|| env("USERPROFILE")).filter(|h| !h.is_empty())
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+fn env_dir(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+}
+
+/// Per-user data directory for localfiles: `$XDG_DATA_HOME/localfiles`, else the
+/// platform default (`~/.local/share`, `~/Library/Application Support` or
+/// `%LOCALAPPDATA%`). Fails when there is no home directory, rather than sharing a
+/// directory such as the temp dir with other users.
+pub fn data_dir() -> anyhow::Result<PathBuf> {
+    let base = env_dir("XDG_DATA_HOME").or_else(|| {
+        if cfg!(windows) {
+            env_dir("LOCALAPPDATA")
+        } else if cfg!(target_os = "macos") {
+            home_dir().map(|h| Path::new(&h).join("Library/Application Support"))
+        } else {
+            home_dir().map(|h| Path::new(&h).join(".local/share"))
+        }
+    });
+    let base = base.ok_or_else(|| {
+        anyhow::anyhow!("No per-user data directory: set HOME or XDG_DATA_HOME")
+    })?;
+    Ok(base.join("localfiles"))
+}
+
+/// Default index directory for a set of roots: `<data_dir>/indexes/<hash>`, so
+/// different workspaces get separate indexes and the same roots always map to the same one.
+pub fn default_index_path(roots: &[PathBuf]) -> anyhow::Result<PathBuf> {
+    let mut roots: Vec<String> = roots.iter().map(|r| r.display().to_string()).collect();
+    roots.sort();
+    roots.dedup();
+    let hash = blake3::hash(roots.join("\n").as_bytes()).to_hex();
+    Ok(data_dir()?.join("indexes").join(&hash[..16]))
+}
+
+/// Whether paths may use the environment variable `name`: `HOME` and those listed in
+/// `LOCALFILES_PATH_VARS` of `env`.
+fn allowed_var(name: &str, env: &dyn Fn(&str) -> Option<String>) -> bool {
+    name == "HOME"
+        || env(PATH_VARS_ENV).is_some_and(|vars| vars.split(',').any(|var| var.trim() == name))
+}
+
+/// Expand `~`, `$HOME`, allowed environment variables and relative paths into an
+/// absolute path.
+///
+/// Unset and disallowed variables are left as written so the resulting error names them.
+pub fn expand(input: &str, base_dir: &Path) -> PathBuf {
+    expand_in(input, base_dir, &env_var)
+}
+
+/// `expand` with the variables of `env` instead of this process's.
+fn expand_in(input: &str, base_dir: &Path, env: &dyn Fn(&str) -> Option<String>) -> PathBuf {
+    let mut expanded = String::with_capacity(input.len());
+
+    let rest = match input.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => match home_in(env) {
+            Some(home) => {
+                expanded.push_str(&home);
+                rest
+            }
+            None => input,
+        },
+        _ => input,
+    };
+
+    let mut chars = rest.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+        let (name, end) = if rest[i + 1..].starts_with('{') {
+            match rest[i + 2..].find('}') {
+                Some(close) => (&rest[i + 2..i + 2 + close], i + 3 + close),
+                None => ("", i + 1),
+            }
+        } else {
+            let len = rest[i + 1..]
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                .unwrap_or(rest.len() - i - 1);
+            (&rest[i + 1..i + 1 + len], i + 1 + len)
+        };
+        let value = match name {
+            "" => None,
+            "HOME" => home_in(env),
+            _ if allowed_var(name, env) => env(name),
+            _ => None,
+        };
+        match value {
+            Some(v) => {
+                expanded.push_str(&v);
+                while chars.peek().is_some_and(|&(j, _)| j < end) {
+                    chars.next();
+                }
+            }
+            None => expanded.push(c),
+        }
+    }
+
+    let path = PathBuf::from(expanded);
+    if path.is_absolute() {
+        path
+    } else {
+        base_dir.join(path)
+    }
+}
+
+/// `resolved`, the expansion of `input`, for messages: `input` as written if it uses `~`
+/// or a variable, so messages never echo the values of variables.
+pub fn shown(input: &str, resolved: &Path) -> String {
+    if input.starts_with('~') || input.contains('$') {
+        input.to_string()
+    } else {
+        resolved.display().to_string()
+    }
+}
+
+/// Path of a `file://` URI, such as a workspace root advertised by an MCP client, with
+/// percent-escapes decoded. `None` for other schemes and remote hosts.
+pub fn from_file_uri(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = match rest.strip_prefix("localhost") {
+        Some(path) => path,
+        None => rest,
+    };
+    if !path.starts_with('/') {
+        return None;
+    }
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8(decoded).ok()?;
+    // `file:///C:/Users/me` on Windows
+    match path.get(1..3) {
+        Some(drive) if cfg!(windows) && drive.ends_with(':') => Some(PathBuf::from(&path[1..])),
+        _ => Some(PathBuf::from(path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_home_vars_and_relative() {
+        let home = home_dir().expect("HOME is set in tests");
+        let base = Path::new("/work/base");
+        assert_eq!(expand("~", base), PathBuf::from(&home));
+        assert_eq!(expand("~/notes", base), Path::new(&home).join("notes"));
+        assert_eq!(expand("$HOME/notes", base), Path::new(&home).join("notes"));
+        assert_eq!(expand("${HOME}/notes", base), Path::new(&home).join("notes"));
+        assert_eq!(expand("docs/a.md", base), PathBuf::from("/work/base/docs/a.md"));
+        assert_eq!(expand("/abs/path", base), PathBuf::from("/abs/path"));
+        // `~user` and unset variables are kept literally
+        assert_eq!(expand("~bob", base), PathBuf::from("/work/base/~bob"));
+        assert_eq!(
+            expand("/x/$LOCALFILES_UNSET_TEST_VAR/y", base),
+            PathBuf::from("/x/$LOCALFILES_UNSET_TEST_VAR/y")
+        );
+
+        // Only variables in LOCALFILES_PATH_VARS are expanded besides HOME
+        let env = |allowed: &'static str| {
+            move |name: &str| match name {
+                "SECRET" => Some("hunter2".to_string()),
+                "PROJECTS" => Some("/srv/projects".to_string()),
+                PATH_VARS_ENV => Some(allowed.to_string()),
+                _ => None,
+            }
+        };
+        let allowing = env("OTHER, PROJECTS");
+        assert_eq!(expand_in("/x/$SECRET", base, &allowing), PathBuf::from("/x/$SECRET"));
+        assert_eq!(
+            expand_in("${PROJECTS}/app", base, &allowing),
+            PathBuf::from("/srv/projects/app")
+        );
+        assert_eq!(
+            expand_in("$PROJECTS/app", base, &env("")),
+            PathBuf::from("/work/base/$PROJECTS/app")
+        );
+
+        // Messages show expanded paths as written
+        assert_eq!(shown("$HOME/notes", &expand("$HOME/notes", base)), "$HOME/notes");
+        assert_eq!(shown("~/notes", &expand("~/notes", base)), "~/notes");
+        assert_eq!(shown("docs", &expand("docs", base)), "/work/base/docs");
+    }
+
+    #[test]
+    fn test_from_file_uri() {
+        assert_eq!(from_file_uri("file:///home/me/proj"), Some(PathBuf::from("/home/me/proj")));
+        assert_eq!(
+            from_file_uri("file://localhost/home/me/My%20Notes"),
+            Some(PathBuf::from("/home/me/My Notes"))
+        );
+        assert_eq!(from_file_uri("file:///a/100%"), Some(PathBuf::from("/a/100%")));
+        assert_eq!(from_file_uri("file://server/share"), None);
+        assert_eq!(from_file_uri("https://example.com/x"), None);
+    }
+
+    #[test]
+    fn test_default_index_path_depends_on_roots_only() {
+        let a = PathBuf::from("/work/a");
+        let b = PathBuf::from("/work/b");
+        let ab = default_index_path(&[a.clone(), b.clone()]).unwrap();
+        assert_eq!(ab, default_index_path(&[b.clone(), a.clone(), a.clone()]).unwrap());
+        assert_ne!(ab, default_index_path(&[a]).unwrap());
+        assert!(ab.starts_with(data_dir().unwrap().join("indexes")));
+    }
+}
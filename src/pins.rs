@@ -0,0 +1,58 @@
+//! Files pinned with `pin_file`, ranked above other matches.
+//!
+//! Saved next to the index as `<index>.pins.json` whenever the set changes, like the
+//! roots manifest, so pins survive restarts and rebuilds for a new schema version. A
+//! pin outlives its file being removed from the index, so a file an editor replaces by
+//! rename stays pinned once it is indexed again.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::manifest;
+
+/// Factor the score of a pinned file's matches is multiplied by.
+pub const PIN_BOOST: f32 = 2.0;
+
+/// Pins of the index at `index_path`.
+pub fn pins_path(index_path: &Path) -> PathBuf {
+    let name = index_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "index".to_string());
+    index_path.with_file_name(format!("{}.pins.json", name))
+}
+
+/// Files pinned for the index at `index_path`; none if nothing was pinned yet.
+pub fn load(index_path: &Path) -> anyhow::Result<BTreeSet<PathBuf>> {
+    let path = pins_path(index_path);
+    match std::fs::read_to_string(&path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace the pins of the index at `index_path` with `pinned`.
+pub fn save(index_path: &Path, pinned: &BTreeSet<PathBuf>) -> anyhow::Result<()> {
+    manifest::write_json_atomic(&pins_path(index_path), pinned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_and_load_pins() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        assert!(load(&index_path).unwrap().is_empty());
+        let pinned = BTreeSet::from([
+            PathBuf::from("/home/me/notes/architecture.md"),
+            PathBuf::from("/home/me/notes/style.md"),
+        ]);
+        save(&index_path, &pinned).unwrap();
+        assert_eq!(pins_path(&index_path), dir.path().join("index.pins.json"));
+        assert_eq!(load(&index_path).unwrap(), pinned);
+    }
+}
@@ -0,0 +1,41 @@
+//! Types for embedding the indexer and watcher in another application.
+//!
+//! ```no_run
+//! use localfiles::prelude::*;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let mut index = FileIndex::builder().in_memory().build()?;
+//! let root = std::path::Path::new("/home/me/notes");
+//! index.index_directory(root)?;
+//! index.commit()?;
+//!
+//! // Keep the index in sync with the filesystem
+//! let (mut watcher, mut events, _overflow) = new_watcher()?;
+//! watcher.watch(root, WatchMode::Native)?;
+//! while let Some(event) = events.recv().await {
+//!     index.apply(&event)?;
+//!     if index.has_uncommitted_changes() {
+//!         index.commit()?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Searches go through a `SearchHandle`, which is cheap to clone and never waits on
+//! indexing. `search_iter` and `search_stream` yield results one at a time, for callers
+//! that stop at the first good hit.
+
+pub use crate::indexer::{
+    FileIndex, FileInfo, HiddenPolicy, IndexBuilder, IndexConfig, IndexStatus, LargeFiles,
+    RecentFile, SearchFilters, SearchHandle, SearchIter, SearchOutput, SearchResult, Snippet,
+};
+pub use crate::crawl::CrawlOrder;
+pub use crate::highlight::Highlight;
+pub use crate::ocr::OcrConfig;
+pub use crate::owner::Owner;
+pub use crate::project::{Project, ProjectSummary};
+pub use crate::retry::FailedFile;
+pub use crate::watcher::{
+    new_watcher, FileEvent, FileWatcher, Overflow, WatchLimitHit, WatchMode,
+};
@@ -0,0 +1,119 @@
+//! Projects: the directories indexed files belong to, found by their marker files.
+//!
+//! A directory holding `Cargo.toml`, `package.json`, `pyproject.toml` or `.git` is the
+//! root of a project, named by its manifest (`[package] name`, `"name"`, `[project]
+//! name` or `[tool.poetry] name`) or else after the directory. Every document is tagged
+//! with the project of its nearest enclosing root, so a whole development folder can be
+//! indexed at once and searched one project at a time.
+
+use std::path::{Path, PathBuf};
+
+/// Marker files, in order of precedence, with the kind of project they make.
+const MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "cargo"),
+    ("package.json", "npm"),
+    ("pyproject.toml", "python"),
+    (".git", "git"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub name: String,
+    pub root: PathBuf,
+    /// `cargo`, `npm`, `python` or `git`, by the marker found.
+    pub kind: &'static str,
+}
+
+/// A project of the kind `list_projects` reports, with its number of indexed files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectSummary {
+    pub project: Project,
+    pub files: usize,
+}
+
+/// Whether a file named `name` makes its directory a project root.
+pub fn is_marker(name: &str) -> bool {
+    MARKERS.iter().any(|(marker, _)| *marker == name)
+}
+
+/// The project rooted at `dir`, if `dir` holds a marker.
+pub fn detect(dir: &Path) -> Option<Project> {
+    let (marker, kind) = MARKERS.iter().find(|(marker, _)| dir.join(marker).exists())?;
+    let manifest = || std::fs::read_to_string(dir.join(marker)).ok();
+    let name = match *kind {
+        "cargo" => manifest().and_then(|m| toml_name(&m, &["package"])),
+        "npm" => manifest().and_then(|m| {
+            let json: serde_json::Value = serde_json::from_str(&m).ok()?;
+            json.get("name")?.as_str().map(str::to_string)
+        }),
+        "python" => manifest().and_then(|m| toml_name(&m, &["project", "tool.poetry"])),
+        _ => None,
+    };
+    let name = name
+        .filter(|name| !name.is_empty())
+        .or_else(|| Some(dir.file_name()?.to_string_lossy().to_string()))?;
+    Some(Project {
+        name,
+        root: dir.to_path_buf(),
+        kind,
+    })
+}
+
+/// The `name = "..."` key of the first of `tables` in a TOML manifest that sets one.
+fn toml_name(content: &str, tables: &[&str]) -> Option<String> {
+    let mut names = tables.iter().map(|_| None).collect::<Vec<Option<String>>>();
+    let mut current = None;
+    for line in content.lines().map(str::trim) {
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header.trim_end_matches(']').trim();
+            current = tables.iter().position(|table| *table == header);
+            continue;
+        }
+        let Some(table) = current else {
+            continue;
+        };
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if key.trim() == "name" && names[table].is_none() {
+            let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+            names[table] = Some(value.to_string());
+        }
+    }
+    names.into_iter().flatten().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_names_from_manifests() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        let cargo = root.join("engine");
+        std::fs::create_dir_all(&cargo).unwrap();
+        std::fs::write(
+            cargo.join("Cargo.toml"),
+            "[workspace]\nname = \"not-this\"\n\n[package]\nname = \"engine-core\"\n",
+        )
+        .unwrap();
+        let detected = detect(&cargo).unwrap();
+        assert_eq!((detected.name.as_str(), detected.kind), ("engine-core", "cargo"));
+
+        let poetry = root.join("tool");
+        std::fs::create_dir_all(&poetry).unwrap();
+        std::fs::write(poetry.join("pyproject.toml"), "[tool.poetry]\nname = 'helper'\n").unwrap();
+        assert_eq!(detect(&poetry).unwrap().name, "helper");
+
+        let web = root.join("web");
+        std::fs::create_dir_all(web.join(".git")).unwrap();
+        std::fs::write(web.join("package.json"), "{\"version\": \"1.0.0\"}").unwrap();
+        // No name in the manifest: named after the directory, kind from the manifest
+        let detected = detect(&web).unwrap();
+        assert_eq!((detected.name.as_str(), detected.kind), ("web", "npm"));
+
+        assert!(detect(root).is_none());
+        assert!(is_marker("Cargo.toml") && !is_marker("Cargo.lock"));
+    }
+}
@@ -0,0 +1,182 @@
+//! Scrubbing everything localfiles stored about indexed files, e.g. when an engagement
+//! ends.
+//!
+//! `localfiles --purge-index` removes the workspace's index (the machine-wide one with
+//! `--daemon`) while no server has it open. The opt-in `purge_index` tool
+//! (`LOCALFILES_PURGE_TOOL`) empties the index of a running server instead, which keeps
+//! serving with no roots. Either way the index's segment files, the files kept next to
+//! it (roots manifest, pins, read counts, quarantined `.corrupt-*` copies), the audit
+//! logs and the OCR cache are overwritten with zeros before they are deleted. Copy-on-write
+//! filesystems and SSDs may still keep old copies of the blocks, so this complements disk
+//! encryption rather than replacing it.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::instance::{self, InstanceLock};
+
+/// Enables the `purge_index` tool.
+pub const PURGE_TOOL_ENV: &str = "LOCALFILES_PURGE_TOOL";
+
+/// Zeros written per call when overwriting a file.
+const CHUNK: usize = 64 * 1024;
+
+/// Files and directories kept next to the index at `index_path`, named `<index>.*`,
+/// except its lock file.
+pub fn sidecar_paths(index_path: &Path) -> Vec<PathBuf> {
+    let (Some(parent), Some(name)) = (index_path.parent(), index_path.file_name()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let lock = instance::lock_path(index_path);
+    let Ok(entries) = std::fs::read_dir(parent) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|entry| entry.path())
+        .filter(|path| *path != lock)
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Where `hold` keeps the segment files of the index at `index_path`.
+pub fn holding_path(index_path: &Path) -> PathBuf {
+    let name = index_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "index".to_string());
+    index_path.with_file_name(format!("{}.purging", name))
+}
+
+/// Hard-link the segment files of the index at `index_path` into `holding_path`, so their
+/// content can still be scrubbed after the emptied index deleted them.
+pub fn hold(index_path: &Path) -> anyhow::Result<()> {
+    let holding = holding_path(index_path);
+    std::fs::create_dir_all(&holding)?;
+    for entry in std::fs::read_dir(index_path)? {
+        let entry = entry?;
+        if is_segment_file(&entry.file_name().to_string_lossy()) {
+            std::fs::hard_link(entry.path(), holding.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `name` is a tantivy segment file (`<segment uuid>.<ext>`), which is never
+/// modified in place and holds indexed content.
+fn is_segment_file(name: &str) -> bool {
+    let id = name.split('.').next().unwrap_or("");
+    name.contains('.') && id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Overwrite the file at `path`, or every file below the directory at `path`, with zeros
+/// and delete it. Symbolic links are deleted, not followed. Returns the number of files
+/// scrubbed, none if `path` does not exist.
+pub fn scrub(path: &Path) -> anyhow::Result<usize> {
+    let meta = match std::fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    if meta.is_dir() {
+        let mut scrubbed = 0;
+        for entry in std::fs::read_dir(path)? {
+            scrubbed += scrub(&entry?.path())?;
+        }
+        std::fs::remove_dir(path)?;
+        return Ok(scrubbed);
+    }
+    if meta.is_file() {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        let zeros = [0u8; CHUNK];
+        let mut left = meta.len();
+        while left > 0 {
+            let n = left.min(CHUNK as u64) as usize;
+            file.write_all(&zeros[..n])?;
+            left -= n as u64;
+        }
+        file.sync_all()?;
+    }
+    std::fs::remove_file(path)?;
+    Ok(1)
+}
+
+/// Scrub the index at `index_path`, the files next to it and `extra` paths (audit logs,
+/// caches), failing if a server has the index open. Returns the paths that existed.
+pub fn purge_index(index_path: &Path, extra: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let lock = InstanceLock::acquire(index_path)?;
+    let mut targets = vec![index_path.to_path_buf()];
+    targets.extend(sidecar_paths(index_path));
+    targets.extend(extra.iter().cloned());
+    let mut purged = Vec::new();
+    for path in targets {
+        if path.exists() || path.is_symlink() {
+            scrub(&path)?;
+            purged.push(path);
+        }
+    }
+    drop(lock);
+    std::fs::remove_file(instance::lock_path(index_path))?;
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_purge_index_scrubs_index_and_sidecars() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().join("abc");
+        std::fs::create_dir_all(index_path.join("sub")).unwrap();
+        std::fs::write(index_path.join("meta.json"), "{}").unwrap();
+        std::fs::write(index_path.join("sub/data"), vec![7u8; CHUNK + 10]).unwrap();
+        std::fs::write(dir.path().join("abc.roots.json"), "[]").unwrap();
+        std::fs::create_dir(dir.path().join("abc.corrupt-1")).unwrap();
+        std::fs::write(dir.path().join("abcd.roots.json"), "[]").unwrap();
+        let audit = dir.path().join("audit.jsonl");
+        std::fs::write(&audit, "{}\n").unwrap();
+        assert_eq!(
+            sidecar_paths(&index_path),
+            [
+                dir.path().join("abc.corrupt-1"),
+                dir.path().join("abc.roots.json")
+            ]
+        );
+
+        let held = InstanceLock::acquire(&index_path).unwrap();
+        assert!(purge_index(&index_path, &[]).is_err());
+        drop(held);
+        let missing = dir.path().join("missing");
+        let purged = purge_index(&index_path, &[audit.clone(), missing]).unwrap();
+        assert_eq!(purged.len(), 4);
+        assert!(!index_path.exists() && !audit.exists());
+        assert!(!instance::lock_path(&index_path).exists());
+        // Another index's files are left alone
+        assert!(dir.path().join("abcd.roots.json").exists());
+    }
+
+    #[test]
+    fn test_hold_links_segment_files() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        std::fs::create_dir(&index_path).unwrap();
+        let segment = "0123456789abcdef0123456789abcdef";
+        std::fs::write(index_path.join(format!("{}.store", segment)), "secret").unwrap();
+        std::fs::write(index_path.join(format!("{}.12.del", segment)), "x").unwrap();
+        std::fs::write(index_path.join("meta.json"), "{}").unwrap();
+        hold(&index_path).unwrap();
+        let holding = holding_path(&index_path);
+        assert_eq!(std::fs::read_dir(&holding).unwrap().count(), 2);
+
+        std::fs::remove_file(index_path.join(format!("{}.store", segment))).unwrap();
+        assert_eq!(scrub(&holding).unwrap(), 2);
+        assert!(!holding.exists());
+        assert!(index_path.join(format!("{}.12.del", segment)).exists());
+    }
+}
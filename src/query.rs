@@ -0,0 +1,504 @@
+//! The search query language: field names, their aliases and the syntax help text.
+//!
+//! Queries are parsed by tantivy's `QueryParser`, which knows fields only by their schema
+//! names. Before parsing, `rewrite` replaces field aliases with those names, so `ext:rs`,
+//! `type:rs` and `extension:rs` are the same query and `path:src` searches `directory`.
+//! A `name:` prefix that is neither a field nor an alias is rejected with an
+//! `UnknownField` error listing the valid fields, instead of tantivy's bare
+//! "field does not exist". Words with `*` or `?` are wildcard patterns (`wildcard_regex`),
+//! which `check_wildcards` rejects with a `BadWildcard` error unless they start with
+//! `MIN_WILDCARD_PREFIX` characters.
+
+use std::fmt;
+
+use tantivy::query::Occur;
+use tantivy::query_grammar::{UserInputAst, UserInputLeaf};
+
+/// A field that can be searched with `name:value`.
+pub struct QueryField {
+    /// Schema field name.
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub description: &'static str,
+    pub example: &'static str,
+}
+
+/// Searchable fields, in the order `syntax` lists them.
+pub const FIELDS: &[QueryField] = &[
+    QueryField {
+        name: "content",
+        aliases: &["text", "body"],
+        description: "File text",
+        example: "content:error",
+    },
+    QueryField {
+        name: "file_name",
+        aliases: &["name", "filename", "file"],
+        description: "File name, split into words",
+        example: "name:readme",
+    },
+    QueryField {
+        name: "extension",
+        aliases: &["ext", "type", "filetype"],
+        description: "File extension, or an extensionless script's interpreter extension",
+        example: "ext:rs",
+    },
+    QueryField {
+        name: "directory",
+        aliases: &["path", "dir", "folder"],
+        description: "Any directory name on the file's path",
+        example: "path:src",
+    },
+    QueryField {
+        name: "file_path",
+        aliases: &[],
+        description: "Exact absolute path",
+        example: "file_path:\"/home/me/notes/todo.md\"",
+    },
+    QueryField {
+        name: "title",
+        aliases: &[],
+        description: "Markdown front matter title",
+        example: "title:garden",
+    },
+    QueryField {
+        name: "tag",
+        aliases: &["tags"],
+        description: "Markdown front matter or inline #tag",
+        example: "tag:meeting",
+    },
+    QueryField {
+        name: "alias",
+        aliases: &["aliases"],
+        description: "Markdown front matter alias",
+        example: "alias:roadmap",
+    },
+    QueryField {
+        name: "key",
+        aliases: &["keys"],
+        description: "Dotted key path in JSON, YAML, TOML or a SQLite schema",
+        example: "key:server.port",
+    },
+    QueryField {
+        name: "columns",
+        aliases: &["column", "col"],
+        description: "CSV/TSV or spreadsheet cell, by column header",
+        example: "columns.status:failed",
+    },
+    QueryField {
+        name: "subject",
+        aliases: &[],
+        description: "Email subject",
+        example: "subject:invoice",
+    },
+    QueryField {
+        name: "from",
+        aliases: &["sender"],
+        description: "Email sender",
+        example: "from:alice",
+    },
+    QueryField {
+        name: "to",
+        aliases: &["recipient"],
+        description: "Email recipients",
+        example: "to:bob",
+    },
+    QueryField {
+        name: "date",
+        aliases: &[],
+        description: "Email date header, exactly as sent",
+        example: "date:\"Mon, 2 Jan 2023 10:00:00 +0000\"",
+    },
+    QueryField {
+        name: "author",
+        aliases: &[],
+        description: "Git author of the file's last commit",
+        example: "author:alice",
+    },
+    QueryField {
+        name: "branch",
+        aliases: &[],
+        description: "Git branch checked out when the file was indexed",
+        example: "branch:main",
+    },
+    QueryField {
+        name: "commit",
+        aliases: &["sha"],
+        description: "Full hash of the file's last git commit",
+        example: "commit:3f2a...",
+    },
+    QueryField {
+        name: "mime",
+        aliases: &["mimetype", "content_type"],
+        description: "MIME type, or a type/* wildcard",
+        example: "mime:\"text/*\"",
+    },
+    QueryField {
+        name: "language",
+        aliases: &["lang"],
+        description: "Programming language, detected from extension, file name and content",
+        example: "language:python",
+    },
+];
+
+/// A query used a field that is neither a field name nor an alias.
+#[derive(Debug)]
+pub struct UnknownField(pub String);
+
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = FIELDS.iter().map(|field| field.name).collect();
+        write!(
+            f,
+            "Unknown field '{}'. Valid fields: {} (see query_syntax for aliases)",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownField {}
+
+/// Schema name of the field called `name` (case-insensitive), with the path after its
+/// first `.` kept for `columns`: `col.Status` is `columns.Status`.
+pub fn resolve(name: &str) -> Result<String, UnknownField> {
+    let (head, path) = match name.split_once('.') {
+        Some((head, path)) => (head, Some(path)),
+        None => (name, None),
+    };
+    let field = FIELDS
+        .iter()
+        .find(|field| {
+            field.name.eq_ignore_ascii_case(head)
+                || field.aliases.iter().any(|a| a.eq_ignore_ascii_case(head))
+        })
+        .filter(|field| path.is_none() || field.name == "columns")
+        .ok_or_else(|| UnknownField(name.to_string()))?;
+    Ok(match path {
+        Some(path) => format!("{}.{}", field.name, path),
+        None => field.name.to_string(),
+    })
+}
+
+/// `query` with every field alias replaced by its field name. Quoted phrases and escaped
+/// colons (`a\:b`) are left alone.
+pub fn rewrite(query: &str) -> Result<String, UnknownField> {
+    let mut out = String::with_capacity(query.len());
+    let mut rest = query;
+    while let Some(c) = rest.chars().next() {
+        if c == '"' {
+            let end = rest[1..].find('"').map_or(rest.len(), |i| i + 2);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let word_start = out
+            .chars()
+            .last()
+            .is_none_or(|p| p.is_whitespace() || matches!(p, '(' | '+' | '-'));
+        if word_start && (c.is_ascii_alphabetic() || c == '_') {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '-')))
+                .unwrap_or(rest.len());
+            let (word, after) = rest.split_at(len);
+            out.push_str(word);
+            if let Some(value) = after.strip_prefix(':') {
+                out.truncate(out.len() - word.len());
+                out.push_str(&resolve(word)?);
+                out.push(':');
+                rest = value;
+            } else {
+                rest = after;
+            }
+            continue;
+        }
+        out.push(c);
+        if c == '\\' {
+            rest = &rest[1..];
+            if let Some(escaped) = rest.chars().next() {
+                out.push(escaped);
+                rest = &rest[escaped.len_utf8()..];
+            }
+        } else {
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    Ok(out)
+}
+
+/// `ast` with its `field:value` terms required when they stand between plain words without
+/// operators, and one of those words required as well: `from:alice vacation` finds Alice's
+/// messages about vacation, not every message from Alice or about vacation. Queries with
+/// operators keep their meaning.
+pub fn require_fields(ast: UserInputAst) -> UserInputAst {
+    let UserInputAst::Clause(clauses) = ast else {
+        return ast;
+    };
+    let is_field = |ast: &UserInputAst| match ast {
+        UserInputAst::Leaf(leaf) => match leaf.as_ref() {
+            UserInputLeaf::Literal(literal) => literal.field_name.is_some(),
+            UserInputLeaf::Range { field, .. } | UserInputLeaf::Set { field, .. } => {
+                field.is_some()
+            }
+            UserInputLeaf::Exists { .. } => true,
+            UserInputLeaf::All => false,
+        },
+        _ => false,
+    };
+    let fields = clauses.iter().filter(|(_, ast)| is_field(ast)).count();
+    let operators = clauses.iter().any(|(occur, _)| occur.is_some());
+    if operators || fields == 0 || fields == clauses.len() {
+        return UserInputAst::Clause(clauses);
+    }
+    let (fields, words): (Vec<_>, Vec<_>) = clauses.into_iter().partition(|(_, ast)| is_field(ast));
+    let mut required: Vec<_> = fields
+        .into_iter()
+        .map(|(_, ast)| (Some(Occur::Must), ast))
+        .collect();
+    required.push((Some(Occur::Must), UserInputAst::Clause(words)));
+    UserInputAst::Clause(required)
+}
+
+/// How the words of a query match the words of files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Whole words only.
+    #[default]
+    Word,
+    /// Words starting with the word typed, so `get_us` finds `get_user`.
+    Prefix,
+    /// Words containing the word typed, so `user` finds `getuser`.
+    Substring,
+}
+
+impl MatchMode {
+    /// Parse a `match` parameter: `word`, `prefix` or `substring`.
+    pub fn parse(name: &str) -> anyhow::Result<MatchMode> {
+        match name.trim().to_lowercase().as_str() {
+            "word" => Ok(MatchMode::Word),
+            "prefix" => Ok(MatchMode::Prefix),
+            "substring" => Ok(MatchMode::Substring),
+            other => anyhow::bail!("Unknown match '{}' (use word, prefix or substring)", other),
+        }
+    }
+}
+
+/// Words and phrases of `query` (already `rewrite`n) that match file text, lowercased,
+/// for placing and highlighting snippets. Operators, excluded terms and other fields'
+/// values are left out; `columns.x:v` becomes `x: v`, the way table rows are indexed.
+pub fn text_terms(query: &str) -> Vec<String> {
+    terms(query, true)
+}
+
+/// `text_terms` as typed, for case-sensitive searches.
+pub fn cased_text_terms(query: &str) -> Vec<String> {
+    terms(query, false)
+}
+
+fn terms(query: &str, lowercase: bool) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut negated = false;
+    for token in &tokens(query) {
+        match token.as_str() {
+            "NOT" => {
+                negated = true;
+                continue;
+            }
+            "AND" | "OR" | "&&" | "||" => continue,
+            _ => {}
+        }
+        let excluded = std::mem::take(&mut negated) || token.starts_with('-');
+        let token = token.trim_start_matches(['+', '-', '(']).trim_end_matches(')');
+        if excluded {
+            continue;
+        }
+        let (field, value) = split_field(token);
+        let value = value.trim_matches('"');
+        let term = match field {
+            None | Some("content") => value.to_string(),
+            Some(field) => match field.strip_prefix("columns.") {
+                Some(column) => format!("{}: {}", column, value),
+                None => continue,
+            },
+        };
+        if !term.is_empty() {
+            terms.push(if lowercase { term.to_lowercase() } else { term });
+        }
+    }
+    terms
+}
+
+/// `query` split at whitespace outside quotes.
+fn tokens(query: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                token.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// The field and value of a `field:value` token; no field for a phrase with a colon.
+fn split_field(token: &str) -> (Option<&str>, &str) {
+    match token.split_once(':') {
+        Some((field, value)) if !field.starts_with('"') => (Some(field), value),
+        _ => (None, token),
+    }
+}
+
+/// Characters a wildcard pattern needs before its first `*` or `?`, so that matching it
+/// does not walk the whole term dictionary.
+pub const MIN_WILDCARD_PREFIX: usize = 2;
+
+/// A wildcard pattern starts with too few characters.
+#[derive(Debug)]
+pub struct BadWildcard(pub String);
+
+impl fmt::Display for BadWildcard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Wildcard pattern '{}' needs at least {} characters before its first * or ?",
+            self.0, MIN_WILDCARD_PREFIX
+        )
+    }
+}
+
+impl std::error::Error for BadWildcard {}
+
+/// Reject wildcard words of `query` that start with fewer than `MIN_WILDCARD_PREFIX`
+/// characters; a lone `*` (every file) or `field:*` (files with the field) is fine.
+pub fn check_wildcards(query: &str) -> Result<(), BadWildcard> {
+    for token in tokens(query) {
+        let word = token.trim_start_matches(['+', '-', '(']).trim_end_matches(')');
+        let (_, value) = split_field(word);
+        if value.starts_with('"') || value == "*" {
+            continue;
+        }
+        if let Some(pos) = value.find(['*', '?']) {
+            if value[..pos].chars().count() < MIN_WILDCARD_PREFIX {
+                return Err(BadWildcard(word.to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Regex over whole indexed values equivalent to the wildcard `pattern`: `*` is any run of
+/// `any` (a regex for one character) and `?` one; everything else matches itself.
+pub fn wildcard_regex(pattern: &str, any: &str) -> String {
+    let mut regex = String::with_capacity(pattern.len() * 2);
+    for c in pattern.chars() {
+        match c {
+            '*' => {
+                regex.push_str(any);
+                regex.push('*');
+            }
+            '?' => regex.push_str(any),
+            c if "\\.+()|[]{}^$#&-~".contains(c) => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    regex
+}
+
+/// Help text for the query language.
+pub fn syntax() -> String {
+    let mut out = String::from(
+        "Query syntax:\n  \
+         words              Files containing the words (text, name, title, alias, subject)\n  \
+         \"exact phrase\"     The words next to each other, in order\n  \
+         a AND b, a OR b    Both / either; words without an operator are combined with OR\n  \
+         +a, -a, NOT a      a is required / excluded\n  \
+         (a OR b) AND c     Grouping\n  \
+         field:value        Only matches in that field; field:\"a phrase\" for several words\n  \
+         field:value words  Files matching field:value and any of the words\n  \
+         conf*, te?t        Wildcards: * any characters, ? one; at least 2 characters first\n  \
+         test_?.py          A wildcard pattern with punctuation matches file names\n\n\
+         Fields (aliases in parentheses):\n",
+    );
+    for field in FIELDS {
+        let name = match field.name {
+            "columns" => "columns.<header>".to_string(),
+            name => name.to_string(),
+        };
+        let aliases = match field.aliases {
+            [] => String::new(),
+            aliases => format!(" ({})", aliases.join(", ")),
+        };
+        out.push_str(&format!(
+            "  {}{} — {}, e.g. {}\n",
+            name, aliases, field.description, field.example
+        ));
+    }
+    out.push_str(
+        "\nThe search tool's file_type, path_prefix, since/until, owner, mime and tracked_only \
+         parameters filter results as well.\n",
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_aliases() {
+        let rewritten = rewrite("ext:rs AND (Path:src OR -type:py) error").unwrap();
+        assert_eq!(rewritten, "extension:rs AND (directory:src OR -extension:py) error");
+        assert_eq!(rewrite("col.status:failed").unwrap(), "columns.status:failed");
+        assert_eq!(rewrite("key:server.port").unwrap(), "key:server.port");
+        // Quoted phrases and escaped colons are not fields
+        assert_eq!(rewrite("\"ext:rs\" a\\:b").unwrap(), "\"ext:rs\" a\\:b");
+        assert_eq!(rewrite("tag:\"project/alpha\"").unwrap(), "tag:\"project/alpha\"");
+
+        let err = rewrite("extention:rs").unwrap_err().to_string();
+        assert!(err.contains("'extention'"));
+        assert!(err.contains("extension, directory"));
+        assert!(rewrite("name.x:y").is_err());
+        assert!(syntax().contains("directory (path, dir, folder)"));
+
+        let query = "(connection OR \"time out\") AND NOT retry -debug +content:Fatal \
+                     columns.status:failed tag:ops";
+        assert_eq!(
+            text_terms(query),
+            vec!["connection", "time out", "fatal", "status: failed"]
+        );
+        assert_eq!(cased_text_terms(query)[2], "Fatal");
+
+        assert!(check_wildcards("conf* name:test_?.py * ext:* \"*x\"").is_ok());
+        let err = check_wildcards("deploy +c*").unwrap_err().to_string();
+        assert!(err.contains("'c*'"));
+        assert!(check_wildcards("path:?onf").is_err());
+        assert_eq!(wildcard_regex("conf*", "."), "conf.*");
+        assert_eq!(wildcard_regex("test_?.py", "[^/]"), r"test_[^/]\.py");
+
+        let parse = |query: &str| tantivy::query_grammar::parse_query(query).ok().unwrap();
+        let required = |query: &str| format!("{:?}", require_fields(parse(query)));
+        assert_eq!(required("from:alice vacation"), r#"(+"from":alice +(*vacation))"#);
+        for query in ["alice vacation", "from:alice ext:eml", "from:alice OR vacation"] {
+            assert_eq!(required(query), format!("{:?}", parse(query)));
+        }
+
+        assert_eq!(MatchMode::parse(" Prefix").unwrap(), MatchMode::Prefix);
+        assert!(MatchMode::parse("fuzzy").is_err());
+    }
+}
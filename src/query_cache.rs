@@ -0,0 +1,116 @@
+//! Outputs of recent searches, reused while the index is unchanged.
+//!
+//! Agents often repeat a search word for word. The cache keeps the outputs of the last
+//! `capacity` distinct searches and empties itself as soon as a search runs against a
+//! newer commit generation, so a cached output is always that of the current snapshot.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::indexer::SearchOutput;
+
+#[derive(Default)]
+pub struct QueryCache {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Generation of the snapshot every cached output came from.
+    generation: u64,
+    /// Output and last use of each search.
+    entries: HashMap<String, (SearchOutput, u64)>,
+    uses: u64,
+}
+
+impl QueryCache {
+    /// Cache of up to `capacity` outputs.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// Output cached for `key` at `generation`.
+    pub fn get(&self, key: &str, generation: u64) -> Option<SearchOutput> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if inner.generation != generation {
+            return None;
+        }
+        inner.uses += 1;
+        let uses = inner.uses;
+        let (output, last_use) = inner.entries.get_mut(key)?;
+        *last_use = uses;
+        Some(output.clone())
+    }
+
+    /// Drop every cached output.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).entries.clear();
+    }
+
+    /// Cache `output` for `key`, dropping everything from older generations and, when
+    /// full, the least recently used output.
+    pub fn insert(&self, key: String, output: &SearchOutput) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if output.generation < inner.generation {
+            return;
+        }
+        if output.generation > inner.generation {
+            inner.generation = output.generation;
+            inner.entries.clear();
+        }
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(&key) {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_use))| *last_use)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+            }
+        }
+        inner.uses += 1;
+        let uses = inner.uses;
+        inner.entries.insert(key, (output.clone(), uses));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(generation: u64, total_count: usize) -> SearchOutput {
+        SearchOutput {
+            results: Vec::new(),
+            total_count,
+            generation,
+            degraded: false,
+        }
+    }
+
+    #[test]
+    fn test_lru_invalidated_by_new_generation() {
+        let cache = QueryCache::new(2);
+        cache.insert("a".to_string(), &output(1, 1));
+        cache.insert("b".to_string(), &output(1, 2));
+        assert_eq!(cache.get("a", 1).map(|o| o.total_count), Some(1));
+        // "b" is the least recently used
+        cache.insert("c".to_string(), &output(1, 3));
+        assert!(cache.get("b", 1).is_none());
+        assert!(cache.get("a", 1).is_some() && cache.get("c", 1).is_some());
+
+        assert!(cache.get("a", 2).is_none());
+        cache.insert("d".to_string(), &output(2, 4));
+        assert!(cache.get("a", 2).is_none());
+        assert_eq!(cache.get("d", 2).map(|o| o.total_count), Some(4));
+        // A search that started before the commit does not repopulate the cache
+        cache.insert("a".to_string(), &output(1, 1));
+        assert!(cache.get("a", 1).is_none());
+    }
+}
@@ -0,0 +1,74 @@
+//! Log of the searches run in a session.
+//!
+//! Lets agents see what they already searched for and humans audit what an agent
+//! looked at. Only the most recent `CAPACITY` entries are kept.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const CAPACITY: usize = 200;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggedQuery {
+    /// Tool that ran the query, e.g. `search` or `history_search`.
+    pub tool: String,
+    pub query: String,
+    /// Filters as `name=value` pairs, in the order given.
+    pub filters: Vec<(String, String)>,
+    pub hits: usize,
+    pub at: SystemTime,
+}
+
+#[derive(Debug, Default)]
+pub struct QueryLog {
+    entries: Mutex<VecDeque<LoggedQuery>>,
+}
+
+impl QueryLog {
+    pub fn record(&self, entry: LoggedQuery) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() == CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Up to `limit` entries, newest first.
+    pub fn recent(&self, limit: usize) -> Vec<LoggedQuery> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().rev().take(limit).cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(query: &str) -> LoggedQuery {
+        LoggedQuery {
+            tool: "search".to_string(),
+            query: query.to_string(),
+            filters: vec![("file_type".to_string(), "rs".to_string())],
+            hits: 1,
+            at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn test_recent_newest_first_and_bounded() {
+        let log = QueryLog::default();
+        for i in 0..CAPACITY + 5 {
+            log.record(entry(&format!("q{}", i)));
+        }
+        let recent = log.recent(3);
+        assert_eq!(recent.len(), 3);
+        assert_eq!(recent[0].query, format!("q{}", CAPACITY + 4));
+        assert_eq!(log.recent(usize::MAX).len(), CAPACITY);
+        assert_eq!(log.recent(usize::MAX).last().unwrap().query, "q5");
+    }
+}
@@ -0,0 +1,114 @@
+//! Ranking rules: per-path factors applied to search scores.
+//!
+//! Set with `LOCALFILES_RANKING` as comma-separated `glob=factor` pairs, e.g.
+//! `docs/**=2.0, vendor/**=0.3`. A factor above 1 boosts matches in the files a glob
+//! selects, below 1 demotes them. The first rule matching a file applies. Globs match
+//! absolute paths; those not starting with `/` match below any directory, so `docs/**`
+//! selects every `docs` directory of every root.
+
+/// Comma-separated `glob=factor` ranking rules.
+pub const RANKING_ENV: &str = "LOCALFILES_RANKING";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankingRule {
+    /// The glob as configured.
+    pub glob: String,
+    /// Factor the scores of matching files are multiplied by.
+    pub factor: f32,
+    /// Regex over the full indexed path equivalent to `glob`.
+    regex: String,
+}
+
+impl RankingRule {
+    /// Parse one `glob=factor` rule.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let Some((glob, factor)) = spec.rsplit_once('=') else {
+            anyhow::bail!("Expected glob=factor, got '{}'", spec);
+        };
+        let glob = glob.trim();
+        if glob.is_empty() {
+            anyhow::bail!("Empty glob in '{}'", spec);
+        }
+        let factor: f32 = factor
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid factor in '{}'", spec))?;
+        if !factor.is_finite() || factor <= 0.0 {
+            anyhow::bail!("Factor must be a positive number in '{}'", spec);
+        }
+        Ok(Self {
+            glob: glob.to_string(),
+            factor,
+            regex: glob_regex(glob),
+        })
+    }
+
+    /// Regex matching the indexed paths the glob selects, for a `RegexQuery`.
+    pub fn regex(&self) -> &str {
+        &self.regex
+    }
+}
+
+/// Parse a `LOCALFILES_RANKING` value; empty entries are ignored.
+pub fn parse_rules(spec: &str) -> anyhow::Result<Vec<RankingRule>> {
+    spec.split(',')
+        .filter(|rule| !rule.trim().is_empty())
+        .map(RankingRule::parse)
+        .collect()
+}
+
+/// Translate `glob` to an (implicitly anchored) regex over absolute paths: `**` matches
+/// across directories, `*` and `?` within one, and a trailing `/` selects everything
+/// below a directory.
+fn glob_regex(glob: &str) -> String {
+    let (mut regex, rest) = match glob.strip_prefix('/') {
+        Some(rest) => (String::from("/"), rest),
+        None => (String::from("(.*/)?"), glob),
+    };
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c if "\\.+()|[]{}^$#&-~".contains(c) => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    if regex.ends_with('/') {
+        regex.push_str(".*");
+    }
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules_and_globs() {
+        let rules = parse_rules("docs/**=2.0, vendor/ = 0.3,,").unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!((rules[0].glob.as_str(), rules[0].factor), ("docs/**", 2.0));
+        assert_eq!(rules[0].regex(), "(.*/)?docs/.*");
+        assert_eq!(rules[1].regex(), "(.*/)?vendor/.*");
+        assert!(parse_rules("docs/**").is_err());
+        assert!(parse_rules("docs/**=0").is_err());
+        assert!(parse_rules("=2").is_err());
+
+        let regex = |glob: &str| RankingRule::parse(&format!("{}=1", glob)).unwrap().regex;
+        assert_eq!(regex("*.lock"), r"(.*/)?[^/]*\.lock");
+        assert_eq!(regex("/home/me/notes/**/draft-?.md"), r"/home/me/notes/(.*/)?draft\-[^/]\.md");
+    }
+}
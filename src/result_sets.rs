@@ -0,0 +1,84 @@
+//! Files returned by earlier searches in a session, for narrowing later searches.
+//!
+//! Each search with results records its files under a short token (`r1`, `r2`, ...), and
+//! a search passing `within: "r1"` only matches those files, so an agent can drill down
+//! without repeating the first query's filters. Only the most recent `CAPACITY` sets
+//! are kept.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+const CAPACITY: usize = 50;
+
+#[derive(Debug, Default)]
+pub struct ResultSets {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    recorded: u64,
+    sets: VecDeque<(String, Vec<String>)>,
+}
+
+impl ResultSets {
+    /// Record `files` (duplicates dropped, order kept) and return their token.
+    pub fn record<I>(&self, files: I) -> String
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut seen = HashSet::new();
+        let files: Vec<String> = files.into_iter().filter(|f| seen.insert(f.clone())).collect();
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.recorded += 1;
+        let token = format!("r{}", inner.recorded);
+        if inner.sets.len() == CAPACITY {
+            inner.sets.pop_front();
+        }
+        inner.sets.push_back((token.clone(), files));
+        token
+    }
+
+    /// Files recorded under `token`, unless it is unknown or was dropped.
+    pub fn get(&self, token: &str) -> Option<Vec<String>> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner
+            .sets
+            .iter()
+            .find(|(t, _)| t == token.trim())
+            .map(|(_, files)| files.clone())
+    }
+
+    /// Drop every set. Tokens are not reused, so earlier ones stay unknown.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).sets.clear();
+    }
+
+    /// Whether `file` is in one of the kept sets, i.e. came up in a recent search.
+    pub fn contains(&self, file: &str) -> bool {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.sets.iter().any(|(_, files)| files.iter().any(|f| f == file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_get_bounded() {
+        let sets = ResultSets::default();
+        let files = ["a.rs", "b.log", "a.rs"].map(str::to_string);
+        let first = sets.record(files);
+        assert_eq!(first, "r1");
+        assert_eq!(sets.get("r1").unwrap(), vec!["a.rs", "b.log"]);
+        assert!(sets.contains("b.log") && !sets.contains("c.md"));
+        for _ in 0..CAPACITY {
+            sets.record(vec!["c.md".to_string()]);
+        }
+        assert!(sets.get("r1").is_none());
+        assert!(!sets.contains("b.log"));
+        assert_eq!(sets.get("r2").unwrap(), vec!["c.md"]);
+        assert!(sets.get("nope").is_none());
+    }
+}
@@ -0,0 +1,124 @@
+//! Files that failed to index, retried with backoff.
+//!
+//! A file can fail to index only for a moment: another process holds a lock on it, or is
+//! half-way through writing it. Failed files are retried after 1s, 2s, 4s and so on; a
+//! file still failing after `MAX_ATTEMPTS` attempts is reported as failed until it
+//! indexes on a later change or is removed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Attempts, the first included, before a file is reported as failed.
+pub const MAX_ATTEMPTS: u32 = 5;
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+
+/// A file that failed every attempt to index it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedFile {
+    pub path: PathBuf,
+    /// Error of the last attempt.
+    pub reason: String,
+    pub attempts: u32,
+    pub last_attempt: SystemTime,
+}
+
+#[derive(Debug, Default)]
+pub struct RetryQueue {
+    entries: HashMap<PathBuf, Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    reason: String,
+    attempts: u32,
+    last_attempt: SystemTime,
+    /// When to try again; `None` once given up, or while a retry is in flight.
+    next: Option<Instant>,
+}
+
+impl RetryQueue {
+    /// Record a failed attempt at indexing `path` at `now`, scheduling another unless it
+    /// has failed `MAX_ATTEMPTS` times.
+    pub fn failed(&mut self, path: &Path, reason: String, now: Instant) {
+        let entry = self.entries.entry(path.to_path_buf()).or_insert(Entry {
+            reason: String::new(),
+            attempts: 0,
+            last_attempt: SystemTime::now(),
+            next: None,
+        });
+        entry.attempts += 1;
+        entry.reason = reason;
+        entry.last_attempt = SystemTime::now();
+        entry.next = (entry.attempts < MAX_ATTEMPTS)
+            .then(|| now + FIRST_DELAY * 2u32.pow(entry.attempts - 1));
+    }
+
+    /// Forget `path`, which indexed or no longer exists.
+    pub fn forget(&mut self, path: &Path) {
+        self.entries.remove(path);
+    }
+
+    /// Paths due for another attempt at `now`, no longer scheduled until they fail again.
+    pub fn take_due(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut due = Vec::new();
+        for (path, entry) in &mut self.entries {
+            if entry.next.is_some_and(|next| next <= now) {
+                entry.next = None;
+                due.push(path.clone());
+            }
+        }
+        due
+    }
+
+    /// When the next retry is due, if any is scheduled.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.entries.values().filter_map(|entry| entry.next).min()
+    }
+
+    /// Files that failed every attempt, by path.
+    pub fn failed_files(&self) -> Vec<FailedFile> {
+        let mut failed: Vec<FailedFile> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.attempts >= MAX_ATTEMPTS)
+            .map(|(path, entry)| FailedFile {
+                path: path.clone(),
+                reason: entry.reason.clone(),
+                attempts: entry.attempts,
+                last_attempt: entry.last_attempt,
+            })
+            .collect();
+        failed.sort_by(|a, b| a.path.cmp(&b.path));
+        failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_then_failed() {
+        let mut queue = RetryQueue::default();
+        let path = Path::new("/notes/locked.md");
+        let start = Instant::now();
+        queue.failed(path, "locked".to_string(), start);
+        assert_eq!(queue.next_due(), Some(start + Duration::from_secs(1)));
+        assert!(queue.take_due(start).is_empty());
+        assert_eq!(queue.take_due(start + Duration::from_secs(1)), vec![path.to_path_buf()]);
+        assert_eq!(queue.next_due(), None);
+
+        queue.failed(path, "locked".to_string(), start);
+        assert_eq!(queue.next_due(), Some(start + Duration::from_secs(2)));
+        for _ in 2..MAX_ATTEMPTS {
+            queue.failed(path, "still locked".to_string(), start);
+        }
+        assert_eq!(queue.next_due(), None);
+        let failed = queue.failed_files();
+        assert_eq!((failed[0].reason.as_str(), failed[0].attempts), ("still locked", 5));
+
+        queue.forget(path);
+        assert!(queue.failed_files().is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+//! Saved searches that report files newly matching them.
+//!
+//! Each saved search belongs to the client session that saved it and remembers the
+//! files it matched when it was last run. After every watcher batch the searches are
+//! re-run, and files that match now but did not before are queued as new matches until
+//! that session collects them with `take_new_matches`.
+//!
+//! Re-running is split so the searches need no lock on the state: `runs` snapshots the
+//! searches, `SavedRun::matches` runs one against a `SearchHandle`, and `record` queues
+//! what it found, ignoring searches removed or replaced in the meantime.
+
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+use crate::indexer::{SearchFilters, SearchHandle};
+
+/// Query and filters of a saved search.
+#[derive(Debug, Clone)]
+pub struct SavedQuery {
+    pub query: String,
+    pub file_type: Option<String>,
+    pub path_prefix: Option<String>,
+}
+
+impl SavedQuery {
+    /// Paths of the files matching now, and the index generation they were found at.
+    pub fn matches(&self, handle: &SearchHandle) -> anyhow::Result<(u64, HashSet<String>)> {
+        let filters = SearchFilters {
+            file_type: self.file_type.clone(),
+            path_prefix: self.path_prefix.clone(),
+            ..SearchFilters::default()
+        };
+        handle.matching_paths(&self.query, &filters)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SavedSearch {
+    pub name: String,
+    pub search: SavedQuery,
+    /// Session that saved it, the only one that lists and collects it.
+    session: u64,
+    /// Tells this search apart from one saved later under the same name.
+    id: u64,
+    /// Files matching as of the last run.
+    matched: HashSet<String>,
+    /// Index generation of the last run.
+    generation: u64,
+    /// Files that started matching since new matches were last taken.
+    new_matches: Vec<NewMatch>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewMatch {
+    pub file_path: String,
+    pub found_at: SystemTime,
+}
+
+impl SavedSearch {
+    /// Number of files matching as of the last run.
+    pub fn match_count(&self) -> usize {
+        self.matched.len()
+    }
+
+    /// New matches waiting to be collected.
+    pub fn pending(&self) -> &[NewMatch] {
+        &self.new_matches
+    }
+}
+
+/// A saved search to re-run, from `SavedSearches::runs`.
+#[derive(Debug, Clone)]
+pub struct SavedRun {
+    pub name: String,
+    pub search: SavedQuery,
+    id: u64,
+}
+
+/// Matches a `SavedRun` found, for `SavedSearches::record`.
+#[derive(Debug)]
+pub struct RunMatches {
+    id: u64,
+    generation: u64,
+    matched: HashSet<String>,
+}
+
+impl SavedRun {
+    /// Run the search, logging a failure.
+    pub fn matches(&self, handle: &SearchHandle) -> Option<RunMatches> {
+        match self.search.matches(handle) {
+            Ok((generation, matched)) => Some(RunMatches {
+                id: self.id,
+                generation,
+                matched,
+            }),
+            Err(e) => {
+                tracing::warn!("Saved search '{}' failed: {}", self.name, e);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SavedSearches {
+    searches: Vec<SavedSearch>,
+    next_id: u64,
+}
+
+impl SavedSearches {
+    /// Save (or replace) `session`'s search named `name`, which `matches` currently
+    /// matches (from `SavedQuery::matches`). Those files are not reported as new.
+    pub fn save(
+        &mut self,
+        session: u64,
+        name: &str,
+        search: SavedQuery,
+        matches: (u64, HashSet<String>),
+    ) -> &SavedSearch {
+        let (generation, matched) = matches;
+        self.next_id += 1;
+        self.searches.retain(|s| s.session != session || s.name != name);
+        self.searches.push(SavedSearch {
+            name: name.to_string(),
+            search,
+            session,
+            id: self.next_id,
+            matched,
+            generation,
+            new_matches: Vec::new(),
+        });
+        self.searches.last().expect("just pushed")
+    }
+
+    /// Remove `session`'s search named `name`; returns whether it existed.
+    pub fn remove(&mut self, session: u64, name: &str) -> bool {
+        let before = self.searches.len();
+        self.searches.retain(|s| s.session != session || s.name != name);
+        self.searches.len() != before
+    }
+
+    /// Drop the searches of sessions `live` rejects, e.g. of clients that disconnected.
+    pub fn retain_sessions(&mut self, live: impl Fn(u64) -> bool) {
+        self.searches.retain(|s| live(s.session));
+    }
+
+    /// `session`'s saved searches.
+    pub fn list(&self, session: u64) -> impl Iterator<Item = &SavedSearch> {
+        self.searches.iter().filter(move |s| s.session == session)
+    }
+
+    /// Every saved search, to re-run without holding the state.
+    pub fn runs(&self) -> Vec<SavedRun> {
+        self.searches
+            .iter()
+            .map(|s| SavedRun {
+                name: s.name.clone(),
+                search: s.search.clone(),
+                id: s.id,
+            })
+            .collect()
+    }
+
+    /// Queue the files that started matching a search since its last run. Matches of a
+    /// search since removed or replaced, or older than its last run, are ignored.
+    /// Returns the number of new matches.
+    pub fn record(&mut self, run: RunMatches) -> usize {
+        let Some(search) = self.searches.iter_mut().find(|s| s.id == run.id) else {
+            return 0;
+        };
+        if run.generation < search.generation {
+            return 0;
+        }
+        let mut new: Vec<&String> = run.matched.difference(&search.matched).collect();
+        new.sort();
+        if !new.is_empty() {
+            tracing::info!(
+                "Saved search '{}' has {} new matches: {}",
+                search.name,
+                new.len(),
+                new.iter().map(|p| p.as_str()).collect::<Vec<_>>().join(", ")
+            );
+        }
+        let now = SystemTime::now();
+        let found = new.len();
+        search.new_matches.extend(new.into_iter().map(|p| NewMatch {
+            file_path: p.clone(),
+            found_at: now,
+        }));
+        // Files that stop matching are reported again if they match later
+        search.matched = run.matched;
+        search.generation = run.generation;
+        found
+    }
+
+    /// Collect and clear the new matches of `session`'s search named `name`, or of all
+    /// its searches.
+    pub fn take_new_matches(
+        &mut self,
+        session: u64,
+        name: Option<&str>,
+    ) -> Vec<(String, Vec<NewMatch>)> {
+        self.searches
+            .iter_mut()
+            .filter(|s| s.session == session && name.is_none_or(|n| s.name == n))
+            .map(|s| (s.name.clone(), std::mem::take(&mut s.new_matches)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::FileIndex;
+    use tempfile::TempDir;
+
+    /// Re-run every saved search the way the watcher does.
+    fn check(saved: &mut SavedSearches, handle: &SearchHandle) -> usize {
+        let runs = saved.runs();
+        let found: Vec<RunMatches> = runs.iter().filter_map(|r| r.matches(handle)).collect();
+        found.into_iter().map(|run| saved.record(run)).sum()
+    }
+
+    fn oom() -> SavedQuery {
+        SavedQuery {
+            query: "OutOfMemory".to_string(),
+            file_type: Some("log".to_string()),
+            path_prefix: None,
+        }
+    }
+
+    #[test]
+    fn test_check_reports_only_new_matches() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::new(Some(dir.path().join("index"))).unwrap();
+        let old_log = fixtures.path().join("old.log");
+        std::fs::write(&old_log, "OutOfMemory at startup").unwrap();
+        idx.index_file(&old_log).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+
+        let mut saved = SavedSearches::default();
+        let matches = oom().matches(&handle).unwrap();
+        let search = saved.save(1, "oom", oom(), matches);
+        assert_eq!(search.match_count(), 1);
+        assert_eq!(check(&mut saved, &handle), 0);
+
+        let new_log = fixtures.path().join("new.log");
+        std::fs::write(&new_log, "worker died: OutOfMemory").unwrap();
+        idx.index_file(&new_log).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(check(&mut saved, &handle), 1);
+        assert_eq!(check(&mut saved, &handle), 0);
+
+        let taken = saved.take_new_matches(1, Some("oom"));
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].1.len(), 1);
+        assert!(taken[0].1[0].file_path.ends_with("new.log"));
+        assert!(saved.take_new_matches(1, None)[0].1.is_empty());
+
+        assert!(saved.remove(1, "oom"));
+        assert_eq!(saved.list(1).count(), 0);
+    }
+
+    #[test]
+    fn test_record_skips_replaced_searches() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::new(Some(dir.path().join("index"))).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+
+        let mut saved = SavedSearches::default();
+        let matches = oom().matches(&handle).unwrap();
+        saved.save(1, "oom", oom(), matches);
+        // A run started before the search is replaced is dropped
+        let stale = saved.runs();
+        let matches = oom().matches(&handle).unwrap();
+        saved.save(1, "oom", oom(), matches);
+
+        let log = fixtures.path().join("crash.log");
+        std::fs::write(&log, "OutOfMemory").unwrap();
+        idx.index_file(&log).unwrap();
+        idx.commit().unwrap();
+        let found: Vec<RunMatches> = stale.iter().filter_map(|r| r.matches(&handle)).collect();
+        assert_eq!(found.into_iter().map(|run| saved.record(run)).sum::<usize>(), 0);
+
+        // So is one against an older snapshot than the last recorded
+        let current = saved.runs()[0].matches(&handle).unwrap();
+        let id = current.id;
+        assert_eq!(saved.record(current), 1);
+        let older = RunMatches {
+            id,
+            generation: 0,
+            matched: HashSet::new(),
+        };
+        assert_eq!(saved.record(older), 0);
+        assert_eq!(saved.list(1).next().unwrap().match_count(), 1);
+        assert_eq!(check(&mut saved, &handle), 0);
+    }
+
+    #[test]
+    fn test_searches_belong_to_their_session() {
+        let dir = TempDir::new().unwrap();
+        let fixtures = TempDir::new().unwrap();
+        let mut idx = FileIndex::new(Some(dir.path().join("index"))).unwrap();
+        idx.commit().unwrap();
+        let handle = idx.search_handle();
+
+        let mut saved = SavedSearches::default();
+        for session in [1, 2] {
+            let matches = oom().matches(&handle).unwrap();
+            saved.save(session, "oom", oom(), matches);
+        }
+        assert_eq!(saved.list(1).count(), 1);
+
+        let log = fixtures.path().join("crash.log");
+        std::fs::write(&log, "OutOfMemory").unwrap();
+        idx.index_file(&log).unwrap();
+        idx.commit().unwrap();
+        assert_eq!(check(&mut saved, &handle), 2);
+        assert_eq!(saved.take_new_matches(1, None)[0].1.len(), 1);
+        // Taking session 1's matches leaves session 2's queued
+        assert_eq!(saved.take_new_matches(2, Some("oom"))[0].1.len(), 1);
+        assert!(saved.take_new_matches(3, None).is_empty());
+        assert!(!saved.remove(3, "oom"));
+
+        saved.retain_sessions(|session| session != 2);
+        assert_eq!(saved.list(1).count(), 1);
+        assert_eq!(saved.list(2).count(), 0);
+    }
+}
@@ -0,0 +1,95 @@
+//! Which indexed files a client session may see.
+//!
+//! Sessions of a shared daemon normally all see the whole index. With isolation on, a
+//! session only sees the roots it indexed itself (with `index_paths`, or as client
+//! workspace roots) and the roots shared with every session, so concurrent clients do
+//! not see each other's files. The index itself stays shared: a root indexed by two
+//! sessions is walked and watched once.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+pub struct SessionScope {
+    isolated: bool,
+    /// Roots every session sees.
+    shared: Arc<Vec<PathBuf>>,
+    /// Roots this session indexed.
+    own: Mutex<Vec<PathBuf>>,
+}
+
+impl SessionScope {
+    /// Sessions see the whole index.
+    pub fn unrestricted() -> Self {
+        Self::default()
+    }
+
+    /// Sessions only see their own roots and `shared`.
+    pub fn isolated(shared: Vec<PathBuf>) -> Self {
+        Self {
+            isolated: true,
+            shared: Arc::new(shared),
+            own: Mutex::default(),
+        }
+    }
+
+    /// Scope of a new session: the same isolation and shared roots, none of its own yet.
+    pub fn new_session(&self) -> Self {
+        Self {
+            isolated: self.isolated,
+            shared: self.shared.clone(),
+            own: Mutex::default(),
+        }
+    }
+
+    pub fn is_isolated(&self) -> bool {
+        self.isolated
+    }
+
+    /// Let the session see `root` and everything below it.
+    pub fn add_root(&self, root: PathBuf) {
+        let mut own = self.own.lock().unwrap_or_else(|e| e.into_inner());
+        if !own.contains(&root) {
+            own.push(root);
+        }
+    }
+
+    /// Roots the session sees; `None` if it sees the whole index.
+    pub fn roots(&self) -> Option<Vec<PathBuf>> {
+        if !self.isolated {
+            return None;
+        }
+        let own = self.own.lock().unwrap_or_else(|e| e.into_inner());
+        Some(self.shared.iter().chain(own.iter()).cloned().collect())
+    }
+
+    /// Whether the session may see the indexed path `path` (an archive member's virtual
+    /// path counts as below the archive's directory).
+    pub fn allows(&self, path: impl AsRef<Path>) -> bool {
+        match self.roots() {
+            None => true,
+            Some(roots) => roots.iter().any(|root| path.as_ref().starts_with(root)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_isolated_sessions_see_own_and_shared_roots() {
+        let unrestricted = SessionScope::unrestricted();
+        assert!(unrestricted.roots().is_none() && unrestricted.allows("/anything"));
+
+        let server = SessionScope::isolated(vec![PathBuf::from("/srv/shared")]);
+        let (a, b) = (server.new_session(), server.new_session());
+        a.add_root(PathBuf::from("/home/a/proj"));
+        assert!(a.allows("/home/a/proj/src/main.rs"));
+        assert!(a.allows("/home/a/proj/snap.zip!/notes.md"));
+        assert!(a.allows("/srv/shared/doc.md"));
+        assert!(!a.allows("/home/a/project/x.rs"));
+        assert!(!b.allows("/home/a/proj/src/main.rs"));
+        assert_eq!(b.roots(), Some(vec![PathBuf::from("/srv/shared")]));
+    }
+}
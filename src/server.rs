@@ -1,20 +1,49 @@
 use std::future::Future;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, Weak};
 
-use notify::RecommendedWatcher;
 use rmcp::handler::server::router::tool::ToolRouter;
-use rmcp::handler::server::tool::Parameters;
-use rmcp::model::{ServerCapabilities, ServerInfo};
-use rmcp::{schemars, tool, tool_handler, tool_router, ServerHandler};
+use rmcp::handler::server::tool::{Parameters, ToolCallContext};
+use rmcp::model::{
+    ArgumentInfo, CallToolRequestParam, CallToolResult, CompleteRequestParam, CompleteResult,
+    CompletionInfo, Content, ListToolsResult, PaginatedRequestParam, ServerCapabilities,
+    ServerInfo,
+};
+use rmcp::service::{NotificationContext, Peer, RequestContext};
+use rmcp::{schemars, tool, tool_router, ErrorData as McpError, RoleServer, ServerHandler};
 use tokio::sync::RwLock;
+use tracing::Instrument;
 
-use localfiles::indexer::FileIndex;
-use localfiles::watcher;
+use localfiles::audit::{AuditEntry, AuditLog};
+use localfiles::budget;
+use localfiles::filter::Filter;
+use localfiles::format::{self, OutputFormat};
+use localfiles::health::{self, Check, HealthReport};
+use localfiles::indexer::{self, FileIndex, HiddenPolicy, SearchFilters, SearchHandle, SkipStats};
+use localfiles::limits::{Limits, Violation};
+use localfiles::logs::{self, TimeRange};
+use localfiles::manifest::RootEntry;
+use localfiles::outline;
+use localfiles::owner::Users;
+use localfiles::paths;
+use localfiles::query::{self, MatchMode};
+use localfiles::query_log::{LoggedQuery, QueryLog};
+use localfiles::result_sets::ResultSets;
+use localfiles::saved::{SavedQuery, SavedSearches};
+use localfiles::scope::SessionScope;
+use localfiles::telemetry;
+use localfiles::watcher::{FileWatcher, Overflow, QueueStats, WatchMode};
+use localfiles::write::{self, WriteMode};
 
 /// Shared state between MCP handler, background watcher task, and indexer.
 pub struct SharedState {
     pub index: FileIndex,
-    pub watcher: RecommendedWatcher,
+    pub watcher: FileWatcher,
+    pub overflow: Arc<Overflow>,
+    pub queue: Arc<QueueStats>,
+    pub saved: SavedSearches,
+    /// Signalled when changes are left for the background task to commit under the
+    /// commit policy, or files that failed to index to retry.
+    pub deferred_commit: Arc<tokio::sync::Notify>,
 }
 
 impl std::fmt::Debug for SharedState {
@@ -25,6 +54,441 @@ impl std::fmt::Debug for SharedState {
 
 pub type AppState = Arc<RwLock<SharedState>>;
 
+/// Longest `wait_for_index` wait, so a request cannot hold a session open indefinitely.
+const MAX_WAIT_MS: u64 = 60_000;
+
+/// Upper bound on any `limit` argument, so one call cannot return an unbounded response.
+const MAX_RESULTS: usize = 1_000;
+/// Upper bound on `snippets_per_file`, so a result cannot quote a whole file.
+const MAX_SNIPPETS_PER_FILE: usize = 20;
+/// Upper bound on `context_lines`.
+const MAX_CONTEXT_LINES: usize = 50;
+/// Most paths one `read_files` call takes.
+const MAX_READ_FILES: usize = 20;
+/// Total content one `read_files` call returns; files past it are listed as skipped.
+const MAX_READ_FILES_BYTES: usize = 256 * 1024;
+/// Most queries one `multi_search` call runs.
+const MAX_MULTI_QUERIES: usize = 10;
+/// Paths listed per kind of discrepancy by `verify_index`.
+const MAX_VERIFY_PATHS: usize = 50;
+/// Most values of an argument completion (the MCP limit).
+const MAX_COMPLETIONS: usize = 100;
+/// `list_files` page size when no `limit` is given.
+const DEFAULT_LIST_PAGE: usize = 200;
+/// Corrected queries offered when a search finds nothing.
+const MAX_DID_YOU_MEAN: usize = 3;
+
+/// `text` with each line prefixed by its number, counting from `start`, like `grep -n`.
+fn numbered_lines(text: &str, start: usize) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| format!("     {:>5}: {}\n", start + i, line))
+        .collect()
+}
+
+/// Wrap plain text output as a successful tool result.
+fn text_result(text: impl Into<String>) -> Result<CallToolResult, McpError> {
+    Ok(CallToolResult::success(vec![Content::text(text.into())]))
+}
+
+/// Wrap a JSON value as a successful tool result, for `format: json`.
+fn json_result(value: serde_json::Value) -> Result<CallToolResult, McpError> {
+    Ok(CallToolResult::success(vec![Content::json(value)?]))
+}
+
+/// One search result as listed by `search` in `format`.
+fn render_result(format: OutputFormat, i: usize, r: &indexer::SearchResult) -> String {
+    match format {
+        OutputFormat::Text => format_result(i, r),
+        OutputFormat::Markdown => format::result_markdown(i, r),
+        OutputFormat::Json => format::result_json(r).to_string(),
+    }
+}
+
+/// One search result as listed by `search`: its position (0-based), location, relevance
+/// and snippets.
+fn format_result(i: usize, r: &indexer::SearchResult) -> String {
+    let mut out = format!(
+        "{}. {} (relevance: {:.2}, score: {:.2})\n   Path: {}\n",
+        i + 1,
+        r.file_name,
+        r.relevance,
+        r.score,
+        format::location(r),
+    );
+    if let Some(modified) = r.modified {
+        out.push_str(&format!("   Modified: {}\n", logs::format_timestamp(modified)));
+    }
+    if let Some(count) = r.match_count {
+        out.push_str(&format!("   Matches: {}\n", count));
+    }
+    if let Some(section) = &r.section {
+        out.push_str(&format!("   Section: {}\n", section));
+    }
+    if let Some((start, end)) = r.log_time {
+        out.push_str(&format!(
+            "   Log time: {} to {}\n",
+            logs::format_timestamp(start),
+            logs::format_timestamp(end)
+        ));
+    }
+    if r.truncated {
+        out.push_str("   Truncated: file exceeds the size limit; only its start and end are indexed\n");
+    }
+    match (r.snippets.is_empty(), r.context_start) {
+        (true, Some(start)) => {
+            out.push_str("   Lines:\n");
+            out.push_str(&numbered_lines(&r.snippet, start));
+            out.push('\n');
+        }
+        (true, None) => out.push_str(&format!("   Snippet: {}\n\n", r.snippet)),
+        (false, _) => {
+            out.push_str("   Snippets:\n");
+            for (i, snippet) in r.snippets.iter().enumerate() {
+                if let Some(start) = snippet.context_start {
+                    if i > 0 {
+                        out.push_str("     --\n");
+                    }
+                    out.push_str(&numbered_lines(&snippet.text, start));
+                    continue;
+                }
+                match (snippet.row, snippet.line_number) {
+                    (Some(row), _) => out.push_str(&format!("     row {}: ", row)),
+                    (None, Some(ln)) => out.push_str(&format!("     line {}: ", ln)),
+                    (None, None) => out.push_str("     "),
+                }
+                out.push_str(&snippet.text);
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Render a timestamp as an age relative to now, e.g. "12s ago".
+fn format_age(time: Option<std::time::SystemTime>) -> String {
+    match time {
+        None => "never".to_string(),
+        Some(t) => match t.elapsed() {
+            Ok(d) => format!("{}s ago", d.as_secs()),
+            Err(_) => "just now".to_string(),
+        },
+    }
+}
+
+/// Format `time` as an RFC 3339 UTC timestamp (`2024-07-01T09:30:00Z`).
+fn format_time(time: std::time::SystemTime) -> String {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+    logs::format_timestamp(secs)
+}
+
+/// Index and, with `watch`, watch `requested` paths (resolved against `base_dir`) with the
+/// state write-locked. Returns the number of files indexed, per-path errors and notes.
+fn index_paths_locked(
+    state: &mut SharedState,
+    requested: &[String],
+    base_dir: &std::path::Path,
+    mode: WatchMode,
+    watch: bool,
+    commit: bool,
+    policy: Option<HiddenPolicy>,
+) -> anyhow::Result<(u64, Vec<String>, Vec<String>)> {
+    let mut total_indexed = 0u64;
+    let mut errors = Vec::new();
+    let mut notes = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+
+    for path_str in requested {
+        // Canonical paths so symlinks and `..` spellings of the same root are detected
+        let expanded = paths::expand(path_str, base_dir);
+        let Ok(canonical) = expanded.canonicalize() else {
+            let shown = paths::shown(path_str, &expanded);
+            errors.push(match shown == *path_str {
+                true => format!("Path does not exist: {}", path_str),
+                false => format!("Path does not exist: {} (resolved to {})", path_str, shown),
+            });
+            continue;
+        };
+        let shown = paths::shown(path_str, &canonical);
+        if shown != *path_str {
+            notes.push(format!("Resolved {} to {}", path_str, shown));
+        }
+        let path = canonical.as_path();
+        if !seen.insert(canonical.clone()) {
+            notes.push(format!("Skipped {}: listed more than once", path_str));
+            continue;
+        }
+        // A root that disappeared and is back keeps its hidden-file policy
+        let policy = policy.clone().or_else(|| {
+            let missing = state.index.missing_roots().iter().find(|root| root.path == path);
+            missing.and_then(|root| root.policy.clone())
+        });
+        let already_watched = match state.index.covering_root(path) {
+            Some(root) if root == path => state.index.is_watched(path),
+            Some(root) => {
+                let kind = if state.index.is_watched(root) { "watched" } else { "unwatched" };
+                notes.push(format!(
+                    "Skipped {}: already covered by {} root {}",
+                    path_str,
+                    kind,
+                    root.display()
+                ));
+                continue;
+            }
+            None => false,
+        };
+        if path.is_dir() {
+            if let Some(policy) = &policy {
+                state.index.set_hidden_policy(path, policy.clone());
+            }
+            if !watch && !already_watched {
+                // Before the walk, so a checkpoint of it resumes without a watcher
+                state.index.set_watched(path, false);
+            }
+            let nested: Vec<_> = state
+                .index
+                .nested_roots(path)
+                .into_iter()
+                .map(|root| (state.index.is_watched(&root), root))
+                .collect();
+            match state.index.index_directory(path) {
+                Ok(count) => total_indexed += count,
+                Err(e) => errors.push(format!("Error indexing {}: {}", path_str, e)),
+            }
+            let skipped = state.index.skipped(path);
+            if skipped.total() > 0 {
+                notes.push(format!(
+                    "Skipped {} files in {}: {}",
+                    skipped.total(),
+                    path_str,
+                    skipped
+                ));
+            }
+            for (watched, root) in nested {
+                if watched {
+                    if let Err(e) = state.watcher.unwatch(&root) {
+                        tracing::warn!("Failed to unwatch {}: {}", root.display(), e);
+                    }
+                }
+                notes.push(format!(
+                    "Merged watched root {} into {}",
+                    root.display(),
+                    path.display()
+                ));
+            }
+        } else {
+            match state.index.index_or_skip(path) {
+                Ok(None) => total_indexed += 1,
+                Ok(Some(reason)) => notes.push(format!("Skipped {}: {}", path_str, reason)),
+                Err(e) => errors.push(format!("Error indexing {}: {}", path_str, e)),
+            }
+        }
+        // Register with file watcher
+        if already_watched {
+            notes.push(format!("Re-indexed {}: already a watched root", path_str));
+            continue;
+        }
+        if !watch {
+            notes.push(format!(
+                "Not watching {}: changes show up only after indexing it again",
+                path_str
+            ));
+            continue;
+        }
+        let watched = match state.watcher.watch(path, mode) {
+            Ok(WatchMode::Poll) if mode == WatchMode::Native => {
+                notes.push(format!(
+                    "Native file events unavailable for {}, watching by polling",
+                    path_str
+                ));
+                true
+            }
+            Ok(_) => true,
+            Err(e) => {
+                errors.push(format!("Error watching {}: {}", path_str, e));
+                false
+            }
+        };
+        // Single files are watched through their directory
+        let watched_dir = if path.is_dir() { Some(path) } else { path.parent() };
+        if let Some(hit) = watched_dir.and_then(|dir| state.watcher.watch_limit_hit(dir)) {
+            notes.push(format!("Warning: {}", hit.message()));
+        }
+        if path.is_dir() {
+            state.index.set_watched(path, watched);
+        }
+    }
+
+    if commit {
+        state.index.commit()?;
+    } else if !state.index.commit_if_due()? && state.index.has_uncommitted_changes() {
+        state.deferred_commit.notify_one();
+    }
+    if state.index.next_retry().is_some() {
+        state.deferred_commit.notify_one();
+    }
+    Ok((total_indexed, errors, notes))
+}
+
+/// Detach roots that no longer exist, deleted or unmounted: stop watching them, drop
+/// their files and list them as missing in `status`. With `reattach`, missing roots that
+/// exist again are indexed and watched as before. Returns whether the index changed.
+pub fn check_roots(state: &mut SharedState, reattach: bool) -> anyhow::Result<bool> {
+    let mut changed = false;
+    for root in state.index.vanished_roots() {
+        if state.index.is_watched(&root) {
+            if let Err(e) = state.watcher.unwatch(&root) {
+                tracing::warn!("Failed to unwatch {}: {}", root.display(), e);
+            }
+        }
+        let removed = state.index.detach_root(&root)?;
+        tracing::warn!("Root {} disappeared, removed {} files", root.display(), removed);
+        changed = true;
+    }
+    if !reattach {
+        return Ok(changed);
+    }
+    let back: Vec<RootEntry> =
+        state.index.missing_roots().iter().filter(|root| root.path.is_dir()).cloned().collect();
+    for root in back {
+        let requested = [root.path.display().to_string()];
+        let mode = WatchMode::Native;
+        let indexed =
+            index_paths_locked(state, &requested, &root.path, mode, root.watched, false, None)?;
+        tracing::info!("Root {} is back, re-indexed {} files", root.path.display(), indexed.0);
+        changed = true;
+    }
+    Ok(changed)
+}
+
+/// Re-index and watch the roots of an index rebuilt for a new schema version, or the root
+/// whose indexing the previous process did not finish, one root per write lock so tools
+/// keep being served in between. Migration progress shows in `status`.
+pub async fn reindex_roots(state: AppState, roots: Vec<RootEntry>) {
+    for root in roots {
+        let path = root.path.display().to_string();
+        let requested = [path.clone()];
+        let result = indexer::write_blocking(state.clone(), move |state| {
+            let base_dir = root.path.clone();
+            let indexed = index_paths_locked(
+                state,
+                &requested,
+                &base_dir,
+                WatchMode::Native,
+                root.watched,
+                true,
+                root.policy,
+            );
+            let files = indexed.as_ref().map_or(0, |(files, _, _)| *files);
+            state.index.migrated_root_done(files);
+            indexed
+        })
+        .await;
+        match result.and_then(|indexed| indexed) {
+            Ok((files, errors, _)) => {
+                tracing::info!("Re-indexed {} files in {}", files, path);
+                for error in errors {
+                    tracing::warn!("{}", error);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to re-index {}: {}", path, e),
+        }
+    }
+}
+
+/// Map a rejected call to an MCP error whose data says why, for clients to back off on.
+fn limit_error(violation: &Violation) -> McpError {
+    let data = match violation {
+        Violation::RateLimited {
+            tool,
+            rate,
+            retry_after,
+        } => serde_json::json!({
+            "kind": violation.kind(),
+            "tool": tool,
+            "limit": rate.to_string(),
+            "retry_after_ms": retry_after.as_millis() as u64,
+        }),
+        Violation::QueryTooLong { argument, len, max } => serde_json::json!({
+            "kind": violation.kind(),
+            "argument": argument,
+            "max": max,
+            "got": len,
+        }),
+        Violation::TooManyPaths { count, max } => serde_json::json!({
+            "kind": violation.kind(),
+            "max": max,
+            "got": count,
+        }),
+    };
+    match violation {
+        Violation::RateLimited { .. } => McpError::invalid_request(violation.to_string(), Some(data)),
+        _ => McpError::invalid_params(violation.to_string(), Some(data)),
+    }
+}
+
+/// Resolve an `owner` parameter (`me`, a user name or a uid) to a uid.
+fn resolve_owner(owner: &Option<String>) -> Result<Option<u32>, McpError> {
+    owner
+        .as_deref()
+        .map(|o| Users::load().resolve(o))
+        .transpose()
+        .map_err(|e| McpError::invalid_params(e.to_string(), None))
+}
+
+/// Parse a `filter` expression parameter.
+fn parse_filter(filter: &Option<String>) -> Result<Option<Filter>, McpError> {
+    filter
+        .as_deref()
+        .map(Filter::parse)
+        .transpose()
+        .map_err(|e| McpError::invalid_params(e.to_string(), None))
+}
+
+/// The pinned files `scope` sees, one per line under a count, marking those no longer
+/// indexed.
+fn pinned_list(index: &FileIndex, scope: &SessionScope) -> String {
+    let pinned: Vec<&std::path::Path> = index.pinned().filter(|p| scope.allows(p)).collect();
+    if pinned.is_empty() {
+        return "No files are pinned.".to_string();
+    }
+    let mut out = format!("Pinned files ({}):\n", pinned.len());
+    for path in pinned {
+        let note = if index.is_indexed(path) { "" } else { " (not indexed)" };
+        out.push_str(&format!("  {}{}\n", path.display(), note));
+    }
+    out
+}
+
+/// Parse a `format` parameter; plain text when omitted.
+fn parse_format(format: &Option<String>) -> Result<OutputFormat, McpError> {
+    format
+        .as_deref()
+        .map_or(Ok(OutputFormat::Text), OutputFormat::parse)
+        .map_err(|e| McpError::invalid_params(e.to_string(), None))
+}
+
+/// Parse a `match` parameter; whole words when omitted.
+fn parse_match(mode: &Option<String>) -> Result<MatchMode, McpError> {
+    mode.as_deref()
+        .map_or(Ok(MatchMode::Word), MatchMode::parse)
+        .map_err(|e| McpError::invalid_params(e.to_string(), None))
+}
+
+/// Map an indexer error to an MCP error, treating query syntax problems as invalid params.
+fn index_error(context: &str, e: anyhow::Error) -> McpError {
+    let data = Some(serde_json::json!({ "detail": e.to_string() }));
+    if e.downcast_ref::<tantivy::query::QueryParserError>().is_some()
+        || e.downcast_ref::<query::UnknownField>().is_some()
+        || e.downcast_ref::<query::BadWildcard>().is_some()
+    {
+        McpError::invalid_params(format!("{}: {}", context, e), data)
+    } else {
+        McpError::internal_error(format!("{}: {}", context, e), data)
+    }
+}
+
 // -- Tool parameter types --
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -33,202 +497,2937 @@ pub struct SearchRequest {
     pub query: String,
     #[schemars(description = "Maximum number of results to return (default: 10)")]
     pub limit: Option<usize>,
-    #[schemars(description = "Filter results by file extension (e.g. \"rs\", \"py\", \"js\"). Omit to search all file types.")]
+    #[schemars(description = "Filter results by file extension (e.g. \"rs\", \"py\", \"js\") or programming language (e.g. \"python\", matching .py and .pyi files and python scripts). Omit to search all file types.")]
     pub file_type: Option<String>,
     #[schemars(description = "Limit results to files whose path matches these directory components (e.g. \"src\", \"tests\"). Components are matched individually, not as a substring.")]
     pub path_prefix: Option<String>,
+    #[schemars(description = "Only return files tracked by git, skipping untracked and non-repository files (default: false)")]
+    pub tracked_only: Option<bool>,
+    #[schemars(description = "Only return .log file chunks with entries at or after this time (e.g. \"2024-07-01\", \"2024-07-01T09:00:00Z\", Unix seconds)")]
+    pub since: Option<String>,
+    #[schemars(description = "Only return .log file chunks with entries at or before this time")]
+    pub until: Option<String>,
+    #[schemars(description = "Only return files owned by this user: \"me\" (the user running the server), a user name or a uid")]
+    pub owner: Option<String>,
+    #[schemars(description = "Only return files of this MIME type, detected from content as well as extension (e.g. \"application/json\", \"text/x-python\"), or of any subtype with \"text/*\"")]
+    pub mime: Option<String>,
+    #[schemars(description = "Show up to this many matching lines per file, each with its line number, instead of one snippet (default: 1, at most 20)")]
+    pub snippets_per_file: Option<usize>,
+    #[schemars(description = "Show this many whole lines before and after each match, with line numbers, instead of a window of characters (at most 50)")]
+    pub context_lines: Option<usize>,
+    #[schemars(description = "Leave out results whose relevance (0 to 1, the score relative to the best result) is below this, e.g. 0.3 to drop weak matches")]
+    pub min_score: Option<f32>,
+    #[schemars(description = "Return one entry per file, with its best snippet and the number of matching lines, instead of one per matching chunk of a log or table (default: false)")]
+    pub group_by_file: Option<bool>,
+    #[schemars(description = "Only search the files of an earlier search's results, by the result set token it returned (e.g. \"r3\"). Use it to narrow results step by step without repeating the earlier query and filters")]
+    pub within: Option<String>,
+    #[schemars(description = "Only return files of this project, by the name list_projects shows (from Cargo.toml, package.json or pyproject.toml, or the directory name of a git repository)")]
+    pub project: Option<String>,
+    #[schemars(description = "Leave out generated files: lockfiles, vendored dependencies (node_modules, vendor), minified bundles and files marked as generated (default: true). Set to false to search them too")]
+    pub exclude_generated: Option<bool>,
+    #[schemars(description = "Only return files pinned with pin_file (default: false). Pinned files rank higher in every search either way")]
+    pub pinned_only: Option<bool>,
+    #[schemars(description = "Match words and phrases only in the case typed, e.g. to find the type Error without every error (default: false). Field filters are unaffected")]
+    pub case_sensitive: Option<bool>,
+    #[serde(rename = "match")]
+    #[schemars(description = "How words match: \"word\" (default) whole words only, \"prefix\" words starting with each word typed (get_us finds get_user, and the last word of a phrase) or \"substring\" words containing it (user finds getuser). Prefix and substring matches rank by the number of fields matched, not by frequency")]
+    pub match_mode: Option<String>,
+    #[schemars(description = "Keep the response under this many characters (at least 500) by dropping extra snippets, then shortening snippets, then leaving out the lowest-ranked results; the response says what was left out")]
+    pub max_response_chars: Option<usize>,
+    #[schemars(description = "Answer within about this many milliseconds, e.g. 50 for search-as-you-type: when the time is short, fewer results are returned and extra snippets and match counts are left out, and the response says so")]
+    pub latency_budget_ms: Option<u64>,
+    #[schemars(description = "Output format: \"text\" (default), \"markdown\" (a heading per result with snippets in code fences) or \"json\" (an object with results, total_count, result_set, did_you_mean when nothing matched, degraded when the latency budget cut the results short, and generation)")]
+    pub format: Option<String>,
+    #[schemars(description = "Filter expression over file metadata, e.g. \"ext in [rs, toml] AND path startswith src AND modified > 2024-01-01\". Fields: ext, language, project, path, mime, owner, tracked, generated, modified; operators: =, !=, in [...], startswith (path), >, >=, <, <= (modified); combine with AND, OR, NOT and parentheses")]
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct MultiSearchRequest {
+    #[schemars(description = "Keyword queries to run, at most 10, in the same syntax as search")]
+    pub queries: Vec<String>,
+    #[schemars(description = "Maximum number of results per query (default: 5)")]
+    pub limit: Option<usize>,
+    #[schemars(description = "Filter results of every query by file extension (e.g. \"rs\", \"md\")")]
+    pub file_type: Option<String>,
+    #[schemars(description = "Limit results of every query to files whose path matches these directory components (e.g. \"src\"), matched individually")]
+    pub path_prefix: Option<String>,
+    #[schemars(description = "Leave out results whose relevance (0 to 1) is below this")]
+    pub min_score: Option<f32>,
+    #[schemars(description = "Return one entry per file for every query (default: false)")]
+    pub group_by_file: Option<bool>,
+    #[schemars(description = "Only return files of this project, by the name list_projects shows")]
+    pub project: Option<String>,
+    #[schemars(description = "Leave out lockfiles, vendored, minified and other generated files (default: true)")]
+    pub exclude_generated: Option<bool>,
+    #[schemars(description = "Filter expression over file metadata applied to every query, in the same syntax as search's filter")]
+    pub filter: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct IndexPathsRequest {
-    #[schemars(description = "List of file or directory paths to index and watch")]
+    #[schemars(description = "List of file or directory paths to index and watch. `~`, `$VARS` and relative paths are expanded.")]
     pub paths: Vec<String>,
+    #[schemars(description = "Watch these paths by polling instead of native file events. Use for NFS/SMB mounts and container bind mounts where changes are otherwise missed (default: false)")]
+    pub poll: Option<bool>,
+    #[schemars(description = "Watch these paths for changes (default: true). Pass false to index a snapshot once, e.g. a large archive directory; status lists it as not watched and index_paths indexes it again on request")]
+    pub watch: Option<bool>,
+    #[schemars(description = "Commit before returning so the files are searchable immediately (default: true). \
+    Pass false when adding many paths in a row to let the commit policy batch them; use wait_for_index to wait for them")]
+    pub commit: Option<bool>,
+    #[schemars(description = "Index dotfiles and dot-directories (e.g. .github/) in these directories. Defaults to the server setting (LOCALFILES_INDEX_HIDDEN, on by default)")]
+    pub hidden: Option<bool>,
+    #[schemars(description = "Directory names to skip in these directories, replacing the default list (.cache, .venv, node_modules, __pycache__, .terraform, ...). Pass [] to skip none")]
+    pub skip_dirs: Option<Vec<String>>,
 }
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct ReadFileRequest {
-    #[schemars(description = "Absolute path of the indexed file to read")]
+    #[schemars(description = "Path of the indexed file to read. `~`, `$VARS` and relative paths are expanded.")]
+    pub path: String,
+    #[schemars(description = "Return at most this many characters (at least 500), cut at a line end, with a note of how much was left out")]
+    pub max_response_chars: Option<usize>,
+    #[schemars(description = "Output format: \"text\" (default), \"markdown\" (the content in a code fence tagged with its language) or \"json\" (an object with path, content and lines)")]
+    pub format: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct FileInfoRequest {
+    #[schemars(description = "Path of the indexed file. `~`, `$VARS` and relative paths are expanded.")]
+    pub path: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct ReadFilesRequest {
+    #[schemars(description = "Paths of indexed files to read, at most 20. `~`, `$VARS` and relative paths are expanded.")]
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct WriteFileRequest {
+    #[schemars(description = "Path of the file to write, below an indexed directory. `~`, `$VARS` and relative paths are expanded; missing parent directories are created.")]
+    pub path: String,
+    #[schemars(description = "The complete new content of the file (at most 1 MB)")]
+    pub content: String,
+    #[schemars(description = "Replace the file if it exists (default: false, which fails instead)")]
+    pub overwrite: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct AppendFileRequest {
+    #[schemars(description = "Path of the file to append to, below an indexed directory; created if missing. `~`, `$VARS` and relative paths are expanded.")]
+    pub path: String,
+    #[schemars(description = "Text to add at the end of the file (at most 1 MB); include a trailing newline to end the line")]
+    pub content: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct DeleteFileRequest {
+    #[schemars(description = "Path of an indexed file to move to the trash. `~`, `$VARS` and relative paths are expanded.")]
     pub path: String,
 }
 
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct PurgeIndexRequest {
+    #[schemars(description = "Must be true: purging deletes the whole index, for every session, and cannot be undone.")]
+    pub confirm: bool,
+    #[schemars(description = "Must be true when the server is a daemon shared by several client sessions, to purge the index of all of them (default: false)")]
+    pub all_sessions: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct RecentFilesRequest {
+    #[schemars(description = "Maximum number of files to return (default: 20)")]
+    pub limit: Option<usize>,
+    #[schemars(description = "Filter by file extension (e.g. \"md\", \"rs\"). Omit to include all file types.")]
+    pub file_type: Option<String>,
+    #[schemars(description = "Limit to files whose path matches these directory components (e.g. \"src\", \"notes\"), matched individually")]
+    pub path_prefix: Option<String>,
+    #[schemars(description = "Only files modified at or after this time (e.g. \"2024-07-01\", \"2024-07-01T09:00:00Z\", Unix seconds)")]
+    pub since: Option<String>,
+    #[schemars(description = "Filter expression over file metadata in the same syntax as search's filter, e.g. \"ext in [md, txt] AND NOT path startswith archive\"")]
+    pub filter: Option<String>,
+    #[schemars(description = "Output format: \"text\" (default), \"markdown\" (a table of paths and modification times) or \"json\" (an array of objects with path and modified)")]
+    pub format: Option<String>,
+}
+
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct ListFilesRequest {
     #[schemars(description = "Filter by file extension (e.g. \"yaml\", \"rs\"). Omit to list all files.")]
     pub file_type: Option<String>,
     #[schemars(description = "Filter to files whose path contains this substring (e.g. \"src/\", \"config/\")")]
     pub path_prefix: Option<String>,
+    #[schemars(description = "Maximum number of paths to return (default: 200, max: 1000)")]
+    pub limit: Option<usize>,
+    #[schemars(description = "Number of matching paths to skip, for paging by position")]
+    pub offset: Option<usize>,
+    #[schemars(description = "Continuation token from the previous page's output; the next page starts after it")]
+    pub cursor: Option<String>,
+    #[schemars(description = "Only list files owned by this user: \"me\" (the user running the server), a user name or a uid")]
+    pub owner: Option<String>,
+    #[schemars(description = "Show each file's owner and permissions after its path, like ls -l (default: false)")]
+    pub long: Option<bool>,
+    #[schemars(description = "Keep the response under this many characters (at least 500) by returning fewer paths; the returned cursor continues after the last path shown")]
+    pub max_response_chars: Option<usize>,
+    #[schemars(description = "Output format: \"text\" (default), \"markdown\" (a bullet list, or a table with long) or \"json\" (an object with files, total and cursor)")]
+    pub format: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct BacklinksRequest {
+    #[schemars(description = "Note name as written in a wiki-link (e.g. \"Project Plan\") or the note's path")]
+    pub note: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct OutlineRequest {
+    #[schemars(description = "Path of the indexed file to outline. `~`, `$VARS` and relative paths are expanded.")]
+    pub path: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct SimilarFilesRequest {
+    #[schemars(description = "Path of an indexed file to find similar files for. `~`, `$VARS` and relative paths are expanded.")]
+    pub path: Option<String>,
+    #[schemars(description = "Text to find similar files for, used when no path is given")]
+    pub text: Option<String>,
+    #[schemars(description = "Maximum number of results to return (default: 10)")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct AnalyzeTermsRequest {
+    #[schemars(description = "Only count terms in files containing this keyword, giving the terms that co-occur with it")]
+    pub keyword: Option<String>,
+    #[schemars(description = "Only count terms in files whose path matches these directory components (e.g. \"src\", \"notes/projects\")")]
+    pub path_prefix: Option<String>,
+    #[schemars(description = "Maximum number of terms to return (default: 30)")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct VerifyIndexRequest {
+    #[schemars(description = "Fix the discrepancies found: drop missing files, re-index changed files and re-index or drop orphaned documents (default: false, report only)")]
+    pub repair: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct WaitForIndexRequest {
+    #[schemars(description = "Generation to wait for, as reported by search, index_paths or status")]
+    pub generation: u64,
+    #[schemars(description = "Maximum time to wait in milliseconds (default: 10000, at most 60000)")]
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct SuggestRequest {
+    #[schemars(description = "Partially typed query; its last word is completed (e.g. \"error hand\", \"tag:pro\")")]
+    pub query: String,
+    #[schemars(description = "Maximum number of suggestions to return (default: 10)")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct SaveSearchRequest {
+    #[schemars(description = "Name of the saved search; saving under an existing name replaces it")]
+    pub name: String,
+    #[schemars(description = "Query to watch for, with the same syntax as search (e.g. \"OutOfMemory\")")]
+    pub query: String,
+    #[schemars(description = "Only match files with this extension (e.g. \"log\")")]
+    pub file_type: Option<String>,
+    #[schemars(description = "Only match files whose path matches these directory components")]
+    pub path_prefix: Option<String>,
+    #[schemars(description = "Remove the saved search with this name instead of saving (default: false)")]
+    pub remove: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct CheckSavedSearchesRequest {
+    #[schemars(description = "Only check this saved search. Omit to check all.")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct PinFileRequest {
+    #[schemars(description = "Path of an indexed file to pin. `~`, `$VARS` and relative paths are expanded.")]
+    pub path: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct UnpinFileRequest {
+    #[schemars(description = "Path of a pinned file to unpin. `~`, `$VARS` and relative paths are expanded.")]
+    pub path: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct RecentSearchesRequest {
+    #[schemars(description = "Maximum number of searches to return, newest first (default: 20)")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct SearchDirectoriesRequest {
+    #[schemars(description = "Keywords describing the directory, e.g. \"authentication service\", matched against its file names, dominant language and README")]
+    pub query: String,
+    #[schemars(description = "Maximum number of directories to return (default: 10)")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct HistorySearchRequest {
+    #[schemars(description = "The keyword query to search for in past revisions")]
+    pub query: String,
+    #[schemars(description = "Only search this indexed revision (e.g. \"v1.2.0\"). Omit to search all indexed revisions.")]
+    pub revision: Option<String>,
+    #[schemars(description = "Maximum number of results to return (default: 10)")]
+    pub limit: Option<usize>,
 }
 
 // -- MCP Server --
 
-#[derive(Debug, Clone)]
-pub struct FileSearchServer {
-    state: AppState,
-    tool_router: ToolRouter<FileSearchServer>,
-}
+/// Optional parts of the server, from the build's features and the index configuration.
+/// Tools that need a missing one are left out of the tool list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Files in git repositories have `author:`, `branch:` and `commit:` fields (`git`).
+    pub git: bool,
+    /// Repositories are indexed at past revisions (`git` and `LOCALFILES_HISTORY`), for
+    /// `history_search`.
+    pub history: bool,
+    /// `outline` lists code symbols, not only markdown headings (`tree-sitter`).
+    pub code_outlines: bool,
+    /// Text an OCR command recognizes in images is indexed.
+    pub ocr: bool,
+    /// `write_file` and `append_file` are enabled (`LOCALFILES_WRITE_TOOLS`).
+    pub write: bool,
+    /// `delete_file` is enabled (`trash` and `LOCALFILES_DELETE_TOOL`).
+    pub delete: bool,
+    /// `purge_index` is enabled (`LOCALFILES_PURGE_TOOL`).
+    pub purge: bool,
+}
+
+impl Capabilities {
+    /// Everything this build supports, whatever the configuration.
+    pub fn compiled() -> Self {
+        Self {
+            git: cfg!(feature = "git"),
+            history: cfg!(feature = "git"),
+            code_outlines: cfg!(feature = "tree-sitter"),
+            ocr: true,
+            write: false,
+            delete: false,
+            purge: false,
+        }
+    }
+
+    /// What this build supports with `index`'s configuration.
+    pub fn of(index: &FileIndex) -> Self {
+        let compiled = Self::compiled();
+        Self {
+            history: compiled.history && index.has_history(),
+            ocr: index.has_ocr(),
+            ..compiled
+        }
+    }
+
+    /// Tools that cannot return anything without a missing capability.
+    fn unavailable_tools(&self) -> Vec<&'static str> {
+        let mut tools = Vec::new();
+        if !self.history {
+            tools.push("history_search");
+        }
+        if !self.write {
+            tools.extend(["write_file", "append_file"]);
+        }
+        if !self.delete {
+            tools.push("delete_file");
+        }
+        if !self.purge {
+            tools.push("purge_index");
+        }
+        tools
+    }
+
+    /// Server instructions, mentioning only the optional tools and fields available.
+    fn instructions(&self) -> String {
+        let mut out = String::from(
+            "A local file search server. Use 'index_paths' to add directories, \
+             then 'search' to find files by keyword. Use 'status' to check index state \
+             and 'health' to diagnose a misbehaving server.\n\
+             Prefer 'search' over grep/find for broad keyword searches — it provides \
+             relevance-ranked full-text search across all indexed files with snippet context. \
+             Use 'file_type' and 'path_prefix' parameters to narrow results, and \
+             'query_syntax' to see the searchable fields (e.g. ext:rs, path:src).",
+        );
+        if self.git {
+            out.push_str(
+                " Files in git repositories can be searched by author:, branch: and commit:.",
+            );
+        }
+        if self.history {
+            out.push_str(" Use 'history_search' to search files at past git revisions.");
+        }
+        if self.code_outlines {
+            out.push_str(
+                " 'outline' lists the headings of markdown files and the symbols of code files.",
+            );
+        } else {
+            out.push_str(" 'outline' lists the headings of markdown files.");
+        }
+        if self.ocr {
+            out.push_str(" Text recognized in images is searchable like file content.");
+        }
+        if self.write {
+            out.push_str(
+                " 'write_file' and 'append_file' edit files in indexed directories and index \
+                 the new content immediately.",
+            );
+        }
+        if self.delete {
+            out.push_str(
+                " 'delete_file' moves an indexed file to the trash and drops it from the index.",
+            );
+        }
+        if self.purge {
+            out.push_str(
+                " 'purge_index' irreversibly deletes the whole index, its manifests, caches and \
+                 audit logs; only use it when asked to.",
+            );
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileSearchServer {
+    state: AppState,
+    /// Lock-free read side of the index, so searches never wait on the writer.
+    search: SearchHandle,
+    /// Directory that relative path arguments are resolved against.
+    base_dir: std::path::PathBuf,
+    /// Searches run in this session, for `recent_searches`.
+    queries: Arc<QueryLog>,
+    /// Files of this session's search results, for `within`.
+    result_sets: Arc<ResultSets>,
+    /// Rate limits and size caps checked before each tool call.
+    limits: Arc<Limits>,
+    /// File access log shared by every session, when auditing is enabled.
+    audit: Option<Arc<AuditLog>>,
+    /// Client name and version, taken from the session's first tool call.
+    client: Arc<std::sync::OnceLock<String>>,
+    /// Decides which tools are listed and what the instructions mention.
+    capabilities: Capabilities,
+    /// Whether to index and watch the workspace roots the client advertises.
+    client_roots: bool,
+    /// Whether roots that disappeared are re-attached when they reappear, for `status`.
+    reattach_roots: bool,
+    /// Roots this session sees, when sessions are isolated from each other.
+    scope: Arc<SessionScope>,
+    /// Whether other client sessions share the index (a session of the daemon), so
+    /// purging it needs `all_sessions`.
+    shared_index: bool,
+    /// Query logs and result sets of every session, cleared when the index is purged.
+    sessions: Arc<Mutex<Vec<SessionHistory>>>,
+    /// Key of this session's saved searches.
+    session_id: u64,
+    /// Directory `delete_file` moves files into instead of the OS trash.
+    trash_dir: Option<std::path::PathBuf>,
+    tool_router: ToolRouter<FileSearchServer>,
+}
+
+/// A session's id, query log and result sets, as long as the session lives.
+type SessionHistory = (u64, Weak<QueryLog>, Weak<ResultSets>);
+
+/// Id of the next session `with_new_session` starts; the first server is session 0.
+static NEXT_SESSION_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+#[tool_router]
+impl FileSearchServer {
+    pub fn new(state: AppState, search: SearchHandle) -> Self {
+        let server = Self {
+            state,
+            search,
+            base_dir: paths::default_base_dir(),
+            queries: Arc::default(),
+            result_sets: Arc::default(),
+            limits: Arc::default(),
+            audit: None,
+            client: Arc::default(),
+            capabilities: Capabilities::compiled(),
+            client_roots: false,
+            reattach_roots: false,
+            scope: Arc::default(),
+            shared_index: false,
+            sessions: Arc::default(),
+            session_id: 0,
+            trash_dir: None,
+            tool_router: Self::router_for(Capabilities::compiled()),
+        };
+        server.register_session();
+        server
+    }
+
+    /// Add this session's query log and result sets to those purging clears.
+    fn register_session(&self) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.retain(|(_, queries, _)| queries.strong_count() > 0);
+        sessions.push((
+            self.session_id,
+            Arc::downgrade(&self.queries),
+            Arc::downgrade(&self.result_sets),
+        ));
+    }
+
+    /// Ids of the sessions still connected.
+    fn live_sessions(&self) -> std::collections::HashSet<u64> {
+        let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions
+            .iter()
+            .filter(|(_, queries, _)| queries.strong_count() > 0)
+            .map(|(id, _, _)| *id)
+            .collect()
+    }
+
+    /// Only list the tools `capabilities` supports.
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self.tool_router = Self::router_for(capabilities);
+        self
+    }
+
+    /// Index and watch the roots clients advertise when they connect or change them.
+    pub fn with_client_roots(mut self, enabled: bool) -> Self {
+        self.client_roots = enabled;
+        self
+    }
+
+    /// Report missing roots as re-attached automatically, as `check_roots` does with
+    /// `reattach`.
+    pub fn with_reattach_roots(mut self, enabled: bool) -> Self {
+        self.reattach_roots = enabled;
+        self
+    }
+
+    /// Isolate sessions from each other's roots, or not, per `scope`.
+    pub fn with_scope(mut self, scope: SessionScope) -> Self {
+        self.scope = Arc::new(scope);
+        self
+    }
+
+    fn router_for(capabilities: Capabilities) -> ToolRouter<Self> {
+        let mut router = Self::tool_router();
+        for tool in capabilities.unavailable_tools() {
+            router.remove_route(tool);
+        }
+        router
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Arc::new(limits);
+        self
+    }
+
+    pub fn with_audit_log(mut self, audit: Option<AuditLog>) -> Self {
+        self.audit = audit.map(Arc::new);
+        self
+    }
+
+    /// Resolve relative path arguments against `base_dir` instead of this process's.
+    pub fn with_base_dir(mut self, base_dir: std::path::PathBuf) -> Self {
+        self.base_dir = base_dir;
+        self
+    }
+
+    /// Move files `delete_file` deletes into `trash_dir`, on their filesystem, instead of
+    /// the OS trash, so tests need not point the process's data directory elsewhere.
+    #[cfg(test)]
+    pub fn with_trash_dir(mut self, trash_dir: std::path::PathBuf) -> Self {
+        self.trash_dir = Some(trash_dir);
+        self
+    }
+
+    /// Start a separate query log, result sets and rate limits, for a new client session
+    /// sharing this server's index.
+    pub fn with_new_session(mut self) -> Self {
+        self.queries = Arc::default();
+        self.result_sets = Arc::default();
+        self.limits = Arc::new(self.limits.new_session());
+        self.client = Arc::default();
+        self.scope = Arc::new(self.scope.new_session());
+        self.shared_index = true;
+        self.session_id = NEXT_SESSION_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.register_session();
+        self
+    }
+
+    /// Write `content` to the file at `path` per `mode` if the index covers it and this
+    /// session sees it, then index and commit it, for `write_file` and `append_file`.
+    async fn write_indexed(
+        &self,
+        tool: &str,
+        path: &str,
+        content: String,
+        mode: WriteMode,
+    ) -> Result<CallToolResult, McpError> {
+        let requested = paths::expand(path, &self.base_dir);
+        let invalid = |e: anyhow::Error| {
+            McpError::invalid_params(
+                e.to_string(),
+                Some(serde_json::json!({
+                    "path": path,
+                    "resolved_path": paths::shown(path, &requested),
+                })),
+            )
+        };
+        let target = write::resolve(&requested).map_err(invalid)?;
+        if !self.scope.allows(&target) {
+            return Err(invalid(anyhow::anyhow!(
+                "{} is outside the roots this session indexed",
+                target.display()
+            )));
+        }
+        let bytes = content.len();
+        let written = target.clone();
+        let generation = indexer::write_blocking(self.state.clone(), move |state| {
+            if !state.index.accepts(&written) {
+                anyhow::bail!(
+                    "{} is not a file the index covers: it must be below a watched root, of an \
+                     indexed file type and outside excluded directories",
+                    written.display()
+                );
+            }
+            write::write(&written, &content, mode)?;
+            state.index.index_file(&written)?;
+            state.index.commit()?;
+            Ok(state.index.generation())
+        })
+        .await
+        .map_err(|e| index_error("Writing failed", e))?
+        .map_err(invalid)?;
+        self.audit(AuditEntry {
+            path: Some(target.display().to_string()),
+            files: vec![target.display().to_string()],
+            ..AuditEntry::new(tool)
+        });
+        let verb = if mode == WriteMode::Append { "Appended" } else { "Wrote" };
+        text_result(format!(
+            "{} {} bytes to {} (indexed, index generation {}).",
+            verb,
+            bytes,
+            target.display(),
+            generation
+        ))
+    }
+
+    /// Read side of the index limited to what this session may see.
+    fn session_search(&self) -> SearchHandle {
+        self.search.scoped(self.scope.roots())
+    }
+
+    /// Index and watch the client's workspace roots, if it advertises any and
+    /// `client_roots` is on. Roots already covered by an indexed root are skipped.
+    async fn index_client_roots(&self, peer: Peer<RoleServer>) {
+        let supported = peer.peer_info().is_some_and(|info| info.capabilities.roots.is_some());
+        if !self.client_roots || !supported {
+            return;
+        }
+        let roots = match peer.list_roots().await {
+            Ok(result) => result.roots,
+            Err(e) => {
+                tracing::warn!("Failed to list client roots: {}", e);
+                return;
+            }
+        };
+        let requested: Vec<String> = roots
+            .iter()
+            .filter_map(|root| match paths::from_file_uri(&root.uri) {
+                Some(path) => Some(path.display().to_string()),
+                None => {
+                    tracing::warn!("Ignoring client root {}: not a local file URI", root.uri);
+                    None
+                }
+            })
+            .collect();
+        for path in &requested {
+            if let Ok(root) = std::path::Path::new(path).canonicalize() {
+                self.scope.add_root(root);
+            }
+        }
+        let base_dir = self.base_dir.clone();
+        let result = indexer::write_blocking(self.state.clone(), move |state| {
+            // Watched roots are kept up to date already, so reconnecting does not re-walk them
+            let new: Vec<String> = requested
+                .into_iter()
+                .filter(|p| {
+                    std::path::Path::new(p)
+                        .canonicalize()
+                        .map_or(true, |path| state.index.covering_root(&path).is_none())
+                })
+                .collect();
+            if new.is_empty() {
+                return Ok((0, Vec::new(), Vec::new()));
+            }
+            tracing::info!("Indexing client roots: {}", new.join(", "));
+            index_paths_locked(state, &new, &base_dir, WatchMode::Native, true, true, None)
+        })
+        .await;
+        match result.and_then(|indexed| indexed) {
+            Ok((0, errors, _)) if errors.is_empty() => {}
+            Ok((files, errors, _)) => {
+                tracing::info!("Indexed {} files in client roots", files);
+                for error in errors {
+                    tracing::warn!("{}", error);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to index client roots: {}", e),
+        }
+    }
+
+    /// Append to the audit log, if enabled, tagging the entry with this session's client.
+    fn audit(&self, mut entry: AuditEntry) {
+        let Some(audit) = &self.audit else { return };
+        entry.client = self.client.get().cloned();
+        if let Err(e) = audit.record(&entry) {
+            tracing::warn!("Failed to write audit log {}: {}", audit.path().display(), e);
+        }
+    }
+
+    fn log_query(&self, tool: &str, query: &str, filters: &[(&str, Option<String>)], hits: usize) {
+        self.queries.record(LoggedQuery {
+            tool: tool.to_string(),
+            query: query.to_string(),
+            filters: filters
+                .iter()
+                .filter_map(|(name, value)| value.clone().map(|v| (name.to_string(), v)))
+                .collect(),
+            hits,
+            at: std::time::SystemTime::now(),
+        });
+    }
+
+    #[tool(
+        description = "Search indexed files by keyword. Returns matching file paths, snippets, and relevance from 0 to 1 (the best result is 1; pass min_score to drop weak matches). \
+        Performs full-text search with relevance ranking across all indexed files. \
+        Supports natural language queries and boolean operators (AND, OR, NOT). \
+        Supports field-based queries: extension:rs, directory:config, content:error. \
+        Markdown front matter is searchable with title:, tag: and alias: (e.g. tag:meeting). \
+        Files in git repositories are searchable with author:, branch: and commit: (e.g. author:alice). \
+        Combine with boolean operators: extension:yaml AND database. \
+        .log files are indexed in chunks of lines; since/until restrict results to log entries in a time range. \
+        CSV/TSV rows are searchable by column with columns.<header>: (e.g. columns.status:failed). \
+        JSON/YAML/TOML keys are searchable by dotted path with key: (e.g. key:server.port). \
+        Fields have aliases such as ext:, type: and path:; call query_syntax for the full list. \
+        Prefer this over grep/find for broad keyword searches across large codebases.",
+        annotations(title = "Search files", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn search(
+        &self,
+        Parameters(req): Parameters<SearchRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = req.limit.unwrap_or(10).min(MAX_RESULTS);
+        tracing::Span::current().record("query", req.query.as_str());
+        let (query, file_type, path_prefix) =
+            (req.query.clone(), req.file_type.clone(), req.path_prefix.clone());
+        let tracked_only = req.tracked_only.unwrap_or(false);
+        let parse_bound = |bound: &Option<String>| {
+            bound
+                .as_deref()
+                .map(logs::parse_time_bound)
+                .transpose()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))
+        };
+        let time = match (parse_bound(&req.since)?, parse_bound(&req.until)?) {
+            (None, None) => None,
+            (since, until) => Some(TimeRange { since, until }),
+        };
+        let filters = SearchFilters {
+            file_type,
+            path_prefix,
+            tracked_only,
+            time,
+            owner: resolve_owner(&req.owner)?,
+            mime: req.mime.clone(),
+            snippets_per_file: req.snippets_per_file.unwrap_or(1).min(MAX_SNIPPETS_PER_FILE),
+            context_lines: req.context_lines.map(|n| n.min(MAX_CONTEXT_LINES)),
+            min_score: req.min_score,
+            group_by_file: req.group_by_file.unwrap_or(false),
+            within: req
+                .within
+                .as_deref()
+                .map(|token| {
+                    self.result_sets.get(token).ok_or_else(|| {
+                        McpError::invalid_params(
+                            format!("Unknown or expired result set '{}'", token),
+                            None,
+                        )
+                    })
+                })
+                .transpose()?,
+            filter: parse_filter(&req.filter)?,
+            project: req.project.clone(),
+            exclude_generated: req.exclude_generated.unwrap_or(true),
+            pinned_only: req.pinned_only.unwrap_or(false),
+            case_sensitive: req.case_sensitive.unwrap_or(false),
+            match_mode: parse_match(&req.match_mode)?,
+            latency_budget: req.latency_budget_ms.map(std::time::Duration::from_millis),
+        };
+        let format = parse_format(&req.format)?;
+        let (mut output, did_you_mean) = self
+            .session_search()
+            .run(move |h| {
+                let output = h.search_filtered(&query, limit, &filters)?;
+                // Spelling corrections, so a typo does not end the search
+                let did_you_mean = match output.results.is_empty() {
+                    true => h.did_you_mean(&query, MAX_DID_YOU_MEAN)?,
+                    false => Vec::new(),
+                };
+                Ok((output, did_you_mean))
+            })
+            .await
+            .map_err(|e| index_error("Search failed", e))?;
+        tracing::Span::current().record("hits", output.total_count);
+        let trimmed = req.max_response_chars.map(|max| {
+            let max = max.max(budget::MIN_RESPONSE_CHARS) - budget::NOTES_CHARS;
+            budget::fit_results(&mut output.results, max, |i, r| render_result(format, i, r))
+        });
+        self.log_query(
+            "search",
+            &req.query,
+            &[
+                ("file_type", req.file_type.clone()),
+                ("path_prefix", req.path_prefix.clone()),
+                ("tracked_only", req.tracked_only.filter(|t| *t).map(|t| t.to_string())),
+                ("since", req.since.clone()),
+                ("until", req.until.clone()),
+                ("owner", req.owner.clone()),
+                ("mime", req.mime.clone()),
+                ("min_score", req.min_score.map(|s| s.to_string())),
+                ("within", req.within.clone()),
+                ("filter", req.filter.clone()),
+                ("project", req.project.clone()),
+                ("exclude_generated", req.exclude_generated.filter(|e| !*e).map(|e| e.to_string())),
+                ("pinned_only", req.pinned_only.filter(|p| *p).map(|p| p.to_string())),
+                ("case_sensitive", req.case_sensitive.filter(|c| *c).map(|c| c.to_string())),
+                ("match", req.match_mode.clone()),
+            ],
+            output.total_count,
+        );
+        self.audit(AuditEntry {
+            query: Some(req.query.clone()),
+            files: output.results.iter().map(|r| r.file_path.clone()).collect(),
+            ..AuditEntry::new("search")
+        });
+        let trimmed = trimmed.filter(|t| !t.is_empty());
+        let token = (!output.results.is_empty())
+            .then(|| self.result_sets.record(output.results.iter().map(|r| r.file_path.clone())));
+        if format == OutputFormat::Json {
+            return json_result(format::without_nulls(serde_json::json!({
+                "results": output.results.iter().map(format::result_json).collect::<Vec<_>>(),
+                "total_count": output.total_count,
+                "trimmed": trimmed.map(|t| t.to_string()),
+                "result_set": token,
+                "did_you_mean": (!did_you_mean.is_empty()).then_some(&did_you_mean),
+                "degraded": output.degraded.then_some(true),
+                "generation": output.generation,
+            })));
+        }
+        match (output, token) {
+            (output, None) if !did_you_mean.is_empty() => text_result(format!(
+                "No results found (index generation {}). Did you mean: {}?",
+                output.generation,
+                did_you_mean.iter().map(|q| format!("\"{}\"", q)).collect::<Vec<_>>().join(", ")
+            )),
+            (output, None) => text_result(format!(
+                "No results found (index generation {}).",
+                output.generation
+            )),
+            (output, Some(token)) => {
+                let mut out: String = output
+                    .results
+                    .iter()
+                    .enumerate()
+                    .map(|(i, r)| render_result(format, i, r))
+                    .collect();
+                if output.total_count > output.results.len() {
+                    out.push_str(&format!(
+                        "(showing {} of {} total matches)\n",
+                        output.results.len(),
+                        output.total_count
+                    ));
+                }
+                if let Some(trimmed) = trimmed {
+                    out.push_str(&format!(
+                        "(trimmed to fit max_response_chars: {}; \
+                         raise it or lower limit for more)\n",
+                        trimmed
+                    ));
+                }
+                if output.degraded {
+                    out.push_str(
+                        "(cut short to fit latency_budget_ms: fewer results, snippets or match \
+                         counts; raise it for complete results)\n",
+                    );
+                }
+                out.push_str(&format!(
+                    "(result set {}; pass within: \"{}\" to search only these files)\n",
+                    token, token
+                ));
+                out.push_str(&format!("(index generation {})\n", output.generation));
+                text_result(out)
+            }
+        }
+    }
+
+    #[tool(
+        description = "Run several keyword searches at once, concurrently and against the same index snapshot, and return the results of each under its query. \
+        Use it to fan out related queries (synonyms, alternative spellings, different angles on a question) in one call instead of one search call each. \
+        Queries use the same syntax as search; the filters apply to every query.",
+        annotations(title = "Search several queries", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn multi_search(
+        &self,
+        Parameters(req): Parameters<MultiSearchRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if req.queries.is_empty() || req.queries.len() > MAX_MULTI_QUERIES {
+            return Err(McpError::invalid_params(
+                format!("Pass between 1 and {} queries", MAX_MULTI_QUERIES),
+                None,
+            ));
+        }
+        let limit = req.limit.unwrap_or(5).min(MAX_RESULTS);
+        tracing::Span::current().record("query", req.queries.join(" | ").as_str());
+        let filters = SearchFilters {
+            file_type: req.file_type.clone(),
+            path_prefix: req.path_prefix.clone(),
+            min_score: req.min_score,
+            group_by_file: req.group_by_file.unwrap_or(false),
+            filter: parse_filter(&req.filter)?,
+            project: req.project.clone(),
+            exclude_generated: req.exclude_generated.unwrap_or(true),
+            ..SearchFilters::default()
+        };
+        let queries = req.queries.clone();
+        let outputs = self
+            .session_search()
+            .run(move |h| Ok(h.multi_search(&queries, limit, &filters)))
+            .await
+            .map_err(|e| index_error("Search failed", e))?;
+
+        let mut out = String::new();
+        let mut hits = 0;
+        let mut files = Vec::new();
+        for (query, output) in req.queries.iter().zip(outputs) {
+            let output = match output {
+                Ok(output) => output,
+                Err(e) => {
+                    out.push_str(&format!("## {}\nError: {}\n\n", query, e));
+                    continue;
+                }
+            };
+            hits += output.total_count;
+            self.log_query(
+                "multi_search",
+                query,
+                &[
+                    ("file_type", req.file_type.clone()),
+                    ("path_prefix", req.path_prefix.clone()),
+                    ("min_score", req.min_score.map(|s| s.to_string())),
+                    ("filter", req.filter.clone()),
+                    ("project", req.project.clone()),
+                    (
+                        "exclude_generated",
+                        req.exclude_generated.filter(|e| !*e).map(|e| e.to_string()),
+                    ),
+                ],
+                output.total_count,
+            );
+            out.push_str(&format!("## {} ({} matches)\n", query, output.total_count));
+            for (i, r) in output.results.iter().enumerate() {
+                out.push_str(&format!(
+                    "{}. {} (relevance: {:.2})\n   {}\n",
+                    i + 1,
+                    format::location(r),
+                    r.relevance,
+                    r.snippet
+                ));
+            }
+            if !output.results.is_empty() {
+                let token = self
+                    .result_sets
+                    .record(output.results.iter().map(|r| r.file_path.clone()));
+                out.push_str(&format!("(result set {})\n", token));
+            }
+            out.push('\n');
+            files.extend(output.results.into_iter().map(|r| r.file_path));
+        }
+        tracing::Span::current().record("hits", hits);
+        self.audit(AuditEntry {
+            query: Some(req.queries.join("\n")),
+            files,
+            ..AuditEntry::new("multi_search")
+        });
+        out.push_str(&format!("(index generation {})\n", self.search.generation()));
+        text_result(out)
+    }
+
+    #[tool(
+        description = "Describe the search query language: operators, phrases, and every searchable \
+        field with its aliases and an example.",
+        annotations(title = "Query syntax", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn query_syntax(&self) -> Result<CallToolResult, McpError> {
+        text_result(query::syntax())
+    }
+
+    #[tool(
+        description = "Add file or directory paths to the search index. Directories are indexed recursively. Files are watched for changes and automatically re-indexed.",
+        annotations(
+            title = "Index paths",
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false,
+        )
+    )]
+    async fn index_paths(
+        &self,
+        Parameters(req): Parameters<IndexPathsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let mode = if req.poll.unwrap_or(false) {
+            WatchMode::Poll
+        } else {
+            WatchMode::Native
+        };
+        let base_dir = self.base_dir.clone();
+        let requested = req.paths.clone();
+        let span = tracing::Span::current();
+        // Walks and commits run on the blocking pool so other sessions keep being served
+        let (total_indexed, errors, notes) = indexer::write_blocking(self.state.clone(), move |state| {
+            let _guard = span.enter();
+            // Per-request overrides of the server's hidden-file policy
+            let policy = (req.hidden.is_some() || req.skip_dirs.is_some()).then(|| {
+                let default = state.index.config().hidden.clone();
+                HiddenPolicy {
+                    hidden: req.hidden.unwrap_or(default.hidden),
+                    skip_dirs: req.skip_dirs.clone().unwrap_or(default.skip_dirs),
+                }
+            });
+            let watch = req.watch.unwrap_or(true);
+            let commit = req.commit.unwrap_or(true);
+            index_paths_locked(state, &req.paths, &base_dir, mode, watch, commit, policy)
+        })
+        .await
+        .map_err(|e| index_error("Indexing failed", e))?
+        .map_err(|e| index_error("Commit failed", e))?;
+        // The session sees what it asked for, also where another session indexed it first
+        for path in &requested {
+            if let Ok(root) = paths::expand(path, &self.base_dir).canonicalize() {
+                self.scope.add_root(root);
+            }
+        }
+
+        if total_indexed == 0 && !errors.is_empty() {
+            return Err(McpError::invalid_params(
+                format!("No paths could be indexed:\n{}", errors.join("\n")),
+                Some(serde_json::json!({ "errors": errors })),
+            ));
+        }
+
+        tracing::Span::current().record("hits", total_indexed);
+        let generation = self.search.generation();
+        let mut msg = format!("Indexed {} files (index generation {}).", total_indexed, generation);
+        for note in &notes {
+            msg.push_str(&format!("\n{}", note));
+        }
+        if !errors.is_empty() {
+            msg.push_str(&format!("\nErrors:\n{}", errors.join("\n")));
+        }
+        text_result(msg)
+    }
+
+    #[tool(
+        description = "Show current index status: number of indexed files, watched paths, and index location.",
+        annotations(title = "Index status", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn status(&self) -> Result<CallToolResult, McpError> {
+        let state = self.state.read().await;
+        let mut status = state.index.status();
+        if self.scope.is_isolated() {
+            status.missing_paths.retain(|path| self.scope.allows(path));
+            let (paths, counts): (Vec<_>, Vec<_>) = status
+                .watched_paths
+                .into_iter()
+                .zip(status.root_counts.into_iter().zip(status.root_skips))
+                .filter(|(path, _)| self.scope.allows(path))
+                .unzip();
+            (status.root_counts, status.root_skips) = counts.into_iter().unzip();
+            status.watched_paths = paths;
+            status.num_files = self.session_search().list_files(None, None).len();
+        }
+        let polled: Vec<String> = state
+            .watcher
+            .polled_paths()
+            .iter()
+            .filter(|p| self.scope.allows(p))
+            .map(|p| p.display().to_string())
+            .collect();
+        let mut msg = format!(
+            "Index Status:\n  Files indexed: {}\n  Watched paths: {}\n  Index location: {}\n  \
+             Polled paths: {}\n  Last indexed: {}\n  Last commit: {}\n  Pending events: {}\n  \
+             Events in last batch: {}\n  Dropped watcher events: {}\n  Index generation: {}",
+            status.num_files,
+            if status.watched_paths.is_empty() {
+                "(none)".to_string()
+            } else {
+                status
+                    .watched_paths
+                    .iter()
+                    .zip(&status.root_counts)
+                    .map(|(p, n)| match status.unwatched_paths.contains(p) {
+                        true => format!("{} ({} files, not watched)", p, n),
+                        false => format!("{} ({} files)", p, n),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+            status
+                .index_path
+                .unwrap_or_else(|| "in memory (not persisted)".to_string()),
+            if polled.is_empty() {
+                "(none)".to_string()
+            } else {
+                polled.join(", ")
+            },
+            format_age(status.last_indexed),
+            format_age(status.last_commit),
+            state.queue.pending(),
+            state.queue.last_batch(),
+            state.overflow.dropped_count(),
+            status.generation,
+        );
+        msg.push_str(&format!(
+            "\n  Segments: {} ({} deleted documents awaiting merge)\n  Index size: {}",
+            status.segments,
+            status.deleted_docs,
+            match status.disk_bytes {
+                Some(bytes) => format!("{:.1} MB", bytes as f64 / 1_000_000.0),
+                None => "in memory".to_string(),
+            }
+        ));
+        if let Some(migration) = &status.migration {
+            let progress = if migration.is_complete() { "done" } else { "in progress" };
+            msg.push_str(&format!(
+                "\n  Schema migration from v{}: {}, {}/{} roots and {} files re-indexed",
+                migration.from_version,
+                progress,
+                migration.done,
+                migration.roots.len(),
+                migration.files_indexed
+            ));
+        }
+        let skipped = status.root_skips.iter().fold(SkipStats::default(), |a, b| a.merge(*b));
+        if skipped.total() > 0 {
+            msg.push_str(&format!(
+                "\n  Skipped files: {} when the roots were last indexed ({})",
+                skipped.total(),
+                skipped
+            ));
+        }
+        let failed = state.index.failed_files();
+        let failed = failed.iter().filter(|f| self.scope.allows(&f.path)).count();
+        if failed > 0 {
+            msg.push_str(&format!("\n  Failed files: {} (list them with failed_files)", failed));
+        }
+        if !status.missing_paths.is_empty() {
+            let reattach = if self.reattach_roots {
+                "re-attached when they reappear"
+            } else {
+                "index them again to re-attach"
+            };
+            msg.push_str(&format!(
+                "\n  Missing roots: {} ({})",
+                status.missing_paths.join(", "),
+                reattach
+            ));
+        }
+        for hit in state.watcher.watch_limit_hits() {
+            msg.push_str(&format!("\n  Warning: {}", hit.message()));
+        }
+        text_result(msg)
+    }
+
+    #[tool(
+        description = "Check that the server works: the index opens, a query runs, the file watcher's event processor \
+        is running, its event channel is not saturated and the index's disk has free space. Returns pass/fail per check \
+        as text and as JSON ({ok, checks: [{name, ok, detail}]}).",
+        annotations(title = "Health check", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn health(&self) -> Result<CallToolResult, McpError> {
+        let (index_path, watcher, channel) = {
+            let state = self.state.read().await;
+            let watcher = if state.watcher.is_running() {
+                Check::pass("watcher", "event processor running")
+            } else {
+                Check::fail("watcher", "event processor stopped; file changes are not indexed")
+            };
+            let (used, capacity) = state.watcher.channel_usage();
+            (state.index.status().index_path, watcher, health::event_channel(used, capacity))
+        };
+        // Opening the index, the query and statvfs touch the disk, so run on the blocking pool
+        let checks = self
+            .search
+            .run(move |search| {
+                let index_path = index_path.as_deref().map(std::path::Path::new);
+                let opens = match index_path {
+                    Some(path) => health::index_opens(path),
+                    None => Check::pass("index_opens", "in memory"),
+                };
+                let started = std::time::Instant::now();
+                let query = match search.search("health", 1, None, None) {
+                    Ok(_) => {
+                        let ms = started.elapsed().as_millis();
+                        Check::pass("query", format!("ran in {}ms", ms))
+                    }
+                    Err(e) => Check::fail("query", e.to_string()),
+                };
+                let disk = match index_path {
+                    Some(path) => health::disk_space(path),
+                    None => Check::pass("disk_space", "in memory"),
+                };
+                Ok(vec![opens, query, watcher, channel, disk])
+            })
+            .await
+            .map_err(|e| index_error("Health check failed", e))?;
+
+        let report = HealthReport::new(checks);
+        Ok(CallToolResult::success(vec![
+            Content::text(report.summary()),
+            Content::json(&report)?,
+        ]))
+    }
+
+    #[tool(
+        description = "Cross-check the index against the filesystem: indexed files that no longer exist, files whose \
+        content changed without being re-indexed, and orphaned documents (in the index but unknown to read_file and \
+        list_files, e.g. left by an earlier session). Pass repair: true to fix them in place instead of rebuilding the index.",
+        annotations(
+            title = "Verify index",
+            destructive_hint = true,
+            idempotent_hint = true,
+            open_world_hint = false,
+        )
+    )]
+    async fn verify_index(
+        &self,
+        Parameters(req): Parameters<VerifyIndexRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let repair = req.repair.unwrap_or(false);
+        let (report, repaired) = indexer::write_blocking(self.state.clone(), move |state| {
+            // Verify against the current files, not a commit that lags behind them
+            if state.index.has_uncommitted_changes() {
+                state.index.commit()?;
+            }
+            let report = state.index.verify()?;
+            let repaired = if repair && !report.is_clean() {
+                let repaired = state.index.repair(&report)?;
+                state.index.commit()?;
+                Some(repaired)
+            } else {
+                None
+            };
+            anyhow::Ok((report, repaired))
+        })
+        .await
+        .map_err(|e| index_error("Verification failed", e))?
+        .map_err(|e| index_error("Verification failed", e))?;
+
+        let mut msg = format!("Checked {} indexed paths.", report.checked);
+        if report.is_clean() {
+            msg.push_str(" The index matches the filesystem.");
+        }
+        for (label, paths) in [
+            ("Missing", &report.missing),
+            ("Changed", &report.changed),
+            ("Orphaned", &report.orphaned),
+        ] {
+            let paths: Vec<_> = paths.iter().filter(|p| self.scope.allows(p)).collect();
+            if paths.is_empty() {
+                continue;
+            }
+            msg.push_str(&format!("\n{} ({}):", label, paths.len()));
+            for path in paths.iter().take(MAX_VERIFY_PATHS) {
+                msg.push_str(&format!("\n  {}", path.display()));
+            }
+            if paths.len() > MAX_VERIFY_PATHS {
+                msg.push_str(&format!("\n  ... and {} more", paths.len() - MAX_VERIFY_PATHS));
+            }
+        }
+        match repaired {
+            Some(n) => msg.push_str(&format!(
+                "\nRepaired {} paths (index generation {}).",
+                n,
+                self.search.generation()
+            )),
+            None if !report.is_clean() => msg.push_str("\nPass repair: true to fix these."),
+            None => {}
+        }
+        text_result(msg)
+    }
+
+    #[tool(
+        description = "Wait until the index reaches a generation, so searches see files indexed by that commit. \
+        search, index_paths and status report the current generation; after changing files, wait for the \
+        current generation + 1 to see them once the file watcher has re-indexed them.",
+        annotations(title = "Wait for index", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn wait_for_index(
+        &self,
+        Parameters(req): Parameters<WaitForIndexRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let timeout = std::time::Duration::from_millis(req.timeout_ms.unwrap_or(10_000).min(MAX_WAIT_MS));
+        if self.search.wait_for_generation(req.generation, timeout).await {
+            text_result(format!("Index is at generation {}.", self.search.generation()))
+        } else {
+            Err(McpError::internal_error(
+                format!(
+                    "Timed out after {}ms waiting for generation {}; the index is at generation {}",
+                    timeout.as_millis(),
+                    req.generation,
+                    self.search.generation()
+                ),
+                Some(serde_json::json!({
+                    "generation": self.search.generation(),
+                    "requested_generation": req.generation,
+                })),
+            ))
+        }
+    }
+
+    #[tool(
+        description = "Read the full contents of an indexed file by its path. Only files that have been indexed via index_paths can be read.",
+        annotations(title = "Read file", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn read_file(
+        &self,
+        Parameters(req): Parameters<ReadFileRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let resolved = paths::expand(&req.path, &self.base_dir);
+        let format = parse_format(&req.format)?;
+        let path = resolved.to_string_lossy().to_string();
+        // Reads of search results count towards usage ranking
+        let searched = self.result_sets.contains(&path);
+        let read = self
+            .session_search()
+            .run(move |h| {
+                let content = h.read_file(&path)?;
+                if searched {
+                    h.record_read(&path);
+                }
+                Ok(content)
+            })
+            .await;
+        match read {
+            Ok(content) => {
+                self.audit(AuditEntry {
+                    path: Some(resolved.display().to_string()),
+                    files: vec![resolved.display().to_string()],
+                    ..AuditEntry::new("read_file")
+                });
+                let (shown, omitted) = match req.max_response_chars {
+                    Some(max) => budget::truncate_text(
+                        &content,
+                        max.max(budget::MIN_RESPONSE_CHARS) - budget::NOTES_CHARS,
+                    ),
+                    None => (content.as_str(), 0),
+                };
+                let lines = content.lines().count();
+                let note = (omitted > 0).then(|| {
+                    format!(
+                        "(showing lines 1-{} of {}; {} characters omitted to fit \
+                         max_response_chars)",
+                        shown.lines().count(),
+                        lines,
+                        omitted
+                    )
+                });
+                let resolved = resolved.display().to_string();
+                match (format, note) {
+                    (OutputFormat::Json, _) => json_result(format::without_nulls(serde_json::json!({
+                        "path": resolved,
+                        "content": shown,
+                        "lines": lines,
+                        "omitted_chars": (omitted > 0).then_some(omitted),
+                    }))),
+                    (OutputFormat::Markdown, note) => text_result(format!(
+                        "**{}**\n\n{}{}",
+                        resolved,
+                        format::code_fence(shown, &resolved),
+                        note.map(|note| format!("\n{}\n", note)).unwrap_or_default()
+                    )),
+                    (OutputFormat::Text, None) => text_result(content),
+                    (OutputFormat::Text, Some(note)) => {
+                        text_result(format!("{}\n{}", shown.trim_end_matches('\n'), note))
+                    }
+                }
+            }
+            Err(e) => Err(McpError::resource_not_found(
+                format!("Error reading file: {}", e),
+                Some(serde_json::json!({
+                    "path": req.path,
+                    "resolved_path": paths::shown(&req.path, &resolved),
+                })),
+            )),
+        }
+    }
+
+    #[tool(
+        description = "Size, modification time, extension, line count, indexing time and content hash of an indexed \
+        file, and whether it changed on disk since it was indexed, without its content. Use it to decide whether a \
+        file is worth reading.",
+        annotations(title = "File info", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn file_info(
+        &self,
+        Parameters(req): Parameters<FileInfoRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let resolved = paths::expand(&req.path, &self.base_dir);
+        let path = resolved.to_string_lossy().to_string();
+        let info = self.session_search().run(move |h| h.file_info(&path)).await.map_err(|e| {
+            McpError::resource_not_found(
+                format!("Error reading file info: {}", e),
+                Some(serde_json::json!({
+                    "path": req.path,
+                    "resolved_path": paths::shown(&req.path, &resolved),
+                })),
+            )
+        })?;
+        self.audit(AuditEntry {
+            path: Some(info.path.display().to_string()),
+            files: vec![info.path.display().to_string()],
+            ..AuditEntry::new("file_info")
+        });
 
-#[tool_router]
-impl FileSearchServer {
-    pub fn new(state: AppState) -> Self {
-        Self {
-            state,
-            tool_router: Self::tool_router(),
+        let secs = |t: std::time::SystemTime| {
+            t.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+        };
+        let mut msg = format!("{}\n", info.path.display());
+        match info.size {
+            Some(size) => msg.push_str(&format!("Size: {} bytes\n", size)),
+            None => msg.push_str("Size: unknown, the file no longer exists\n"),
+        }
+        if let Some(modified) = info.modified {
+            msg.push_str(&format!(
+                "Modified: {} ({})\n",
+                logs::format_date(secs(modified)),
+                format_age(Some(modified))
+            ));
+        }
+        msg.push_str(&format!("Extension: {}\n", info.extension.as_deref().unwrap_or("none")));
+        match info.lines {
+            Some(lines) => msg.push_str(&format!("Lines: {}\n", lines)),
+            None => msg.push_str("Lines: unknown\n"),
+        }
+        if let Some(indexed_at) = info.indexed_at {
+            msg.push_str(&format!(
+                "Indexed: {} ({})\n",
+                logs::format_date(secs(indexed_at)),
+                format_age(Some(indexed_at))
+            ));
         }
+        msg.push_str(&format!(
+            "Content hash: {}\n",
+            info.content_hash.as_deref().map_or("unknown".to_string(), |h| format!("blake3:{}", h))
+        ));
+        msg.push_str(match info.changed {
+            Some(true) => "Changed since indexing: yes, search results may be out of date",
+            Some(false) => "Changed since indexing: no",
+            None => "Changed since indexing: unknown",
+        });
+        text_result(msg)
     }
 
     #[tool(
-        description = "Search indexed files by keyword. Returns matching file paths, snippets, and relevance scores. \
-        Performs full-text search with relevance ranking across all indexed files. \
-        Supports natural language queries and boolean operators (AND, OR, NOT). \
-        Supports field-based queries: extension:rs, directory:config, content:error. \
-        Combine with boolean operators: extension:yaml AND database. \
-        Prefer this over grep/find for broad keyword searches across large codebases."
+        description = "Read several indexed files in one call, e.g. the top results of a search. Each file comes \
+        under a `==> path <==` header with its content or the reason it could not be read. Takes at most 20 paths \
+        and returns at most 256 KB in total; files past that are listed as skipped, to read separately.",
+        annotations(title = "Read files", read_only_hint = true, open_world_hint = false)
     )]
-    async fn search(&self, Parameters(req): Parameters<SearchRequest>) -> String {
-        let limit = req.limit.unwrap_or(10);
-        let state = self.state.read().await;
-        match state.index.search(
-            &req.query,
-            limit,
-            req.file_type.as_deref(),
-            req.path_prefix.as_deref(),
-        ) {
-            Err(e) => format!("Search error: {}", e),
-            Ok(output) if output.results.is_empty() => "No results found.".to_string(),
-            Ok(output) => {
-                let mut out = String::new();
-                for (i, r) in output.results.iter().enumerate() {
-                    let path_display = match r.line_number {
-                        Some(ln) => format!("{}:{}", r.file_path, ln),
-                        None => r.file_path.clone(),
-                    };
+    async fn read_files(
+        &self,
+        Parameters(req): Parameters<ReadFilesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if req.paths.is_empty() || req.paths.len() > MAX_READ_FILES {
+            return Err(McpError::invalid_params(
+                format!("Pass between 1 and {} paths, got {}", MAX_READ_FILES, req.paths.len()),
+                Some(serde_json::json!({ "max": MAX_READ_FILES, "got": req.paths.len() })),
+            ));
+        }
+        let resolved: Vec<String> = req
+            .paths
+            .iter()
+            .map(|p| paths::expand(p, &self.base_dir).to_string_lossy().to_string())
+            .collect();
+        let requested: Vec<(String, bool)> =
+            resolved.iter().map(|p| (p.clone(), self.result_sets.contains(p))).collect();
+        let contents = self
+            .session_search()
+            .run(move |h| {
+                Ok(requested
+                    .iter()
+                    .map(|(path, searched)| {
+                        let content = h.read_file(path)?;
+                        if *searched {
+                            h.record_read(path);
+                        }
+                        Ok(content)
+                    })
+                    .collect::<Vec<anyhow::Result<String>>>())
+            })
+            .await
+            .map_err(|e| McpError::internal_error(format!("Read task failed: {}", e), None))?;
+
+        let mut out = String::new();
+        let mut total = 0;
+        let mut read = Vec::new();
+        for (path, content) in resolved.iter().zip(contents) {
+            match content {
+                Ok(content) if total + content.len() > MAX_READ_FILES_BYTES => {
                     out.push_str(&format!(
-                        "{}. {} (score: {:.2})\n   Path: {}\n   Snippet: {}\n\n",
-                        i + 1,
-                        r.file_name,
-                        r.score,
-                        path_display,
-                        r.snippet
+                        "==> {} (skipped) <==\nOver the {} KB limit of one call ({} bytes); \
+                         read it separately\n\n",
+                        path,
+                        MAX_READ_FILES_BYTES / 1024,
+                        content.len()
                     ));
                 }
-                if output.total_count > output.results.len() {
-                    out.push_str(&format!(
-                        "(showing {} of {} total matches)\n",
-                        output.results.len(),
-                        output.total_count
-                    ));
+                Ok(content) => {
+                    total += content.len();
+                    out.push_str(&format!("==> {} <==\n{}", path, content));
+                    if !content.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    out.push('\n');
+                    read.push(path.clone());
+                }
+                Err(e) => {
+                    out.push_str(&format!("==> {} (error) <==\nError reading file: {}\n\n", path, e))
                 }
-                out
             }
         }
+        if !read.is_empty() {
+            self.audit(AuditEntry {
+                files: read,
+                ..AuditEntry::new("read_files")
+            });
+        }
+        text_result(out.trim_end().to_string())
     }
 
     #[tool(
-        description = "Add file or directory paths to the search index. Directories are indexed recursively. Files are watched for changes and automatically re-indexed."
+        description = "Create a file, or replace one with overwrite: true, below an indexed directory, and index it \
+        before returning so it is immediately searchable. Only files of indexed types outside excluded directories \
+        can be written. Available when the server runs with LOCALFILES_WRITE_TOOLS=1.",
+        annotations(
+            title = "Write file",
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
     )]
-    async fn index_paths(&self, Parameters(req): Parameters<IndexPathsRequest>) -> String {
-        let mut state = self.state.write().await;
-        let mut total_indexed = 0u64;
-        let mut errors = Vec::new();
+    async fn write_file(
+        &self,
+        Parameters(req): Parameters<WriteFileRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let mode = if req.overwrite.unwrap_or(false) {
+            WriteMode::Overwrite
+        } else {
+            WriteMode::Create
+        };
+        self.write_indexed("write_file", &req.path, req.content, mode).await
+    }
 
-        for path_str in &req.paths {
-            let path = std::path::Path::new(path_str);
-            if !path.exists() {
-                errors.push(format!("Path does not exist: {}", path_str));
-                continue;
+    #[tool(
+        description = "Append text to the end of a file below an indexed directory (creating it if missing), e.g. \
+        to add an entry to a log or notes file, and index it before returning. Available when the server runs with \
+        LOCALFILES_WRITE_TOOLS=1.",
+        annotations(
+            title = "Append to file",
+            read_only_hint = false,
+            destructive_hint = false,
+            open_world_hint = false
+        )
+    )]
+    async fn append_file(
+        &self,
+        Parameters(req): Parameters<AppendFileRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        self.write_indexed("append_file", &req.path, req.content, WriteMode::Append).await
+    }
+
+    #[tool(
+        description = "Move an indexed file to the OS trash and remove it from the index, e.g. to clean up files \
+        generated during a task. The file can be restored from the trash. Only files already in the index can be \
+        deleted, not directories. Available when the server runs with LOCALFILES_DELETE_TOOL=1.",
+        annotations(
+            title = "Delete file",
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = false,
+            open_world_hint = false
+        )
+    )]
+    async fn delete_file(
+        &self,
+        Parameters(req): Parameters<DeleteFileRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let requested = paths::expand(&req.path, &self.base_dir);
+        let invalid = |e: anyhow::Error| {
+            McpError::invalid_params(
+                e.to_string(),
+                Some(serde_json::json!({
+                    "path": req.path,
+                    "resolved_path": paths::shown(&req.path, &requested),
+                })),
+            )
+        };
+        let target = requested
+            .canonicalize()
+            .map_err(|e| invalid(anyhow::anyhow!("Cannot delete {}: {}", requested.display(), e)))?;
+        if !self.scope.allows(&target) {
+            return Err(invalid(anyhow::anyhow!(
+                "{} is outside the roots this session indexed",
+                target.display()
+            )));
+        }
+        let deleted = target.clone();
+        let trash_dir = self.trash_dir.clone();
+        let generation = indexer::write_blocking(self.state.clone(), move |state| {
+            if !state.index.is_indexed(&deleted) {
+                anyhow::bail!("{} is not an indexed file", deleted.display());
             }
-            if path.is_dir() {
-                match state.index.index_directory(path) {
-                    Ok(count) => total_indexed += count,
-                    Err(e) => errors.push(format!("Error indexing {}: {}", path_str, e)),
-                }
-            } else {
-                match state.index.index_file(path) {
-                    Ok(()) => total_indexed += 1,
-                    Err(e) => errors.push(format!("Error indexing {}: {}", path_str, e)),
+            write::trash(&deleted, trash_dir.as_deref())?;
+            state.index.remove_file(&deleted)?;
+            state.index.commit()?;
+            Ok(state.index.generation())
+        })
+        .await
+        .map_err(|e| index_error("Deleting failed", e))?
+        .map_err(invalid)?;
+        self.audit(AuditEntry {
+            path: Some(target.display().to_string()),
+            files: vec![target.display().to_string()],
+            ..AuditEntry::new("delete_file")
+        });
+        text_result(format!(
+            "Moved {} to the trash (removed from the index, index generation {}).",
+            target.display(),
+            generation
+        ))
+    }
+
+    #[tool(
+        description = "Delete everything stored about indexed files: the index, its roots, pins and read counts, \
+        cached OCR output and the audit logs, overwriting the files before deleting them, and forget the roots and \
+        saved searches. The server keeps running with an empty index. Requires confirm: true, and \
+        all_sessions: true in a daemon shared by several client sessions. Available when the server runs with \
+        LOCALFILES_PURGE_TOOL=1.",
+        annotations(
+            title = "Purge index",
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn purge_index(
+        &self,
+        Parameters(req): Parameters<PurgeIndexRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if !req.confirm {
+            return Err(McpError::invalid_params("Pass confirm: true to purge the index", None));
+        }
+        if self.shared_index && req.all_sessions != Some(true) {
+            return Err(McpError::invalid_params(
+                "Other client sessions share this index; pass all_sessions: true to purge it for \
+                 all of them",
+                None,
+            ));
+        }
+        let (mut scrubbed, generation) = indexer::write_blocking(self.state.clone(), |state| {
+            let watched: Vec<_> = state.index.roots().into_iter().filter(|r| r.watched).collect();
+            // A purge that fails leaves the roots watched and the saved searches in place
+            let scrubbed = state.index.purge()?;
+            for root in watched {
+                if let Err(e) = state.watcher.unwatch(&root.path) {
+                    tracing::warn!("Failed to unwatch {}: {}", root.path.display(), e);
                 }
             }
-            // Register with file watcher
-            if let Err(e) = watcher::watch_path(&mut state.watcher, path) {
-                errors.push(format!("Error watching {}: {}", path_str, e));
+            state.saved = SavedSearches::default();
+            Ok((scrubbed, state.index.generation()))
+        })
+        .await
+        .map_err(|e| index_error("Purging failed", e))?
+        .map_err(|e| index_error("Purging failed", e))?;
+        if let Some(audit) = &self.audit {
+            let logs = audit.purge().map_err(|e| index_error("Purging the audit log failed", e))?;
+            scrubbed.extend(logs);
+        }
+        // Every session's history names purged files
+        let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        for (_, queries, result_sets) in sessions {
+            if let Some(queries) = queries.upgrade() {
+                queries.clear();
+            }
+            if let Some(result_sets) = result_sets.upgrade() {
+                result_sets.clear();
             }
         }
+        tracing::warn!("Purged the index ({} paths scrubbed)", scrubbed.len());
+        self.audit(AuditEntry::new("purge_index"));
 
-        // Commit all changes at once
-        if let Err(e) = state.index.commit() {
-            errors.push(format!("Commit failed: {}", e));
+        let mut msg = format!(
+            "Purged the index (index generation {}): it is empty and has no roots. Scrubbed {} \
+             paths:",
+            generation,
+            scrubbed.len()
+        );
+        for path in &scrubbed {
+            msg.push_str(&format!("\n- {}", path.display()));
         }
+        text_result(msg)
+    }
 
-        let mut msg = format!("Indexed {} files.", total_indexed);
-        if !errors.is_empty() {
-            msg.push_str(&format!("\nErrors:\n{}", errors.join("\n")));
+    #[tool(
+        description = "Show the structure of an indexed file with line numbers: markdown headings, or \
+        functions, types, classes and impls for code. Use it to navigate a large file, then read the \
+        relevant part instead of the whole file.",
+        annotations(title = "File outline", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn outline(
+        &self,
+        Parameters(req): Parameters<OutlineRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let resolved = paths::expand(&req.path, &self.base_dir);
+        let error_data = Some(serde_json::json!({
+            "path": req.path,
+            "resolved_path": paths::shown(&req.path, &resolved),
+        }));
+        let path = resolved.clone();
+        let path_shown = resolved.display().to_string();
+        let content = self
+            .session_search()
+            .run(move |h| h.read_file(&path.to_string_lossy()))
+            .await
+            .map_err(|e| {
+                McpError::resource_not_found(format!("Error reading file: {}", e), error_data.clone())
+            })?;
+        // Parsing large files with tree-sitter is CPU-bound
+        let entries = tokio::task::spawn_blocking(move || outline::outline(&resolved, &content))
+            .await
+            .map_err(|e| McpError::internal_error(format!("Outline task failed: {}", e), None))?
+            .map_err(|e| McpError::invalid_params(e.to_string(), error_data))?;
+        self.audit(AuditEntry {
+            path: Some(path_shown.clone()),
+            files: vec![path_shown],
+            ..AuditEntry::new("outline")
+        });
+        if entries.is_empty() {
+            return text_result(format!("No headings or definitions found in {}.", req.path));
         }
-        msg
+        let out = entries
+            .iter()
+            .map(|e| format!("{}{}: {} {}", "  ".repeat(e.depth), e.line, e.kind, e.name))
+            .collect::<Vec<_>>()
+            .join("\n");
+        text_result(out)
     }
 
     #[tool(
-        description = "Show current index status: number of indexed files, watched paths, and index location."
+        description = "Find indexed files whose content is most similar to a given file (by path) or to a piece of text. \
+        Useful for finding duplicated logic, or the test file that corresponds to a source file.",
+        annotations(title = "Similar files", read_only_hint = true, open_world_hint = false)
     )]
-    async fn status(&self) -> String {
-        let state = self.state.read().await;
-        let status = state.index.status();
-        format!(
-            "Index Status:\n  Files indexed: {}\n  Watched paths: {}\n  Index location: {}",
-            status.num_files,
-            if status.watched_paths.is_empty() {
-                "(none)".to_string()
+    async fn similar_files(
+        &self,
+        Parameters(req): Parameters<SimilarFilesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let resolved = req
+            .path
+            .as_deref()
+            .map(|p| paths::expand(p, &self.base_dir).to_string_lossy().to_string());
+        let shown = req.path.as_deref().zip(resolved.as_deref());
+        let shown = shown.map(|(path, resolved)| paths::shown(path, resolved.as_ref()));
+        if resolved.is_none() && req.text.as_deref().is_none_or(|t| t.trim().is_empty()) {
+            return Err(McpError::invalid_params("Provide either 'path' or 'text'", None));
+        }
+        let (path, text, limit) = (resolved.clone(), req.text.clone(), req.limit.unwrap_or(10).min(MAX_RESULTS));
+        let similar = self
+            .session_search()
+            .run(move |h| h.similar_files(path.as_deref(), text.as_deref(), limit))
+            .await
+            .map_err(|e| {
+                McpError::invalid_params(
+                    format!("Similar files lookup failed: {}", e),
+                    Some(serde_json::json!({ "path": req.path, "resolved_path": shown })),
+                )
+            })?;
+        tracing::Span::current().record("hits", similar.len());
+        self.audit(AuditEntry {
+            path: resolved,
+            query: req.text.clone(),
+            files: similar.iter().map(|f| f.file_path.clone()).collect(),
+            ..AuditEntry::new("similar_files")
+        });
+        if similar.is_empty() {
+            return text_result("No similar files found.");
+        }
+        let out = similar
+            .iter()
+            .enumerate()
+            .map(|(i, f)| format!("{}. {} (score: {:.2})", i + 1, f.file_path, f.score))
+            .collect::<Vec<_>>()
+            .join("\n");
+        text_result(out)
+    }
+
+    #[tool(
+        description = "List the most frequent terms across indexed files, by number of files containing each. \
+        Narrow to a directory subtree with path_prefix, or pass a keyword to get the terms that co-occur with it. \
+        Useful for getting oriented in an unfamiliar codebase or note collection.",
+        annotations(title = "Analyze terms", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn analyze_terms(
+        &self,
+        Parameters(req): Parameters<AnalyzeTermsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Some(keyword) = &req.keyword {
+            tracing::Span::current().record("query", keyword.as_str());
+        }
+        let (keyword, path_prefix, limit) =
+            (req.keyword.clone(), req.path_prefix.clone(), req.limit.unwrap_or(30).min(MAX_RESULTS));
+        let stats = self
+            .session_search()
+            .run(move |h| h.analyze_terms(keyword.as_deref(), path_prefix.as_deref(), limit))
+            .await
+            .map_err(|e| index_error("Term analysis failed", e))?;
+        if stats.terms.is_empty() {
+            return text_result("No terms found for the given filters.");
+        }
+        // Dictionary counts include split files' chunks and deleted documents
+        let unit = if stats.approximate { "documents" } else { "files" };
+        let mut out = format!(
+            "Top terms across {} {}{}:\n",
+            stats.documents,
+            unit,
+            match (stats.sampled, stats.approximate) {
+                (true, _) => " (sampled)",
+                (false, true) => " (approximate)",
+                (false, false) => "",
+            }
+        );
+        for (term, count) in &stats.terms {
+            out.push_str(&format!("  {} ({} {})\n", term, count, unit));
+        }
+        text_result(out)
+    }
+
+    #[tool(
+        description = "Complete the last word of a partially typed search query from terms in the index, \
+        most frequent first. A field prefix such as tag: or extension: completes values of that field.",
+        annotations(title = "Suggest queries", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn suggest(
+        &self,
+        Parameters(req): Parameters<SuggestRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        tracing::Span::current().record("query", req.query.as_str());
+        let (query, limit) = (req.query.clone(), req.limit.unwrap_or(10).min(MAX_RESULTS));
+        let suggestions = self
+            .session_search()
+            .run(move |h| h.suggest(&query, limit))
+            .await
+            .map_err(|e| {
+                McpError::invalid_params(format!("Suggest failed: {}", e), None)
+            })?;
+        tracing::Span::current().record("hits", suggestions.len());
+        if suggestions.is_empty() {
+            return text_result("No suggestions.");
+        }
+        let out = suggestions
+            .iter()
+            .map(|(query, files)| format!("{} ({} files)", query, files))
+            .collect::<Vec<_>>()
+            .join("\n");
+        text_result(out)
+    }
+
+    #[tool(
+        description = "Save a search query to watch. When re-indexing after file changes makes files newly match it, \
+        they are recorded; collect them with check_saved_searches. E.g. name \"oom\", query \"OutOfMemory\", file_type \"log\".",
+        annotations(
+            title = "Save search",
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false,
+        )
+    )]
+    async fn save_search(
+        &self,
+        Parameters(req): Parameters<SaveSearchRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if req.remove.unwrap_or(false) {
+            let mut state = self.state.write().await;
+            return if state.saved.remove(self.session_id, &req.name) {
+                text_result(format!("Removed saved search '{}'.", req.name))
             } else {
-                status.watched_paths.join(", ")
-            },
-            status.index_path,
+                Err(McpError::invalid_params(
+                    format!("No saved search named '{}'", req.name),
+                    None,
+                ))
+            };
+        }
+        tracing::Span::current().record("query", req.query.as_str());
+        // Runs the query once to record current matches
+        let search = SavedQuery {
+            query: req.query.clone(),
+            file_type: req.file_type.clone(),
+            path_prefix: req.path_prefix.clone(),
+        };
+        let saved = search.clone();
+        let matches = self
+            .session_search()
+            .run(move |h| saved.matches(h))
+            .await
+            .map_err(|e| index_error("Saving search failed", e))?;
+        let live = self.live_sessions();
+        let match_count = {
+            let mut state = self.state.write().await;
+            // Searches of disconnected sessions are never collected again
+            state.saved.retain_sessions(|id| live.contains(&id));
+            state.saved.save(self.session_id, &req.name, search, matches).match_count()
+        };
+        text_result(format!(
+            "Saved search '{}' ({} files match now). New matches will be reported by check_saved_searches.",
+            req.name, match_count
+        ))
+    }
+
+    #[tool(
+        description = "Report files that newly matched saved searches since the last check, and clear them.",
+        annotations(
+            title = "Check saved searches",
+            destructive_hint = false,
+            idempotent_hint = false,
+            open_world_hint = false,
         )
+    )]
+    async fn check_saved_searches(
+        &self,
+        Parameters(req): Parameters<CheckSavedSearchesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut state = self.state.write().await;
+        if state.saved.list(self.session_id).next().is_none() {
+            return text_result("No saved searches. Add one with save_search.");
+        }
+        let taken = state.saved.take_new_matches(self.session_id, req.name.as_deref());
+        if taken.is_empty() {
+            return Err(McpError::invalid_params(
+                format!("No saved search named '{}'", req.name.unwrap_or_default()),
+                None,
+            ));
+        }
+        let mut out = String::new();
+        for (name, mut matches) in taken {
+            matches.retain(|m| self.scope.allows(&m.file_path));
+            if matches.is_empty() {
+                out.push_str(&format!("{}: no new matches\n", name));
+                continue;
+            }
+            out.push_str(&format!("{}: {} new matches\n", name, matches.len()));
+            for m in matches {
+                out.push_str(&format!("  {} (found {})\n", m.file_path, format_age(Some(m.found_at))));
+            }
+        }
+        text_result(out)
     }
 
     #[tool(
-        description = "Read the full contents of an indexed file by its path. Only files that have been indexed via index_paths can be read."
+        description = "Pin an indexed file so its matches rank above others in every search, and so search with \
+        pinned_only: true finds it. Use it for the handful of documents (specs, architecture notes, style guides) that \
+        should be preferred. Pins persist across restarts. Lists the pinned files.",
+        annotations(
+            title = "Pin file",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
     )]
-    async fn read_file(&self, Parameters(req): Parameters<ReadFileRequest>) -> String {
-        let state = self.state.read().await;
-        match state.index.read_file(&req.path) {
-            Ok(content) => content,
-            Err(e) => format!("Error reading file: {}", e),
+    async fn pin_file(
+        &self,
+        Parameters(req): Parameters<PinFileRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let requested = paths::expand(&req.path, &self.base_dir);
+        let path = requested.canonicalize().unwrap_or_else(|_| requested.clone());
+        if !self.scope.allows(&path) {
+            return Err(McpError::invalid_params(
+                format!("{} is outside the roots this session indexed", path.display()),
+                None,
+            ));
         }
+        let scope = self.scope.clone();
+        let (pinned, all) = indexer::write_blocking(self.state.clone(), move |state| {
+            let pinned = state.index.pin(&path)?;
+            state.index.commit()?;
+            let all = pinned_list(&state.index, &scope);
+            Ok((pinned, all))
+        })
+        .await
+        .map_err(|e| index_error("Pinning failed", e))?
+        .map_err(|e: anyhow::Error| {
+            McpError::invalid_params(
+                e.to_string(),
+                Some(serde_json::json!({
+                    "path": req.path,
+                    "resolved_path": paths::shown(&req.path, &requested),
+                })),
+            )
+        })?;
+        text_result(format!("Pinned {}.\n\n{}", pinned.display(), all))
     }
 
     #[tool(
-        description = "List all indexed file paths, optionally filtered by file extension or path prefix."
+        description = "Unpin a file pinned with pin_file, so it ranks like any other. Lists the files still pinned.",
+        annotations(
+            title = "Unpin file",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
     )]
-    async fn list_files(&self, Parameters(req): Parameters<ListFilesRequest>) -> String {
-        let state = self.state.read().await;
-        let files = state.index.list_files(
+    async fn unpin_file(
+        &self,
+        Parameters(req): Parameters<UnpinFileRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let requested = paths::expand(&req.path, &self.base_dir);
+        // A pinned file may be gone from disk
+        let path = requested.canonicalize().unwrap_or(requested);
+        let scope = self.scope.clone();
+        let unpinned = path.clone();
+        let (removed, all) = indexer::write_blocking(self.state.clone(), move |state| {
+            let removed = scope.allows(&unpinned) && state.index.unpin(&unpinned);
+            if removed {
+                state.index.commit()?;
+            }
+            Ok((removed, pinned_list(&state.index, &scope)))
+        })
+        .await
+        .map_err(|e| index_error("Unpinning failed", e))?
+        .map_err(|e| index_error("Unpinning failed", e))?;
+        if !removed {
+            return Err(McpError::invalid_params(
+                format!("{} is not pinned", path.display()),
+                None,
+            ));
+        }
+        text_result(format!("Unpinned {}.\n\n{}", path.display(), all))
+    }
+
+    #[tool(
+        description = "List the searches run in this session, newest first, with their filters, hit counts and times. \
+        Check it to avoid repeating a query.",
+        annotations(title = "Recent searches", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn recent_searches(
+        &self,
+        Parameters(req): Parameters<RecentSearchesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let recent = self.queries.recent(req.limit.unwrap_or(20));
+        if recent.is_empty() {
+            return text_result("No searches in this session yet.");
+        }
+        let out = recent
+            .iter()
+            .map(|q| {
+                let filters = q
+                    .filters
+                    .iter()
+                    .map(|(name, value)| format!(" {}={}", name, value))
+                    .collect::<String>();
+                format!(
+                    "{} ({}) \"{}\"{}: {} hits",
+                    format_age(Some(q.at)),
+                    q.tool,
+                    q.query,
+                    filters,
+                    q.hits
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        text_result(out)
+    }
+
+    #[tool(
+        description = "List the most recently modified indexed files, newest first, with their modification times. \
+        Optionally filtered by file extension, path components and a since time, e.g. since: today's date for the \
+        files touched today.",
+        annotations(title = "Recent files", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn recent_files(
+        &self,
+        Parameters(req): Parameters<RecentFilesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = req.limit.unwrap_or(20).clamp(1, MAX_RESULTS);
+        let since = req
+            .since
+            .as_deref()
+            .map(logs::parse_time_bound)
+            .transpose()
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        let filters = SearchFilters {
+            file_type: req.file_type.clone(),
+            path_prefix: req.path_prefix.clone(),
+            filter: parse_filter(&req.filter)?,
+            ..SearchFilters::default()
+        };
+        let format = parse_format(&req.format)?;
+        let recent = self
+            .session_search()
+            .run(move |h| h.recent_files(limit, since, &filters))
+            .await
+            .map_err(|e| index_error("Listing recent files failed", e))?;
+        self.audit(AuditEntry {
+            path: req.path_prefix.clone(),
+            query: req.file_type.as_ref().map(|t| format!("file_type={}", t)),
+            ..AuditEntry::new("recent_files")
+        });
+        let modified = |f: &indexer::RecentFile| format_time(f.modified);
+        if format == OutputFormat::Json {
+            return json_result(serde_json::Value::Array(
+                recent
+                    .iter()
+                    .map(|f| serde_json::json!({ "path": f.file_path, "modified": modified(f) }))
+                    .collect(),
+            ));
+        }
+        if recent.is_empty() {
+            return text_result("No indexed files match the given filters.");
+        }
+        if format == OutputFormat::Markdown {
+            let rows: Vec<Vec<String>> = recent
+                .iter()
+                .map(|f| {
+                    let age = format_age(Some(f.modified));
+                    vec![format!("`{}`", f.file_path), modified(f), age]
+                })
+                .collect();
+            return text_result(format::table(&["Path", "Modified", "Age"], &rows));
+        }
+        let out = recent
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                format!(
+                    "{}. {} (modified {}, {})",
+                    i + 1,
+                    f.file_path,
+                    modified(f),
+                    format_age(Some(f.modified))
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        text_result(out)
+    }
+
+    #[tool(
+        description = "List indexed file paths in sorted order, \
+        optionally filtered by file extension, path prefix or owner. \
+        Pass long: true to show each file's owner and permissions. \
+        Returns one page (200 paths by default) with the total count; pass the returned cursor to get the next page.",
+        annotations(title = "List files", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn list_files(
+        &self,
+        Parameters(req): Parameters<ListFilesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = parse_format(&req.format)?;
+        let mut files = self.session_search().list_files(
             req.file_type.as_deref(),
             req.path_prefix.as_deref(),
         );
-        if files.is_empty() {
-            "No indexed files match the given filters.".to_string()
+        if let Some(uid) = resolve_owner(&req.owner)? {
+            files.retain(|f| self.search.file_owner(f).is_some_and(|o| o.uid == uid));
+        }
+        self.audit(AuditEntry {
+            path: req.path_prefix.clone(),
+            query: req.file_type.as_ref().map(|t| format!("file_type={}", t)),
+            ..AuditEntry::new("list_files")
+        });
+        if files.is_empty() && format != OutputFormat::Json {
+            return text_result("No indexed files match the given filters.");
+        }
+        let total = files.len();
+        let limit = req.limit.unwrap_or(DEFAULT_LIST_PAGE).clamp(1, MAX_RESULTS);
+        // Paths are sorted, so a cursor (the last path of the previous page) stays valid
+        // while files are added or removed between pages
+        let start = format::page_start(&files, req.cursor.as_deref(), req.offset.unwrap_or(0));
+        let page = files.get(start..).unwrap_or_default();
+        let mut page = &page[..page.len().min(limit)];
+        if page.is_empty() && format != OutputFormat::Json {
+            return text_result(format!("No more files ({} match the given filters).", total));
+        }
+        // (path, then with long: owner, permissions and modification time)
+        let long = req.long.unwrap_or(false);
+        let mut entries: Vec<(&String, [Option<String>; 3])> = page
+            .iter()
+            .map(|f| {
+                let owner = long.then(|| self.search.file_owner(f)).flatten();
+                let modified = long.then(|| self.search.file_modified(f)).flatten();
+                let (owner, permissions) = owner.map(|o| (o.name(), o.permissions())).unzip();
+                (f, [owner, permissions, modified.map(format_time)])
+            })
+            .collect();
+        let json = |(f, [owner, permissions, modified]): &(&String, [Option<String>; 3])| {
+            format::without_nulls(serde_json::json!({
+                "path": f,
+                "owner": owner,
+                "permissions": permissions,
+                "modified": modified,
+            }))
+        };
+        let line = |entry: &(&String, [Option<String>; 3])| {
+            let details = entry.1.iter().map(|d| d.as_deref().unwrap_or(""));
+            match format {
+                OutputFormat::Json => json(entry).to_string(),
+                OutputFormat::Markdown if long => {
+                    format!("| `{}` | {} |", entry.0, details.collect::<Vec<_>>().join(" | "))
+                }
+                OutputFormat::Markdown => format!("- `{}`", entry.0),
+                OutputFormat::Text if long => {
+                    let details: Vec<&str> = details.filter(|d| !d.is_empty()).collect();
+                    format!("{}  {}", entry.0, details.join(" "))
+                }
+                OutputFormat::Text => entry.0.to_string(),
+            }
+        };
+        // Fewer paths to fit the budget; the cursor then continues after the last one shown
+        if let Some(max) = req.max_response_chars {
+            let max = max.max(budget::MIN_RESPONSE_CHARS) - budget::NOTES_CHARS;
+            let mut used = 0;
+            let fit = entries
+                .iter()
+                .position(|entry| {
+                    used += line(entry).chars().count() + 1;
+                    used > max
+                })
+                .map_or(entries.len(), |over| over.max(1));
+            entries.truncate(fit);
+            page = &page[..fit];
+        }
+        let end = start + page.len();
+        if format == OutputFormat::Json {
+            return json_result(format::without_nulls(serde_json::json!({
+                "files": entries.iter().map(json).collect::<Vec<_>>(),
+                "start": start,
+                "total": total,
+                "cursor": (end < total).then(|| page[page.len() - 1].clone()),
+            })));
+        }
+        let mut out = entries.iter().map(line).collect::<Vec<_>>().join("\n");
+        if format == OutputFormat::Markdown && long {
+            let headers = ["Path", "Owner", "Permissions", "Modified"];
+            out = format!("{}{}", format::table(&headers, &[]), out);
+        }
+        if end < total {
+            out.push_str(&format!(
+                "\n\n(files {}-{} of {}; next page: cursor={})",
+                start + 1,
+                end,
+                total,
+                format::encode_cursor(&page[page.len() - 1])
+            ));
+        } else if start > 0 {
+            out.push_str(&format!("\n\n(files {}-{} of {})", start + 1, end, total));
+        } else {
+            out.push_str(&format!("\n\n({} files)", total));
+        }
+        text_result(out)
+    }
+
+    #[tool(
+        description = "List indexed markdown notes that link to a note, via [[Note Name]] wiki-links or relative markdown links.",
+        annotations(title = "Backlinks", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn backlinks(
+        &self,
+        Parameters(req): Parameters<BacklinksRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let sources = self.session_search().backlinks(&req.note);
+        self.audit(AuditEntry {
+            query: Some(req.note.clone()),
+            files: sources.clone(),
+            ..AuditEntry::new("backlinks")
+        });
+        if sources.is_empty() {
+            text_result(format!("No indexed notes link to {}.", req.note))
+        } else {
+            let count = sources.len();
+            let mut out = sources.join("\n");
+            out.push_str(&format!("\n\n({} notes)", count));
+            text_result(out)
+        }
+    }
+
+    #[tool(
+        description = "Search file contents at past git revisions (tags and revisions configured with LOCALFILES_HISTORY). \
+        Each result names the revision and its commit date, e.g. to find when a config key existed. \
+        Supports the same query syntax as search.",
+        annotations(title = "Search history", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn history_search(
+        &self,
+        Parameters(req): Parameters<HistorySearchRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        tracing::Span::current().record("query", req.query.as_str());
+        let (query, limit, revision) = (req.query.clone(), req.limit.unwrap_or(10).min(MAX_RESULTS), req.revision.clone());
+        let output = self
+            .session_search()
+            .run(move |h| h.search_history(&query, limit, revision.as_deref()))
+            .await
+            .map_err(|e| index_error("History search failed", e))?;
+        tracing::Span::current().record("hits", output.total_count);
+        self.log_query(
+            "history_search",
+            &req.query,
+            &[("revision", req.revision.clone())],
+            output.total_count,
+        );
+        self.audit(AuditEntry {
+            query: Some(req.query.clone()),
+            files: output.results.iter().map(|r| r.file_path.clone()).collect(),
+            ..AuditEntry::new("history_search")
+        });
+        if output.results.is_empty() {
+            return text_result(
+                "No results found in indexed revisions. History is only indexed for git \
+                 repositories when LOCALFILES_HISTORY is set.",
+            );
+        }
+        let mut out = String::new();
+        for (i, r) in output.results.iter().enumerate() {
+            let path_display = match r.line_number {
+                Some(ln) => format!("{}:{}", r.file_path, ln),
+                None => r.file_path.clone(),
+            };
+            out.push_str(&format!(
+                "{}. {} (relevance: {:.2})\n   Path: {}\n   Revision: {}{}\n   Snippet: {}\n\n",
+                i + 1,
+                r.file_name,
+                r.relevance,
+                path_display,
+                r.revision.as_deref().unwrap_or("?"),
+                r.commit_time
+                    .map(|t| format!(" ({})", logs::format_date(t)))
+                    .unwrap_or_default(),
+                r.snippet,
+            ));
+        }
+        text_result(out)
+    }
+
+    #[tool(
+        description = "Find directories rather than files: searches one summary per indexed directory, made of its file names, \
+        its dominant programming language and the head of its README. Use it to locate the folder implementing something \
+        (e.g. \"authentication service\") before searching or listing its files. Requires LOCALFILES_DIRECTORY_SUMMARIES=1.",
+        annotations(title = "Search directories", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn search_directories(
+        &self,
+        Parameters(req): Parameters<SearchDirectoriesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        tracing::Span::current().record("query", req.query.as_str());
+        let search = self.session_search();
+        if !search.has_directory_summaries() {
+            return text_result(
+                "Directory summaries are off. Start the server with \
+                 LOCALFILES_DIRECTORY_SUMMARIES=1 to index them.",
+            );
+        }
+        let (query, limit) = (req.query.clone(), req.limit.unwrap_or(10).min(MAX_RESULTS));
+        let output = search
+            .run(move |h| h.search_directories(&query, limit))
+            .await
+            .map_err(|e| index_error("Directory search failed", e))?;
+        tracing::Span::current().record("hits", output.total_count);
+        self.log_query("search_directories", &req.query, &[], output.total_count);
+        self.audit(AuditEntry {
+            query: Some(req.query.clone()),
+            files: output.results.iter().map(|r| r.file_path.clone()).collect(),
+            ..AuditEntry::new("search_directories")
+        });
+        if output.results.is_empty() {
+            return text_result(format!(
+                "No directories found (index generation {}).",
+                output.generation
+            ));
+        }
+        let mut out = String::new();
+        for (i, r) in output.results.iter().enumerate() {
+            out.push_str(&format!(
+                "{}. {}/ (relevance: {:.2})\n   Path: {}\n   {}\n\n",
+                i + 1,
+                r.file_name,
+                r.relevance,
+                r.file_path,
+                r.snippet,
+            ));
+        }
+        out.push_str(&format!("(index generation {})\n", output.generation));
+        text_result(out)
+    }
+
+    #[tool(
+        description = "List the projects indexed files belong to: directories with a Cargo.toml, package.json, pyproject.toml or .git, \
+        named from their manifest, with their root and number of indexed files. Pass a name as search's project to search one project.",
+        annotations(title = "List projects", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn list_projects(&self) -> Result<CallToolResult, McpError> {
+        let projects = self.session_search().list_projects();
+        if projects.is_empty() {
+            return text_result("No projects found among indexed files.");
+        }
+        let out = projects
+            .iter()
+            .map(|p| {
+                format!(
+                    "{} ({}, {} files)\n   Root: {}",
+                    p.project.name,
+                    p.project.kind,
+                    p.files,
+                    p.project.root.display()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        text_result(out)
+    }
+
+    #[tool(
+        description = "List tags from markdown front matter across all indexed files, with the number of files using each. Search a tag with tag:name.",
+        annotations(title = "List tags", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn list_tags(&self) -> Result<CallToolResult, McpError> {
+        let tags = self.session_search().list_tags();
+        if tags.is_empty() {
+            text_result("No tags found in indexed files.")
+        } else {
+            let out = tags
+                .iter()
+                .map(|(tag, n)| format!("{} ({})", tag, n))
+                .collect::<Vec<_>>()
+                .join("\n");
+            text_result(out)
+        }
+    }
+
+    #[tool(
+        description = "List files that could not be indexed after several attempts, e.g. because another process keeps them locked, with the error of the last attempt. They are indexed again on their next change.",
+        annotations(title = "List failed files", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn failed_files(&self) -> Result<CallToolResult, McpError> {
+        let state = self.state.read().await;
+        let failed: Vec<String> = state
+            .index
+            .failed_files()
+            .into_iter()
+            .filter(|f| self.scope.allows(&f.path))
+            .map(|f| {
+                format!(
+                    "{} ({} attempts, last {}): {}",
+                    f.path.display(),
+                    f.attempts,
+                    format_age(Some(f.last_attempt)),
+                    f.reason
+                )
+            })
+            .collect();
+        if failed.is_empty() {
+            text_result("No files failed to index.")
         } else {
-            let count = files.len();
-            let mut out = files.join("\n");
-            out.push_str(&format!("\n\n({} files)", count));
-            out
+            text_result(failed.join("\n"))
         }
     }
 }
 
-#[tool_handler]
+/// Span of one tool call. Tool handlers fill in `query` and `hits` via `Span::current()`.
+fn tool_span(request_id: &dyn std::fmt::Display, tool: &str) -> tracing::Span {
+    tracing::info_span!(
+        "tool_call",
+        request_id = %request_id,
+        tool = %tool,
+        query = tracing::field::Empty,
+        hits = tracing::field::Empty,
+        duration_ms = tracing::field::Empty,
+    )
+}
+
 impl ServerHandler for FileSearchServer {
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let tool = request.name.to_string();
+        self.client.get_or_init(|| match context.peer.peer_info() {
+            Some(info) => format!("{} {}", info.client_info.name, info.client_info.version),
+            None => "unknown".to_string(),
+        });
+        metrics::counter!(telemetry::TOOL_CALLS_TOTAL, "tool" => tool.clone()).increment(1);
+        let span = tool_span(&context.id, &tool);
+        if let Err(violation) = self.limits.check(&tool, request.arguments.as_ref()) {
+            metrics::counter!(telemetry::TOOL_REJECTED_TOTAL, "tool" => tool, "kind" => violation.kind())
+                .increment(1);
+            let _guard = span.enter();
+            tracing::warn!("Tool call rejected: {}", violation);
+            return Err(limit_error(&violation));
+        }
+        let started = std::time::Instant::now();
+        let tcc = ToolCallContext::new(self, request, context);
+        let result = self.tool_router.call(tcc).instrument(span.clone()).await;
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        let _guard = span.enter();
+        match &result {
+            Ok(_) => tracing::info!("Tool call completed"),
+            Err(e) => {
+                metrics::counter!(telemetry::TOOL_ERRORS_TOTAL, "tool" => tool).increment(1);
+                tracing::warn!("Tool call failed: {}", e.message);
+            }
+        }
+        result
+    }
+
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        tracing::info!("Client initialized");
+        // Listing roots is a request to the client, answered while this handler is idle
+        let server = self.clone();
+        tokio::spawn(async move { server.index_client_roots(context.peer).await });
+    }
+
+    async fn on_roots_list_changed(&self, context: NotificationContext<RoleServer>) {
+        let server = self.clone();
+        tokio::spawn(async move { server.index_client_roots(context.peer).await });
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+    }
+
+    async fn complete(
+        &self,
+        request: CompleteRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CompleteResult, McpError> {
+        // Arguments are completed by name, whichever prompt or tool the client composes
+        let ArgumentInfo { name, value } = request.argument;
+        let search = self.session_search();
+        let values = match name.as_str() {
+            "file_type" => search.run(move |s| s.complete_file_type(&value)).await,
+            "path_prefix" => search.run(move |s| s.complete_path_prefix(&value)).await,
+            _ => Ok(Vec::new()),
+        }
+        .map_err(|e| index_error("Completion failed", e))?;
+        Ok(CompleteResult {
+            completion: CompletionInfo {
+                total: Some(values.len() as u32),
+                has_more: Some(values.len() > MAX_COMPLETIONS),
+                values: values.into_iter().take(MAX_COMPLETIONS).collect(),
+            },
+        })
+    }
+
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
-            instructions: Some(
-                "A local file search server. Use 'index_paths' to add directories, \
-                 then 'search' to find files by keyword. Use 'status' to check index state.\n\
-                 Prefer 'search' over grep/find for broad keyword searches — it provides \
-                 relevance-ranked full-text search across all indexed files with snippet context. \
-                 Use 'file_type' and 'path_prefix' parameters to narrow results."
-                    .to_string(),
-            ),
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            instructions: Some(self.capabilities.instructions()),
+            capabilities: ServerCapabilities::builder()
+                .enable_completions()
+                .enable_tools()
+                .build(),
             ..Default::default()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    /// A server over a fresh index of the files in `root`, auditing to `dir`.
+    fn test_server(dir: &Path, root: &Path, capabilities: Capabilities) -> FileSearchServer {
+        let mut index = FileIndex::new(Some(dir.join("index"))).unwrap();
+        index.index_directory(root).unwrap();
+        index.commit().unwrap();
+        let search = index.search_handle();
+        let (watcher, _events, overflow) = localfiles::watcher::new_watcher().unwrap();
+        let state = Arc::new(RwLock::new(SharedState {
+            index,
+            watcher,
+            overflow,
+            queue: Arc::default(),
+            saved: Default::default(),
+            deferred_commit: Arc::default(),
+        }));
+        let audit = AuditLog::open(dir.join("audit.jsonl"), 1_000_000).unwrap();
+        FileSearchServer::new(state, search)
+            .with_capabilities(capabilities)
+            .with_audit_log(Some(audit))
+    }
+
+    /// Text of a tool result.
+    fn text(result: &CallToolResult) -> String {
+        let value = serde_json::to_value(result).unwrap();
+        value["content"][0]["text"].as_str().unwrap_or_default().to_string()
+    }
+
+    fn audited(dir: &Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(dir.join("audit.jsonl"))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    /// Tool arguments parsed from JSON, as the client sends them.
+    fn args<T: serde::de::DeserializeOwned>(json: serde_json::Value) -> Parameters<T> {
+        Parameters(serde_json::from_value(json).unwrap())
+    }
+
+    #[test]
+    fn test_tool_annotations() {
+        let tools = FileSearchServer::tool_router().list_all();
+        let hints = |name: &str| {
+            let tool = tools.iter().find(|t| t.name == name).unwrap();
+            let a = tool.annotations.clone().unwrap();
+            (a.read_only_hint, a.destructive_hint)
+        };
+        for tool in &tools {
+            let name = &tool.name;
+            let a = tool.annotations.as_ref().unwrap_or_else(|| panic!("{} not annotated", name));
+            assert!(a.title.as_deref().is_some_and(|t| !t.is_empty()), "{} has no title", name);
+            assert_eq!(a.open_world_hint, Some(false), "{}", name);
+            // Clients assume a tool that is not read-only may be destructive unless told
+            if a.read_only_hint != Some(true) {
+                assert!(a.destructive_hint.is_some(), "{} leaves destructive_hint out", name);
+            }
+        }
+        for name in ["search", "read_file", "read_files", "list_files", "status", "outline"] {
+            assert_eq!(hints(name).0, Some(true), "{}", name);
+        }
+        for name in ["write_file", "delete_file", "purge_index", "verify_index"] {
+            assert_eq!(hints(name).1, Some(true), "{}", name);
+        }
+        for name in ["index_paths", "append_file", "pin_file", "save_search"] {
+            assert_ne!(hints(name).0, Some(true), "{}", name);
+            assert_eq!(hints(name).1, Some(false), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_router_lists_only_available_tools() {
+        let names = |capabilities: Capabilities| -> Vec<String> {
+            let router = FileSearchServer::router_for(capabilities);
+            let mut names: Vec<String> =
+                router.list_all().into_iter().map(|t| t.name.to_string()).collect();
+            names.sort();
+            names
+        };
+        let optional =
+            ["append_file", "delete_file", "history_search", "purge_index", "write_file"];
+        let everything = Capabilities {
+            history: true,
+            write: true,
+            delete: true,
+            purge: true,
+            ..Capabilities::compiled()
+        };
+        let all = names(everything);
+        assert_eq!(all.len(), FileSearchServer::tool_router().list_all().len());
+
+        let none = Capabilities {
+            history: false,
+            ..Capabilities::compiled()
+        };
+        let basic = names(none);
+        for tool in optional {
+            assert!(all.iter().any(|t| t == tool), "{} missing with every capability", tool);
+            assert!(!basic.iter().any(|t| t == tool), "{} listed without its capability", tool);
+        }
+        assert_eq!(basic.len() + optional.len(), all.len());
+
+        let write = names(Capabilities { write: true, ..none });
+        let added: Vec<&String> = write.iter().filter(|t| !basic.contains(t)).collect();
+        assert_eq!(added, ["append_file", "write_file"]);
+    }
+
+    #[tokio::test]
+    async fn test_failing_tools_return_mcp_errors() {
+        use rmcp::model::ErrorCode;
+
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("a.md"), "alpha").unwrap();
+        let server = test_server(dir.path(), &root, Capabilities::compiled());
+
+        let err = server.search(args(serde_json::json!({ "query": "(alpha" }))).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::INVALID_PARAMS);
+        assert!(err.message.starts_with("Search failed"), "{}", err.message);
+        let err = server.search(args(serde_json::json!({ "query": "nosuchfield:x" }))).await;
+        assert_eq!(err.unwrap_err().code, ErrorCode::INVALID_PARAMS);
+
+        let missing = root.join("missing.md").display().to_string();
+        let read = server.read_file(args(serde_json::json!({ "path": missing }))).await;
+        let err = read.unwrap_err();
+        assert_eq!(err.code, ErrorCode::RESOURCE_NOT_FOUND);
+        assert_eq!(err.data.unwrap()["path"], missing);
+
+        // Success stays a plain result
+        let found = server.search(args(serde_json::json!({ "query": "alpha" }))).await.unwrap();
+        assert!(text(&found).contains("a.md"));
+    }
+
+    /// Log output shared with a test subscriber.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_span_records_query_and_hits() {
+        use rmcp::ServiceExt;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("a.md"), "alpha").unwrap();
+        std::fs::write(root.join("b.md"), "beta").unwrap();
+        let server = test_server(dir.path(), &root, Capabilities::compiled());
+
+        let logs = Captured::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_current_span(true)
+            .with_writer(move || writer.clone())
+            .finish();
+        // The single-threaded test runtime runs the served session on this thread
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        // Drive `call_tool` as a client would, over an in-memory stream
+        let (client, transport) = tokio::io::duplex(64 * 1024);
+        let serving = tokio::spawn(server.serve(transport));
+        let (reader, mut writer) = tokio::io::split(client);
+        let mut replies = tokio::io::BufReader::new(reader).lines();
+        let line = |message: serde_json::Value| format!("{}\n", message).into_bytes();
+        let initialize = line(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "clientInfo": { "name": "test", "version": "1" },
+            },
+        }));
+        writer.write_all(&initialize).await.unwrap();
+        replies.next_line().await.unwrap().expect("no initialize reply");
+        let initialized = line(serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized",
+        }));
+        writer.write_all(&initialized).await.unwrap();
+        let call = line(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "tools/call",
+            "params": { "name": "search", "arguments": { "query": "alpha" } },
+        }));
+        writer.write_all(&call).await.unwrap();
+        loop {
+            let line = replies.next_line().await.unwrap().expect("no tools/call reply");
+            let reply: serde_json::Value = serde_json::from_str(&line).unwrap();
+            if reply["id"] == 7 {
+                assert!(reply["result"]["content"][0]["text"].as_str().unwrap().contains("a.md"));
+                break;
+            }
+        }
+        let service = serving.await.unwrap().unwrap();
+        service.cancel().await.unwrap();
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let line = logs
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|line| line["fields"]["message"] == "Tool call completed")
+            .expect("no completion event logged");
+        let fields = &line["span"];
+        assert_eq!(fields["name"], "tool_call");
+        assert_eq!(fields["request_id"], "7");
+        assert_eq!(fields["tool"], "search");
+        assert_eq!(fields["query"], "alpha");
+        assert_eq!(fields["hits"], 1);
+        assert!(fields["duration_ms"].is_u64(), "{}", fields);
+    }
+
+    #[tokio::test]
+    async fn test_read_files_reports_each_failure_and_reads_the_rest() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("a.md"), "alpha").unwrap();
+        std::fs::write(root.join("big.md"), "x".repeat(MAX_READ_FILES_BYTES)).unwrap();
+        std::fs::write(root.join("c.md"), "gamma\n").unwrap();
+        let server = test_server(dir.path(), &root, Capabilities::compiled());
+
+        let path = |name: &str| root.join(name).display().to_string();
+        let (a, big, c, missing) = (path("a.md"), path("big.md"), path("c.md"), path("gone.md"));
+        let paths = [&a, &missing, &big, &c];
+        let result = server.read_files(args(serde_json::json!({ "paths": paths }))).await;
+        let out = text(&result.unwrap());
+        assert!(out.contains(&format!("==> {} <==\nalpha\n", a)), "{}", out);
+        assert!(out.contains(&format!("==> {} (error) <==\nError reading file", missing)));
+        assert!(out.contains(&format!("==> {} (skipped) <==\nOver the 256 KB limit", big)));
+        assert!(out.ends_with(&format!("==> {} <==\ngamma", c)), "{}", out);
+
+        // Only the files actually returned are audited
+        let entries = audited(dir.path());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["files"], serde_json::json!([a, c]));
+
+        let none: [&str; 0] = [];
+        let err = server.read_files(args(serde_json::json!({ "paths": none }))).await;
+        assert_eq!(err.unwrap_err().code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_list_files_long_shows_modification_time() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap().join("root");
+        std::fs::create_dir(&root).unwrap();
+        let note = root.join("note.md");
+        std::fs::write(&note, "dated").unwrap();
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_704_164_645);
+        std::fs::File::options().write(true).open(&note).unwrap().set_modified(modified).unwrap();
+        let server = test_server(dir.path(), &root, Capabilities::compiled());
+        let list = |format: &str| {
+            server.list_files(args(serde_json::json!({ "long": true, "format": format })))
+        };
+
+        let out = text(&list("markdown").await.unwrap());
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].ends_with("| Modified |"), "{}", out);
+        let row = format!("| `{}` |", note.display());
+        let row = lines.iter().find(|l| l.starts_with(&row)).expect("no row for the file");
+        assert!(row.ends_with("| 2024-01-02T03:04:05Z |"), "{}", row);
+
+        let out = text(&list("text").await.unwrap());
+        assert!(out.lines().next().unwrap().ends_with(" 2024-01-02T03:04:05Z"), "{}", out);
+
+        let out = text(&list("json").await.unwrap());
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(json["files"][0]["modified"], "2024-01-02T03:04:05Z");
+
+        // Without long, only the paths
+        let plain = server.list_files(args(serde_json::json!({}))).await.unwrap();
+        assert!(!text(&plain).contains("2024-01-02"), "{}", text(&plain));
+    }
+
+    fn delete(path: &Path) -> Parameters<DeleteFileRequest> {
+        Parameters(DeleteFileRequest {
+            path: path.display().to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_delete_file_refusals() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap().join("root");
+        std::fs::create_dir(&root).unwrap();
+        let note = root.join("note.md");
+        std::fs::write(&note, "keep me").unwrap();
+        let delete_on = Capabilities {
+            delete: true,
+            ..Capabilities::compiled()
+        };
+        let server = test_server(dir.path(), &root, delete_on);
+
+        // Not indexed: created after the index was built
+        let later = root.join("later.md");
+        std::fs::write(&later, "new").unwrap();
+        let err = server.delete_file(delete(&later)).await.unwrap_err();
+        assert!(err.message.contains("is not an indexed file"), "{}", err.message);
+        assert!(later.exists());
+
+        // Out of scope: an isolated session that indexed no roots sees nothing
+        let isolated = server.clone().with_scope(SessionScope::isolated(Vec::new()));
+        let err = isolated.delete_file(delete(&note)).await.unwrap_err();
+        assert!(err.message.contains("outside the roots"), "{}", err.message);
+        assert!(note.exists());
+
+        // Without the trash feature nothing is deleted, and the file stays indexed
+        #[cfg(not(feature = "trash"))]
+        {
+            let err = server.delete_file(delete(&note)).await.unwrap_err();
+            assert!(err.message.contains("lacks the trash feature"), "{}", err.message);
+            assert!(note.exists());
+            assert!(server.state.read().await.index.is_indexed(&note));
+        }
+        assert!(audited(dir.path()).is_empty());
+    }
+
+    #[cfg(feature = "trash")]
+    #[tokio::test]
+    async fn test_delete_file_trashes_and_audits() {
+        let dir = TempDir::new().unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        let root = base.join("root");
+        std::fs::create_dir(&root).unwrap();
+        let scratch = root.join("scratch.md");
+        std::fs::write(&scratch, "temporary").unwrap();
+        let delete_on = Capabilities {
+            delete: true,
+            ..Capabilities::compiled()
+        };
+        // Not the user's own trash
+        let trash = base.join("trash");
+        let server = test_server(dir.path(), &root, delete_on).with_trash_dir(trash.clone());
+
+        let result = server.delete_file(delete(&scratch)).await.unwrap();
+        assert!(text(&result).starts_with("Moved"), "{}", text(&result));
+        assert!(!scratch.exists());
+        assert_eq!(std::fs::read_to_string(trash.join("scratch.md")).unwrap(), "temporary");
+        assert!(!server.state.read().await.index.is_indexed(&scratch));
+        let entries = audited(dir.path());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["tool"], "delete_file");
+        assert_eq!(entries[0]["path"], scratch.display().to_string());
+    }
+}
@@ -0,0 +1,70 @@
+//! Cell text of `.xlsx`, `.xlsm`, `.xls` and `.ods` spreadsheets.
+//!
+//! Each sheet is indexed under a virtual path made of the workbook path, `!/` and the
+//! sheet name, e.g. `/docs/budget.xlsx!/Q3`, in chunks of rows like `.csv` files, so
+//! results name the sheet and the row. A row is indexed as its non-empty cells joined by
+//! ` | `. Reading spreadsheets requires the `spreadsheets` feature; without it no file is
+//! treated as one.
+
+use std::path::Path;
+
+/// A sheet's rows as text; `rows[i]` is sheet row `i + 1`, empty if the row has no cells.
+pub struct Sheet {
+    pub name: String,
+    pub rows: Vec<String>,
+}
+
+/// Whether `path` names a spreadsheet whose sheets should be indexed.
+pub fn is_spreadsheet(path: &Path) -> bool {
+    if !cfg!(feature = "spreadsheets") {
+        return false;
+    }
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| matches!(e.to_lowercase().as_str(), "xlsx" | "xlsm" | "xls" | "ods"))
+}
+
+/// Read the text of every sheet in the workbook at `path`, skipping empty sheets.
+#[cfg(feature = "spreadsheets")]
+pub fn read_sheets(path: &Path) -> anyhow::Result<Vec<Sheet>> {
+    use calamine::Reader;
+
+    let mut workbook = calamine::open_workbook_auto(path)?;
+    let mut sheets = Vec::new();
+    for name in workbook.sheet_names() {
+        let range = workbook.worksheet_range(&name)?;
+        let Some((first_row, _)) = range.start() else {
+            continue;
+        };
+        let mut rows = vec![String::new(); first_row as usize];
+        for row in range.rows() {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|cell| cell.to_string().replace(['\r', '\n'], " "))
+                .filter(|cell| !cell.trim().is_empty())
+                .collect();
+            rows.push(cells.join(" | "));
+        }
+        while rows.last().is_some_and(|r| r.is_empty()) {
+            rows.pop();
+        }
+        if !rows.is_empty() {
+            sheets.push(Sheet { name, rows });
+        }
+    }
+    Ok(sheets)
+}
+
+#[cfg(not(feature = "spreadsheets"))]
+pub fn read_sheets(path: &Path) -> anyhow::Result<Vec<Sheet>> {
+    anyhow::bail!("Spreadsheet support is not enabled: {}", path.display())
+}
+
+/// Read one sheet as text, one row per line.
+pub fn read_sheet(path: &Path, name: &str) -> anyhow::Result<String> {
+    read_sheets(path)?
+        .into_iter()
+        .find(|sheet| sheet.name == name)
+        .map(|sheet| sheet.rows.join("\n"))
+        .ok_or_else(|| anyhow::anyhow!("No sheet {} in {}", name, path.display()))
+}
@@ -0,0 +1,153 @@
+//! Schema and text rows of SQLite databases.
+//!
+//! A database is indexed as one document listing its tables and columns:
+//!
+//! ```text
+//! table users
+//! column users.email (TEXT)
+//! users: email: alice@example.com | name: Alice
+//! ```
+//!
+//! Table and column names also go into the `key` field (`key:users.email`). Rows are only
+//! included when a row limit is configured, and only their text values. Reading
+//! databases requires the `sqlite` feature; without it no file is treated as one.
+
+use std::path::Path;
+
+/// Environment variable enabling database indexing: `schema` or rows per table.
+pub const SQLITE_ENV: &str = "LOCALFILES_SQLITE";
+
+/// The first bytes of every SQLite 3 database file.
+const HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Whether `path` has a database extension (`.sqlite`, `.sqlite3`, `.db`, `.db3`).
+pub fn is_database(path: &Path) -> bool {
+    if !cfg!(feature = "sqlite") {
+        return false;
+    }
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(is_database_extension)
+}
+
+/// Whether `extension` is one of the database extensions.
+pub fn is_database_extension(extension: &str) -> bool {
+    matches!(extension.to_lowercase().as_str(), "sqlite" | "sqlite3" | "db" | "db3")
+}
+
+/// Whether the file at `path` starts with the SQLite header; other `.db` formats don't.
+pub fn has_header(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .is_ok_and(|_| header == HEADER)
+}
+
+/// Parse a `LOCALFILES_SQLITE` value: `schema` (no rows) or rows per table.
+pub fn parse_rows(spec: &str) -> anyhow::Result<usize> {
+    let spec = spec.trim();
+    if spec.eq_ignore_ascii_case("schema") {
+        return Ok(0);
+    }
+    spec.parse()
+        .map_err(|_| anyhow::anyhow!("Expected 'schema' or a row count, got '{}'", spec))
+}
+
+/// Table and column key paths (`users`, `users.email`) listed in `extract` output, with
+/// their lines.
+pub fn schema_keys(text: &str) -> Vec<(String, usize)> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let key = match line.strip_prefix("table ") {
+                Some(table) => table,
+                None => line.strip_prefix("column ")?.rsplit_once(" (")?.0,
+            };
+            Some((key.to_string(), i + 1))
+        })
+        .collect()
+}
+
+/// Describe the tables and columns of the database at `path`, followed by up to `rows`
+/// rows of text values per table.
+#[cfg(feature = "sqlite")]
+pub fn extract(path: &Path, rows: usize) -> anyhow::Result<String> {
+    use rusqlite::types::ValueRef;
+    use rusqlite::{Connection, OpenFlags};
+
+    let db = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let tables: Vec<String> = db
+        .prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' \
+             AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    let mut text = String::new();
+    for table in tables {
+        text.push_str(&format!("table {}\n", table));
+        let columns: Vec<(String, String)> = db
+            .prepare("SELECT name, type FROM pragma_table_info(?1)")?
+            .query_map([&table], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        for (column, kind) in &columns {
+            text.push_str(&format!("column {}.{} ({})\n", table, column, kind));
+        }
+        if rows == 0 || columns.is_empty() {
+            continue;
+        }
+        let mut select = db.prepare(&format!("SELECT * FROM {} LIMIT ?1", quote(&table)))?;
+        let mut result = select.query([rows as i64])?;
+        while let Some(row) = result.next()? {
+            let cells: Vec<String> = columns
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (column, _))| match row.get_ref(i).ok()? {
+                    ValueRef::Text(value) if !value.is_empty() => {
+                        let value = String::from_utf8_lossy(value).replace(['\r', '\n'], " ");
+                        Some(format!("{}: {}", column, value))
+                    }
+                    _ => None,
+                })
+                .collect();
+            if !cells.is_empty() {
+                text.push_str(&format!("{}: {}\n", table, cells.join(" | ")));
+            }
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn extract(path: &Path, _rows: usize) -> anyhow::Result<String> {
+    anyhow::bail!("SQLite support is not enabled: {}", path.display())
+}
+
+/// Quote `name` as an SQL identifier.
+#[cfg(feature = "sqlite")]
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rows_and_schema_keys() {
+        assert_eq!(parse_rows("schema").unwrap(), 0);
+        assert_eq!(parse_rows(" 50 ").unwrap(), 50);
+        assert!(parse_rows("all").is_err());
+
+        let text = "table users\ncolumn users.email (TEXT)\ncolumn users.Full Name ()\n\
+                    users: email: a@b.c\n";
+        let keys: Vec<String> = schema_keys(text).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec!["users", "users.email", "users.Full Name"]);
+    }
+}
@@ -0,0 +1,100 @@
+//! Directory summaries: one synthetic document per directory, for `search_directories`.
+//!
+//! A query such as "authentication service" often describes a folder better than any
+//! single file in it. With summaries on, every directory holding indexed files gets a
+//! document listing its files, its dominant language and the head of its README, so
+//! the folder itself can match. Summaries are rewritten at commit time for directories
+//! whose files changed, and are left out of every other search.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+/// File names listed per summary.
+const MAX_LISTED_FILES: usize = 200;
+/// Lines of the README included.
+const README_LINES: usize = 20;
+/// Bytes of the README read, at most.
+const README_BYTES: u64 = 4_096;
+
+/// Text of the summary of `dir`, holding the files `files` (name and language).
+pub fn summary_text(
+    dir: &Path,
+    files: &[(String, Option<&'static str>)],
+    readme: Option<&str>,
+) -> String {
+    let mut text = format!("Directory {}\n", dir.display());
+    if let Some(language) = dominant_language(files) {
+        text.push_str(&format!("Language: {}\n", language));
+    }
+    let names: Vec<&str> = files
+        .iter()
+        .take(MAX_LISTED_FILES)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    text.push_str(&format!("Files: {}", names.join(", ")));
+    if files.len() > MAX_LISTED_FILES {
+        text.push_str(&format!(" and {} more", files.len() - MAX_LISTED_FILES));
+    }
+    text.push('\n');
+    if let Some(readme) = readme {
+        text.push_str("README:\n");
+        text.push_str(readme);
+    }
+    text
+}
+
+/// The language most files are in; ties go to the alphabetically first.
+pub fn dominant_language(files: &[(String, Option<&'static str>)]) -> Option<&'static str> {
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    for language in files.iter().filter_map(|(_, language)| *language) {
+        *counts.entry(language).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+        .map(|(language, _)| language)
+}
+
+/// Whether `name` is a README file.
+pub fn is_readme(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name == "readme" || name.starts_with("readme.")
+}
+
+/// First lines of the README at `path`.
+pub fn readme_head(path: &Path) -> Option<String> {
+    let mut head = Vec::new();
+    std::fs::File::open(path)
+        .ok()?
+        .take(README_BYTES)
+        .read_to_end(&mut head)
+        .ok()?;
+    let head = String::from_utf8_lossy(&head);
+    let lines: Vec<&str> = head.lines().take(README_LINES).collect();
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_lists_files_language_and_readme() {
+        let files = vec![
+            ("README.md".to_string(), Some("markdown")),
+            ("login.rs".to_string(), Some("rust")),
+            ("session.rs".to_string(), Some("rust")),
+            ("notes.txt".to_string(), None),
+        ];
+        let text = summary_text(Path::new("/src/auth"), &files, Some("# Auth service"));
+        assert_eq!(
+            text,
+            "Directory /src/auth\nLanguage: rust\n\
+             Files: README.md, login.rs, session.rs, notes.txt\nREADME:\n# Auth service"
+        );
+        let tie = [("a.py".to_string(), Some("python")), ("b.go".to_string(), Some("go"))];
+        assert_eq!(dominant_language(&tie), Some("go"));
+        assert!(is_readme("README") && is_readme("Readme.rst") && !is_readme("readmes.txt"));
+    }
+}
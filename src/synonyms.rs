@@ -0,0 +1,94 @@
+//! Query-time synonyms, so domain shorthand finds the files that spell it out.
+//!
+//! Loaded from the file `LOCALFILES_SYNONYMS` names: one group of equivalent words or
+//! phrases per line, separated by commas (`k8s, kubernetes`), with `#` starting a
+//! comment. A word or quoted phrase of a query that is in a group matches any member of
+//! the group, as if they were combined with OR. Matching ignores case.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Path of the synonyms file.
+pub const SYNONYMS_ENV: &str = "LOCALFILES_SYNONYMS";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Synonyms {
+    /// Groups of equivalent terms, lowercased.
+    groups: Vec<Vec<String>>,
+    /// Group of each term.
+    group_of: HashMap<String, usize>,
+}
+
+impl Synonyms {
+    /// Parse the lines of a synonyms file.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut synonyms = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let group: Vec<String> = line
+                .split(',')
+                .map(|term| term.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase())
+                .filter(|term| !term.is_empty())
+                .collect();
+            match group.len() {
+                0 => continue,
+                1 => anyhow::bail!("Line {}: '{}' has no synonyms", i + 1, group[0]),
+                _ => {}
+            }
+            for term in &group {
+                if synonyms.group_of.insert(term.clone(), synonyms.groups.len()).is_some() {
+                    anyhow::bail!("Line {}: '{}' is already in another group", i + 1, term);
+                }
+            }
+            synonyms.groups.push(group);
+        }
+        Ok(synonyms)
+    }
+
+    /// Read and parse the synonyms file at `path`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path.display(), e))?;
+        Self::parse(&text)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Terms equivalent to `term`, without `term` itself; none if it is in no group.
+    pub fn expand(&self, term: &str) -> Vec<&str> {
+        let term = term.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        match self.group_of.get(&term) {
+            Some(&group) => self.groups[group]
+                .iter()
+                .filter(|other| **other != term)
+                .map(String::as_str)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_expand() {
+        let synonyms = Synonyms::parse(
+            "# shorthand\nauth, authentication\n\nk8s, Kubernetes,  kube # cluster\n\
+             gcp, google   cloud\n",
+        )
+        .unwrap();
+        assert_eq!(synonyms.expand("AUTH"), ["authentication"]);
+        assert_eq!(synonyms.expand("kubernetes"), ["k8s", "kube"]);
+        assert_eq!(synonyms.expand("Google Cloud"), ["gcp"]);
+        assert!(synonyms.expand("deploy").is_empty());
+        assert!(Synonyms::parse("").unwrap().is_empty());
+
+        let err = Synonyms::parse("auth, authentication\nauth, login").unwrap_err();
+        assert!(err.to_string().contains("Line 2: 'auth'"));
+        assert!(Synonyms::parse("auth").is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! Header-aware indexing of `.csv` and `.tsv` files.
+//!
+//! The first record is the header. Data rows are indexed in chunks of `ROWS_PER_CHUNK`,
+//! one `column: cell | ...` line per row in the chunk's text. Each row's cells also go into
+//! the `columns` JSON field under their normalized column names, so `columns.status:failed`
+//! finds rows whose `Status` column contains "failed". Results report the 1-based data row.
+
+use std::path::Path;
+
+/// Data rows per indexed chunk.
+pub const ROWS_PER_CHUNK: usize = 100;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Table {
+    /// Normalized column names (see `column_key`), in header order.
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Whether `path` is indexed as a table.
+pub fn is_tabular(path: &Path) -> bool {
+    delimiter(path).is_some()
+}
+
+/// Field delimiter for `path`: `,` for `.csv`, tab for `.tsv`.
+pub fn delimiter(path: &Path) -> Option<char> {
+    let extension = path.extension().and_then(|e| e.to_str())?;
+    if extension.eq_ignore_ascii_case("csv") {
+        Some(',')
+    } else if extension.eq_ignore_ascii_case("tsv") {
+        Some('\t')
+    } else {
+        None
+    }
+}
+
+/// Column name as used in queries: lowercase, with runs of other characters than
+/// letters and digits replaced by `_` (`Order ID` becomes `order_id`).
+pub fn column_key(name: &str) -> String {
+    let mut key = String::new();
+    for c in name.trim().chars() {
+        if c.is_alphanumeric() {
+            key.extend(c.to_lowercase());
+        } else if !key.is_empty() && !key.ends_with('_') {
+            key.push('_');
+        }
+    }
+    key.trim_end_matches('_').to_string()
+}
+
+/// Parse delimited `content` (RFC 4180 quoting: `"a, b"`, `"say ""hi"""`, line breaks
+/// inside quotes). Unnamed columns are called `column_<n>`.
+pub fn parse(content: &str, delimiter: char) -> Table {
+    let mut records = records(content, delimiter).into_iter();
+    let Some(header) = records.next() else {
+        return Table::default();
+    };
+    let columns = header
+        .iter()
+        .enumerate()
+        .map(|(i, name)| match column_key(name) {
+            key if key.is_empty() => format!("column_{}", i + 1),
+            key => key,
+        })
+        .collect();
+    Table {
+        columns,
+        rows: records.filter(|r| r.iter().any(|cell| !cell.is_empty())).collect(),
+    }
+}
+
+fn records(content: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut cell = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                cell.push('"');
+                chars.next();
+            }
+            '"' if in_quotes => in_quotes = false,
+            '"' if cell.is_empty() => in_quotes = true,
+            c if c == delimiter && !in_quotes => record.push(std::mem::take(&mut cell)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                record.push(std::mem::take(&mut cell));
+                records.push(std::mem::take(&mut record));
+            }
+            c => cell.push(c),
+        }
+    }
+    if !cell.is_empty() || !record.is_empty() {
+        record.push(cell);
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quoted_cells_and_headers() {
+        let table = parse(
+            "Order ID,Status,Note\n1,failed,\"timeout, retried\"\n\n2,ok,\"said \"\"hi\"\"\nthen left\"\n",
+            ',',
+        );
+        assert_eq!(table.columns, vec!["order_id", "status", "note"]);
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[0][2], "timeout, retried");
+        assert_eq!(table.rows[1][2], "said \"hi\"\nthen left");
+
+        let tsv = parse("a\t\nx\ty", '\t');
+        assert_eq!(tsv.columns, vec!["a", "column_2"]);
+        assert_eq!(tsv.rows, vec![vec!["x".to_string(), "y".to_string()]]);
+    }
+}
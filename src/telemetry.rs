@@ -0,0 +1,103 @@
+//! Metric names and the optional Prometheus exporter.
+//!
+//! Instrumentation goes through the `metrics` facade and is a no-op unless a
+//! recorder is installed. Building with `--features metrics` and setting
+//! `LOCALFILES_METRICS_ADDR` (e.g. `127.0.0.1:9898`) serves `/metrics` on that address.
+
+pub const SEARCHES_TOTAL: &str = "localfiles_searches_total";
+pub const SEARCH_DURATION: &str = "localfiles_search_duration_seconds";
+pub const SEARCH_HITS: &str = "localfiles_search_hits";
+pub const SEARCH_CACHE_HITS_TOTAL: &str = "localfiles_search_cache_hits_total";
+pub const TOOL_CALLS_TOTAL: &str = "localfiles_tool_calls_total";
+pub const TOOL_ERRORS_TOTAL: &str = "localfiles_tool_errors_total";
+pub const TOOL_REJECTED_TOTAL: &str = "localfiles_tool_rejected_total";
+pub const INDEXED_FILES: &str = "localfiles_indexed_files";
+pub const INDEX_SIZE_BYTES: &str = "localfiles_index_size_bytes";
+pub const COMMIT_DURATION: &str = "localfiles_commit_duration_seconds";
+pub const WATCHER_PENDING: &str = "localfiles_watcher_pending_events";
+pub const WATCHER_DROPPED_TOTAL: &str = "localfiles_watcher_dropped_events_total";
+pub const BATCH_EVENTS: &str = "localfiles_watcher_batch_events";
+
+pub const METRICS_ADDR_ENV: &str = "LOCALFILES_METRICS_ADDR";
+
+/// Install the Prometheus exporter if `LOCALFILES_METRICS_ADDR` is set.
+///
+/// Must be called from within a tokio runtime. Returns the bound address, if any.
+#[cfg(feature = "metrics")]
+pub fn init() -> anyhow::Result<Option<std::net::SocketAddr>> {
+    let addr = match std::env::var(METRICS_ADDR_ENV) {
+        Ok(a) if !a.trim().is_empty() => a.trim().parse::<std::net::SocketAddr>()?,
+        _ => return Ok(None),
+    };
+    metrics_exporter_prometheus::PrometheusBuilder::new()
+        .with_http_listener(addr)
+        .install()?;
+    describe();
+    Ok(Some(addr))
+}
+
+/// Without the `metrics` feature there is no exporter; warn if one was requested.
+#[cfg(not(feature = "metrics"))]
+pub fn init() -> anyhow::Result<Option<std::net::SocketAddr>> {
+    if std::env::var_os(METRICS_ADDR_ENV).is_some() {
+        tracing::warn!(
+            "{} is set but localfiles was built without the `metrics` feature",
+            METRICS_ADDR_ENV
+        );
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "metrics")]
+fn describe() {
+    use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+    describe_counter!(SEARCHES_TOTAL, "Search queries executed");
+    describe_histogram!(SEARCH_DURATION, Unit::Seconds, "Search latency");
+    describe_histogram!(SEARCH_HITS, "Results returned per search");
+    describe_counter!(SEARCH_CACHE_HITS_TOTAL, "Searches answered from the query cache");
+    describe_counter!(TOOL_CALLS_TOTAL, "MCP tool invocations by tool");
+    describe_counter!(TOOL_ERRORS_TOTAL, "MCP tool invocations that returned an error");
+    describe_counter!(TOOL_REJECTED_TOTAL, "MCP tool invocations rejected by rate limits or size caps");
+    describe_gauge!(INDEXED_FILES, "Files currently in the index");
+    describe_gauge!(INDEX_SIZE_BYTES, Unit::Bytes, "Size of the on-disk index");
+    describe_histogram!(COMMIT_DURATION, Unit::Seconds, "Index commit latency");
+    describe_gauge!(WATCHER_PENDING, "Watcher events received but not yet applied");
+    describe_counter!(WATCHER_DROPPED_TOTAL, "Watcher events that overflowed the channel");
+    describe_histogram!(BATCH_EVENTS, "Events applied per watcher batch");
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use crate::indexer::FileIndex;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_metrics_render_search_and_index_series() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("files");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "gauge test").unwrap();
+
+        metrics::with_local_recorder(&recorder, || {
+            describe();
+            let mut idx = FileIndex::new(Some(dir.path().join("index"))).unwrap();
+            idx.index_directory(&root).unwrap();
+            idx.commit().unwrap();
+            assert_eq!(idx.search("gauge", 10, None, None).unwrap().results.len(), 1);
+        });
+
+        let rendered = handle.render();
+        for name in [SEARCHES_TOTAL, SEARCH_DURATION, SEARCH_HITS, COMMIT_DURATION] {
+            assert!(rendered.contains(name), "{} missing from:\n{}", name, rendered);
+        }
+        assert!(rendered.contains(&format!("{} 1", INDEXED_FILES)), "{}", rendered);
+        let size = rendered
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{} ", INDEX_SIZE_BYTES)))
+            .expect("index size gauge missing");
+        assert!(size.parse::<f64>().unwrap() > 0.0);
+    }
+}
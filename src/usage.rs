@@ -0,0 +1,174 @@
+//! Usage-based ranking: files read after turning up in search results rank a little
+//! higher in later searches.
+//!
+//! Off unless `LOCALFILES_USAGE_RANKING` is set (`IndexConfig::usage_ranking`). The
+//! server counts a read when `read_file` or `read_files` opens a file from a recent
+//! result set; the counts are saved next to the index as `<index>.usage.json` at most
+//! every `SAVE_INTERVAL` and when the index is dropped, so what was learned survives
+//! restarts. The boost grows with the
+//! logarithm of the reads and is capped at `MAX_USAGE_BOOST`, so usage breaks ties
+//! between comparable matches without outranking clearly better ones.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::manifest;
+
+/// Turns usage-based ranking on.
+pub const USAGE_ENV: &str = "LOCALFILES_USAGE_RANKING";
+
+/// Largest factor a file's reads multiply the score of its matches by.
+pub const MAX_USAGE_BOOST: f32 = 1.5;
+
+/// Boost per natural-log unit of reads.
+const USAGE_WEIGHT: f32 = 0.1;
+
+/// Least time between two saves of the counts; reads in between are saved together.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Read counts of the index at `index_path`.
+pub fn usage_path(index_path: &Path) -> PathBuf {
+    let name = index_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "index".to_string());
+    index_path.with_file_name(format!("{}.usage.json", name))
+}
+
+/// Factor the score of a file read `reads` times is multiplied by.
+pub fn boost(reads: u32) -> f32 {
+    (1.0 + USAGE_WEIGHT * (reads as f32).ln_1p()).min(MAX_USAGE_BOOST)
+}
+
+/// Reads of search results per indexed path, shared by every handle on the index.
+#[derive(Debug, Default)]
+pub struct Usage {
+    /// Where the counts are saved; kept in memory only without one.
+    path: Option<PathBuf>,
+    reads: Mutex<Reads>,
+}
+
+#[derive(Debug, Default)]
+struct Reads {
+    counts: BTreeMap<String, u32>,
+    /// Counted since the last save.
+    unsaved: bool,
+    saved_at: Option<Instant>,
+}
+
+impl Usage {
+    /// Counts saved for the index at `index_path`, or none.
+    pub fn open(index_path: Option<&Path>) -> Self {
+        let path = index_path.map(usage_path);
+        let reads = match path.as_deref().map(load).transpose() {
+            Ok(reads) => reads.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("Failed to read the usage counts: {}", e);
+                BTreeMap::new()
+            }
+        };
+        Self {
+            path,
+            reads: Mutex::new(Reads {
+                counts: reads,
+                ..Reads::default()
+            }),
+        }
+    }
+
+    /// Count a read of `file`, saving the counts unless they were saved less than
+    /// `SAVE_INTERVAL` ago.
+    pub fn record(&self, file: &str) -> anyhow::Result<()> {
+        let mut reads = self.reads.lock().unwrap_or_else(|e| e.into_inner());
+        *reads.counts.entry(file.to_string()).or_default() += 1;
+        reads.unsaved = true;
+        if reads.saved_at.is_some_and(|at| at.elapsed() < SAVE_INTERVAL) {
+            return Ok(());
+        }
+        self.save(&mut reads)
+    }
+
+    /// Save reads counted since the last save.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        let mut reads = self.reads.lock().unwrap_or_else(|e| e.into_inner());
+        self.save(&mut reads)
+    }
+
+    /// Reads of `file` counted so far.
+    pub fn reads(&self, file: &str) -> u32 {
+        let reads = self.reads.lock().unwrap_or_else(|e| e.into_inner());
+        reads.counts.get(file).copied().unwrap_or(0)
+    }
+
+    /// Forget every read, in memory only; `purge` deletes the saved counts.
+    pub fn clear(&self) {
+        let mut reads = self.reads.lock().unwrap_or_else(|e| e.into_inner());
+        reads.counts.clear();
+        reads.unsaved = false;
+    }
+
+    /// Score factor of every file read at least once.
+    pub fn boosts(&self) -> HashMap<String, f32> {
+        let reads = self.reads.lock().unwrap_or_else(|e| e.into_inner());
+        reads.counts.iter().map(|(file, reads)| (file.clone(), boost(*reads))).collect()
+    }
+
+    fn save(&self, reads: &mut Reads) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !reads.unsaved {
+            return Ok(());
+        }
+        manifest::write_json_atomic(path, &reads.counts)?;
+        reads.unsaved = false;
+        reads.saved_at = Some(Instant::now());
+        Ok(())
+    }
+}
+
+impl Drop for Usage {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            tracing::warn!("Failed to save the usage counts: {}", e);
+        }
+    }
+}
+
+fn load(path: &Path) -> anyhow::Result<BTreeMap<String, u32>> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_record_persist_and_boost() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().join("index");
+        let usage = Usage::open(Some(&index_path));
+        assert!(usage.boosts().is_empty());
+        usage.record("/notes/a.md").unwrap();
+        usage.record("/notes/a.md").unwrap();
+        assert_eq!(usage_path(&index_path), dir.path().join("index.usage.json"));
+        // The second read is saved with later ones, or when the counts are dropped
+        assert_eq!(Usage::open(Some(&index_path)).reads("/notes/a.md"), 1);
+        drop(usage);
+
+        let usage = Usage::open(Some(&index_path));
+        assert_eq!(usage.reads("/notes/a.md"), 2);
+        assert_eq!(usage.boosts()["/notes/a.md"], boost(2));
+
+        assert_eq!(boost(0), 1.0);
+        assert!(boost(1) > 1.0 && boost(1) < boost(10));
+        assert_eq!(boost(u32::MAX), MAX_USAGE_BOOST);
+    }
+}
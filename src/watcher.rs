@@ -1,7 +1,17 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use tokio::sync::mpsc;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::{mpsc, Notify};
+
+use crate::telemetry;
+
+const CHANNEL_CAPACITY: usize = 256;
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 pub enum FileEvent {
     Created(PathBuf),
@@ -9,39 +19,582 @@ pub enum FileEvent {
     Removed(PathBuf),
 }
 
-/// Create a new file watcher and a channel receiver for file events.
+impl FileEvent {
+    pub fn path(&self) -> &Path {
+        match self {
+            FileEvent::Created(p) | FileEvent::Modified(p) | FileEvent::Removed(p) => p,
+        }
+    }
+}
+
+/// Events waiting for the next batch: the latest one per path, so that each path is
+/// applied at most once per batch however often it changed.
+#[derive(Default)]
+pub struct PendingEvents {
+    events: HashMap<PathBuf, FileEvent>,
+}
+
+impl PendingEvents {
+    /// Queue `event`, replacing any earlier event for its path.
+    pub fn push(&mut self, event: FileEvent) {
+        self.events.insert(event.path().to_path_buf(), event);
+    }
+
+    /// Queue `event` unless its path already has one.
+    pub fn push_if_absent(&mut self, event: FileEvent) {
+        self.events.entry(event.path().to_path_buf()).or_insert(event);
+    }
+
+    /// Number of distinct paths queued.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether no event is queued.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Take every queued event for processing.
+    pub fn take(&mut self) -> Vec<FileEvent> {
+        self.events.drain().map(|(_, event)| event).collect()
+    }
+}
+
+/// Events that did not fit in the watcher channel.
 ///
-/// The caller keeps the `RecommendedWatcher` alive and uses it to register paths.
-/// File events are sent through the returned mpsc receiver.
-pub fn new_watcher() -> anyhow::Result<(RecommendedWatcher, mpsc::Receiver<FileEvent>)> {
-    let (tx, rx) = mpsc::channel::<FileEvent>(256);
-
-    let watcher =
-        notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
-            if let Ok(event) = res {
-                let events: Vec<FileEvent> = match event.kind {
-                    EventKind::Create(_) => {
-                        event.paths.into_iter().map(FileEvent::Created).collect()
-                    }
-                    EventKind::Modify(_) => {
-                        event.paths.into_iter().map(FileEvent::Modified).collect()
-                    }
-                    EventKind::Remove(_) => {
-                        event.paths.into_iter().map(FileEvent::Removed).collect()
+/// notify's callback runs on its own thread and must never block, so when the
+/// channel is full the affected paths are coalesced here instead. The consumer
+/// drains them with `take_paths` and rescans each one from disk.
+#[derive(Default)]
+pub struct Overflow {
+    dropped: AtomicU64,
+    paths: Mutex<HashSet<PathBuf>>,
+    notify: Notify,
+}
+
+impl Overflow {
+    /// Total number of events that overflowed the channel since startup.
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Take all paths awaiting a rescan.
+    pub fn take_paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.paths.lock().unwrap_or_else(|e| e.into_inner());
+        paths.drain().collect()
+    }
+
+    /// Wait until at least one event overflows.
+    pub async fn notified(&self) {
+        self.notify.notified().await
+    }
+
+    fn record(&self, path: PathBuf) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        metrics::counter!(telemetry::WATCHER_DROPPED_TOTAL).increment(1);
+        self.paths
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(path);
+        self.notify.notify_one();
+    }
+}
+
+/// Progress of the background event processor, shared with the status tool.
+#[derive(Default)]
+pub struct QueueStats {
+    pending: AtomicUsize,
+    last_batch: AtomicUsize,
+}
+
+impl QueueStats {
+    /// Events received but not yet applied to the index.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Number of events applied in the most recent batch.
+    pub fn last_batch(&self) -> usize {
+        self.last_batch.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pending(&self, n: usize) {
+        self.pending.store(n, Ordering::Relaxed);
+        metrics::gauge!(telemetry::WATCHER_PENDING).set(n as f64);
+    }
+
+    pub fn finish_batch(&self, processed: usize) {
+        self.last_batch.store(processed, Ordering::Relaxed);
+        self.pending.store(0, Ordering::Relaxed);
+        metrics::gauge!(telemetry::WATCHER_PENDING).set(0.0);
+        metrics::histogram!(telemetry::BATCH_EVENTS).record(processed as f64);
+    }
+}
+
+/// Paths the watcher reports events for.
+#[derive(Default)]
+struct WatchFilter {
+    /// Directories watched recursively; every event below them is reported.
+    roots: HashSet<PathBuf>,
+    /// Individually watched files, keyed by their parent directory, which is watched
+    /// non-recursively so that atomic saves (write temp file, rename over) are seen.
+    files: HashMap<PathBuf, HashSet<PathBuf>>,
+}
+
+impl WatchFilter {
+    fn allows(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+            || path
+                .parent()
+                .and_then(|dir| self.files.get(dir))
+                .is_some_and(|files| files.contains(path))
+    }
+}
+
+/// How a path is watched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchMode {
+    /// The platform's native backend (inotify, FSEvents, ReadDirectoryChangesW).
+    Native,
+    /// Periodic rescans, for network filesystems and container bind mounts where
+    /// native events are not delivered.
+    Poll,
+}
+
+/// The native backend ran out of watches while registering `path`: on Linux every
+/// watched directory takes one of the user's `fs.inotify.max_user_watches`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchLimitHit {
+    pub path: PathBuf,
+    /// The current limit, where the platform exposes it.
+    pub max_user_watches: Option<u64>,
+    /// Whether `path` is polled instead; otherwise it is not watched at all.
+    pub polled: bool,
+}
+
+impl WatchLimitHit {
+    /// What happened and how to fix it, for logs and tool responses.
+    pub fn message(&self) -> String {
+        let limit = match self.max_user_watches {
+            Some(n) => format!(" (fs.inotify.max_user_watches = {})", n),
+            None => String::new(),
+        };
+        let outcome = if self.polled {
+            "polling it instead, which is slower to notice changes"
+        } else {
+            "it is not watched"
+        };
+        format!(
+            "File watch limit reached{} while watching {}; {}. Raise the limit \
+             (e.g. `sudo sysctl fs.inotify.max_user_watches=524288`), or skip large \
+             directories with skip_dirs, then index the path again",
+            limit,
+            self.path.display(),
+            outcome
+        )
+    }
+}
+
+/// Whether `e` means the OS limit on watches is used up (inotify's ENOSPC).
+fn is_watch_limit(e: &notify::Error) -> bool {
+    matches!(e.kind, notify::ErrorKind::MaxFilesWatch)
+}
+
+/// `fs.inotify.max_user_watches`, on Linux.
+fn max_user_watches() -> Option<u64> {
+    std::fs::read_to_string("/proc/sys/fs/inotify/max_user_watches")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Turns notify events into `FileEvent`s on the channel. Shared by the native and
+/// polling backends.
+#[derive(Clone)]
+struct EventSink {
+    tx: mpsc::Sender<FileEvent>,
+    overflow: Arc<Overflow>,
+    filter: Arc<Mutex<WatchFilter>>,
+}
+
+impl EventSink {
+    fn handle(&self, res: Result<Event, notify::Error>) {
+        let event = match res {
+            Ok(event) => event,
+            Err(e) if is_watch_limit(&e) => {
+                tracing::warn!(
+                    "File watch limit reached, changes in new directories may be missed: {}",
+                    e
+                );
+                return;
+            }
+            Err(e) => {
+                tracing::warn!("File watcher error: {}", e);
+                return;
+            }
+        };
+        let paths: Vec<PathBuf> = {
+            let filter = self.filter.lock().unwrap_or_else(|e| e.into_inner());
+            event.paths.into_iter().filter(|p| filter.allows(p)).collect()
+        };
+        let events: Vec<FileEvent> = match event.kind {
+            EventKind::Create(_) => paths.into_iter().map(FileEvent::Created).collect(),
+            // A rename reports both ends: the old name is gone, the new one exists
+            EventKind::Modify(ModifyKind::Name(_)) => paths
+                .into_iter()
+                .map(|p| {
+                    if p.exists() {
+                        FileEvent::Created(p)
+                    } else {
+                        FileEvent::Removed(p)
                     }
-                    _ => vec![],
-                };
-                for fe in events {
-                    let _ = tx.blocking_send(fe);
+                })
+                .collect(),
+            EventKind::Modify(_) => paths.into_iter().map(FileEvent::Modified).collect(),
+            EventKind::Remove(_) => paths.into_iter().map(FileEvent::Removed).collect(),
+            _ => vec![],
+        };
+        for fe in events {
+            match self.tx.try_send(fe) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(fe)) => {
+                    self.overflow.record(fe.path().to_path_buf());
                 }
+                Err(mpsc::error::TrySendError::Closed(_)) => return,
+            }
+        }
+    }
+}
+
+/// Native and polling notify watchers plus the set of registered paths their events
+/// are filtered to.
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    /// Created on first use, since most setups never need it.
+    poller: Option<PollWatcher>,
+    poll_interval: Duration,
+    polled: Vec<PathBuf>,
+    /// Poll paths the native backend has no watches left for, instead of failing.
+    limit_fallback: bool,
+    limit_hits: Vec<WatchLimitHit>,
+    sink: EventSink,
+}
+
+impl FileWatcher {
+    /// Watch a directory recursively, or a single file through its parent directory.
+    ///
+    /// If the native backend refuses the path, it is polled instead. Returns the mode
+    /// actually used.
+    pub fn watch(&mut self, path: &Path, mode: WatchMode) -> anyhow::Result<WatchMode> {
+        if path.is_dir() {
+            let used = self.register(path, RecursiveMode::Recursive, mode)?;
+            self.lock_filter().roots.insert(path.to_path_buf());
+            return Ok(used);
+        }
+
+        let parent = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let watching_parent = self.lock_filter().files.contains_key(&parent);
+        let used = if watching_parent {
+            if self.polled.contains(&parent) {
+                WatchMode::Poll
+            } else {
+                WatchMode::Native
+            }
+        } else {
+            self.register(&parent, RecursiveMode::NonRecursive, mode)?
+        };
+        self.lock_filter()
+            .files
+            .entry(parent)
+            .or_default()
+            .insert(path.to_path_buf());
+        Ok(used)
+    }
+
+    /// Stop watching a directory previously registered with `watch`.
+    pub fn unwatch(&mut self, dir: &Path) -> anyhow::Result<()> {
+        self.lock_filter().roots.remove(dir);
+        self.limit_hits.retain(|hit| hit.path != dir);
+        if let Some(i) = self.polled.iter().position(|p| p == dir) {
+            self.polled.remove(i);
+            if let Some(poller) = &mut self.poller {
+                poller.unwatch(dir)?;
             }
-        })?;
+        } else {
+            self.watcher.unwatch(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Interval between rescans for polled paths. Applies to paths polled from now on.
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    /// Paths watched by polling, including native fallbacks.
+    pub fn polled_paths(&self) -> &[PathBuf] {
+        &self.polled
+    }
+
+    /// Whether the consumer of the event channel is still running; events are lost once
+    /// it has stopped.
+    pub fn is_running(&self) -> bool {
+        !self.sink.tx.is_closed()
+    }
+
+    /// Events queued in the channel and its capacity.
+    pub fn channel_usage(&self) -> (usize, usize) {
+        let capacity = self.sink.tx.max_capacity();
+        (capacity - self.sink.tx.capacity(), capacity)
+    }
+
+    /// Whether paths refused for lack of native watches are polled (the default) or
+    /// fail to be watched.
+    pub fn set_limit_fallback(&mut self, enabled: bool) {
+        self.limit_fallback = enabled;
+    }
+
+    /// Paths registered while the native watch limit was exhausted.
+    pub fn watch_limit_hits(&self) -> &[WatchLimitHit] {
+        &self.limit_hits
+    }
+
+    /// The watch limit hit recorded for `path`, if registering it exhausted the limit.
+    pub fn watch_limit_hit(&self, path: &Path) -> Option<&WatchLimitHit> {
+        self.limit_hits.iter().find(|hit| hit.path == path)
+    }
+
+    fn register(
+        &mut self,
+        target: &Path,
+        recursive: RecursiveMode,
+        mode: WatchMode,
+    ) -> anyhow::Result<WatchMode> {
+        if mode == WatchMode::Native {
+            match self.watcher.watch(target, recursive) {
+                Ok(()) => return Ok(WatchMode::Native),
+                Err(e) if is_watch_limit(&e) => return self.watch_limit_reached(target, recursive),
+                Err(e) => tracing::warn!(
+                    "Native watcher failed for {}, falling back to polling: {}",
+                    target.display(),
+                    e
+                ),
+            }
+        }
+        self.poller()?.watch(target, recursive)?;
+        self.polled.push(target.to_path_buf());
+        Ok(WatchMode::Poll)
+    }
+
+    /// The native backend ran out of watches part-way through `target`. Drop the watches
+    /// it did add, then poll `target`, or fail if the fallback is off.
+    fn watch_limit_reached(
+        &mut self,
+        target: &Path,
+        recursive: RecursiveMode,
+    ) -> anyhow::Result<WatchMode> {
+        // The partial recursive watch is removed with its root; there is none if the root
+        // itself was refused
+        let _ = self.watcher.unwatch(target);
+        let hit = WatchLimitHit {
+            path: target.to_path_buf(),
+            max_user_watches: max_user_watches(),
+            polled: self.limit_fallback,
+        };
+        tracing::warn!("{}", hit.message());
+        self.limit_hits.retain(|h| h.path != target);
+        self.limit_hits.push(hit);
+        if !self.limit_fallback {
+            anyhow::bail!("no file watches left for {}", target.display());
+        }
+        self.poller()?.watch(target, recursive)?;
+        self.polled.push(target.to_path_buf());
+        Ok(WatchMode::Poll)
+    }
+
+    fn poller(&mut self) -> anyhow::Result<&mut PollWatcher> {
+        if self.poller.is_none() {
+            let sink = self.sink.clone();
+            let config = notify::Config::default().with_poll_interval(self.poll_interval);
+            self.poller = Some(PollWatcher::new(
+                move |res: Result<Event, notify::Error>| sink.handle(res),
+                config,
+            )?);
+        }
+        Ok(self.poller.as_mut().expect("poller was just created"))
+    }
 
-    Ok((watcher, rx))
+    fn lock_filter(&self) -> std::sync::MutexGuard<'_, WatchFilter> {
+        self.sink.filter.lock().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
-/// Helper to add a path to a watcher.
-pub fn watch_path(watcher: &mut RecommendedWatcher, path: &Path) -> anyhow::Result<()> {
-    watcher.watch(path, RecursiveMode::Recursive)?;
-    Ok(())
+/// Create a new file watcher and a channel receiver for file events.
+///
+/// The caller keeps the `FileWatcher` alive and uses it to register paths.
+/// File events are sent through the returned mpsc receiver; events that arrive
+/// while the channel is full are recorded in the returned `Overflow`.
+pub fn new_watcher() -> anyhow::Result<(
+    FileWatcher,
+    mpsc::Receiver<FileEvent>,
+    Arc<Overflow>,
+)> {
+    let (tx, rx) = mpsc::channel::<FileEvent>(CHANNEL_CAPACITY);
+    let overflow = Arc::new(Overflow::default());
+    let sink = EventSink {
+        tx,
+        overflow: overflow.clone(),
+        filter: Arc::new(Mutex::new(WatchFilter::default())),
+    };
+
+    let sink_cb = sink.clone();
+    let watcher = notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
+        sink_cb.handle(res)
+    })?;
+
+    let file_watcher = FileWatcher {
+        watcher,
+        poller: None,
+        poll_interval: DEFAULT_POLL_INTERVAL,
+        polled: Vec::new(),
+        limit_fallback: true,
+        limit_hits: Vec::new(),
+        sink,
+    };
+    Ok((file_watcher, rx, overflow))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overflow_coalesces_paths() {
+        let overflow = Overflow::default();
+        overflow.record(PathBuf::from("/tmp/a.rs"));
+        overflow.record(PathBuf::from("/tmp/a.rs"));
+        overflow.record(PathBuf::from("/tmp/b.rs"));
+        assert_eq!(overflow.dropped_count(), 3);
+        let mut paths = overflow.take_paths();
+        paths.sort();
+        assert_eq!(paths, vec![PathBuf::from("/tmp/a.rs"), PathBuf::from("/tmp/b.rs")]);
+        assert!(overflow.take_paths().is_empty());
+    }
+
+    #[test]
+    fn test_pending_events_keep_latest_per_path() {
+        let mut pending = PendingEvents::default();
+        pending.push(FileEvent::Created(PathBuf::from("/tmp/a.rs")));
+        pending.push(FileEvent::Modified(PathBuf::from("/tmp/a.rs")));
+        pending.push(FileEvent::Modified(PathBuf::from("/tmp/b.rs")));
+        pending.push(FileEvent::Removed(PathBuf::from("/tmp/a.rs")));
+        // A retry does not override the change the watcher reported
+        pending.push_if_absent(FileEvent::Modified(PathBuf::from("/tmp/a.rs")));
+        pending.push_if_absent(FileEvent::Modified(PathBuf::from("/tmp/c.rs")));
+        assert_eq!(pending.len(), 3);
+
+        let mut events = pending.take();
+        events.sort_by(|a, b| a.path().cmp(b.path()));
+        assert!(matches!(&events[0], FileEvent::Removed(p) if p == Path::new("/tmp/a.rs")));
+        assert!(matches!(&events[1], FileEvent::Modified(p) if p == Path::new("/tmp/b.rs")));
+        assert!(matches!(&events[2], FileEvent::Modified(p) if p == Path::new("/tmp/c.rs")));
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_filter_single_files_and_roots() {
+        let mut filter = WatchFilter::default();
+        filter.roots.insert(PathBuf::from("/proj"));
+        filter
+            .files
+            .entry(PathBuf::from("/notes"))
+            .or_default()
+            .insert(PathBuf::from("/notes/todo.md"));
+        assert!(filter.allows(Path::new("/proj/src/deep/a.rs")));
+        assert!(filter.allows(Path::new("/notes/todo.md")));
+        assert!(!filter.allows(Path::new("/notes/.todo.md.swp")));
+        assert!(!filter.allows(Path::new("/notes/sub/todo.md")));
+    }
+
+    #[tokio::test]
+    async fn test_single_file_atomic_save() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let target = root.join("todo.md");
+        std::fs::write(&target, "v1").unwrap();
+
+        let (mut watcher, mut rx, _overflow) = new_watcher().unwrap();
+        assert_eq!(watcher.watch(&target, WatchMode::Native).unwrap(), WatchMode::Native);
+
+        // Editor-style save: write a sibling temp file, then rename it over the target
+        let temp = root.join(".todo.md.tmp");
+        std::fs::write(&temp, "v2").unwrap();
+        std::fs::rename(&temp, &target).unwrap();
+
+        let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(5);
+        let mut saw_target = false;
+        while let Ok(Some(event)) = tokio::time::timeout_at(deadline, rx.recv()).await {
+            assert_eq!(event.path(), target, "events for other files must be filtered out");
+            if matches!(event, FileEvent::Created(_) | FileEvent::Modified(_)) {
+                saw_target = true;
+                break;
+            }
+        }
+        assert!(saw_target, "expected an event for the saved file");
+    }
+
+    #[tokio::test]
+    async fn test_watch_limit_falls_back_to_polling() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        assert!(is_watch_limit(&notify::Error::new(notify::ErrorKind::MaxFilesWatch)));
+        assert!(!is_watch_limit(&notify::Error::generic("other")));
+
+        let (mut watcher, _rx, _overflow) = new_watcher().unwrap();
+        let mode = watcher.watch_limit_reached(&root, RecursiveMode::Recursive).unwrap();
+        assert_eq!(mode, WatchMode::Poll);
+        assert_eq!(watcher.polled_paths(), std::slice::from_ref(&root));
+        let hit = watcher.watch_limit_hit(&root).unwrap();
+        assert!(hit.polled);
+        assert!(hit.message().contains("max_user_watches"));
+
+        watcher.unwatch(&root).unwrap();
+        assert!(watcher.watch_limit_hits().is_empty());
+        watcher.set_limit_fallback(false);
+        assert!(watcher.watch_limit_reached(&root, RecursiveMode::Recursive).is_err());
+        assert!(!watcher.watch_limit_hit(&root).unwrap().polled);
+        assert!(watcher.polled_paths().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_poll_mode_reports_new_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+
+        let (mut watcher, mut rx, _overflow) = new_watcher().unwrap();
+        watcher.set_poll_interval(Duration::from_millis(50));
+        assert_eq!(watcher.watch(&root, WatchMode::Poll).unwrap(), WatchMode::Poll);
+        assert_eq!(watcher.polled_paths(), std::slice::from_ref(&root));
+
+        let created = root.join("new.md");
+        std::fs::write(&created, "hello").unwrap();
+        let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(5);
+        let mut saw_created = false;
+        while let Ok(Some(event)) = tokio::time::timeout_at(deadline, rx.recv()).await {
+            if event.path() == created {
+                saw_created = true;
+                break;
+            }
+        }
+        assert!(saw_created, "expected the poller to report the new file");
+        assert!(watcher.is_running());
+        assert_eq!(watcher.channel_usage().1, CHANNEL_CAPACITY);
+        drop(rx);
+        assert!(!watcher.is_running());
+    }
 }
@@ -0,0 +1,279 @@
+//! Opt-in writes for the `write_file`, `append_file` and `delete_file` tools.
+//!
+//! Off unless `LOCALFILES_WRITE_TOOLS` is set. Writes are confined to files the index
+//! would cover: below a watched root the session can see, of an indexed file type, and
+//! not excluded by the root's hidden-file policy. The target is resolved through its
+//! existing ancestors first, so a symlink cannot lead a write outside its root. The
+//! server indexes and commits the written file before answering.
+//!
+//! `delete_file` needs the `trash` feature and `LOCALFILES_DELETE_TOOL`. It only takes
+//! files already indexed, and moves them to the OS trash rather than unlinking them, so
+//! a mistaken delete can be undone by hand.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Enables `write_file` and `append_file`.
+pub const WRITE_TOOLS_ENV: &str = "LOCALFILES_WRITE_TOOLS";
+
+/// Enables `delete_file`, in builds with the `trash` feature.
+pub const DELETE_TOOL_ENV: &str = "LOCALFILES_DELETE_TOOL";
+
+/// Largest content accepted by one write.
+pub const MAX_WRITE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    /// A new file; fails if it exists.
+    Create,
+    /// A new file, or replace an existing one.
+    Overwrite,
+    /// Add to the end of a file, creating it if missing.
+    Append,
+}
+
+/// `path` with its existing part canonicalized, so symlinks are followed before the
+/// sandbox check. `path` must be absolute, without `..` in its missing part. A symlink
+/// to a missing file is refused, since writing through it would create its target
+/// wherever it points.
+pub fn resolve(path: &Path) -> anyhow::Result<PathBuf> {
+    if !path.is_absolute() {
+        anyhow::bail!("Path must be absolute: {}", path.display());
+    }
+    let mut existing = path;
+    let mut missing = Vec::new();
+    // `symlink_metadata`, unlike `exists`, finds dangling links
+    while std::fs::symlink_metadata(existing).is_err() {
+        // A `..` has no file name, so one in the missing part is rejected here
+        let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+            anyhow::bail!(
+                "Path must not contain '..' below a missing directory: {}",
+                path.display()
+            );
+        };
+        missing.push(name);
+        existing = parent;
+    }
+    let mut resolved = existing.canonicalize().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => {
+            anyhow::anyhow!("{} is a symbolic link to a missing file", existing.display())
+        }
+        _ => e.into(),
+    })?;
+    for name in missing.into_iter().rev() {
+        resolved.push(name);
+    }
+    Ok(resolved)
+}
+
+/// Write `content` to the resolved path `path` per `mode`, creating missing parent
+/// directories. Overwrites go through a temporary file renamed into place, so readers
+/// never see a half-written file; it gets the replaced file's permissions and, on unix,
+/// its owner and group. When the owner cannot be carried over (a file of another user
+/// this one may write to), the file is rewritten in place instead.
+pub fn write(path: &Path, content: &str, mode: WriteMode) -> anyhow::Result<()> {
+    if content.len() > MAX_WRITE_BYTES {
+        anyhow::bail!(
+            "Content is {} bytes; at most {} can be written at once",
+            content.len(),
+            MAX_WRITE_BYTES
+        );
+    }
+    if path.is_dir() {
+        anyhow::bail!("{} is a directory", path.display());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match mode {
+        WriteMode::Create => {
+            let mut file = no_follow(std::fs::OpenOptions::new().write(true).create_new(true))
+                .open(path)
+                .map_err(|e| match e.kind() {
+                    std::io::ErrorKind::AlreadyExists => anyhow::anyhow!(
+                        "{} already exists; pass overwrite: true to replace it",
+                        path.display()
+                    ),
+                    _ => e.into(),
+                })?;
+            file.write_all(content.as_bytes())?;
+        }
+        WriteMode::Overwrite => {
+            let existing = std::fs::metadata(path).ok();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let temp = path.with_file_name(format!(".{}.localfiles-tmp", name));
+            let Some(mut file) = create_temp(&temp, existing.as_ref())? else {
+                // Another user's file: only they can own a replacement
+                let mut file = no_follow(std::fs::OpenOptions::new().write(true).truncate(true))
+                    .open(path)?;
+                file.write_all(content.as_bytes())?;
+                return Ok(());
+            };
+            let replaced = file
+                .write_all(content.as_bytes())
+                .and_then(|()| std::fs::rename(&temp, path));
+            if let Err(e) = replaced {
+                let _ = std::fs::remove_file(&temp);
+                return Err(e.into());
+            }
+        }
+        WriteMode::Append => {
+            let mut file =
+                no_follow(std::fs::OpenOptions::new().append(true).create(true)).open(path)?;
+            file.write_all(content.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// `options` refusing to open a symlink, such as one planted after `resolve` checked the
+/// path.
+fn no_follow(options: &mut std::fs::OpenOptions) -> &mut std::fs::OpenOptions {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+    options
+}
+
+/// Create the temporary file for an overwrite, with the permissions, owner and group of
+/// the `existing` file. A leftover from an interrupted write is replaced; a link planted
+/// there is not followed. `None` if the owner cannot be carried over.
+fn create_temp(
+    temp: &Path,
+    existing: Option<&std::fs::Metadata>,
+) -> anyhow::Result<Option<std::fs::File>> {
+    let _ = std::fs::remove_file(temp);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // Never readable by more users than the file it replaces, even briefly
+    #[cfg(unix)]
+    if let Some(existing) = existing {
+        use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+        options.mode(existing.mode() & 0o777);
+    }
+    let file = options.open(temp)?;
+    let Some(existing) = existing else {
+        return Ok(Some(file));
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let (uid, gid) = (existing.uid(), existing.gid());
+        let created = file.metadata()?;
+        if (created.uid(), created.gid()) != (uid, gid) {
+            if let Err(e) = std::os::unix::fs::fchown(&file, Some(uid), Some(gid)) {
+                let _ = std::fs::remove_file(temp);
+                return match e.kind() {
+                    std::io::ErrorKind::PermissionDenied => Ok(None),
+                    _ => Err(e.into()),
+                };
+            }
+        }
+    }
+    // After the owner, since changing it clears setuid and setgid bits
+    file.set_permissions(existing.permissions())?;
+    Ok(Some(file))
+}
+
+/// Move the file at `path` to the OS trash, or into `trash_dir` (on the same filesystem)
+/// when one is given.
+#[cfg(feature = "trash")]
+pub fn trash(path: &Path, trash_dir: Option<&Path>) -> anyhow::Result<()> {
+    if !path.is_file() {
+        anyhow::bail!("{} is not a file", path.display());
+    }
+    let Some(dir) = trash_dir else {
+        return trash::delete(path)
+            .map_err(|e| anyhow::anyhow!("Moving {} to the trash failed: {}", path.display(), e));
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    std::fs::create_dir_all(dir)?;
+    let mut target = dir.join(&name);
+    let mut n = 1;
+    while std::fs::symlink_metadata(&target).is_ok() {
+        target = dir.join(format!("{}.{}", name, n));
+        n += 1;
+    }
+    std::fs::rename(path, &target).map_err(|e| {
+        anyhow::anyhow!("Moving {} to {} failed: {}", path.display(), dir.display(), e)
+    })
+}
+
+/// Without the `trash` feature nothing can be moved to the trash.
+#[cfg(not(feature = "trash"))]
+pub fn trash(path: &Path, _trash_dir: Option<&Path>) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "Cannot move {} to the trash: this build lacks the trash feature",
+        path.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_and_write_modes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let note = resolve(&root.join("notes/new/today.md")).unwrap();
+        assert_eq!(note, root.join("notes/new/today.md"));
+        assert!(resolve(Path::new("notes/today.md")).is_err());
+        assert!(resolve(&root.join("notes/../../etc/passwd")).is_err());
+
+        write(&note, "# Today\n", WriteMode::Create).unwrap();
+        assert!(write(&note, "again", WriteMode::Create).is_err());
+        write(&note, "- item\n", WriteMode::Append).unwrap();
+        assert_eq!(std::fs::read_to_string(&note).unwrap(), "# Today\n- item\n");
+        write(&note, "replaced\n", WriteMode::Overwrite).unwrap();
+        assert_eq!(std::fs::read_to_string(&note).unwrap(), "replaced\n");
+        let leftovers = std::fs::read_dir(note.parent().unwrap()).unwrap().count();
+        assert_eq!(leftovers, 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+            // Overwrites keep the mode, owner and group, and do not follow a link
+            // planted where the temporary file goes
+            let secret = root.join("secret.md");
+            write(&secret, "v1", WriteMode::Create).unwrap();
+            std::fs::set_permissions(&secret, std::fs::Permissions::from_mode(0o640)).unwrap();
+            let before = std::fs::metadata(&secret).unwrap();
+            let decoy = root.join("decoy.md");
+            std::fs::write(&decoy, "untouched").unwrap();
+            std::os::unix::fs::symlink(&decoy, root.join(".secret.md.localfiles-tmp")).unwrap();
+            write(&secret, "v2", WriteMode::Overwrite).unwrap();
+            let after = std::fs::metadata(&secret).unwrap();
+            assert_eq!(std::fs::read_to_string(&secret).unwrap(), "v2");
+            assert_eq!(after.mode() & 0o7777, 0o640);
+            assert_eq!((after.uid(), after.gid()), (before.uid(), before.gid()));
+            assert_ne!(after.ino(), before.ino());
+            assert_eq!(std::fs::read_to_string(&decoy).unwrap(), "untouched");
+            assert!(!root.join(".secret.md.localfiles-tmp").exists());
+
+            // A symlink resolves to its target, which the caller then checks
+            let outside = tempfile::TempDir::new().unwrap();
+            let link = root.join("escape");
+            std::os::unix::fs::symlink(outside.path(), &link).unwrap();
+            let resolved = resolve(&link.join("x.md")).unwrap();
+            assert!(!resolved.starts_with(&root));
+
+            // A dangling link to a file outside the root is neither resolved as a new
+            // file below the root nor written through
+            let target = outside.path().join("profile");
+            let dangling = root.join("notes.md");
+            std::os::unix::fs::symlink(&target, &dangling).unwrap();
+            let err = resolve(&dangling).unwrap_err();
+            assert!(err.to_string().contains("symbolic link to a missing file"), "{}", err);
+            std::os::unix::fs::symlink(outside.path().join("gone"), root.join("dir")).unwrap();
+            assert!(resolve(&root.join("dir/x.md")).is_err());
+            for mode in [WriteMode::Create, WriteMode::Append] {
+                assert!(write(&dangling, "export EVIL=1\n", mode).is_err());
+                assert!(!target.exists());
+            }
+        }
+    }
+}